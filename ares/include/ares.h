@@ -0,0 +1,74 @@
+/* The C interface to the ares vm, built with the `capi` feature.
+ *
+ * Contexts and values are opaque pointers that the caller owns and must
+ * free with `ares_free` and `ares_value_free`.  Strings returned by these
+ * functions are freed with `ares_string_free`.  Functions that can fail
+ * return null (or 0); `ares_last_error` describes the most recent failure
+ * on a context.
+ */
+#ifndef ARES_H
+#define ARES_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct AresContext AresContext;
+typedef struct AresValue AresValue;
+
+/* `args` points at `argc` values that are only valid during the call.
+ * The callback returns a new value, which the vm takes ownership of, or
+ * null for nil. */
+typedef AresValue *(*AresNativeFn)(void *user_data, size_t argc, const AresValue *const *args);
+
+/* The kinds returned by `ares_value_kind`. */
+#define ARES_NIL 0
+#define ARES_INT 1
+#define ARES_FLOAT 2
+#define ARES_BOOL 3
+#define ARES_STRING 4
+#define ARES_OTHER 5
+
+AresContext *ares_new(void);
+void ares_free(AresContext *ctx);
+
+/* Returns the result, which is nil for programs that don't produce a
+ * value, or null if evaluation failed. */
+AresValue *ares_eval(AresContext *ctx, const char *source);
+/* The string belongs to the context. */
+const char *ares_last_error(const AresContext *ctx);
+
+/* Returns 1 on success. */
+int ares_register_fn(AresContext *ctx, const char *name, AresNativeFn f, void *user_data);
+int ares_set_global(AresContext *ctx, const char *name, const AresValue *value);
+/* Null if the global isn't defined. */
+AresValue *ares_get_global(AresContext *ctx, const char *name);
+
+void ares_value_free(AresValue *value);
+int ares_value_kind(const AresValue *value);
+
+AresValue *ares_new_nil(void);
+AresValue *ares_new_int(int64_t i);
+AresValue *ares_new_float(double f);
+AresValue *ares_new_bool(int b);
+/* Null if `s` is not utf-8. */
+AresValue *ares_new_string(const char *s);
+
+/* These return 0 if `value` is of another kind. */
+int ares_get_int(const AresValue *value, int64_t *out);
+int ares_get_float(const AresValue *value, double *out);
+int ares_get_bool(const AresValue *value, int *out);
+/* A copy of the string, or null if `value` isn't a string. */
+char *ares_get_string(const AresValue *value);
+/* Formats any value the way the repl prints it. */
+char *ares_to_string(const AresContext *ctx, const AresValue *value);
+void ares_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,145 @@
+extern crate ares;
+extern crate ares_syntax;
+extern crate typed_arena;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+use ares::compiler::{CompileError, Diagnostic, format_source, parse_iter};
+use ares::host::*;
+use ares::vm::{Value, user_function};
+use ares_syntax::SymbolIntern;
+use typed_arena::Arena;
+
+const USAGE: &'static str = "usage: ares <command> <file>
+
+commands:
+    run            runs the file
+    check          parses and binds the file without running it, printing what is wrong with it
+    fmt            prints the file formatted
+    dump-ast       prints the syntax tree of each form in the file as JSON
+    dump-bytecode  prints the instructions and constants that the file compiles to
+";
+
+fn usage() -> ! {
+    write!(io::stderr(), "{}", USAGE).unwrap();
+    process::exit(2);
+}
+
+fn report(diagnostics: &[Diagnostic], source: &str) {
+    for diagnostic in diagnostics {
+        write!(io::stderr(), "{}", diagnostic.render(source)).unwrap();
+    }
+}
+
+fn new_context() -> Context<()> {
+    let mut ctx = Context::<()>::new();
+    ctx.set_global("print", user_function::<(), _>(None, |args, _, ctx| {
+        if ctx.check_arity(&args, 1) {
+            println!("{}", ctx.format_value(&args[0]));
+        }
+        Value::Nil
+    }));
+    ctx
+}
+
+fn run(source: &str) -> bool {
+    let mut state = ();
+    let mut ctx = new_context();
+    match ctx.eval(&mut state, source) {
+        Ok(_) => true,
+        Err(e) => {
+            report(&e.to_diagnostics(ctx.interner()), source);
+            false
+        }
+    }
+}
+
+fn check(source: &str) -> bool {
+    let mut ctx = new_context();
+    let result = ctx.check(source);
+    report(&ctx.diagnostics(), source);
+    result.is_ok()
+}
+
+fn fmt(source: &str) -> bool {
+    match format_source(source) {
+        Ok(formatted) => {
+            print!("{}", formatted);
+            true
+        }
+        Err(e) => {
+            report(&CompileError::from(e).to_diagnostics(&SymbolIntern::new()), source);
+            false
+        }
+    }
+}
+
+fn dump_ast(source: &str) -> bool {
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let forms: Result<Vec<_>, _> = parse_iter(source, &mut interner, &arena).collect();
+    match forms {
+        Ok(forms) => {
+            for form in forms {
+                println!("{}", form.to_json(&interner));
+            }
+            true
+        }
+        Err(e) => {
+            report(&CompileError::from(e).to_diagnostics(&interner), source);
+            false
+        }
+    }
+}
+
+fn dump_bytecode(source: &str) -> bool {
+    let mut ctx = new_context();
+    match ctx.compile(source) {
+        Ok(program) => {
+            println!("instructions:");
+            for (i, instr) in program.instructions().iter().enumerate() {
+                println!("{:>6}  {:?}", i, instr);
+            }
+            println!("constants:");
+            for (i, constant) in program.constants().iter().enumerate() {
+                println!("{:>6}  {:?}", i, constant);
+            }
+            true
+        }
+        Err(_) => {
+            report(&ctx.diagnostics(), source);
+            false
+        }
+    }
+}
+
+/// The front end for the other tools: `ares <command> <file>`.  Problems
+/// with the file are printed as diagnostics, and the exit code is 1 if
+/// there were any errors.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 2 {
+        usage();
+    }
+    let command: fn(&str) -> bool = match &args[0][..] {
+        "run" => run,
+        "check" => check,
+        "fmt" => fmt,
+        "dump-ast" => dump_ast,
+        "dump-bytecode" => dump_bytecode,
+        _ => usage(),
+    };
+
+    let path = &args[1];
+    let mut source = String::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut source)) {
+        writeln!(io::stderr(), "{}: {}", path, e).unwrap();
+        process::exit(1);
+    }
+    if !command(&source) {
+        process::exit(1);
+    }
+}
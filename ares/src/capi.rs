@@ -0,0 +1,334 @@
+//! A C interface to the vm.
+//!
+//! Contexts and values are handed out as opaque pointers that the caller
+//! owns and must free with `ares_free` and `ares_value_free`.  Strings
+//! returned by these functions are freed with `ares_string_free`.
+//! Functions that can fail return null (or 0); `ares_last_error`
+//! describes the most recent failure on a context.
+//!
+//! `include/ares.h` declares these functions for C.  The library is built
+//! as a `staticlib` and a `cdylib` to link against.
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use libc::{c_char, c_int, c_void};
+
+use host::{Context, ContextLike};
+use vm::{Value, user_function};
+
+pub struct AresContext {
+    context: Context<()>,
+    last_error: Option<CString>,
+}
+
+pub struct AresValue(Value);
+
+/// The type of `ares_register_fn` callbacks.  `args` points at `argc`
+/// values that are only valid during the call.  The callback returns a
+/// new value, which the vm takes ownership of, or null for nil.
+pub type AresNativeFn = extern "C" fn(user_data: *mut c_void, argc: usize, args: *const *const AresValue)
+                                      -> *mut AresValue;
+
+pub const ARES_NIL: c_int = 0;
+pub const ARES_INT: c_int = 1;
+pub const ARES_FLOAT: c_int = 2;
+pub const ARES_BOOL: c_int = 3;
+pub const ARES_STRING: c_int = 4;
+pub const ARES_OTHER: c_int = 5;
+
+fn boxed(value: Value) -> *mut AresValue {
+    Box::into_raw(Box::new(AresValue(value)))
+}
+
+fn to_c_string(s: String) -> CString {
+    // Interior nuls can't be represented, so they are dropped.
+    CString::new(s.replace('\0', "")).unwrap()
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ares_new() -> *mut AresContext {
+    Box::into_raw(Box::new(AresContext {
+        context: Context::new(),
+        last_error: None,
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_free(ctx: *mut AresContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+/// Evaluates `source`.  Returns the result, which is nil for programs
+/// that don't produce a value, or null if evaluation failed.
+#[no_mangle]
+pub unsafe extern "C" fn ares_eval(ctx: *mut AresContext, source: *const c_char) -> *mut AresValue {
+    let ctx = match ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => return ptr::null_mut(),
+    };
+    let source = match str_arg(source) {
+        Some(source) => source,
+        None => {
+            ctx.last_error = Some(to_c_string("source is not valid utf-8".into()));
+            return ptr::null_mut();
+        }
+    };
+
+    match ctx.context.eval(&mut (), source) {
+        Ok(value) => {
+            ctx.last_error = None;
+            boxed(value.unwrap_or(Value::Nil))
+        }
+        Err(e) => {
+            let message = ctx.context.format_error(e);
+            ctx.last_error = Some(to_c_string(message));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The error from the last call that failed on `ctx`, or null.  The
+/// string belongs to the context.
+#[no_mangle]
+pub unsafe extern "C" fn ares_last_error(ctx: *const AresContext) -> *const c_char {
+    match ctx.as_ref().and_then(|ctx| ctx.last_error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Defines a global function called `name` that calls `f` with
+/// `user_data`.  Returns 1 on success.
+#[no_mangle]
+pub unsafe extern "C" fn ares_register_fn(ctx: *mut AresContext, name: *const c_char,
+                                          f: AresNativeFn, user_data: *mut c_void) -> c_int {
+    let (ctx, name) = match (ctx.as_mut(), str_arg(name)) {
+        (Some(ctx), Some(name)) => (ctx, name.to_string()),
+        _ => return 0,
+    };
+
+    let function = user_function::<(), _>(Some(name.clone()), move |args, _, _| {
+        let handles: Vec<AresValue> = args.into_iter().map(AresValue).collect();
+        let pointers: Vec<*const AresValue> = handles.iter().map(|h| h as *const AresValue).collect();
+        let result = f(user_data, pointers.len(), pointers.as_ptr());
+        if result.is_null() {
+            Value::Nil
+        } else {
+            Box::from_raw(result).0
+        }
+    });
+    ctx.context.set_global(&name[..], function);
+    1
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_set_global(ctx: *mut AresContext, name: *const c_char,
+                                         value: *const AresValue) -> c_int {
+    match (ctx.as_mut(), str_arg(name), value.as_ref()) {
+        (Some(ctx), Some(name), Some(value)) => {
+            ctx.context.set_global(name, value.0.clone());
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// The value of the global `name`, or null if it isn't defined.
+#[no_mangle]
+pub unsafe extern "C" fn ares_get_global(ctx: *mut AresContext, name: *const c_char) -> *mut AresValue {
+    match (ctx.as_mut(), str_arg(name)) {
+        (Some(ctx), Some(name)) => match ctx.context.get_global(name) {
+            Some(value) => boxed(value.clone()),
+            None => ptr::null_mut(),
+        },
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_value_free(value: *mut AresValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_value_kind(value: *const AresValue) -> c_int {
+    match value.as_ref().map(|v| &v.0) {
+        None | Some(&Value::Nil) => ARES_NIL,
+        Some(&Value::Int(_)) => ARES_INT,
+        Some(&Value::Float(_)) => ARES_FLOAT,
+        Some(&Value::Bool(_)) => ARES_BOOL,
+        Some(&Value::String(_)) | Some(&Value::Substring(_)) => ARES_STRING,
+        Some(_) => ARES_OTHER,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn ares_new_nil() -> *mut AresValue {
+    boxed(Value::Nil)
+}
+
+#[no_mangle]
+pub extern "C" fn ares_new_int(i: i64) -> *mut AresValue {
+    boxed(Value::Int(i))
+}
+
+#[no_mangle]
+pub extern "C" fn ares_new_float(f: f64) -> *mut AresValue {
+    boxed(Value::Float(f))
+}
+
+#[no_mangle]
+pub extern "C" fn ares_new_bool(b: c_int) -> *mut AresValue {
+    boxed(Value::Bool(b != 0))
+}
+
+/// Copies `s` into a new string value.  Returns null if `s` is not utf-8.
+#[no_mangle]
+pub unsafe extern "C" fn ares_new_string(s: *const c_char) -> *mut AresValue {
+    match str_arg(s) {
+        Some(s) => boxed(s.into()),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Writes the int in `value` to `out`.  Returns 0 if `value` isn't an int.
+#[no_mangle]
+pub unsafe extern "C" fn ares_get_int(value: *const AresValue, out: *mut i64) -> c_int {
+    match (value.as_ref(), out.as_mut()) {
+        (Some(&AresValue(Value::Int(i))), Some(out)) => {
+            *out = i;
+            1
+        }
+        _ => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_get_float(value: *const AresValue, out: *mut f64) -> c_int {
+    match (value.as_ref(), out.as_mut()) {
+        (Some(&AresValue(Value::Float(f))), Some(out)) => {
+            *out = f;
+            1
+        }
+        _ => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_get_bool(value: *const AresValue, out: *mut c_int) -> c_int {
+    match (value.as_ref(), out.as_mut()) {
+        (Some(&AresValue(Value::Bool(b))), Some(out)) => {
+            *out = b as c_int;
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// A copy of the string in `value`, or null if it isn't a string.
+#[no_mangle]
+pub unsafe extern "C" fn ares_get_string(value: *const AresValue) -> *mut c_char {
+    match value.as_ref().and_then(|v| v.0.expect_str().ok()) {
+        Some(s) => to_c_string(s.to_string()).into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Formats any value the way the repl prints it.
+#[no_mangle]
+pub unsafe extern "C" fn ares_to_string(ctx: *const AresContext, value: *const AresValue) -> *mut c_char {
+    match (ctx.as_ref(), value.as_ref()) {
+        (Some(ctx), Some(value)) => to_c_string(ctx.context.format_value(&value.0)).into_raw(),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ares_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[test]
+fn eval_through_the_c_interface() {
+    use std::slice;
+
+    extern "C" fn add_one(user_data: *mut c_void, argc: usize, args: *const *const AresValue) -> *mut AresValue {
+        let calls = unsafe { &mut *(user_data as *mut u32) };
+        *calls += 1;
+        let args = unsafe { slice::from_raw_parts(args, argc) };
+        let mut i = 0;
+        if argc != 1 || unsafe { ares_get_int(args[0], &mut i) } == 0 {
+            return ptr::null_mut();
+        }
+        ares_new_int(i + 1)
+    }
+
+    unsafe {
+        let mut calls = 0u32;
+        let ctx = ares_new();
+        let name = CString::new("add_one").unwrap();
+        assert_eq!(ares_register_fn(ctx, name.as_ptr(), add_one, &mut calls as *mut u32 as *mut c_void), 1);
+
+        let source = CString::new("add_one(41)").unwrap();
+        let value = ares_eval(ctx, source.as_ptr());
+        let mut out = 0;
+        assert_eq!(ares_value_kind(value), ARES_INT);
+        assert_eq!(ares_get_int(value, &mut out), 1);
+        assert_eq!(out, 42);
+        assert_eq!(calls, 1);
+        ares_value_free(value);
+
+        let source = CString::new("\"a\" + ").unwrap();
+        assert!(ares_eval(ctx, source.as_ptr()).is_null());
+        assert!(!ares_last_error(ctx).is_null());
+
+        let source = CString::new("\"hi\"").unwrap();
+        let value = ares_eval(ctx, source.as_ptr());
+        let s = ares_get_string(value);
+        assert_eq!(CStr::from_ptr(s).to_str(), Ok("hi"));
+        ares_string_free(s);
+        ares_value_free(value);
+
+        ares_free(ctx);
+    }
+}
+
+#[test]
+fn header_declares_every_function() {
+    const HEADER: &'static str = include_str!("../include/ares.h");
+    const SOURCE: &'static str = include_str!("capi.rs");
+
+    let exported: Vec<&str> = SOURCE.lines()
+        .filter(|line| line.starts_with("pub") && line.contains("extern \"C\" fn "))
+        .map(|line| line.split("fn ").nth(1).unwrap().split('(').next().unwrap())
+        .collect();
+    let declared: Vec<&str> = HEADER.lines()
+        .filter(|line| line.contains(" ares_") || line.contains("*ares_"))
+        .filter_map(|line| line.split("ares_").nth(1))
+        .map(|rest| rest.split('(').next().unwrap())
+        .collect();
+    let exported: Vec<String> = exported.iter().map(|name| name.to_string()).collect();
+    let declared: Vec<String> = declared.iter().map(|name| format!("ares_{}", name)).collect();
+    assert!(!exported.is_empty());
+    assert_eq!(exported, declared);
+
+    for &(name, kind) in &[("ARES_NIL", ARES_NIL), ("ARES_INT", ARES_INT), ("ARES_FLOAT", ARES_FLOAT),
+                           ("ARES_BOOL", ARES_BOOL), ("ARES_STRING", ARES_STRING), ("ARES_OTHER", ARES_OTHER)] {
+        assert!(HEADER.contains(&format!("#define {} {}\n", name, kind)[..]), "{}", name);
+    }
+}
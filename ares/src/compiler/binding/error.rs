@@ -3,7 +3,29 @@ use compiler::parse::Span;
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum BindingError {
-    CouldNotBind(Symbol, Span),
+    /// A name that isn't defined.  The last field is a name in scope
+    /// with a similar spelling, which may be what was meant.
+    CouldNotBind(Symbol, Span, Option<Symbol>),
     AlreadyDefined(Symbol),
+    /// A call to a special form from another lisp that isn't bound here.
+    /// `syntax` is what ares uses instead.
+    SpecialFormCall { name: Symbol, syntax: &'static str, span: Span },
+    /// `recur` was used outside of a loop, or inside a lambda that is in
+    /// one.
+    RecurOutsideLoop(Span),
+    /// `recur` was given a different number of values than its loop has
+    /// variables.
+    RecurArity { expected: u32, got: u32, span: Span },
+    /// `recur` was used somewhere other than where the value of its loop
+    /// is produced, like an argument to a call.
+    RecurNotInTail(Span),
+    /// A definition somewhere other than a statement of a block or of the
+    /// top level.  `letrec` defines names inside of an expression.
+    DefineNotAllowedHere(Span),
+    /// A decimal literal, like `1.50d`, in a vm built without the
+    /// `decimal` feature.
+    DecimalsUnsupported(Span),
+    /// A decimal literal with more digits than a decimal can hold.
+    DecimalOutOfRange(Span),
     Multiple(Vec<BindingError>)
 }
@@ -0,0 +1,139 @@
+use std::cell::Cell;
+use util::collections::HashSet;
+
+use compiler::binding::{Bound, BoundRef, BoundVisitor, SymbolBindSource, walk_bound};
+
+/// Where the value of a variable lives at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    /// Never captured, so it lives directly on the stack.
+    Stack,
+    /// Captured by a closure, but never changes after it is captured,
+    /// so the closure gets its own copy of the value.
+    Captured,
+    /// Captured by a closure and changed afterwards, so the value is
+    /// shared through a heap allocated cell.
+    Cell,
+    Global,
+}
+
+impl SymbolBindSource {
+    /// Returns where this variable is stored.  Only meaningful once
+    /// `analyze_escapes` has been run over the tree that binds it.
+    pub fn storage(&self) -> Storage {
+        match self {
+            &SymbolBindSource::Arg{ref upvar, ref mutated, ..} |
+            &SymbolBindSource::LocalDefine{ref upvar, ref mutated, ..} => {
+                match (upvar.get(), mutated.get()) {
+                    (false, _) => Storage::Stack,
+                    (true, false) => Storage::Captured,
+                    (true, true) => Storage::Cell,
+                }
+            }
+            &SymbolBindSource::Upvar{ref mutated, ..} => {
+                if mutated.get() { Storage::Cell } else { Storage::Captured }
+            }
+            &SymbolBindSource::Global(_) => Storage::Global,
+        }
+    }
+}
+
+/// Decides which captured variables need a cell.
+///
+/// A closure can copy the value of a variable that it captures unless the
+/// variable changes after the closure is created.  That happens when it is
+/// assigned to, or when its definition is only stored after a closure has
+/// already captured it (as with recursive local functions).  The tree is
+/// walked in evaluation order so that the captures seen so far are exactly
+/// the ones that happened before each store.
+pub fn analyze_escapes<'bound, 'ast: 'bound>(bound: &[BoundRef<'bound, 'ast>]) {
+    let mut escapes = Escapes { captured: HashSet::new() };
+    for b in bound {
+        escapes.visit(b);
+    }
+}
+
+struct Escapes {
+    // Upvars share their `mutated` flag with the variable that they
+    // capture, so its address identifies the variable.
+    captured: HashSet<*const Cell<bool>>,
+}
+
+impl Escapes {
+    fn store(&self, source: &SymbolBindSource) {
+        let flag = source.mutated_flag();
+        if self.captured.contains(&(&*flag as *const Cell<bool>)) {
+            flag.set(true);
+        }
+    }
+}
+
+impl<'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for Escapes {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Lambda { ref upvar_list, .. } => {
+                for source in upvar_list {
+                    self.captured.insert(&*source.mutated_flag() as *const Cell<bool>);
+                }
+                walk_bound(self, bound);
+            }
+            // A loop could run the assignment after any capture, so it
+            // doesn't matter where the closure is.
+            &Bound::Assign(_, ref source, value, _) => {
+                source.set_as_mutated();
+                self.visit(value);
+            }
+            &Bound::Define(_, ref source, value, _) => {
+                self.visit(value);
+                self.store(source);
+            }
+            &Bound::Letrec { ref bindings, body, .. } => {
+                for &(_, _, value) in bindings {
+                    self.visit(value);
+                }
+                for &(_, ref source, _) in bindings {
+                    self.store(source);
+                }
+                self.visit(body);
+            }
+            &Bound::NamedLet { ref source, function, ref inits, .. } => {
+                self.visit(function);
+                self.store(source);
+                for init in inits {
+                    self.visit(init);
+                }
+            }
+            _ => walk_bound(self, bound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use compiler::binding::{Bound, Storage};
+    use compiler::binding::test::ok_bind_1;
+
+    #[test]
+    fn only_escaping_variables_leave_the_stack() {
+        let (bound, mut interner) = ok_bind_1("fn(a, b) {
+            var c = a;
+            var d = 0;
+            var g = fn() { b + d };
+            d = 1;
+            c
+        }");
+
+        let (bindings, bodies) = match bound {
+            &Bound::Lambda { ref bindings, body: &Bound::BlockExpression(ref bodies, _), .. } => (bindings, bodies),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(bindings.bindings[&interner.intern("a")].storage(), Storage::Stack);
+        assert_eq!(bindings.bindings[&interner.intern("b")].storage(), Storage::Captured);
+
+        let defined: Vec<Storage> = bodies.iter().filter_map(|b| match b {
+            &&Bound::Define(_, ref source, _, _) => Some(source.storage()),
+            _ => None,
+        }).collect();
+        assert_eq!(defined, vec![Storage::Stack, Storage::Cell, Storage::Stack]);
+    }
+}
@@ -0,0 +1,106 @@
+use util::collections::HashSet;
+
+use ares_syntax::Symbol;
+use compiler::binding::{Bound, BoundRef, BoundVisitor, walk_bound};
+
+impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
+    /// Finds every symbol that is referenced inside of this node, but
+    /// isn't defined inside of it.
+    ///
+    /// For a lambda, this is the set of variables that it captures along
+    /// with every global that it reads or writes.
+    pub fn free_variables(&self) -> HashSet<Symbol> {
+        let mut collector = FreeVariables {
+            scopes: vec![vec![]],
+            free: HashSet::new(),
+        };
+        collector.visit(self);
+        collector.free
+    }
+}
+
+struct FreeVariables {
+    scopes: Vec<Vec<Symbol>>,
+    free: HashSet<Symbol>,
+}
+
+impl FreeVariables {
+    fn reference(&mut self, symbol: Symbol) {
+        if !self.scopes.iter().any(|scope| scope.contains(&symbol)) {
+            self.free.insert(symbol);
+        }
+    }
+
+    fn define(&mut self, symbol: Symbol) {
+        self.scopes.last_mut().unwrap().push(symbol);
+    }
+
+    fn scoped<F: FnOnce(&mut FreeVariables)>(&mut self, symbols: &[Symbol], f: F) {
+        self.scopes.push(symbols.to_vec());
+        f(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for FreeVariables {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Symbol { symbol, .. } => self.reference(symbol),
+            &Bound::Lambda { ref arg_symbols, body, .. } => {
+                self.scoped(arg_symbols, |this| this.visit(body));
+            }
+            &Bound::BlockExpression(..) |
+            &Bound::BlockStatement(..) => {
+                self.scoped(&[], |this| walk_bound(this, bound));
+            }
+            &Bound::Assign(symbol, _, value, _) => {
+                self.reference(symbol);
+                self.visit(value);
+            }
+            &Bound::Define(symbol, _, value, _) => {
+                // The value can refer to the symbol being defined
+                self.define(symbol);
+                self.visit(value);
+            }
+            _ => walk_bound(self, bound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use util::collections::HashSet;
+    use compiler::binding::Bound;
+    use compiler::binding::test::ok_bind_1;
+
+    fn free_names(program: &str) -> Vec<String> {
+        let (bound, interner) = ok_bind_1(program);
+        let mut names: Vec<String> = bound.free_variables()
+                                             .into_iter()
+                                             .map(|s| interner.lookup_or_anon(s))
+                                             .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn closed_terms() {
+        assert!(free_names("var a = 1;").is_empty());
+        assert!(free_names("fn(x) { x }").is_empty());
+        assert!(free_names("{ var a = 1; var b = 2; fn(x) { x + a + b } }").is_empty());
+    }
+
+    #[test]
+    fn nested_lambdas() {
+        let (bound, mut interner) = ok_bind_1("fn(a) { var b = a; fn(c) { a + b + c } }");
+        assert!(bound.free_variables().is_empty());
+
+        // Dig out the inner lambda and check that it captures a and b
+        let inner = match bound {
+            &Bound::Lambda { body: &Bound::BlockExpression(ref bodies, _), .. } => bodies[1],
+            other => panic!("unexpected {:?}", other),
+        };
+        let expected: HashSet<_> = vec![interner.intern("a"), interner.intern("b")].into_iter().collect();
+        assert_eq!(inner.free_variables(), expected);
+    }
+}
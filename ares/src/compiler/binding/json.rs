@@ -0,0 +1,204 @@
+use ares_syntax::{AstRef, JsonObject, SymbolIntern, Symbol, json_array, json_string};
+use compiler::binding::{Bound, SymbolBindSource};
+
+fn symbol_json(symbol: Symbol, interner: &SymbolIntern) -> String {
+    json_string(&interner.lookup_or_anon(symbol))
+}
+
+impl SymbolBindSource {
+    pub fn to_json(&self, interner: &SymbolIntern) -> String {
+        match self {
+            &SymbolBindSource::Arg { position, ref upvar, ref used, .. } |
+            &SymbolBindSource::Upvar { position, ref upvar, ref used, .. } |
+            &SymbolBindSource::LocalDefine { position, ref upvar, ref used, .. } => {
+                let kind = match self {
+                    &SymbolBindSource::Arg { .. } => "Arg",
+                    &SymbolBindSource::Upvar { .. } => "Upvar",
+                    _ => "LocalDefine",
+                };
+                JsonObject::new(kind).raw("position", position.to_string())
+                                     .raw("upvar", upvar.get().to_string())
+                                     .raw("used", used.get().to_string())
+                                     .finish()
+            }
+            &SymbolBindSource::Global(symbol) => {
+                JsonObject::new("Global").raw("name", symbol_json(symbol, interner)).finish()
+            }
+        }
+    }
+}
+
+impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
+    /// Encodes this tree as JSON.  Every node carries the span of the
+    /// Ast that it was bound from, and every symbol carries its resolved
+    /// `SymbolBindSource`.
+    pub fn to_json(&self, interner: &SymbolIntern) -> String {
+        use compiler::binding::Bound::*;
+
+        let many = |bounds: &[&Bound]| json_array(bounds.iter().map(|b| b.to_json(interner)));
+        let node = |kind: &str, ast: AstRef| JsonObject::new(kind).raw("span", ast.span().to_json());
+        let binop = |kind: &str, l: &Bound, r: &Bound, ast: AstRef| {
+            node(kind, ast).raw("left", l.to_json(interner))
+                           .raw("right", r.to_json(interner))
+                           .finish()
+        };
+
+        match self {
+            &Literal(ast) => node("Literal", ast).raw("value", ast.to_json(interner)).finish(),
+            &Quote(ast) => node("Quote", ast).raw("value", ast.to_json(interner)).finish(),
+            &Bound::Symbol { symbol, ref source, ast } => {
+                node("Symbol", ast).raw("name", symbol_json(symbol, interner))
+                                   .raw("source", source.to_json(interner))
+                                   .finish()
+            }
+
+            &ListLit(ref e, ast) => node("ListLit", ast).raw("elements", many(e)).finish(),
+            &MapLit(ref pairs, ast) => {
+                let pairs = json_array(pairs.iter().map(|&(k, v)| {
+                    json_array(vec![k.to_json(interner), v.to_json(interner)])
+                }));
+                node("MapLit", ast).raw("pairs", pairs).finish()
+            }
+            &ListAccess(l, r, ast) => {
+                node("ListAccess", ast).raw("target", l.to_json(interner))
+                                       .raw("index", r.to_json(interner))
+                                       .finish()
+            }
+            &Add(l, r, ast) => binop("Add", l, r, ast),
+            &Sub(l, r, ast) => binop("Sub", l, r, ast),
+            &Mul(l, r, ast) => binop("Mul", l, r, ast),
+            &Div(l, r, ast) => binop("Div", l, r, ast),
+            &LessThan(l, r, ast) => binop("LessThan", l, r, ast),
+            &LessThanOrEqual(l, r, ast) => binop("LessThanOrEqual", l, r, ast),
+            &GreaterThan(l, r, ast) => binop("GreaterThan", l, r, ast),
+            &GreaterThanOrEqual(l, r, ast) => binop("GreaterThanOrEqual", l, r, ast),
+            &Equal(l, r, ast) => binop("Equal", l, r, ast),
+            &NotEqual(l, r, ast) => binop("NotEqual", l, r, ast),
+
+            &FnCall(receiver, ref args, ast, ref tail) => {
+                node("FnCall", ast).raw("function", receiver.to_json(interner))
+                                   .raw("args", many(args))
+                                   .raw("tail", tail.get().to_string())
+                                   .finish()
+            }
+            &IfExpression(c, t, f, ast) => {
+                node("IfExpression", ast).raw("condition", c.to_json(interner))
+                                         .raw("then", t.to_json(interner))
+                                         .raw("else", f.to_json(interner))
+                                         .finish()
+            }
+            &IfStatement(c, t, f, ast) => {
+                let f = f.map(|f| f.to_json(interner)).unwrap_or_else(|| "null".to_string());
+                node("IfStatement", ast).raw("condition", c.to_json(interner))
+                                        .raw("then", t.to_json(interner))
+                                        .raw("else", f)
+                                        .finish()
+            }
+            &Lambda { ref arg_symbols, num_args, has_rest_params, body, ast,
+                      ref bindings, ref upvar_list, ref is_shifter, ref name } => {
+                let args = json_array(arg_symbols.iter().map(|&s| symbol_json(s, interner)));
+                let upvars = json_array(upvar_list.iter().map(|u| u.to_json(interner)));
+                node("Lambda", ast).raw("args", args)
+                                   .raw("num_args", num_args.to_string())
+                                   .raw("has_rest_params", has_rest_params.to_string())
+                                   .raw("num_upvars", bindings.num_upvars.to_string())
+                                   .raw("num_declarations", bindings.num_declarations.to_string())
+                                   .raw("upvars", upvars)
+                                   .raw("is_shifter", is_shifter.get().to_string())
+                                   .raw("name", name.get().map(|s| symbol_json(s, interner))
+                                                           .unwrap_or_else(|| "null".to_string()))
+                                   .raw("body", body.to_json(interner))
+                                   .finish()
+            }
+            &Contract { ref requires, ref ensures, body, ref result, ast } => {
+                node("Contract", ast).raw("requires", many(requires))
+                                     .raw("ensures", many(ensures))
+                                     .raw("body", body.to_json(interner))
+                                     .raw("result", result.to_json(interner))
+                                     .finish()
+            }
+            &BlockExpression(ref e, ast) => node("BlockExpression", ast).raw("body", many(e)).finish(),
+            &BlockStatement(ref e, ast) => node("BlockStatement", ast).raw("body", many(e)).finish(),
+            &Assign(symbol, ref source, value, ast) => {
+                node("Assign", ast).raw("name", symbol_json(symbol, interner))
+                                   .raw("source", source.to_json(interner))
+                                   .raw("value", value.to_json(interner))
+                                   .finish()
+            }
+            &Define(symbol, ref source, value, ast) => {
+                node("Define", ast).raw("name", symbol_json(symbol, interner))
+                                   .raw("source", source.to_json(interner))
+                                   .raw("value", value.to_json(interner))
+                                   .finish()
+            }
+            &Loop { ref variables, body, ast } => {
+                let variables = json_array(variables.iter().map(|&(name, ref source, init)| {
+                    json_array(vec![symbol_json(name, interner),
+                                    source.to_json(interner),
+                                    init.to_json(interner)])
+                }));
+                node("Loop", ast).raw("variables", variables)
+                                 .raw("body", body.to_json(interner))
+                                 .finish()
+            }
+            &Recur(ref values, ref targets, ast) => {
+                let targets = json_array(targets.iter().map(|t| t.to_json(interner)));
+                node("Recur", ast).raw("values", many(values))
+                                  .raw("targets", targets)
+                                  .finish()
+            }
+            &Letrec { ref bindings, body, ast } => {
+                let bindings = json_array(bindings.iter().map(|&(name, ref source, value)| {
+                    json_array(vec![symbol_json(name, interner),
+                                    source.to_json(interner),
+                                    value.to_json(interner)])
+                }));
+                node("Letrec", ast).raw("bindings", bindings)
+                                   .raw("body", body.to_json(interner))
+                                   .finish()
+            }
+            &NamedLet { name, ref source, function, ref inits, ast } => {
+                node("NamedLet", ast).raw("name", symbol_json(name, interner))
+                                     .raw("source", source.to_json(interner))
+                                     .raw("function", function.to_json(interner))
+                                     .raw("inits", many(inits))
+                                     .finish()
+            }
+            &Shift(ref symbols, closure, ast) => {
+                node("Shift", ast).raw("symbols", many(symbols))
+                                  .raw("closure", closure.to_json(interner))
+                                  .finish()
+            }
+            &Reset(ref symbols, closure, ast) => {
+                node("Reset", ast).raw("symbols", many(symbols))
+                                  .raw("closure", closure.to_json(interner))
+                                  .finish()
+            }
+            &Import { ref defines, namespace, ref version, ast } => {
+                let defines = json_array(defines.iter().map(|d| d.to_json(interner)));
+                node("Import", ast).raw("defines", defines)
+                                   .raw("namespace", symbol_json(namespace, interner))
+                                   .string("version", version)
+                                   .finish()
+            }
+            &ImportThis { name, namespace, ref version } => {
+                JsonObject::new("ImportThis").raw("span", "null")
+                                             .raw("name", symbol_json(name, interner))
+                                             .raw("namespace", symbol_json(namespace, interner))
+                                             .string("version", version)
+                                             .finish()
+            }
+        }
+    }
+}
+
+#[test]
+fn encode_bound() {
+    use compiler::binding::test::ok_bind_1;
+
+    let (bound, interner) = ok_bind_1("fn(a) { a }");
+    let json = bound.to_json(&interner);
+    assert!(json.starts_with("{\"type\":\"Lambda\""));
+    assert!(json.contains("\"args\":[\"a\"]"));
+    assert!(json.contains("\"name\":\"a\",\"source\":{\"type\":\"Arg\",\"position\":0,\"upvar\":false,\"used\":true}"));
+}
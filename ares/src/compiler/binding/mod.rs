@@ -1,13 +1,28 @@
 use typed_arena::Arena;
-use std::collections::{HashMap, HashSet};
+use util::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::cell::Cell;
 
 mod error;
+mod escape;
+mod free_variables;
+mod json;
+mod special_forms;
+mod tail;
+mod types;
+mod visit;
 pub use self::error::BindingError;
+pub use self::escape::Storage;
+use self::escape::analyze_escapes;
+pub use self::types::{Type, check_types};
+pub use self::visit::{BoundVisitor, BoundFolder, walk_bound, fold_bound};
 
-use compiler::parse::{Ast, AstRef};
+use compiler::parse::{Ast, AstRef, Span};
+use compiler::Diagnostics;
 use vm::Modules;
+#[cfg(feature = "decimal")]
+use vm::Decimal;
+use util::edit_distance;
 use ares_syntax::{Symbol, SymbolIntern};
 
 // 2 concepts, Binders and Bound nodes
@@ -18,9 +33,11 @@ use ares_syntax::{Symbol, SymbolIntern};
 
 pub type BoundRef<'bound, 'ast> = &'bound Bound<'bound, 'ast>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Bound<'bound, 'ast: 'bound> {
     Literal(AstRef<'ast>),
+    /// An `Ast::Quote`.  Nothing inside of it is bound.
+    Quote(AstRef<'ast>),
     Symbol {
         symbol: Symbol,
         source: SymbolBindSource,
@@ -66,7 +83,9 @@ pub enum Bound<'bound, 'ast: 'bound> {
         BoundRef<'bound, 'ast>,
         BoundRef<'bound, 'ast>,
         AstRef<'ast>),
-    FnCall(BoundRef<'bound, 'ast>, Vec<BoundRef<'bound, 'ast>>, AstRef<'ast>),
+    /// The last field is set by `mark_tail_positions` when the call
+    /// is the last thing evaluated before its lambda returns.
+    FnCall(BoundRef<'bound, 'ast>, Vec<BoundRef<'bound, 'ast>>, AstRef<'ast>, Cell<bool>),
     IfExpression(BoundRef<'bound, 'ast>,
        BoundRef<'bound, 'ast>,
        BoundRef<'bound, 'ast>,
@@ -77,16 +96,60 @@ pub enum Bound<'bound, 'ast: 'bound> {
        AstRef<'ast>),
     Lambda {
         arg_symbols: Vec<Symbol>,
+        /// The number of arguments that the lambda must be called with.
+        /// When `has_rest_params` is set, the last argument collects any
+        /// extra arguments into a list.
+        num_args: u32,
+        has_rest_params: bool,
         body: BoundRef<'bound, 'ast>,
         ast: AstRef<'ast>,
         bindings: LambdaBindings,
         upvar_list: Vec<SymbolBindSource>,
         is_shifter: Cell<bool>,
+        /// Set from `fn name(..)`, or by the `var` that the lambda is
+        /// assigned to.
+        name: Cell<Option<Symbol>>,
+    },
+    /// The body of a lambda with `requires` and `ensures` clauses.
+    /// `result` is the local that holds the body's value while the
+    /// `ensures` clauses are checked.
+    Contract {
+        requires: Vec<BoundRef<'bound, 'ast>>,
+        ensures: Vec<BoundRef<'bound, 'ast>>,
+        body: BoundRef<'bound, 'ast>,
+        result: SymbolBindSource,
+        ast: AstRef<'ast>,
     },
     BlockExpression(Vec<BoundRef<'bound, 'ast>>, AstRef<'ast>),
     BlockStatement(Vec<BoundRef<'bound, 'ast>>, AstRef<'ast>),
     Assign(Symbol, SymbolBindSource, BoundRef<'bound, 'ast>, AstRef<'ast>),
     Define(Symbol, SymbolBindSource, BoundRef<'bound, 'ast>, AstRef<'ast>),
+    /// The initial values are evaluated outside of the loop, then stored
+    /// in its variables before the body runs.
+    Loop {
+        variables: Vec<(Symbol, SymbolBindSource, BoundRef<'bound, 'ast>)>,
+        body: BoundRef<'bound, 'ast>,
+        ast: AstRef<'ast>,
+    },
+    /// The new values for the variables of the innermost loop, along with
+    /// those variables.
+    Recur(Vec<BoundRef<'bound, 'ast>>, Vec<SymbolBindSource>, AstRef<'ast>),
+    /// Every variable is declared before any of the values are computed,
+    /// so that they can refer to each other.
+    Letrec {
+        bindings: Vec<(Symbol, SymbolBindSource, BoundRef<'bound, 'ast>)>,
+        body: BoundRef<'bound, 'ast>,
+        ast: AstRef<'ast>,
+    },
+    /// A function that can call itself through `source`, called once with
+    /// the initial values.
+    NamedLet {
+        name: Symbol,
+        source: SymbolBindSource,
+        function: BoundRef<'bound, 'ast>,
+        inits: Vec<BoundRef<'bound, 'ast>>,
+        ast: AstRef<'ast>,
+    },
     Shift(Vec<BoundRef<'bound, 'ast>>, BoundRef<'bound, 'ast>, AstRef<'ast>),
     Reset(Vec<BoundRef<'bound, 'ast>>, BoundRef<'bound, 'ast>, AstRef<'ast>),
     Import {
@@ -107,14 +170,21 @@ pub enum SymbolBindSource {
     Arg {
         position: u32,
         upvar: Rc<Cell<bool>>,
+        used: Rc<Cell<bool>>,
+        mutated: Rc<Cell<bool>>,
     },
+    /// Shares `mutated` with the variable that it captures.
     Upvar {
         position: u32,
         upvar: Rc<Cell<bool>>,
+        used: Rc<Cell<bool>>,
+        mutated: Rc<Cell<bool>>,
     },
     LocalDefine {
         position: u32,
         upvar: Rc<Cell<bool>>,
+        used: Rc<Cell<bool>>,
+        mutated: Rc<Cell<bool>>,
     },
     Global(Symbol),
 }
@@ -131,22 +201,78 @@ impl SymbolBindSource {
         }
     }
 
+    fn mark_used(&self) {
+        match self {
+            &SymbolBindSource::Arg{ref used, ..} |
+            &SymbolBindSource::Upvar{ref used, ..} |
+            &SymbolBindSource::LocalDefine{ref used, ..}  => {
+                used.set(true)
+            }
+            _ => {}
+        }
+    }
+
+    // Records that the variable can change after a closure captures it.
+    fn set_as_mutated(&self) {
+        match self {
+            &SymbolBindSource::Arg{ref mutated, ..} |
+            &SymbolBindSource::Upvar{ref mutated, ..} |
+            &SymbolBindSource::LocalDefine{ref mutated, ..}  => {
+                mutated.set(true)
+            }
+            _ => {}
+        }
+    }
+
+    fn mutated_flag(&self) -> Rc<Cell<bool>> {
+        match self {
+            &SymbolBindSource::Arg{ref mutated, ..} |
+            &SymbolBindSource::Upvar{ref mutated, ..} |
+            &SymbolBindSource::LocalDefine{ref mutated, ..}  => mutated.clone(),
+            &SymbolBindSource::Global(_) => Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Returns true if this variable is kept in a cell, which is only
+    /// needed when it is captured by a closure and can change after that.
+    /// Other captured variables are copied into the closure.
+    pub fn is_cell(&self) -> bool {
+        self.storage() == Storage::Cell
+    }
+
+    /// Returns true if this binding has been read from.
+    /// Globals are always considered to be used.
+    pub fn is_used(&self) -> bool {
+        match self {
+            &SymbolBindSource::Arg{ref used, ..} |
+            &SymbolBindSource::Upvar{ref used, ..} |
+            &SymbolBindSource::LocalDefine{ref used, ..}  => used.get(),
+            &SymbolBindSource::Global(_) => true,
+        }
+    }
+
     fn new_arg(position: u32) -> SymbolBindSource {
         SymbolBindSource::Arg {
             position: position,
             upvar: Rc::new(Cell::new(false)),
+            used: Rc::new(Cell::new(false)),
+            mutated: Rc::new(Cell::new(false)),
         }
     }
-    fn new_upvar(position: u32) -> SymbolBindSource {
+    fn new_upvar(position: u32, mutated: Rc<Cell<bool>>) -> SymbolBindSource {
         SymbolBindSource::Upvar {
             position: position,
             upvar: Rc::new(Cell::new(false)),
+            used: Rc::new(Cell::new(false)),
+            mutated: mutated,
         }
     }
     fn new_local_define(position: u32) -> SymbolBindSource {
         SymbolBindSource::LocalDefine {
             position: position,
             upvar: Rc::new(Cell::new(false)),
+            used: Rc::new(Cell::new(false)),
+            mutated: Rc::new(Cell::new(false)),
         }
     }
     fn new_global(symbol: Symbol) -> SymbolBindSource {
@@ -157,10 +283,10 @@ impl SymbolBindSource {
 struct BuckStopsHereBinder<'a> {
     globals: HashSet<Symbol>,
     modules: Option<&'a Modules>,
-    my_module: Symbol
+    my_module: Symbol,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct LambdaBindings {
     pub bindings: HashMap<Symbol, SymbolBindSource>,
     pub num_args: u32,
@@ -178,6 +304,15 @@ struct LambdaBinder<'a> {
 struct BlockBinder<'a> {
     parent: &'a mut Binder,
     symbol_map: HashMap<Symbol, Symbol>,
+    // Definitions later in the block, which closures can already refer to.
+    pending: HashMap<Symbol, (Symbol, SymbolBindSource)>,
+}
+
+// Binds the body of a loop, so that `recur` can find its variables.
+// Everything else is left to the block binder that defines them.
+struct LoopBinder<'a> {
+    parent: &'a mut Binder,
+    variables: Vec<SymbolBindSource>,
 }
 
 trait Binder {
@@ -185,6 +320,13 @@ trait Binder {
     fn already_binds(&self, symbol: Symbol) -> bool;
     fn lookup(&mut self, symbol: Symbol, from_closure: bool) -> Option<SymbolBindSource>;
     fn module(&self) -> Symbol;
+    /// The variables of the innermost loop that a `recur` here would
+    /// rebind.  Loops outside of the current lambda can't be reached.
+    fn loop_variables(&self) -> Option<Vec<SymbolBindSource>>;
+    /// False at the top level, where declarations become globals.
+    fn inside_lambda(&self) -> bool;
+    /// Adds every name that a lookup from here could find to `out`.
+    fn visible_names(&self, out: &mut Vec<Symbol>);
 }
 
 impl LambdaBindings {
@@ -252,10 +394,13 @@ impl<'a> Binder for LambdaBinder<'a> {
                 None => None,
                 Some(g@SymbolBindSource::Global(_)) => Some(g),
                 Some(other) => {
+                    // Being captured by a closure counts as a use.
+                    other.mark_used();
                     let upvar_position = self.upvar_list.len();
+                    let mutated = other.mutated_flag();
                     self.upvar_list.push(other);
                     self.bindings.num_upvars += 1;
-                    let source = SymbolBindSource::new_upvar(upvar_position as u32);
+                    let source = SymbolBindSource::new_upvar(upvar_position as u32, mutated);
                     self.bindings.bindings.insert(symbol, source.clone());
                     Some(source)
                 }
@@ -266,6 +411,19 @@ impl<'a> Binder for LambdaBinder<'a> {
     fn module(&self) -> Symbol {
         self.parent.module()
     }
+
+    fn loop_variables(&self) -> Option<Vec<SymbolBindSource>> {
+        None
+    }
+
+    fn inside_lambda(&self) -> bool {
+        true
+    }
+
+    fn visible_names(&self, out: &mut Vec<Symbol>) {
+        out.extend(self.args.iter().cloned());
+        self.parent.visible_names(out);
+    }
 }
 
 impl <'a> BlockBinder<'a> {
@@ -273,12 +431,35 @@ impl <'a> BlockBinder<'a> {
         BlockBinder {
             parent: parent,
             symbol_map: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Declares every definition among `statements` up front, so that the
+    /// closures in a block can call each other no matter which order they
+    /// are defined in.  Until its definition is reached, a name is only
+    /// visible from inside of closures; anything else still sees what
+    /// it refers to outside of the block.
+    fn predeclare(&mut self, statements: &[Ast], interner: &mut SymbolIntern) {
+        for statement in statements {
+            if let &Ast::Define(symbol, _, _) = statement {
+                if self.symbol_map.contains_key(&symbol) || self.pending.contains_key(&symbol) {
+                    continue;
+                }
+                let mask = interner.gensym();
+                let source = self.parent.add_declaration(mask, interner);
+                self.pending.insert(symbol, (mask, source));
+            }
         }
     }
 }
 
 impl <'a> Binder for BlockBinder<'a> {
     fn add_declaration(&mut self, symbol: Symbol, interner: &mut SymbolIntern) -> SymbolBindSource {
+        if let Some((mask, source)) = self.pending.remove(&symbol) {
+            self.symbol_map.insert(symbol, mask);
+            return source;
+        }
         let mask = interner.gensym();
         self.symbol_map.insert(symbol, mask);
         self.parent.add_declaration(mask, interner)
@@ -291,13 +472,59 @@ impl <'a> Binder for BlockBinder<'a> {
     fn lookup(&mut self, symbol: Symbol, from_closure: bool) -> Option<SymbolBindSource> {
         match self.symbol_map.get(&symbol) {
             Some(&translated) => self.parent.lookup(translated, from_closure),
-            None => self.parent.lookup(symbol, from_closure)
+            None => match self.pending.get(&symbol) {
+                Some(&(mask, _)) if from_closure => self.parent.lookup(mask, from_closure),
+                _ => self.parent.lookup(symbol, from_closure),
+            }
         }
     }
 
     fn module(&self) -> Symbol {
         self.parent.module()
     }
+
+    fn loop_variables(&self) -> Option<Vec<SymbolBindSource>> {
+        self.parent.loop_variables()
+    }
+
+    fn inside_lambda(&self) -> bool {
+        self.parent.inside_lambda()
+    }
+
+    fn visible_names(&self, out: &mut Vec<Symbol>) {
+        out.extend(self.symbol_map.keys().cloned());
+        self.parent.visible_names(out);
+    }
+}
+
+impl <'a> Binder for LoopBinder<'a> {
+    fn add_declaration(&mut self, symbol: Symbol, interner: &mut SymbolIntern) -> SymbolBindSource {
+        self.parent.add_declaration(symbol, interner)
+    }
+
+    fn already_binds(&self, symbol: Symbol) -> bool {
+        self.parent.already_binds(symbol)
+    }
+
+    fn lookup(&mut self, symbol: Symbol, from_closure: bool) -> Option<SymbolBindSource> {
+        self.parent.lookup(symbol, from_closure)
+    }
+
+    fn module(&self) -> Symbol {
+        self.parent.module()
+    }
+
+    fn loop_variables(&self) -> Option<Vec<SymbolBindSource>> {
+        Some(self.variables.clone())
+    }
+
+    fn inside_lambda(&self) -> bool {
+        self.parent.inside_lambda()
+    }
+
+    fn visible_names(&self, out: &mut Vec<Symbol>) {
+        self.parent.visible_names(out);
+    }
 }
 
 impl <'a> Binder for BuckStopsHereBinder<'a> {
@@ -330,13 +557,92 @@ impl <'a> Binder for BuckStopsHereBinder<'a> {
     fn module(&self) -> Symbol {
         self.my_module
     }
+
+    fn loop_variables(&self) -> Option<Vec<SymbolBindSource>> {
+        None
+    }
+
+    fn inside_lambda(&self) -> bool {
+        false
+    }
+
+    fn visible_names(&self, out: &mut Vec<Symbol>) {
+        out.extend(self.globals.iter().cloned());
+        if let Some(modules) = self.modules {
+            out.extend(modules.names_in(self.my_module));
+        }
+    }
+}
+
+/// The name in scope that `symbol` is most likely a misspelling of, if
+/// any of them are close enough.
+fn suggest_name(symbol: Symbol, binder: &Binder, interner: &SymbolIntern) -> Option<Symbol> {
+    let name = interner.lookup(symbol)?;
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut candidates = vec![];
+    binder.visible_names(&mut candidates);
+    candidates.into_iter()
+              .filter(|&candidate| candidate != symbol)
+              // Gensyms have no names, so the masks of block definitions
+              // are skipped here.
+              .filter_map(|candidate| interner.lookup(candidate).map(|n| (candidate, n)))
+              .map(|(candidate, n)| (edit_distance(name, n), n, candidate))
+              .filter(|&(distance, _, _)| distance <= max_distance)
+              .min()
+              .map(|(_, _, candidate)| candidate)
 }
 
 impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
+    /// The Ast that this node was bound from.  Only `ImportThis` is
+    /// generated by the binder without one.
+    pub fn ast(&self) -> Option<AstRef<'ast>> {
+        use self::Bound::*;
+        match self {
+            &Literal(ast) |
+            &Quote(ast) |
+            &Symbol { ast, .. } |
+            &ListLit(_, ast) |
+            &MapLit(_, ast) |
+            &ListAccess(_, _, ast) |
+            &Add(_, _, ast) |
+            &Sub(_, _, ast) |
+            &Mul(_, _, ast) |
+            &Div(_, _, ast) |
+            &LessThan(_, _, ast) |
+            &LessThanOrEqual(_, _, ast) |
+            &GreaterThan(_, _, ast) |
+            &GreaterThanOrEqual(_, _, ast) |
+            &Equal(_, _, ast) |
+            &NotEqual(_, _, ast) |
+            &FnCall(_, _, ast, _) |
+            &IfExpression(_, _, _, ast) |
+            &IfStatement(_, _, _, ast) |
+            &Lambda { ast, .. } |
+            &Contract { ast, .. } |
+            &BlockExpression(_, ast) |
+            &BlockStatement(_, ast) |
+            &Assign(_, _, _, ast) |
+            &Define(_, _, _, ast) |
+            &Loop { ast, .. } |
+            &Recur(_, _, ast) |
+            &NamedLet { ast, .. } |
+            &Letrec { ast, .. } |
+            &Shift(_, _, ast) |
+            &Reset(_, _, ast) |
+            &Import { ast, .. } => Some(ast),
+            &ImportThis { .. } => None,
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.ast().map(|ast| ast.span())
+    }
+
     pub fn bind_top(asts: &[AstRef<'ast>],
                     arena: &'bound Arena<Bound<'bound, 'ast>>,
                     modules: Option<&Modules>,
-                    interner: &mut SymbolIntern)
+                    interner: &mut SymbolIntern,
+                    diagnostics: &mut Diagnostics)
                     -> Result<Vec<BoundRef<'bound, 'ast>>, BindingError> {
         let mut buck = BuckStopsHereBinder {
             globals: HashSet::new(),
@@ -345,7 +651,16 @@ impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
             my_module: interner.precomputed.default_namespace, 
         };
 
-       asts.iter().map(|ast| Bound::bind(ast, arena, &mut buck, modules, interner)).collect()
+        let bound: Vec<_> = asts.iter()
+                                .map(|ast| Bound::bind_statement(ast, arena, &mut buck, modules, interner))
+                                .collect::<Result<_, _>>()?;
+        for b in &bound {
+            // Nothing at the top level is inside of a lambda.
+            b.mark_tail_positions(false);
+        }
+        analyze_escapes(&bound);
+        diagnostics.run_lints(&bound, interner);
+        Ok(bound)
     }
 
     fn bind_all<I>(asts: I, 
@@ -355,10 +670,20 @@ impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
             interner: &mut SymbolIntern)
             -> Result<Vec<BoundRef<'bound, 'ast>>, BindingError>
     where I: IntoIterator<Item=AstRef<'ast>> {
+        let results: Vec<_> = asts.into_iter()
+                                  .map(|ast| Bound::bind(ast, arena, binder, modules, interner))
+                                  .collect();
+        Bound::gather(results)
+    }
+
+    // Reports every error in `results`, or all of the bound nodes if
+    // there weren't any.
+    fn gather(results: Vec<Result<BoundRef<'bound, 'ast>, BindingError>>)
+              -> Result<Vec<BoundRef<'bound, 'ast>>, BindingError> {
         let mut out_ok = vec![];
         let mut out_err = vec![];
-        for ast in asts {
-            match Bound::bind(ast, arena, binder, modules, interner) {
+        for result in results {
+            match result {
                 Ok(o) => out_ok.push(o),
                 Err(e) => out_err.push(e),
             }
@@ -371,185 +696,344 @@ impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
         }
     }
 
+    fn bind_lambda(name: Option<Symbol>,
+                   args: &Vec<Symbol>,
+                   body: AstRef<'ast>,
+                   ast: AstRef<'ast>,
+                   arena: &'bound Arena<Bound<'bound, 'ast>>,
+                   binder: &mut Binder,
+                   modules: Option<&Modules>,
+                   interner: &mut SymbolIntern)
+                   -> Result<Bound<'bound, 'ast>, BindingError> {
+        let mut new_binder = LambdaBinder::new(binder, args);
+        let bound_body = Bound::bind(body, arena, &mut new_binder, modules, interner)?;
+        Ok(Bound::Lambda {
+            arg_symbols: args.clone(),
+            num_args: args.len() as u32,
+            has_rest_params: false,
+            body: bound_body,
+            ast: ast,
+            bindings: new_binder.bindings,
+            upvar_list: new_binder.upvar_list,
+            is_shifter: Cell::new(false),
+            name: Cell::new(name),
+        })
+    }
+
+    /// Binds an expression.  Definitions are only allowed as statements
+    /// of a block or of the top level, so finding one here is an error.
     fn bind(ast: AstRef<'ast>,
             arena: &'bound Arena<Bound<'bound, 'ast>>,
             binder: &mut Binder,
             modules: Option<&Modules>,
             interner: &mut SymbolIntern)
             -> Result<BoundRef<'bound, 'ast>, BindingError> {
-        Ok(arena.alloc(match ast {
+        match ast {
+            &Ast::Define(_, _, span) |
+            &Ast::Import(_, _, _, span) => Err(BindingError::DefineNotAllowedHere(span)),
+            _ => Bound::bind_statement(ast, arena, binder, modules, interner),
+        }
+    }
+
+    fn bind_statements<I>(asts: I,
+                          arena: &'bound Arena<Bound<'bound, 'ast>>,
+                          binder: &mut Binder,
+                          modules: Option<&Modules>,
+                          interner: &mut SymbolIntern)
+                          -> Result<Vec<BoundRef<'bound, 'ast>>, BindingError>
+    where I: IntoIterator<Item=AstRef<'ast>> {
+        let results: Vec<_> = asts.into_iter()
+                                  .map(|ast| Bound::bind_statement(ast, arena, binder, modules, interner))
+                                  .collect();
+        Bound::gather(results)
+    }
+
+    /// Binds a statement of a block or of the top level, which can also be
+    /// a definition.
+    ///
+    /// Nodes are bound with an explicit stack of the ones whose children
+    /// are still being bound, so that long chains like `a + b + c + ...`
+    /// can't overflow the stack.  Only the nodes that open a scope recurse
+    /// (see `bind_scope`), and those need brackets, which the parser
+    /// limits.
+    fn bind_statement(ast: AstRef<'ast>,
+                      arena: &'bound Arena<Bound<'bound, 'ast>>,
+                      binder: &mut Binder,
+                      modules: Option<&Modules>,
+                      interner: &mut SymbolIntern)
+                      -> Result<BoundRef<'bound, 'ast>, BindingError> {
+        let mut pending: Vec<Pending<'bound, 'ast>> = vec![];
+        let mut next = Next::Start(ast, true);
+        loop {
+            next = match next {
+                Next::Start(ast, statement) => match Bound::start(ast, statement, arena, binder, modules, interner) {
+                    Step::Done(result) => Next::Finished(result),
+                    Step::Forward(inner) => Next::Start(inner, false),
+                    Step::Pending(node) => {
+                        pending.push(node);
+                        Bound::advance(&mut pending, arena)
+                    }
+                },
+                Next::Finished(result) => {
+                    if pending.is_empty() {
+                        return result;
+                    }
+                    pending.last_mut().unwrap().accept(result);
+                    Bound::advance(&mut pending, arena)
+                }
+            };
+        }
+    }
+
+    // Moves on to the next child of the innermost pending node, or builds
+    // it if there are none left.
+    fn advance(pending: &mut Vec<Pending<'bound, 'ast>>, arena: &'bound Arena<Bound<'bound, 'ast>>)
+               -> Next<'bound, 'ast> {
+        let child = pending.last_mut().unwrap().next_child();
+        match child {
+            Ok(Some(child)) => Next::Start(child, false),
+            Ok(None) => Next::Finished(Ok(&*arena.alloc(pending.pop().unwrap().build()))),
+            Err(error) => {
+                pending.pop();
+                Next::Finished(Err(error))
+            }
+        }
+    }
+
+    // Begins binding `ast`.  Leaves are bound right away; anything else
+    // with children is left pending until they have been bound.
+    fn start(ast: AstRef<'ast>,
+             statement: bool,
+             arena: &'bound Arena<Bound<'bound, 'ast>>,
+             binder: &mut Binder,
+             modules: Option<&Modules>,
+             interner: &mut SymbolIntern)
+             -> Step<'bound, 'ast> {
+        let leaf = |bound| Step::Done(Ok(&*arena.alloc(bound)));
+        let node = |children| Step::Pending(Pending::new(ast, children, None));
+        match ast {
+            &Ast::Define(_, _, span) |
+            &Ast::Import(_, _, _, span) if !statement => Step::Done(Err(BindingError::DefineNotAllowedHere(span))),
             &Ast::BoolLit(_, _) |
             &Ast::StringLit(_, _) |
             &Ast::FloatLit(_, _) |
             &Ast::SymbolLit(_, _) |
             &Ast::NilLit(_) |
-            &Ast::IntLit(_, _) => Bound::Literal(ast),
-            &Ast::ListLit(ref elements, _) => {
-                Bound::ListLit(elements.iter()
-                                            .map(|element| {
-                                                Bound::bind(element, arena, binder, modules, interner)
-                                            })
-                                            .collect::<Result<Vec<_>, _>>()?,
-                               ast)
-            }
-            &Ast::ListAccess(ref target, ref index, _) => {
-                Bound::ListAccess(
-                    Bound::bind(target, arena, binder, modules, interner)?,
-                    Bound::bind(index, arena, binder, modules, interner)?,
-                    ast)
-            }
-            &Ast::MapLit(ref elements, _) => {
-                let mut bound = Vec::with_capacity(elements.len());
-                for &(ref k, ref v) in elements {
-                    let k = Bound::bind(k, arena, binder, modules, interner)?;
-                    let v = Bound::bind(v, arena, binder, modules, interner)?;
-                    bound.push((k, v));
-                }
-                Bound::MapLit(bound, ast)
-            }
+            &Ast::IntLit(_, _) => leaf(Bound::Literal(ast)),
+            #[cfg(feature = "decimal")]
+            &Ast::DecimalLit(ref digits, span) if Decimal::parse(digits).is_none() =>
+                Step::Done(Err(BindingError::DecimalOutOfRange(span))),
+            #[cfg(feature = "decimal")]
+            &Ast::DecimalLit(..) => leaf(Bound::Literal(ast)),
+            #[cfg(not(feature = "decimal"))]
+            &Ast::DecimalLit(_, span) => Step::Done(Err(BindingError::DecimalsUnsupported(span))),
+            &Ast::Quote(..) => leaf(Bound::Quote(ast)),
             &Ast::Identifier(symbol, span) => {
                 let source = match binder.lookup(symbol, false) {
                     Some(source) => source,
-                    None => return Err(BindingError::CouldNotBind(symbol, span)),
+                    None => return Step::Done(Err(BindingError::CouldNotBind(symbol, span,
+                                                                             suggest_name(symbol, binder, interner)))),
                 };
-
-                //println!("source for {} is {:?}", interner.lookup_or_anon(symbol), source);
-
-                Bound::Symbol {
+                source.mark_used();
+                leaf(Bound::Symbol {
                     symbol: symbol,
                     ast: ast,
                     source: source,
+                })
+            }
+            &Ast::ListLit(ref elements, _) => node(vec![Children::Each(elements.iter().collect())]),
+            &Ast::ListAccess(target, index, _) => node(vec![Children::Each(vec![target, index])]),
+            &Ast::MapLit(ref elements, _) => {
+                let mut children = Vec::with_capacity(elements.len() * 2);
+                for &(ref k, v) in elements {
+                    children.push(k);
+                    children.push(v);
+                }
+                node(vec![Children::Each(children)])
+            }
+            &Ast::Add(left, right, _) |
+            &Ast::Sub(left, right, _) |
+            &Ast::Mul(left, right, _) |
+            &Ast::Div(left, right, _) |
+            &Ast::LessThan(left, right, _) |
+            &Ast::LessThanOrEqual(left, right, _) |
+            &Ast::GreaterThan(left, right, _) |
+            &Ast::GreaterThanOrEqual(left, right, _) |
+            &Ast::Equal(left, right, _) |
+            &Ast::NotEqual(left, right, _) => node(vec![Children::Each(vec![left, right])]),
+            &Ast::FnCall(receiver, ref arguments, _) => {
+                if let &Ast::Identifier(symbol, span) = receiver {
+                    if binder.lookup(symbol, false).is_none() {
+                        if let Some(syntax) = special_forms::special_form(symbol, interner) {
+                            return Step::Done(Err(BindingError::SpecialFormCall { name: symbol, syntax: syntax, span: span }));
+                        }
+                    }
+                }
+                node(vec![Children::Each(vec![receiver]), Children::All(arguments.iter().collect())])
+            }
+            &Ast::IfExpression(a, b, c, _) => node(vec![Children::Each(vec![a, b, c])]),
+            &Ast::IfStatement(a, b, c, _) => node(vec![Children::Each(vec![a, b].into_iter().chain(c).collect())]),
+            &Ast::Assign(symbol, value, _) => {
+                match binder.lookup(symbol, false) {
+                    Some(source) => Step::Pending(Pending::new(ast, vec![Children::Each(vec![value])],
+                                                               Some(vec![source]))),
+                    None => Step::Done(Err(BindingError::CouldNotBind(symbol, ast.span(),
+                                                                      suggest_name(symbol, binder, interner)))),
+                }
+            }
+            &Ast::Define(symbol, value, _) => {
+                if binder.already_binds(symbol) {
+                    return Step::Done(Err(BindingError::AlreadyDefined(symbol)));
                 }
+                let source = binder.add_declaration(symbol, interner);
+                Step::Pending(Pending::new(ast, vec![Children::Each(vec![value])], Some(vec![source])))
+            }
+            &Ast::Recur(ref values, span) => {
+                let variables = match binder.loop_variables() {
+                    Some(variables) => variables,
+                    None => return Step::Done(Err(BindingError::RecurOutsideLoop(span))),
+                };
+                if variables.len() != values.len() {
+                    return Step::Done(Err(BindingError::RecurArity {
+                        expected: variables.len() as u32,
+                        got: values.len() as u32,
+                        span: span,
+                    }));
+                }
+                Step::Pending(Pending::new(ast, vec![Children::All(values.iter().collect())], Some(variables)))
             }
-            &Ast::Add(ref left, ref right, _) => {
-                Bound::Add(Bound::bind(left, arena, binder, modules, interner)?,
-                           Bound::bind(right, arena, binder, modules, interner)?,
-                           ast)
-            }
-            &Ast::Sub(ref left, ref right, _) => {
-                Bound::Sub(Bound::bind(left, arena, binder, modules, interner)?,
-                           Bound::bind(right, arena, binder, modules, interner)?,
-                           ast)
-            }
-            &Ast::Mul(ref left, ref right, _) => {
-                Bound::Mul(Bound::bind(left, arena, binder, modules, interner)?,
-                           Bound::bind(right, arena, binder, modules, interner)?,
-                           ast)
-            }
-            &Ast::Div(ref left, ref right, _) => {
-                Bound::Div(Bound::bind(left, arena, binder, modules, interner)?,
-                           Bound::bind(right, arena, binder, modules, interner)?,
-                           ast)
-            }
-            &Ast::LessThan(ref left, ref right, _) => {
-                Bound::LessThan(Bound::bind(left, arena, binder, modules, interner)?,
-                                Bound::bind(right, arena, binder, modules, interner)?,
-                                ast)
-            }
-            &Ast::LessThanOrEqual(ref left, ref right, _) => {
-                Bound::LessThanOrEqual(Bound::bind(left, arena, binder, modules, interner)?,
-                                Bound::bind(right, arena, binder, modules, interner)?,
-                                ast)
-            }
-            &Ast::GreaterThan(ref left, ref right, _) => {
-                Bound::GreaterThan(Bound::bind(left, arena, binder, modules, interner)?,
-                                Bound::bind(right, arena, binder, modules, interner)?,
-                                ast)
-            }
-            &Ast::GreaterThanOrEqual(ref left, ref right, _) => {
-                Bound::GreaterThanOrEqual(Bound::bind(left, arena, binder, modules, interner)?,
-                                Bound::bind(right, arena, binder, modules, interner)?,
-                                ast)
-            }
-            &Ast::Equal(ref left, ref right, _) => {
-                Bound::Equal(Bound::bind(left, arena, binder, modules, interner)?,
-                                Bound::bind(right, arena, binder, modules, interner)?,
-                                ast)
-            }
-            &Ast::NotEqual(ref left, ref right, _) => {
-                Bound::NotEqual(Bound::bind(left, arena, binder, modules, interner)?,
-                                Bound::bind(right, arena, binder, modules, interner)?,
-                                ast)
-            }
-            &Ast::FnCall(ref receiver, ref arguments, _) => {
-                let bound_receiver = Bound::bind(receiver, arena, binder, modules, interner)?;
-                let bound_arguments = Bound::bind_all(arguments, arena, binder, modules, interner)?;
-                Bound::FnCall(bound_receiver, bound_arguments, ast)
-            }
-            &Ast::IfExpression(ref a, ref b, ref c, _) => {
-                Bound::IfExpression(Bound::bind(a, arena, binder, modules, interner)? as &_,
-                          Bound::bind(b, arena, binder, modules, interner)? as &_,
-                          Bound::bind(c, arena, binder, modules, interner)? as &_,
-                          ast)
-            }
-            &Ast::IfStatement(ref a, ref b, ref c, _) => {
-                Bound::IfStatement(Bound::bind(a, arena, binder, modules, interner)? as &_,
-                          Bound::bind(b, arena, binder, modules, interner)?,
-                          rearrange(c.map(|c| Bound::bind(c, arena, binder, modules, interner)))?,
-                          ast)
-            }
-            &Ast::Closure(ref _name, ref args, ref body_block, _) => {
+            &Ast::Shift(ref symbols, closure, _) |
+            &Ast::Reset(ref symbols, closure, _) =>
+                node(vec![Children::All(symbols.iter().collect()), Children::Each(vec![closure])]),
+            // The block has its own binder, so nothing defined inside of
+            // it is visible after the `do`.
+            &Ast::Do(body, _) => Step::Forward(body),
+            _ => Step::Done(Bound::bind_scope(ast, arena, binder, modules, interner).map(|b| &*arena.alloc(b))),
+        }
+    }
+
+    // Binds the nodes that open a new scope, whose children are bound
+    // with a binder of their own.
+    fn bind_scope(ast: AstRef<'ast>,
+                  arena: &'bound Arena<Bound<'bound, 'ast>>,
+                  binder: &mut Binder,
+                  modules: Option<&Modules>,
+                  interner: &mut SymbolIntern)
+                  -> Result<Bound<'bound, 'ast>, BindingError> {
+        Ok(match ast {
+            &Ast::Closure(name, ref args, _, ref body_block, _) => {
                 // TODO: Bind name to "this function"
                 // args.len() must be 1 for now because that's how many argument lists there are.
                 assert!(args.len() == 1);
-                let mut new_binder = LambdaBinder::new(binder, &args[0]);
-                let bound_body = Bound::bind(body_block, arena, &mut new_binder, modules, interner)?;
-                Bound::Lambda {
-                    arg_symbols: args[0].clone(),
-                    body: bound_body,
+                Bound::bind_lambda(name, &args[0], body_block, ast, arena, binder, modules, interner)?
+            }
+            &Ast::Contract(ref requires, ref ensures, body, _) => {
+                let requires = Bound::bind_all(requires, arena, binder, modules, interner)?;
+                let body = Bound::bind(body, arena, binder, modules, interner)?;
+                // `result` is only in scope for the ensures clauses.
+                let mut new_binder = BlockBinder::new(binder);
+                let result = interner.intern("result");
+                let result = new_binder.add_declaration(result, interner);
+                let ensures = Bound::bind_all(ensures, arena, &mut new_binder, modules, interner)?;
+                Bound::Contract {
+                    requires: requires,
+                    ensures: ensures,
+                    body: body,
+                    result: result,
                     ast: ast,
-                    bindings: new_binder.bindings,
-                    upvar_list: new_binder.upvar_list,
-                    is_shifter: Cell::new(false),
                 }
             }
             &Ast::BlockExpression(ref bodies, _) => {
                 let mut new_binder = BlockBinder::new(binder);
-                let bound_bodies = Bound::bind_all(bodies, arena, &mut new_binder, modules, interner)?;
+                new_binder.predeclare(bodies, interner);
+                let bound_bodies = Bound::bind_statements(bodies, arena, &mut new_binder, modules, interner)?;
                 Bound::BlockExpression(bound_bodies, ast)
             }
             &Ast::BlockStatement(ref bodies, _) => {
                 let mut new_binder = BlockBinder::new(binder);
-                let bound_bodies = Bound::bind_all(bodies, arena, &mut new_binder, modules, interner)?;
+                new_binder.predeclare(bodies, interner);
+                let bound_bodies = Bound::bind_statements(bodies, arena, &mut new_binder, modules, interner)?;
                 Bound::BlockStatement(bound_bodies, ast)
             }
-            &Ast::Assign(symbol, value, _) => {
-                match binder.lookup(symbol, false) {
-                    Some(source@SymbolBindSource::LocalDefine{..}) |
-                    Some(source@SymbolBindSource::Arg{..}) |
-                    Some(source@SymbolBindSource::Upvar{..}) => {
-                        let value = Bound::bind(value, arena, binder, modules, interner)?;
-                        Bound::Assign(symbol, source, value, ast)
-                    }
-                    Some(source@SymbolBindSource::Global(_)) => {
-                        let value = Bound::bind(value, arena, binder, modules, interner)?;
-                        Bound::Assign(symbol, source, value, ast)
+            &Ast::Loop(..) if !binder.inside_lambda() => {
+                // At the top level the loop's variables would be globals,
+                // so the loop runs in a lambda of its own instead, which
+                // is called right away.
+                let lambda = Bound::bind_lambda(None, &Vec::new(), ast, ast, arena, binder, modules, interner)?;
+                Bound::FnCall(arena.alloc(lambda), Vec::new(), ast, Cell::new(false))
+            }
+            &Ast::Loop(ref variables, body, _) => {
+                let inits = Bound::bind_all(variables.iter().map(|&(_, ref init)| init),
+                                            arena, binder, modules, interner)?;
+                let mut block_binder = BlockBinder::new(binder);
+                let mut bound_variables = Vec::with_capacity(variables.len());
+                for (&(name, _), init) in variables.iter().zip(inits) {
+                    if block_binder.already_binds(name) {
+                        return Err(BindingError::AlreadyDefined(name));
                     }
-                    None => return Err(BindingError::CouldNotBind(symbol, ast.span()))
+                    let source = block_binder.add_declaration(name, interner);
+                    bound_variables.push((name, source, init));
                 }
-            }
-            &Ast::Define(symbol, value, _) => {
-                if binder.already_binds(symbol) {
-                    return Err(BindingError::AlreadyDefined(symbol));
+                let mut loop_binder = LoopBinder {
+                    variables: bound_variables.iter().map(|&(_, ref source, _)| source.clone()).collect(),
+                    parent: &mut block_binder,
+                };
+                let bound_body = Bound::bind(body, arena, &mut loop_binder, modules, interner)?;
+                if let Some(span) = bound_body.misplaced_recur(true) {
+                    return Err(BindingError::RecurNotInTail(span));
+                }
+                Bound::Loop {
+                    variables: bound_variables,
+                    body: bound_body,
+                    ast: ast,
                 }
-                let source = binder.add_declaration(symbol, interner);
-                let bound_value = Bound::bind(value, arena, binder, modules, interner)?;
-                Bound::Define(symbol, source, bound_value, ast)
             }
-            &Ast::Shift(ref symbols, ref closure, _) => {
-                let bound_symbols = Bound::bind_all(symbols, arena, binder, modules, interner)?;
-                let bound_closure = Bound::bind(closure, arena, binder, modules, interner)?;
-                if let &Bound::Lambda{ ref is_shifter, .. } = bound_closure {
-                    is_shifter.set(true);
-                } else {
-                    panic!("shift called without lambda body");
+            &Ast::Letrec(ref bindings, body, _) => {
+                let mut block_binder = BlockBinder::new(binder);
+                let mut sources = Vec::with_capacity(bindings.len());
+                for &(name, _) in bindings {
+                    if block_binder.already_binds(name) {
+                        return Err(BindingError::AlreadyDefined(name));
+                    }
+                    let source = block_binder.add_declaration(name, interner);
+                    sources.push(source);
+                }
+                let mut bound_bindings = Vec::with_capacity(bindings.len());
+                for (&(name, ref value), source) in bindings.iter().zip(sources) {
+                    let bound_value = Bound::bind(value, arena, &mut block_binder, modules, interner)?;
+                    if let &Bound::Lambda { name: ref lambda_name, .. } = bound_value {
+                        if lambda_name.get().is_none() {
+                            lambda_name.set(Some(name));
+                        }
+                    }
+                    bound_bindings.push((name, source, bound_value));
+                }
+                let bound_body = Bound::bind(body, arena, &mut block_binder, modules, interner)?;
+                Bound::Letrec {
+                    bindings: bound_bindings,
+                    body: bound_body,
+                    ast: ast,
                 }
-                Bound::Shift(bound_symbols, bound_closure, ast)
             }
-            &Ast::Reset(ref symbols, ref closure, _) => {
-                let bound_symbols = Bound::bind_all(symbols, arena, binder, modules, interner)?;
-                let bound_closure = Bound::bind(closure, arena, binder, modules, interner)?;
-                Bound::Reset(bound_symbols, bound_closure, ast)
+            &Ast::NamedLet(name, ref variables, body, _) => {
+                // The initial values are bound outside of the function's
+                // scope, but like a letrec, its name is declared before the
+                // function so that it can call itself.
+                let inits = Bound::bind_all(variables.iter().map(|&(_, ref init)| init),
+                                            arena, binder, modules, interner)?;
+                let args: Vec<Symbol> = variables.iter().map(|&(arg, _)| arg).collect();
+                let mut block_binder = BlockBinder::new(binder);
+                let source = block_binder.add_declaration(name, interner);
+                let function = Bound::bind_lambda(Some(name), &args, body, ast, arena,
+                                                  &mut block_binder, modules, interner)?;
+                source.mark_used();
+                Bound::NamedLet {
+                    name: name,
+                    source: source,
+                    function: arena.alloc(function),
+                    inits: inits,
+                    ast: ast,
+                }
             }
             &Ast::Import(ref names, ref namespace, ref version, _) => {
                 let mut sources = vec![];
@@ -583,15 +1067,296 @@ impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
                     ast: ast
                 }
             }
-        }))
+            other => unreachable!("{:?} doesn't open a scope", other),
+        })
     }
 }
 
-fn rearrange<T, E>(obj: Option<Result<T, E>>) -> Result<Option<T>, E> {
-    match obj {
-        None => Ok(None),
-        Some(Ok(t)) => Ok(Some(t)),
-        Some(Err(e)) => Err(e)
+// What `bind_statement` does next.
+enum Next<'bound, 'ast: 'bound> {
+    // Begin binding a node, which is a statement if the flag is set.
+    Start(AstRef<'ast>, bool),
+    // A node has been bound, or has failed to bind.
+    Finished(Result<BoundRef<'bound, 'ast>, BindingError>),
+}
+
+enum Step<'bound, 'ast: 'bound> {
+    Done(Result<BoundRef<'bound, 'ast>, BindingError>),
+    // Bind this node in place of the current one.
+    Forward(AstRef<'ast>),
+    Pending(Pending<'bound, 'ast>),
+}
+
+enum Children<'ast> {
+    // Bound in order, stopping at the first one that fails.
+    Each(Vec<AstRef<'ast>>),
+    // Every one is bound, and all of their errors are reported.
+    All(Vec<AstRef<'ast>>),
+}
+
+// A node whose children are being bound.
+struct Pending<'bound, 'ast: 'bound> {
+    ast: AstRef<'ast>,
+    children: Vec<Children<'ast>>,
+    // The position of the next child to bind in `children`
+    group: usize,
+    index: usize,
+    bound: Vec<BoundRef<'bound, 'ast>>,
+    errors: Vec<BindingError>,
+    // Bind sources that were found before the children were bound: the
+    // variable of an assignment or definition, or the loop variables of
+    // a `recur`.
+    sources: Option<Vec<SymbolBindSource>>,
+}
+
+impl <'bound, 'ast: 'bound> Pending<'bound, 'ast> {
+    fn new(ast: AstRef<'ast>, children: Vec<Children<'ast>>, sources: Option<Vec<SymbolBindSource>>)
+           -> Pending<'bound, 'ast> {
+        Pending {
+            ast: ast,
+            children: children,
+            group: 0,
+            index: 0,
+            bound: vec![],
+            errors: vec![],
+            sources: sources,
+        }
+    }
+
+    fn accept(&mut self, result: Result<BoundRef<'bound, 'ast>, BindingError>) {
+        match result {
+            Ok(bound) => self.bound.push(bound),
+            Err(error) => self.errors.push(error),
+        }
+    }
+
+    // The next child to bind, or an error if this node can't be bound
+    // because of one of its children.
+    fn next_child(&mut self) -> Result<Option<AstRef<'ast>>, BindingError> {
+        loop {
+            let (asts, each) = match self.children.get(self.group) {
+                Some(&Children::Each(ref asts)) => (asts, true),
+                Some(&Children::All(ref asts)) => (asts, false),
+                None => return Ok(None),
+            };
+            if each && !self.errors.is_empty() {
+                return Err(self.errors.pop().unwrap());
+            }
+            if let Some(&child) = asts.get(self.index) {
+                self.index += 1;
+                return Ok(Some(child));
+            }
+            match self.errors.len() {
+                0 => {}
+                1 => return Err(self.errors.pop().unwrap()),
+                _ => return Err(BindingError::Multiple(self.errors.split_off(0))),
+            }
+            self.group += 1;
+            self.index = 0;
+        }
+    }
+
+    // Builds the node out of its bound children.
+    fn build(self) -> Bound<'bound, 'ast> {
+        let Pending { ast, mut bound, sources, .. } = self;
+        let mut sources = sources.unwrap_or(vec![]);
+        match ast {
+            &Ast::ListLit(..) => Bound::ListLit(bound, ast),
+            &Ast::ListAccess(..) => Bound::ListAccess(bound[0], bound[1], ast),
+            &Ast::MapLit(..) => Bound::MapLit(bound.chunks(2).map(|pair| (pair[0], pair[1])).collect(), ast),
+            &Ast::Add(..) => Bound::Add(bound[0], bound[1], ast),
+            &Ast::Sub(..) => Bound::Sub(bound[0], bound[1], ast),
+            &Ast::Mul(..) => Bound::Mul(bound[0], bound[1], ast),
+            &Ast::Div(..) => Bound::Div(bound[0], bound[1], ast),
+            &Ast::LessThan(..) => Bound::LessThan(bound[0], bound[1], ast),
+            &Ast::LessThanOrEqual(..) => Bound::LessThanOrEqual(bound[0], bound[1], ast),
+            &Ast::GreaterThan(..) => Bound::GreaterThan(bound[0], bound[1], ast),
+            &Ast::GreaterThanOrEqual(..) => Bound::GreaterThanOrEqual(bound[0], bound[1], ast),
+            &Ast::Equal(..) => Bound::Equal(bound[0], bound[1], ast),
+            &Ast::NotEqual(..) => Bound::NotEqual(bound[0], bound[1], ast),
+            &Ast::FnCall(..) => {
+                let arguments = bound.split_off(1);
+                Bound::FnCall(bound[0], arguments, ast, Cell::new(false))
+            }
+            &Ast::IfExpression(..) => Bound::IfExpression(bound[0], bound[1], bound[2], ast),
+            &Ast::IfStatement(..) => Bound::IfStatement(bound[0], bound[1], bound.get(2).cloned(), ast),
+            &Ast::Assign(symbol, _, _) => Bound::Assign(symbol, sources.pop().unwrap(), bound[0], ast),
+            &Ast::Define(symbol, _, _) => {
+                if let &Bound::Lambda { ref name, .. } = bound[0] {
+                    if name.get().is_none() {
+                        name.set(Some(symbol));
+                    }
+                }
+                Bound::Define(symbol, sources.pop().unwrap(), bound[0], ast)
+            }
+            &Ast::Recur(..) => Bound::Recur(bound, sources, ast),
+            &Ast::Shift(..) => {
+                let closure = bound.pop().unwrap();
+                if let &Bound::Lambda{ ref is_shifter, .. } = closure {
+                    is_shifter.set(true);
+                } else {
+                    panic!("shift called without lambda body");
+                }
+                Bound::Shift(bound, closure, ast)
+            }
+            &Ast::Reset(..) => {
+                let closure = bound.pop().unwrap();
+                Bound::Reset(bound, closure, ast)
+            }
+            other => unreachable!("{:?} has no children to bind", other),
+        }
+    }
+}
+
+
+#[test]
+fn binding_is_repeatable_after_restoring_interner() {
+    use compiler::parse;
+
+    let program = "var f = fn(a) { reset { shift k { k(a) } } }; f(1);";
+    let mut interner = SymbolIntern::new();
+    let snapshot = interner.snapshot();
+
+    let bind = |interner: &mut SymbolIntern| {
+        let ast_arena = Arena::new();
+        let bound_arena = Arena::new();
+        let mut diagnostics = Diagnostics::new();
+        let asts = parse::parse(program, interner, &ast_arena).unwrap();
+        let asts: Vec<_> = asts.into_iter().map(|a| &*ast_arena.alloc(a)).collect();
+        let bounds = Bound::bind_top(&asts, &bound_arena, None, interner, &mut diagnostics).unwrap();
+        bounds.iter().map(|b| b.to_json(interner)).collect::<Vec<_>>()
+    };
+
+    let first = bind(&mut interner);
+    interner.restore(&snapshot);
+    let second = bind(&mut interner);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn unbound_special_forms_suggest_syntax() {
+    use compiler::parse;
+
+    let bind = |program: &str| {
+        let mut interner = SymbolIntern::new();
+        let ast_arena = Arena::new();
+        let bound_arena = Arena::new();
+        let mut diagnostics = Diagnostics::new();
+        let asts = parse::parse(program, &mut interner, &ast_arena).unwrap();
+        let asts: Vec<_> = asts.into_iter().map(|a| &*ast_arena.alloc(a)).collect();
+        Bound::bind_top(&asts, &bound_arena, None, &mut interner, &mut diagnostics).map(|_| ())
+    };
+
+    match bind("lambda(1);") {
+        Err(BindingError::SpecialFormCall { syntax, .. }) => assert_eq!(syntax, "fn(args) { body }"),
+        other => panic!("expected SpecialFormCall, got {:?}", other),
+    }
+    match bind("begin(1);") {
+        Err(BindingError::SpecialFormCall { syntax, .. }) => assert_eq!(syntax, "do { statements }"),
+        other => panic!("expected SpecialFormCall, got {:?}", other),
+    }
+    // A program that defines the name gets an ordinary call.
+    assert!(bind("var lambda = fn(x) { x }; lambda(1);").is_ok());
+    // Names that aren't special forms keep the usual error.
+    match bind("missing(1);") {
+        Err(BindingError::CouldNotBind(..)) => {}
+        other => panic!("expected CouldNotBind, got {:?}", other),
     }
 }
 
+#[test]
+fn unbound_names_suggest_close_ones() {
+    use compiler::parse;
+
+    let suggestion = |program: &str| {
+        let mut interner = SymbolIntern::new();
+        let ast_arena = Arena::new();
+        let bound_arena = Arena::new();
+        let mut diagnostics = Diagnostics::new();
+        let asts = parse::parse(program, &mut interner, &ast_arena).unwrap();
+        let asts: Vec<_> = asts.into_iter().map(|a| &*ast_arena.alloc(a)).collect();
+        match Bound::bind_top(&asts, &bound_arena, None, &mut interner, &mut diagnostics) {
+            Err(BindingError::CouldNotBind(_, _, suggestion)) => suggestion.map(|s| interner.lookup_or_anon(s)),
+            other => panic!("expected CouldNotBind, got {:?}", other.map(|_| ())),
+        }
+    };
+
+    assert_eq!(suggestion("var length = 1; lenght + 1;"), Some("length".to_string()));
+    // Arguments and block definitions are in scope too.
+    assert_eq!(suggestion("fn(count) { cout };"), Some("count".to_string()));
+    assert_eq!(suggestion("fn() { var total = 1; totl };"), Some("total".to_string()));
+    // Names that are too different aren't suggested.
+    assert_eq!(suggestion("var apple = 1; zebra;"), None);
+}
+
+#[test]
+fn definitions_must_be_statements() {
+    let mut interner = SymbolIntern::new();
+    let ast_arena = Arena::new();
+    let bound_arena = Arena::new();
+    let mut diagnostics = Diagnostics::new();
+    let x = interner.intern("x");
+    let one: &Ast = ast_arena.alloc(Ast::IntLit(1, Span::new(8, 9)));
+    let define: &Ast = ast_arena.alloc(Ast::Define(x, one, Span::new(0, 10)));
+    let sum: &Ast = ast_arena.alloc(Ast::Add(define, one, Span::new(0, 14)));
+    match Bound::bind_top(&[sum], &bound_arena, None, &mut interner, &mut diagnostics) {
+        Err(BindingError::DefineNotAllowedHere(span)) => assert_eq!(span, Span::new(0, 10)),
+        other => panic!("expected DefineNotAllowedHere, got {:?}", other),
+    }
+    assert!(Bound::bind_top(&[define], &bound_arena, None, &mut interner, &mut diagnostics).is_ok());
+}
+
+#[test]
+fn long_chains_bind_without_recursing() {
+    let mut interner = SymbolIntern::new();
+    let ast_arena = Arena::new();
+    let bound_arena = Arena::new();
+    let mut binder = BuckStopsHereBinder {
+        globals: HashSet::new(),
+        modules: None,
+        my_module: interner.precomputed.default_namespace,
+    };
+
+    let one: &Ast = ast_arena.alloc(Ast::IntLit(1, Span::new(0, 1)));
+    let mut chain = one;
+    for _ in 0 .. 100000 {
+        chain = ast_arena.alloc(Ast::Add(chain, one, Span::new(0, 1)));
+    }
+    match Bound::bind_statement(chain, &bound_arena, &mut binder, None, &mut interner) {
+        Ok(&Bound::Add(_, &Bound::Literal(_), _)) => {}
+        other => panic!("expected an addition, got {:?}", other.map(|_| ())),
+    }
+
+    let missing = interner.intern("missing");
+    let mut chain: &Ast = ast_arena.alloc(Ast::Identifier(missing, Span::new(0, 7)));
+    for _ in 0 .. 100000 {
+        chain = ast_arena.alloc(Ast::Mul(one, chain, Span::new(0, 1)));
+    }
+    match Bound::bind_statement(chain, &bound_arena, &mut binder, None, &mut interner) {
+        Err(BindingError::CouldNotBind(symbol, _, _)) => assert_eq!(symbol, missing),
+        other => panic!("expected CouldNotBind, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use compiler::parse::test::ok_parse_1;
+
+    pub fn ok_bind_1(program: &str) -> (BoundRef<'static, 'static>, SymbolIntern) {
+        ok_bind_1_full(program, &mut Diagnostics::new())
+    }
+
+    /// Parses and binds a program of one form, reporting to `diagnostics`.
+    /// The arena is leaked so that the bound tree outlives this call.
+    pub fn ok_bind_1_full(program: &str, diagnostics: &mut Diagnostics) -> (BoundRef<'static, 'static>, SymbolIntern) {
+        use std::mem::{transmute, forget};
+        let (ast, mut interner) = ok_parse_1(program);
+        let arena: Arena<Bound> = Arena::new();
+        let arena_ref: &'static _ = unsafe { transmute(&arena) };
+        let mut bound = Bound::bind_top(&[ast], arena_ref, None, &mut interner, diagnostics).unwrap();
+        assert!(bound.len() == 1);
+        forget(arena);
+        (bound.pop().unwrap(), interner)
+    }
+}
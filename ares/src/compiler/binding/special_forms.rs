@@ -0,0 +1,20 @@
+use ares_syntax::{Symbol, SymbolIntern};
+
+/// Names that are special forms in other lisps, along with the syntax that
+/// ares uses in their place.  These are only consulted once a call head
+/// fails to bind, so a program that defines its own `lambda` calls it like
+/// any other function.
+const SPECIAL_FORMS: &'static [(&'static str, &'static str)] = &[
+    ("quote", "'symbol"),
+    ("define", "var name = value"),
+    ("lambda", "fn(args) { body }"),
+    ("block", "{ statements }"),
+    ("begin", "do { statements }"),
+];
+
+/// The ares syntax to suggest when `symbol` names an unbound special form.
+pub fn special_form(symbol: Symbol, interner: &SymbolIntern) -> Option<&'static str> {
+    SPECIAL_FORMS.iter()
+                 .find(|&&(name, _)| interner.symbol_for_name(name) == Some(symbol))
+                 .map(|&(_, syntax)| syntax)
+}
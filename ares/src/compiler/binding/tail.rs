@@ -0,0 +1,256 @@
+use compiler::binding::Bound;
+use compiler::parse::Span;
+
+impl<'bound, 'ast: 'bound> Bound<'bound, 'ast> {
+    /// Returns true if this is a function call whose result is immediately
+    /// returned from the enclosing lambda.
+    pub fn is_tail_call(&self) -> bool {
+        match self {
+            &Bound::FnCall(_, _, _, ref tail) => tail.get(),
+            _ => false,
+        }
+    }
+
+    /// Walks the tree and marks every function call that is in tail position.
+    ///
+    /// An expression is in tail position if it is the body of a lambda, the
+    /// last expression of a block expression in tail position, or a branch
+    /// of an if-expression in tail position.
+    pub fn mark_tail_positions(&self, is_tail: bool) {
+        match self {
+            &Bound::Literal(_) |
+            &Bound::Quote(_) |
+            &Bound::Symbol { .. } |
+            &Bound::ImportThis { .. } => {}
+            &Bound::FnCall(receiver, ref args, _, ref tail) => {
+                tail.set(is_tail);
+                receiver.mark_tail_positions(false);
+                for arg in args {
+                    arg.mark_tail_positions(false);
+                }
+            }
+            &Bound::Lambda { body, .. } => body.mark_tail_positions(true),
+            // The ensures clauses run after the body returns, so its
+            // calls can only be tail calls if there aren't any.
+            &Bound::Contract { ref requires, ref ensures, body, .. } => {
+                for clause in requires.iter().chain(ensures) {
+                    clause.mark_tail_positions(false);
+                }
+                body.mark_tail_positions(is_tail && ensures.is_empty());
+            }
+            &Bound::BlockExpression(ref bodies, _) => {
+                if let Some((last, init)) = bodies.split_last() {
+                    for body in init {
+                        body.mark_tail_positions(false);
+                    }
+                    last.mark_tail_positions(is_tail);
+                }
+            }
+            // A statement block always evaluates to nil,
+            // so nothing inside of it can be in tail position.
+            &Bound::BlockStatement(ref bodies, _) => {
+                for body in bodies {
+                    body.mark_tail_positions(false);
+                }
+            }
+            &Bound::IfExpression(cond, tru, fals, _) => {
+                cond.mark_tail_positions(false);
+                tru.mark_tail_positions(is_tail);
+                fals.mark_tail_positions(is_tail);
+            }
+            &Bound::IfStatement(cond, tru, fals, _) => {
+                cond.mark_tail_positions(false);
+                tru.mark_tail_positions(false);
+                if let Some(fals) = fals {
+                    fals.mark_tail_positions(false);
+                }
+            }
+            &Bound::ListLit(ref elements, _) => {
+                for element in elements {
+                    element.mark_tail_positions(false);
+                }
+            }
+            &Bound::MapLit(ref pairs, _) => {
+                for &(k, v) in pairs {
+                    k.mark_tail_positions(false);
+                    v.mark_tail_positions(false);
+                }
+            }
+            &Bound::ListAccess(l, r, _) |
+            &Bound::Add(l, r, _) |
+            &Bound::Sub(l, r, _) |
+            &Bound::Mul(l, r, _) |
+            &Bound::Div(l, r, _) |
+            &Bound::LessThan(l, r, _) |
+            &Bound::LessThanOrEqual(l, r, _) |
+            &Bound::GreaterThan(l, r, _) |
+            &Bound::GreaterThanOrEqual(l, r, _) |
+            &Bound::Equal(l, r, _) |
+            &Bound::NotEqual(l, r, _) => {
+                l.mark_tail_positions(false);
+                r.mark_tail_positions(false);
+            }
+            &Bound::Assign(_, _, value, _) |
+            &Bound::Define(_, _, value, _) => value.mark_tail_positions(false),
+            &Bound::Loop { ref variables, body, .. } => {
+                for &(_, _, init) in variables {
+                    init.mark_tail_positions(false);
+                }
+                body.mark_tail_positions(is_tail);
+            }
+            &Bound::Recur(ref values, _, _) => {
+                for value in values {
+                    value.mark_tail_positions(false);
+                }
+            }
+            &Bound::Letrec { ref bindings, body, .. } => {
+                for &(_, _, value) in bindings {
+                    value.mark_tail_positions(false);
+                }
+                body.mark_tail_positions(is_tail);
+            }
+            &Bound::NamedLet { function, ref inits, .. } => {
+                function.mark_tail_positions(false);
+                for init in inits {
+                    init.mark_tail_positions(false);
+                }
+            }
+            &Bound::Shift(ref symbols, closure, _) |
+            &Bound::Reset(ref symbols, closure, _) => {
+                for symbol in symbols {
+                    symbol.mark_tail_positions(false);
+                }
+                closure.mark_tail_positions(false);
+            }
+            &Bound::Import { ref defines, .. } => {
+                for define in defines {
+                    define.mark_tail_positions(false);
+                }
+            }
+        }
+    }
+
+    /// Finds a `recur` that would leave values behind on the stack when it
+    /// jumps back to the start of its loop.  `at_base` is true where the
+    /// stack is as it was at the start of the loop: where the loop's value
+    /// is produced, and at each statement of a block there.
+    ///
+    /// The bodies of nested loops are checked when they are bound, and a
+    /// `recur` can't reach a loop from inside of a lambda.
+    pub fn misplaced_recur(&self, at_base: bool) -> Option<Span> {
+        match self {
+            &Bound::Recur(ref values, _, ast) => {
+                if at_base {
+                    first_misplaced(values.iter().cloned())
+                } else {
+                    Some(ast.span())
+                }
+            }
+            &Bound::Literal(_) |
+            &Bound::Quote(_) |
+            &Bound::Symbol { .. } |
+            &Bound::Lambda { .. } |
+            &Bound::Contract { .. } |
+            &Bound::ImportThis { .. } => None,
+            &Bound::Loop { ref variables, .. } => {
+                first_misplaced(variables.iter().map(|&(_, _, init)| init))
+            }
+            &Bound::NamedLet { ref inits, .. } => first_misplaced(inits.iter().cloned()),
+            &Bound::Letrec { ref bindings, body, .. } => {
+                first_misplaced(bindings.iter().map(|&(_, _, value)| value))
+                    .or_else(|| body.misplaced_recur(at_base))
+            }
+            &Bound::BlockExpression(ref bodies, _) |
+            &Bound::BlockStatement(ref bodies, _) => {
+                bodies.iter().filter_map(|b| b.misplaced_recur(at_base)).next()
+            }
+            &Bound::IfExpression(cond, tru, fals, _) => {
+                cond.misplaced_recur(false)
+                    .or_else(|| tru.misplaced_recur(at_base))
+                    .or_else(|| fals.misplaced_recur(at_base))
+            }
+            &Bound::IfStatement(cond, tru, fals, _) => {
+                cond.misplaced_recur(false)
+                    .or_else(|| tru.misplaced_recur(at_base))
+                    .or_else(|| fals.and_then(|f| f.misplaced_recur(at_base)))
+            }
+            &Bound::FnCall(receiver, ref args, _, _) => {
+                receiver.misplaced_recur(false).or_else(|| first_misplaced(args.iter().cloned()))
+            }
+            &Bound::ListLit(ref elements, _) => first_misplaced(elements.iter().cloned()),
+            &Bound::MapLit(ref pairs, _) => {
+                first_misplaced(pairs.iter().flat_map(|&(k, v)| vec![k, v]))
+            }
+            &Bound::ListAccess(l, r, _) |
+            &Bound::Add(l, r, _) |
+            &Bound::Sub(l, r, _) |
+            &Bound::Mul(l, r, _) |
+            &Bound::Div(l, r, _) |
+            &Bound::LessThan(l, r, _) |
+            &Bound::LessThanOrEqual(l, r, _) |
+            &Bound::GreaterThan(l, r, _) |
+            &Bound::GreaterThanOrEqual(l, r, _) |
+            &Bound::Equal(l, r, _) |
+            &Bound::NotEqual(l, r, _) => l.misplaced_recur(false).or_else(|| r.misplaced_recur(false)),
+            &Bound::Assign(_, _, value, _) |
+            &Bound::Define(_, _, value, _) => value.misplaced_recur(false),
+            &Bound::Shift(ref symbols, closure, _) |
+            &Bound::Reset(ref symbols, closure, _) => {
+                first_misplaced(symbols.iter().cloned()).or_else(|| closure.misplaced_recur(false))
+            }
+            &Bound::Import { ref defines, .. } => first_misplaced(defines.iter()),
+        }
+    }
+}
+
+fn first_misplaced<'b, 'bound: 'b, 'ast: 'bound, I>(bounds: I) -> Option<Span>
+    where I: IntoIterator<Item = &'b Bound<'bound, 'ast>>
+{
+    bounds.into_iter().filter_map(|b| b.misplaced_recur(false)).next()
+}
+
+#[cfg(test)]
+mod test {
+    use compiler::binding::Bound;
+    use compiler::binding::test::ok_bind_1;
+
+    // Binds a lambda and returns whether each of the top level
+    // expressions in its body is a tail call.
+    fn tail_calls(program: &str) -> Vec<bool> {
+        let (bound, _) = ok_bind_1(program);
+        match bound {
+            &Bound::Lambda { body: &Bound::BlockExpression(ref bodies, _), .. } |
+            &Bound::Lambda { body: &Bound::BlockStatement(ref bodies, _), .. } => {
+                bodies.iter().map(|b| b.is_tail_call()).collect()
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn last_call_in_block() {
+        assert_eq!(tail_calls("fn(f) { f(1); f(2) }"), vec![false, true]);
+        assert_eq!(tail_calls("fn(f) { f(1); f(2); }"), vec![false, false]);
+    }
+
+    #[test]
+    fn arguments_are_not_tail_calls() {
+        let (bound, _) = ok_bind_1("fn(f) { f(f(1)) }");
+        match bound {
+            &Bound::Lambda { body: &Bound::BlockExpression(ref bodies, _), .. } => {
+                assert!(bodies[0].is_tail_call());
+                match bodies[0] {
+                    &Bound::FnCall(_, ref args, _, _) => assert!(!args[0].is_tail_call()),
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn top_level_calls_are_not_tail_calls() {
+        let (bound, _) = ok_bind_1("(fn(x) { x })(1)");
+        assert!(!bound.is_tail_call());
+    }
+}
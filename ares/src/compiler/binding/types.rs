@@ -0,0 +1,456 @@
+use std::cell::Cell;
+use util::collections::{HashMap, HashSet};
+
+use ares_syntax::{Signature, Symbol, SymbolIntern, TypeName};
+use compiler::binding::{Bound, BoundRef, BoundVisitor, SymbolBindSource, walk_bound};
+use compiler::parse::{Ast, Span};
+use compiler::{Diagnostics, Warning};
+
+/// The type of an expression as far as the checker can tell.  `Any` is
+/// used for everything that isn't annotated or obvious, and never causes
+/// a warning, so unannotated code stays dynamic.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    Int,
+    Float,
+    /// Any kind of number: an int, float, rational or decimal.
+    Number,
+    String,
+    Symbol,
+    List,
+    Map,
+    Fn,
+}
+
+impl Type {
+    /// The type that an annotation names, like `int` in `fn(x: int)`.
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "any" => Type::Any,
+            "nil" => Type::Nil,
+            "bool" => Type::Bool,
+            "int" => Type::Int,
+            "float" => Type::Float,
+            "number" => Type::Number,
+            "string" => Type::String,
+            "symbol" => Type::Symbol,
+            "list" => Type::List,
+            "map" => Type::Map,
+            "fn" => Type::Fn,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Type::Any => "any",
+            Type::Nil => "nil",
+            Type::Bool => "bool",
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Symbol => "symbol",
+            Type::List => "list",
+            Type::Map => "map",
+            Type::Fn => "fn",
+        }
+    }
+
+    /// Whether a value of type `found` could be used where `self` is
+    /// expected.
+    pub fn accepts(&self, found: Type) -> bool {
+        match (*self, found) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Number, Type::Int) | (Type::Number, Type::Float) => true,
+            // A number might turn out to be the int or float that was asked for.
+            (Type::Int, Type::Number) | (Type::Float, Type::Number) => true,
+            (expected, found) => expected == found,
+        }
+    }
+}
+
+/// The types of a lambda, from its annotations or what could be inferred.
+#[derive(Clone, Debug)]
+struct FnType {
+    params: Vec<Type>,
+    ret: Type,
+    /// The last parameter collects any extra arguments.
+    rest: bool,
+}
+
+/// Identifies a variable independently of the name it is known by.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Binding {
+    /// Arguments and local definitions are told apart by the address of
+    /// the flag that the binder shares between every use of them.
+    Local(usize),
+    Global(Symbol),
+}
+
+fn binding(source: &SymbolBindSource) -> Option<Binding> {
+    match source {
+        &SymbolBindSource::Arg { ref used, .. } |
+        &SymbolBindSource::LocalDefine { ref used, .. } => {
+            Some(Binding::Local(&**used as *const Cell<bool> as usize))
+        }
+        &SymbolBindSource::Global(symbol) => Some(Binding::Global(symbol)),
+        // An upvar's source is made fresh for every lambda that captures
+        // it, so it can't be matched up with its definition.
+        &SymbolBindSource::Upvar { .. } => None,
+    }
+}
+
+fn signature<'ast>(ast: &'ast Ast<'ast>) -> Option<&'ast Signature> {
+    match ast {
+        &Ast::Closure(_, _, Some(ref sig), _, _) => Some(sig),
+        _ => None,
+    }
+}
+
+/// Collects every variable that is assigned to after its definition.
+struct Assigned(HashSet<Binding>);
+
+impl <'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for Assigned {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        if let &Bound::Assign(_, ref source, _, _) = bound {
+            if let Some(b) = binding(source) {
+                self.0.insert(b);
+            }
+        }
+        walk_bound(self, bound);
+    }
+}
+
+/// Checks the annotations in `bounds` and reports every mismatch that
+/// can be found without running the program as a `Warning::TypeMismatch`.
+///
+/// Only values whose types are certain are checked: literals, annotated
+/// arguments, variables that are never reassigned, and the results of
+/// lambdas that are defined with `var`.  Types flow forward from
+/// definitions to uses, so this also finds arithmetic on values that can't
+/// be numbers (`Warning::InvalidOperands`) and calls to known lambdas with
+/// the wrong number of arguments (`Warning::WrongArity`).  Annotations that
+/// don't name a type are reported as `Warning::UnknownType`.
+pub fn check_types<'bound, 'ast: 'bound>(bounds: &[BoundRef<'bound, 'ast>],
+                                         interner: &SymbolIntern,
+                                         diagnostics: &mut Diagnostics) {
+    let mut assigned = Assigned(HashSet::new());
+    for &bound in bounds {
+        assigned.visit(bound);
+    }
+
+    let mut checker = TypeChecker {
+        interner: interner,
+        diagnostics: diagnostics,
+        assigned: assigned.0,
+        variables: HashMap::new(),
+        functions: HashMap::new(),
+    };
+    for &bound in bounds {
+        checker.check(bound);
+    }
+}
+
+struct TypeChecker<'a> {
+    interner: &'a SymbolIntern,
+    diagnostics: &'a mut Diagnostics,
+    assigned: HashSet<Binding>,
+    /// The types of annotated arguments, and of variables that are never
+    /// reassigned.
+    variables: HashMap<Binding, Type>,
+    /// The types of lambdas that are defined with `var` and never
+    /// reassigned.
+    functions: HashMap<Binding, FnType>,
+}
+
+impl <'a> TypeChecker<'a> {
+    fn lookup(&self, name: Option<TypeName>) -> Type {
+        name.and_then(|t| Type::from_name(&self.interner.lookup_or_anon(t.name))).unwrap_or(Type::Any)
+    }
+
+    /// Like `lookup`, but warns about names that aren't types.
+    fn resolve(&mut self, name: Option<TypeName>) -> Type {
+        if let Some(t) = name {
+            if Type::from_name(&self.interner.lookup_or_anon(t.name)).is_none() {
+                self.diagnostics.warn(Warning::UnknownType { name: t.name, span: t.span });
+            }
+        }
+        self.lookup(name)
+    }
+
+    /// The type of `lambda` from its annotations, or `None` if it isn't a
+    /// lambda.  Parameters without annotations are `Any`.
+    fn fn_type(&self, lambda: &Bound) -> Option<FnType> {
+        match lambda {
+            &Bound::Lambda { num_args, has_rest_params, ast, .. } => {
+                let sig = signature(ast);
+                let annotated = sig.and_then(|sig| sig.params.get(0));
+                let param = |i: usize| annotated.and_then(|params| params.get(i).cloned()).and_then(|t| t);
+                Some(FnType {
+                    params: (0 .. num_args as usize).map(|i| self.lookup(param(i))).collect(),
+                    ret: self.lookup(sig.and_then(|sig| sig.ret)),
+                    rest: has_rest_params,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: Type, found: Type, span: Option<Span>) {
+        if !expected.accepts(found) {
+            self.diagnostics.warn(Warning::TypeMismatch {
+                expected: expected,
+                found: found,
+                span: span.unwrap_or_else(Span::dummy),
+            });
+        }
+    }
+
+    /// Warns if `left op right` can't work because one side is certainly
+    /// not a number.
+    fn expect_numbers(&mut self, op: &'static str, left: Type, right: Type, span: Option<Span>) {
+        if !Type::Number.accepts(left) || !Type::Number.accepts(right) {
+            self.diagnostics.warn(Warning::InvalidOperands {
+                op: op,
+                left: left,
+                right: right,
+                span: span.unwrap_or_else(Span::dummy),
+            });
+        }
+    }
+
+    fn check_arith<'bound, 'ast: 'bound>(&mut self, op: &'static str,
+                                         l: BoundRef<'bound, 'ast>, r: BoundRef<'bound, 'ast>,
+                                         bound: BoundRef<'bound, 'ast>) -> Type {
+        let (left, right) = (self.check(l), self.check(r));
+        self.expect_numbers(op, left, right, bound.span());
+        match (left, right) {
+            // Ints that don't divide evenly make a rational.
+            (Type::Int, Type::Int) if op == "/" => Type::Number,
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Float, t) | (t, Type::Float) if t != Type::Any && Type::Number.accepts(t) => Type::Float,
+            _ => Type::Any,
+        }
+    }
+
+    fn check_ordering<'bound, 'ast: 'bound>(&mut self, op: &'static str,
+                                            l: BoundRef<'bound, 'ast>, r: BoundRef<'bound, 'ast>,
+                                            bound: BoundRef<'bound, 'ast>) -> Type {
+        let (left, right) = (self.check(l), self.check(r));
+        self.expect_numbers(op, left, right, bound.span());
+        Type::Bool
+    }
+
+    /// Checks a lambda and returns the type of its body.
+    fn check_lambda<'bound, 'ast: 'bound>(&mut self, lambda: BoundRef<'bound, 'ast>) -> Type {
+        let (arg_symbols, body, ast, bindings) = match lambda {
+            &Bound::Lambda { ref arg_symbols, body, ast, ref bindings, .. } => (arg_symbols, body, ast, bindings),
+            other => return self.check(other),
+        };
+        let sig = signature(ast);
+        if let Some(sig) = sig {
+            let params = sig.params.get(0).map_or(&[][..], |p| &p[..]);
+            for (arg, &t) in arg_symbols.iter().zip(params) {
+                let t = self.resolve(t);
+                if t != Type::Any {
+                    if let Some(b) = bindings.bindings.get(arg).and_then(binding) {
+                        self.variables.insert(b, t);
+                    }
+                }
+            }
+        }
+        let found = self.check(body);
+        if let Some(ret) = sig.and_then(|sig| sig.ret) {
+            let expected = self.resolve(Some(ret));
+            self.expect(expected, found, body.span());
+        }
+        found
+    }
+
+    fn check_all<'bound, 'ast: 'bound>(&mut self, bounds: &[BoundRef<'bound, 'ast>]) -> Type {
+        let mut last = Type::Nil;
+        for &bound in bounds {
+            last = self.check(bound);
+        }
+        last
+    }
+
+    /// Checks `bound` and everything inside of it, and returns its type.
+    fn check<'bound, 'ast: 'bound>(&mut self, bound: BoundRef<'bound, 'ast>) -> Type {
+        match bound {
+            &Bound::Literal(ast) => match ast {
+                &Ast::BoolLit(..) => Type::Bool,
+                &Ast::StringLit(..) => Type::String,
+                &Ast::IntLit(..) => Type::Int,
+                &Ast::FloatLit(..) => Type::Float,
+                &Ast::SymbolLit(..) => Type::Symbol,
+                &Ast::NilLit(..) => Type::Nil,
+                _ => Type::Any,
+            },
+            &Bound::Quote(_) => Type::List,
+            &Bound::Symbol { ref source, .. } => {
+                binding(source).and_then(|b| self.variables.get(&b).cloned()).unwrap_or(Type::Any)
+            }
+            &Bound::ListLit(ref elements, _) => {
+                self.check_all(elements);
+                Type::List
+            }
+            &Bound::MapLit(ref pairs, _) => {
+                for &(k, v) in pairs {
+                    self.check(k);
+                    self.check(v);
+                }
+                Type::Map
+            }
+            &Bound::ListAccess(l, r, _) => {
+                self.check(l);
+                self.check(r);
+                Type::Any
+            }
+            &Bound::Add(l, r, _) => self.check_arith("+", l, r, bound),
+            &Bound::Sub(l, r, _) => self.check_arith("-", l, r, bound),
+            &Bound::Mul(l, r, _) => self.check_arith("*", l, r, bound),
+            &Bound::Div(l, r, _) => self.check_arith("/", l, r, bound),
+            &Bound::LessThan(l, r, _) => self.check_ordering("<", l, r, bound),
+            &Bound::LessThanOrEqual(l, r, _) => self.check_ordering("<=", l, r, bound),
+            &Bound::GreaterThan(l, r, _) => self.check_ordering(">", l, r, bound),
+            &Bound::GreaterThanOrEqual(l, r, _) => self.check_ordering(">=", l, r, bound),
+            &Bound::Equal(l, r, _) |
+            &Bound::NotEqual(l, r, _) => {
+                self.check(l);
+                self.check(r);
+                Type::Bool
+            }
+            &Bound::FnCall(receiver, ref args, _, _) => {
+                self.check(receiver);
+                let arg_types: Vec<Type> = args.iter().map(|&arg| self.check(arg)).collect();
+                let fn_type = match receiver {
+                    &Bound::Symbol { ref source, .. } => binding(source).and_then(|b| self.functions.get(&b).cloned()),
+                    other => self.fn_type(other),
+                };
+                match fn_type {
+                    Some(fn_type) => {
+                        let (expected, got) = (fn_type.params.len(), args.len());
+                        if got != expected && !(fn_type.rest && got + 1 >= expected) {
+                            self.diagnostics.warn(Warning::WrongArity {
+                                expected: expected as u32,
+                                got: got as u32,
+                                span: bound.span().unwrap_or_else(Span::dummy),
+                            });
+                        }
+                        for ((&expected, &found), &arg) in fn_type.params.iter().zip(&arg_types).zip(args) {
+                            self.expect(expected, found, arg.span());
+                        }
+                        fn_type.ret
+                    }
+                    None => Type::Any,
+                }
+            }
+            &Bound::IfExpression(c, t, f, _) => {
+                self.check(c);
+                let (t, f) = (self.check(t), self.check(f));
+                if t == f { t } else { Type::Any }
+            }
+            &Bound::IfStatement(c, t, f, _) => {
+                self.check(c);
+                self.check(t);
+                if let Some(f) = f {
+                    self.check(f);
+                }
+                Type::Nil
+            }
+            &Bound::Lambda { .. } => {
+                self.check_lambda(bound);
+                Type::Fn
+            }
+            &Bound::BlockExpression(ref bodies, _) => self.check_all(bodies),
+            &Bound::BlockStatement(ref bodies, _) => {
+                self.check_all(bodies);
+                Type::Nil
+            }
+            &Bound::Assign(_, ref source, value, _) => {
+                let found = self.check(value);
+                let expected = binding(source).and_then(|b| self.variables.get(&b).cloned());
+                if let Some(expected) = expected {
+                    self.expect(expected, found, value.span());
+                }
+                found
+            }
+            &Bound::Define(_, ref source, value, _) => {
+                let b = binding(source).filter(|b| !self.assigned.contains(b));
+                match (b, self.fn_type(value)) {
+                    (Some(b), Some(fn_type)) => {
+                        // Recorded before the body is checked so that
+                        // recursive calls are checked too.
+                        let annotated_ret = fn_type.ret != Type::Any;
+                        self.functions.insert(b, fn_type);
+                        let ret = self.check_lambda(value);
+                        if !annotated_ret {
+                            if let Some(fn_type) = self.functions.get_mut(&b) {
+                                fn_type.ret = ret;
+                            }
+                        }
+                    }
+                    (Some(b), None) => {
+                        let found = self.check(value);
+                        if found != Type::Any {
+                            self.variables.insert(b, found);
+                        }
+                    }
+                    (None, _) => {
+                        self.check(value);
+                    }
+                }
+                Type::Nil
+            }
+            &Bound::Contract { ref requires, ref ensures, body, ref result, .. } => {
+                for &clause in requires {
+                    let found = self.check(clause);
+                    self.expect(Type::Bool, found, clause.span());
+                }
+                let found = self.check(body);
+                if let Some(b) = binding(result).filter(|_| found != Type::Any) {
+                    self.variables.insert(b, found);
+                }
+                for &clause in ensures {
+                    let found = self.check(clause);
+                    self.expect(Type::Bool, found, clause.span());
+                }
+                found
+            }
+            &Bound::Loop { ref variables, body, .. } => {
+                for &(_, _, init) in variables {
+                    self.check(init);
+                }
+                self.check(body)
+            }
+            &Bound::Recur(ref values, _, _) => {
+                self.check_all(values);
+                Type::Any
+            }
+            &Bound::Letrec { ref bindings, body, .. } => {
+                for &(_, _, value) in bindings {
+                    self.check(value);
+                }
+                self.check(body)
+            }
+            &Bound::NamedLet { function, ref inits, .. } => {
+                self.check(function);
+                self.check_all(inits);
+                Type::Any
+            }
+            &Bound::Shift(ref symbols, closure, _) |
+            &Bound::Reset(ref symbols, closure, _) => {
+                self.check_all(symbols);
+                self.check(closure);
+                Type::Any
+            }
+            &Bound::Import { .. } |
+            &Bound::ImportThis { .. } => Type::Nil,
+        }
+    }
+}
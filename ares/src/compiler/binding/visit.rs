@@ -0,0 +1,273 @@
+use typed_arena::Arena;
+use compiler::binding::{Bound, BoundRef};
+
+/// Walks over a Bound tree without modifying it.
+///
+/// Override `visit` to inspect the nodes that you are interested in, and
+/// call `walk_bound` from it to continue on to the children.
+pub trait BoundVisitor<'bound, 'ast: 'bound> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        walk_bound(self, bound)
+    }
+}
+
+/// Visits every direct child of `bound`.
+pub fn walk_bound<'bound, 'ast, V>(visitor: &mut V, bound: BoundRef<'bound, 'ast>)
+where 'ast: 'bound, V: BoundVisitor<'bound, 'ast> + ?Sized {
+    match bound {
+        &Bound::Literal(_) |
+        &Bound::Quote(_) |
+        &Bound::Symbol { .. } |
+        &Bound::ImportThis { .. } => {}
+
+        &Bound::ListLit(ref elements, _) |
+        &Bound::BlockExpression(ref elements, _) |
+        &Bound::BlockStatement(ref elements, _) => {
+            for element in elements {
+                visitor.visit(element);
+            }
+        }
+        &Bound::MapLit(ref pairs, _) => {
+            for &(k, v) in pairs {
+                visitor.visit(k);
+                visitor.visit(v);
+            }
+        }
+
+        &Bound::ListAccess(l, r, _) |
+        &Bound::Add(l, r, _) |
+        &Bound::Sub(l, r, _) |
+        &Bound::Mul(l, r, _) |
+        &Bound::Div(l, r, _) |
+        &Bound::LessThan(l, r, _) |
+        &Bound::LessThanOrEqual(l, r, _) |
+        &Bound::GreaterThan(l, r, _) |
+        &Bound::GreaterThanOrEqual(l, r, _) |
+        &Bound::Equal(l, r, _) |
+        &Bound::NotEqual(l, r, _) => {
+            visitor.visit(l);
+            visitor.visit(r);
+        }
+
+        &Bound::FnCall(receiver, ref args, _, _) => {
+            visitor.visit(receiver);
+            for arg in args {
+                visitor.visit(arg);
+            }
+        }
+        &Bound::IfExpression(c, t, f, _) => {
+            visitor.visit(c);
+            visitor.visit(t);
+            visitor.visit(f);
+        }
+        &Bound::IfStatement(c, t, f, _) => {
+            visitor.visit(c);
+            visitor.visit(t);
+            if let Some(f) = f {
+                visitor.visit(f);
+            }
+        }
+        &Bound::Lambda { body, .. } => visitor.visit(body),
+        &Bound::Contract { ref requires, ref ensures, body, .. } => {
+            for clause in requires {
+                visitor.visit(clause);
+            }
+            visitor.visit(body);
+            for clause in ensures {
+                visitor.visit(clause);
+            }
+        }
+        &Bound::Assign(_, _, value, _) |
+        &Bound::Define(_, _, value, _) => visitor.visit(value),
+        &Bound::Loop { ref variables, body, .. } => {
+            for &(_, _, init) in variables {
+                visitor.visit(init);
+            }
+            visitor.visit(body);
+        }
+        &Bound::Recur(ref values, _, _) => {
+            for value in values {
+                visitor.visit(value);
+            }
+        }
+        &Bound::Letrec { ref bindings, body, .. } => {
+            for &(_, _, value) in bindings {
+                visitor.visit(value);
+            }
+            visitor.visit(body);
+        }
+        &Bound::NamedLet { function, ref inits, .. } => {
+            visitor.visit(function);
+            for init in inits {
+                visitor.visit(init);
+            }
+        }
+        &Bound::Shift(ref symbols, closure, _) |
+        &Bound::Reset(ref symbols, closure, _) => {
+            for symbol in symbols {
+                visitor.visit(symbol);
+            }
+            visitor.visit(closure);
+        }
+        &Bound::Import { ref defines, .. } => {
+            for define in defines {
+                visitor.visit(define);
+            }
+        }
+    }
+}
+
+/// Rebuilds a Bound tree, allocating the new nodes in `arena()`.
+///
+/// Override `fold` to replace the nodes that you are interested in, and
+/// call `fold_bound` from it to rebuild the rest.
+pub trait BoundFolder<'bound, 'ast: 'bound> {
+    fn arena(&self) -> &'bound Arena<Bound<'bound, 'ast>>;
+
+    fn fold(&mut self, bound: BoundRef<'bound, 'ast>) -> BoundRef<'bound, 'ast> {
+        fold_bound(self, bound)
+    }
+}
+
+/// Rebuilds `bound` by folding every direct child of it.
+pub fn fold_bound<'bound, 'ast, F>(folder: &mut F, bound: BoundRef<'bound, 'ast>) -> BoundRef<'bound, 'ast>
+where 'ast: 'bound, F: BoundFolder<'bound, 'ast> + ?Sized {
+    use compiler::binding::Bound::*;
+
+    macro_rules! fold_vec {
+        ($e: expr) => {
+            $e.iter().map(|&b| folder.fold(b)).collect()
+        }
+    }
+
+    let rebuilt = match bound {
+        &Literal(_) | &Quote(_) | &Symbol { .. } | &ImportThis { .. } => return bound,
+
+        &ListLit(ref e, ast) => ListLit(fold_vec!(e), ast),
+        &BlockExpression(ref e, ast) => BlockExpression(fold_vec!(e), ast),
+        &BlockStatement(ref e, ast) => BlockStatement(fold_vec!(e), ast),
+        &MapLit(ref pairs, ast) => {
+            let pairs = pairs.iter().map(|&(k, v)| (folder.fold(k), folder.fold(v))).collect();
+            MapLit(pairs, ast)
+        }
+
+        &ListAccess(l, r, ast) => ListAccess(folder.fold(l), folder.fold(r), ast),
+        &Add(l, r, ast) => Add(folder.fold(l), folder.fold(r), ast),
+        &Sub(l, r, ast) => Sub(folder.fold(l), folder.fold(r), ast),
+        &Mul(l, r, ast) => Mul(folder.fold(l), folder.fold(r), ast),
+        &Div(l, r, ast) => Div(folder.fold(l), folder.fold(r), ast),
+        &LessThan(l, r, ast) => LessThan(folder.fold(l), folder.fold(r), ast),
+        &LessThanOrEqual(l, r, ast) => LessThanOrEqual(folder.fold(l), folder.fold(r), ast),
+        &GreaterThan(l, r, ast) => GreaterThan(folder.fold(l), folder.fold(r), ast),
+        &GreaterThanOrEqual(l, r, ast) => GreaterThanOrEqual(folder.fold(l), folder.fold(r), ast),
+        &Equal(l, r, ast) => Equal(folder.fold(l), folder.fold(r), ast),
+        &NotEqual(l, r, ast) => NotEqual(folder.fold(l), folder.fold(r), ast),
+
+        &FnCall(receiver, ref args, ast, ref tail) => {
+            let receiver = folder.fold(receiver);
+            FnCall(receiver, fold_vec!(args), ast, tail.clone())
+        }
+        &IfExpression(c, t, f, ast) => IfExpression(folder.fold(c), folder.fold(t), folder.fold(f), ast),
+        &IfStatement(c, t, f, ast) => {
+            let c = folder.fold(c);
+            let t = folder.fold(t);
+            IfStatement(c, t, f.map(|f| folder.fold(f)), ast)
+        }
+        &Lambda { ref arg_symbols, num_args, has_rest_params, body, ast,
+                  ref bindings, ref upvar_list, ref is_shifter, ref name } => {
+            Lambda {
+                arg_symbols: arg_symbols.clone(),
+                num_args: num_args,
+                has_rest_params: has_rest_params,
+                body: folder.fold(body),
+                ast: ast,
+                bindings: bindings.clone(),
+                upvar_list: upvar_list.clone(),
+                is_shifter: is_shifter.clone(),
+                name: name.clone(),
+            }
+        }
+        &Contract { ref requires, ref ensures, body, ref result, ast } => {
+            let requires = fold_vec!(requires);
+            let body = folder.fold(body);
+            Contract {
+                requires: requires,
+                ensures: fold_vec!(ensures),
+                body: body,
+                result: result.clone(),
+                ast: ast,
+            }
+        }
+        &Assign(symbol, ref source, value, ast) => Assign(symbol, source.clone(), folder.fold(value), ast),
+        &Define(symbol, ref source, value, ast) => Define(symbol, source.clone(), folder.fold(value), ast),
+        &Loop { ref variables, body, ast } => {
+            let variables = variables.iter()
+                                     .map(|&(name, ref source, init)| (name, source.clone(), folder.fold(init)))
+                                     .collect();
+            Loop {
+                variables: variables,
+                body: folder.fold(body),
+                ast: ast,
+            }
+        }
+        &Recur(ref values, ref targets, ast) => Recur(fold_vec!(values), targets.clone(), ast),
+        &Letrec { ref bindings, body, ast } => {
+            let bindings = bindings.iter()
+                                   .map(|&(name, ref source, value)| (name, source.clone(), folder.fold(value)))
+                                   .collect();
+            Letrec {
+                bindings: bindings,
+                body: folder.fold(body),
+                ast: ast,
+            }
+        }
+        &NamedLet { name, ref source, function, ref inits, ast } => {
+            let function = folder.fold(function);
+            NamedLet {
+                name: name,
+                source: source.clone(),
+                function: function,
+                inits: fold_vec!(inits),
+                ast: ast,
+            }
+        }
+        &Shift(ref symbols, closure, ast) => {
+            let symbols = fold_vec!(symbols);
+            Shift(symbols, folder.fold(closure), ast)
+        }
+        &Reset(ref symbols, closure, ast) => {
+            let symbols = fold_vec!(symbols);
+            Reset(symbols, folder.fold(closure), ast)
+        }
+        &Import { ref defines, namespace, ref version, ast } => {
+            Import {
+                defines: defines.iter().map(|d| folder.fold(d).clone()).collect(),
+                namespace: namespace,
+                version: version.clone(),
+                ast: ast,
+            }
+        }
+    };
+
+    folder.arena().alloc(rebuilt)
+}
+
+#[test]
+fn count_symbols() {
+    use compiler::binding::test::ok_bind_1;
+
+    struct Counter(u32);
+    impl <'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for Counter {
+        fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+            if let &Bound::Symbol { .. } = bound {
+                self.0 += 1;
+            }
+            walk_bound(self, bound);
+        }
+    }
+
+    let (bound, _) = ok_bind_1("fn(a, b) { var c = a; [a, b, c] }");
+    let mut counter = Counter(0);
+    counter.visit(bound);
+    assert_eq!(counter.0, 4);
+}
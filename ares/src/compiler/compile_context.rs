@@ -1,3 +1,6 @@
+use util::collections::HashMap;
+
+use ares_syntax::Span;
 use vm::{Value, Instr, ClosureClass};
 
 pub enum CompileOptLevel {
@@ -12,19 +15,98 @@ pub struct ShiftMeta {
     pub return_pos: u32,
 }
 
+/// Statistics about the contents of the constant pool.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct ConstantPoolStats {
+    /// The total number of constants in the pool.
+    pub constants: usize,
+    /// The number of distinct string constants in the pool.
+    pub strings: usize,
+    /// The number of times a string constant was requested
+    /// that was already in the pool.
+    pub string_hits: usize,
+    /// The number of times any constant was requested that was
+    /// already in the pool.
+    pub hits: usize,
+}
+
+// The contents of a constant, used to find an equal constant that is
+// already in the pool.  Floats are compared by their bits so that
+// `NaN` can be pooled and `0.0` stays apart from `-0.0`.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+enum ConstantKey {
+    Int(i64),
+    Float(u64),
+    String(String),
+}
+
+/// Settings that control how source code is compiled.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CompileOptions {
+    /// Logs an event at the end of every compilation phase under the
+    /// `ares::compile` target.
+    pub log_phases: bool,
+    /// Compiles the `requires` and `ensures` clauses of lambdas into
+    /// checks.  When this is off the clauses are bound but never run.
+    pub check_contracts: bool,
+    /// How deeply brackets may be nested.  Deeper programs are rejected
+    /// with `ParseError::TooDeep`, since the compiler recurses once for
+    /// every level and could overflow the stack.
+    pub max_nesting_depth: u32,
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        CompileOptions {
+            log_phases: false,
+            check_contracts: true,
+            max_nesting_depth: ::compiler::parse::DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CompileContext {
     constants: Vec<Value>,
+    pooled: HashMap<ConstantKey, u32>,
+    strings: usize,
+    string_hits: usize,
+    hits: usize,
     closure_classes: Vec<ClosureClass>,
     shift_metadata: Vec<ShiftMeta>,
+    // Code position of an `Execute` instruction -> the span of the call
+    call_sites: HashMap<u32, Span>,
+    // Code position of a `Require` or `Ensure` -> the span of its clause
+    contract_checks: HashMap<u32, Span>,
+    // Code position where each node starts -> the span of the node
+    spans: Vec<(u32, Span)>,
+    // Closure classes before this one have had their positions computed
+    located_classes: usize,
+    // The closure classes of the lambdas currently being emitted, innermost last
+    lambda_stack: Vec<u32>,
+    // The code positions that `recur` jumps back to for the loops
+    // currently being emitted, innermost last
+    loop_stack: Vec<u32>,
+    options: CompileOptions,
 }
 
 impl CompileContext {
     pub fn new() -> CompileContext {
         CompileContext {
             constants: vec![],
+            pooled: HashMap::new(),
+            strings: 0,
+            string_hits: 0,
+            hits: 0,
             closure_classes: vec![],
             shift_metadata: vec![],
+            call_sites: HashMap::new(),
+            contract_checks: HashMap::new(),
+            spans: vec![],
+            located_classes: 0,
+            lambda_stack: vec![],
+            loop_stack: vec![],
+            options: CompileOptions::default(),
         }
     }
 
@@ -38,12 +120,99 @@ impl CompileContext {
         self.shift_metadata[id as usize]
     }
 
+    pub fn add_call_site(&mut self, code_pos: u32, span: Span) {
+        self.call_sites.insert(code_pos, span);
+    }
+
+    pub fn add_contract_check(&mut self, code_pos: u32, span: Span) {
+        self.contract_checks.insert(code_pos, span);
+    }
+
+    pub fn options(&self) -> CompileOptions {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: CompileOptions) {
+        self.options = options;
+    }
+
+    pub fn add_span(&mut self, code_pos: u32, span: Span) {
+        self.spans.push((code_pos, span));
+    }
+
+    /// The span of every node that has been emitted, along with the code
+    /// position that its instructions start at.
+    pub fn spans(&self) -> &[(u32, Span)] {
+        &self.spans
+    }
+
+    pub fn call_site_span(&self, code_pos: u32) -> Option<Span> {
+        self.call_sites.get(&code_pos).cloned()
+    }
+
+    /// The span of the clause that the `Require` or `Ensure` at
+    /// `code_pos` checks.
+    pub fn contract_check_span(&self, code_pos: u32) -> Option<Span> {
+        self.contract_checks.get(&code_pos).cloned()
+    }
+
     pub fn add_closure_class(&mut self, class: ClosureClass) -> u32 {
         let id = self.closure_classes.len();
         self.closure_classes.push(class);
         id as u32
     }
 
+    /// Fills in the position of every closure class added since the last
+    /// call, given the source that they were compiled from.
+    pub(crate) fn locate_closures(&mut self, source: &str) {
+        for class in &mut self.closure_classes[self.located_classes..] {
+            if let (None, Some(span)) = (class.position, class.span) {
+                let position = span.start_position(source);
+                class.position = Some((position.line(), position.column()));
+            }
+        }
+        self.located_classes = self.closure_classes.len();
+    }
+
+    pub(crate) fn enter_lambda(&mut self, id: u32) {
+        self.lambda_stack.push(id);
+    }
+
+    pub(crate) fn exit_lambda(&mut self) {
+        self.lambda_stack.pop();
+    }
+
+    /// The closure class of the innermost lambda being emitted.
+    pub(crate) fn current_lambda(&self) -> Option<u32> {
+        self.lambda_stack.last().cloned()
+    }
+
+    pub(crate) fn enter_loop(&mut self, start: u32) {
+        self.loop_stack.push(start);
+    }
+
+    pub(crate) fn exit_loop(&mut self) {
+        self.loop_stack.pop();
+    }
+
+    /// Where the innermost loop being emitted starts.
+    pub(crate) fn current_loop(&self) -> Option<u32> {
+        self.loop_stack.last().cloned()
+    }
+
+    /// The closure classes of the lambdas that `class` was written inside,
+    /// innermost first.
+    pub fn enclosing_classes(&self, class: &ClosureClass) -> Vec<&ClosureClass> {
+        let mut out = vec![];
+        let mut parent = class.parent;
+        while let Some(id) = parent {
+            let class = &self.closure_classes[id as usize];
+            out.push(class);
+            parent = class.parent;
+        }
+        out
+    }
+
     pub fn get_lambda_class(&self, id: u32) -> ClosureClass {
         self.closure_classes[id as usize].clone()
     }
@@ -54,7 +223,118 @@ impl CompileContext {
         Instr::LoadConstant(id as u32)
     }
 
+    /// Adds a string to the constant pool, reusing the existing constant
+    /// if the same string has already been added.  Every evaluation of
+    /// the literal shares the same allocation.
+    pub fn add_string_constant(&mut self, constant: &str) -> Instr {
+        let key = ConstantKey::String(constant.to_string());
+        if self.pooled.contains_key(&key) {
+            self.string_hits += 1;
+        } else {
+            self.strings += 1;
+        }
+        self.add_pooled_constant(key, constant.into())
+    }
+
+    /// Adds an integer to the constant pool, reusing the existing
+    /// constant if the same integer has already been added.
+    pub fn add_int_constant(&mut self, constant: i64) -> Instr {
+        self.add_pooled_constant(ConstantKey::Int(constant), constant.into())
+    }
+
+    /// Adds a float to the constant pool, reusing the existing constant
+    /// if a float with the same bits has already been added.
+    pub fn add_float_constant(&mut self, constant: f64) -> Instr {
+        self.add_pooled_constant(ConstantKey::Float(constant.to_bits()), constant.into())
+    }
+
+    fn add_pooled_constant(&mut self, key: ConstantKey, constant: Value) -> Instr {
+        if let Some(&id) = self.pooled.get(&key) {
+            self.hits += 1;
+            return Instr::LoadConstant(id);
+        }
+
+        let instr = self.add_constant(constant);
+        if let Instr::LoadConstant(id) = instr {
+            self.pooled.insert(key, id);
+        }
+        instr
+    }
+
     pub fn get_constant(&self, id: u32) -> Value {
         self.constants[id as usize].clone()
     }
+
+    pub(crate) fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    pub(crate) fn closure_classes(&self) -> &[ClosureClass] {
+        &self.closure_classes
+    }
+
+    pub(crate) fn shift_metadata(&self) -> &[ShiftMeta] {
+        &self.shift_metadata
+    }
+
+    /// Every call site that has been recorded, ordered by code position.
+    pub(crate) fn call_sites(&self) -> Vec<(u32, Span)> {
+        sorted(&self.call_sites)
+    }
+
+    /// Every contract check that has been recorded, ordered by code position.
+    pub(crate) fn contract_checks(&self) -> Vec<(u32, Span)> {
+        sorted(&self.contract_checks)
+    }
+
+    pub fn pool_stats(&self) -> ConstantPoolStats {
+        ConstantPoolStats {
+            constants: self.constants.len(),
+            strings: self.strings,
+            string_hits: self.string_hits,
+            hits: self.hits,
+        }
+    }
+}
+
+fn sorted(positions: &HashMap<u32, Span>) -> Vec<(u32, Span)> {
+    let mut sorted: Vec<(u32, Span)> = positions.iter().map(|(&p, &s)| (p, s)).collect();
+    sorted.sort_by_key(|&(p, _)| p);
+    sorted
+}
+
+#[test]
+fn string_constants_are_interned() {
+    let mut cc = CompileContext::new();
+    let a = cc.add_string_constant("hello");
+    let b = cc.add_string_constant("world");
+    let c = cc.add_string_constant("hello");
+    assert_eq!(a, c);
+    assert!(a != b);
+    assert_eq!(cc.pool_stats(), ConstantPoolStats {
+        constants: 2,
+        strings: 2,
+        string_hits: 1,
+        hits: 1,
+    });
+}
+
+#[test]
+fn numeric_constants_are_interned() {
+    let mut cc = CompileContext::new();
+    let a = cc.add_int_constant(1 << 40);
+    let b = cc.add_float_constant(1.5);
+    let c = cc.add_int_constant(1 << 40);
+    let d = cc.add_float_constant(1.5);
+    let e = cc.add_float_constant(-0.0);
+    let f = cc.add_float_constant(0.0);
+    assert_eq!(a, c);
+    assert_eq!(b, d);
+    assert!(e != f);
+    assert_eq!(cc.pool_stats(), ConstantPoolStats {
+        constants: 4,
+        strings: 0,
+        string_hits: 0,
+        hits: 2,
+    });
 }
@@ -0,0 +1,135 @@
+//! Converts between quoted data in the syntax tree and the values that it
+//! stands for.  Quotes, and anything else that treats code as data, go
+//! through here so that both directions agree on what each piece becomes.
+//!
+//! | syntax                    | value             |
+//! |---------------------------|-------------------|
+//! | `nil`, `true`, `false`    | themselves        |
+//! | `1`, `2.5`, `"text"`      | themselves        |
+//! | `name`                    | the symbol `name` |
+//! | `(a b c)`                 | a list            |
+//! | `{k1 v1 k2 v2}`           | a map             |
+//!
+//! Symbols in the syntax tree are interned when it is parsed, so the
+//! values share an interner with the code that they came from.
+
+use typed_arena::Arena;
+
+use compiler::parse::{Ast, Span};
+use vm::{Value, ValueKind};
+
+// Deeper values are assumed to be cyclic through a cell.
+const MAX_DEPTH: u32 = 512;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum DataError {
+    /// The syntax at this span is code, like a call or a closure, rather
+    /// than data.
+    NotData(Span),
+    /// Values of this kind have no syntax, like functions and rationals.
+    Unsupported(ValueKind),
+    TooDeep,
+}
+
+/// The value of a piece of quoted data, like the `ListLit` inside of an
+/// `Ast::Quote`.
+pub fn ast_to_value(ast: &Ast) -> Result<Value, DataError> {
+    match ast {
+        &Ast::NilLit(_) => Ok(Value::Nil),
+        &Ast::BoolLit(b, _) => Ok(Value::Bool(b)),
+        &Ast::IntLit(i, _) => Ok(Value::Int(i)),
+        &Ast::FloatLit(f, _) => Ok(Value::Float(f)),
+        &Ast::StringLit(ref s, _) => Ok(Value::string(s.clone())),
+        &Ast::Identifier(s, _) | &Ast::SymbolLit(s, _) => Ok(Value::Symbol(s)),
+        &Ast::ListLit(ref items, _) => {
+            let items = items.iter().map(ast_to_value).collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::list(items))
+        }
+        &Ast::MapLit(ref pairs, _) => {
+            let pairs = pairs.iter()
+                             .map(|&(ref k, v)| Ok((ast_to_value(k)?, ast_to_value(v)?)))
+                             .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::map(pairs))
+        }
+        other => Err(DataError::NotData(other.span())),
+    }
+}
+
+/// The syntax for `value`, which `ast_to_value` turns back into an equal
+/// value.  Symbols become identifiers and the keys of maps are sorted so
+/// that equal maps always give the same syntax.  Cells are replaced by
+/// the value that they hold.
+///
+/// The syntax has dummy spans.
+pub fn value_to_ast<'a>(value: &Value, arena: &'a Arena<Ast<'a>>) -> Result<Ast<'a>, DataError> {
+    value_to_ast_at(value, arena, 0)
+}
+
+fn value_to_ast_at<'a>(value: &Value, arena: &'a Arena<Ast<'a>>, depth: u32) -> Result<Ast<'a>, DataError> {
+    if depth > MAX_DEPTH {
+        return Err(DataError::TooDeep);
+    }
+
+    let span = Span::dummy();
+    Ok(match value {
+        &Value::Nil => Ast::NilLit(span),
+        &Value::Bool(b) => Ast::BoolLit(b, span),
+        &Value::Int(i) => Ast::IntLit(i, span),
+        &Value::Float(f) => Ast::FloatLit(f, span),
+        &Value::String(_) | &Value::Substring(_) => Ast::StringLit(value.expect_str().unwrap().to_string(), span),
+        &Value::Symbol(s) => Ast::Identifier(s, span),
+        &Value::Cell(ref c) => value_to_ast_at(&*c.borrow(), arena, depth + 1)?,
+        &Value::List(ref items) => {
+            let items = items.iter()
+                             .map(|item| value_to_ast_at(item, arena, depth + 1))
+                             .collect::<Result<Vec<_>, _>>()?;
+            Ast::ListLit(items, span)
+        }
+        &Value::Map(ref pairs) => {
+            let mut out = vec![];
+            for (k, v) in pairs.borrow().sorted() {
+                let k = value_to_ast_at(k, arena, depth + 1)?;
+                let v = value_to_ast_at(v, arena, depth + 1)?;
+                out.push((k, &*arena.alloc(v)));
+            }
+            Ast::MapLit(out, span)
+        }
+        other => return Err(DataError::Unsupported(other.kind())),
+    })
+}
+
+#[test]
+fn data_round_trips() {
+    use ares_syntax::SymbolIntern;
+
+    let mut interner = SymbolIntern::new();
+    let arena = Arena::new();
+    let asts = ::compiler::parse::parse("'(a 1 2.5 \"s\" nil true (b) {k (v)})", &mut interner, &arena).unwrap();
+    let data = match asts[0] {
+        Ast::Quote(data, _) => data,
+        ref other => panic!("expected a quote, got {:?}", other),
+    };
+
+    let value = ast_to_value(data).unwrap();
+    let sym = |interner: &mut SymbolIntern, name: &str| Value::Symbol(interner.intern(name));
+    assert_eq!(value, Value::list(vec![
+        sym(&mut interner, "a"),
+        Value::Int(1),
+        Value::Float(2.5),
+        Value::string("s"),
+        Value::Nil,
+        Value::Bool(true),
+        Value::list(vec![sym(&mut interner, "b")]),
+        Value::map(vec![(sym(&mut interner, "k"), Value::list(vec![sym(&mut interner, "v")]))]),
+    ]));
+
+    // Values don't remember where they were written.
+    let back = value_to_ast(&value, &arena).unwrap();
+    assert!(back.equals_sans_span(data), "{:?} != {:?}", back, data);
+    assert_eq!(ast_to_value(&back).unwrap(), value);
+
+    let code = ::compiler::parse::parse("f(x)", &mut interner, &arena).unwrap();
+    assert_eq!(ast_to_value(&code[0]), Err(DataError::NotData(code[0].span())));
+    assert_eq!(value_to_ast(&::vm::Rational::new(1, 3).unwrap().into_value(), &arena),
+               Err(DataError::Unsupported(ValueKind::Rational)));
+}
@@ -0,0 +1,364 @@
+use std::fmt::Write;
+
+use ares_syntax::{Span, Symbol, SymbolIntern, JsonObject, json_array};
+use compiler::Type;
+use compiler::binding::BoundRef;
+use compiler::lint::LintRegistry;
+
+/// How serious a `Diagnostic` is.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    /// Compilation stopped.
+    Error,
+    /// Compilation went on, but the code is probably wrong.
+    Warning,
+    /// Information that doesn't point at a problem.
+    Note,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Severity::Error => "error",
+            &Severity::Warning => "warning",
+            &Severity::Note => "note",
+        }
+    }
+}
+
+/// Extra information attached to a `Diagnostic`, like where a shadowed
+/// variable was first defined.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+/// A problem found in a program, whichever phase of compilation found it.
+///
+/// Errors from the parser and binder and the warnings that compilation
+/// collects can all be turned into one, so that embedders can show them
+/// the same way.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A short identifier that stays the same between releases, like
+    /// `E0100` for an error or `W0001` for a warning.
+    pub code: &'static str,
+    pub message: String,
+    pub primary_span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn new<M: Into<String>>(severity: Severity, code: &'static str, message: M, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            severity: severity,
+            code: code,
+            message: message.into(),
+            primary_span: span,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_note<M: Into<String>>(mut self, message: M, span: Option<Span>) -> Diagnostic {
+        self.notes.push(Note { message: message.into(), span: span });
+        self
+    }
+
+    /// Renders the diagnostic as text, quoting the lines of `source` that
+    /// its spans point at:
+    ///
+    /// ```text
+    /// error[E0100]: `y` is not defined
+    ///  --> 1:9
+    ///   |
+    /// 1 | var x = y;
+    ///   |         ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity.name(), self.code, self.message);
+        if let Some(span) = self.primary_span {
+            render_span(&mut out, source, span);
+        }
+        for note in &self.notes {
+            writeln!(out, "note: {}", note.message).unwrap();
+            if let Some(span) = note.span {
+                render_span(&mut out, source, span);
+            }
+        }
+        out
+    }
+
+    /// Encodes the diagnostic as a JSON object, for tools that aren't
+    /// written in rust:
+    ///
+    /// ```text
+    /// {"type":"diagnostic","severity":"error","code":"E0100",
+    ///  "message":"`y` is not defined",
+    ///  "span":{"type":"span","start":8,"end":9,"line":1,"column":9,"end_line":1,"end_column":10},
+    ///  "notes":[{"type":"note","message":"...","span":null}]}
+    /// ```
+    ///
+    /// Offsets are in bytes and lines and columns start at 1, counted in
+    /// `source`.  Spans that are missing are `null`.
+    pub fn to_json(&self, source: &str) -> String {
+        let notes = self.notes.iter().map(|note| {
+            JsonObject::new("note")
+                .string("message", &note.message)
+                .raw("span", span_json(note.span, source))
+                .finish()
+        });
+        JsonObject::new("diagnostic")
+            .string("severity", self.severity.name())
+            .string("code", self.code)
+            .string("message", &self.message)
+            .raw("span", span_json(self.primary_span, source))
+            .raw("notes", json_array(notes))
+            .finish()
+    }
+}
+
+/// Encodes `diagnostics` as a JSON array of the objects that
+/// `Diagnostic::to_json` produces.
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic], source: &str) -> String {
+    json_array(diagnostics.iter().map(|d| d.to_json(source)))
+}
+
+fn span_json(span: Option<Span>, source: &str) -> String {
+    let span = match span {
+        Some(span) => span,
+        None => return "null".to_string(),
+    };
+    let start = span.start_position(source);
+    let end = span.end_position(source);
+    JsonObject::new("span")
+        .raw("start", span.start().to_string())
+        .raw("end", span.end().to_string())
+        .raw("line", start.line().to_string())
+        .raw("column", start.column().to_string())
+        .raw("end_line", end.line().to_string())
+        .raw("end_column", end.column().to_string())
+        .finish()
+}
+
+// Quotes the first line of `span` and underlines the part of it that the
+// span covers.
+fn render_span(out: &mut String, source: &str, span: Span) {
+    let start = span.start_position(source);
+    let end = span.end_position(source);
+    let text = source.lines().nth(start.line() as usize - 1).unwrap_or("");
+    let end_column = if end.line() == start.line() {
+        end.column()
+    } else {
+        text.chars().count() as u32 + 1
+    };
+    let underline = (end_column.saturating_sub(start.column()) as usize).max(1);
+
+    let line_number = start.line().to_string();
+    let gutter = " ".repeat(line_number.len());
+    writeln!(out, "{}--> {}:{}", gutter, start.line(), start.column()).unwrap();
+    writeln!(out, "{} |", gutter).unwrap();
+    writeln!(out, "{} | {}", line_number, text).unwrap();
+    writeln!(out, "{} | {}{}", gutter, " ".repeat(start.column() as usize - 1), "^".repeat(underline)).unwrap();
+}
+
+/// A problem found while compiling that doesn't stop compilation.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Warning {
+    /// A local variable was defined but never read.
+    UnusedVariable {
+        name: Symbol,
+        span: Span,
+    },
+    /// A lambda argument was never read.
+    UnusedArgument {
+        name: Symbol,
+        span: Span,
+    },
+    /// A definition masks a variable or argument from an outer scope.
+    Shadowing {
+        name: Symbol,
+        span: Span,
+        original: Span,
+    },
+    /// A value whose type is known doesn't match the annotation that it
+    /// is checked against.
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    /// An annotation names a type that doesn't exist.
+    UnknownType {
+        name: Symbol,
+        span: Span,
+    },
+    /// Arithmetic or an ordering comparison on a value that is known not
+    /// to be a number, like `"a" + 1`.
+    InvalidOperands {
+        op: &'static str,
+        left: Type,
+        right: Type,
+        span: Span,
+    },
+    /// A lambda whose parameters are known is called with the wrong
+    /// number of arguments.
+    WrongArity {
+        expected: u32,
+        got: u32,
+        span: Span,
+    },
+    /// An `if` whose condition is a boolean literal.
+    ConstantCondition {
+        value: bool,
+        span: Span,
+    },
+    /// A block with no statements in it.
+    EmptyBlock {
+        span: Span,
+    },
+    /// `==` or `!=` with a lambda literal, which is never equal to
+    /// anything.
+    LambdaComparison {
+        span: Span,
+    },
+    /// Found by a lint that was added to the `LintRegistry`.
+    Lint {
+        lint: &'static str,
+        message: String,
+        span: Span,
+    },
+}
+
+impl Warning {
+    pub fn code(&self) -> &'static str {
+        match self {
+            &Warning::UnusedVariable { .. } => "W0001",
+            &Warning::UnusedArgument { .. } => "W0002",
+            &Warning::Shadowing { .. } => "W0003",
+            &Warning::TypeMismatch { .. } => "W0004",
+            &Warning::UnknownType { .. } => "W0005",
+            &Warning::InvalidOperands { .. } => "W0006",
+            &Warning::WrongArity { .. } => "W0007",
+            &Warning::ConstantCondition { .. } => "W0008",
+            &Warning::EmptyBlock { .. } => "W0009",
+            &Warning::LambdaComparison { .. } => "W0010",
+            &Warning::Lint { .. } => "W0100",
+        }
+    }
+
+    pub fn to_diagnostic(&self, interner: &SymbolIntern) -> Diagnostic {
+        let warning = |message: String, span: Span| {
+            Diagnostic::new(Severity::Warning, self.code(), message, Some(span))
+        };
+        match self {
+            &Warning::UnusedVariable { name, span } =>
+                warning(format!("`{}` is never used", interner.lookup_or_anon(name)), span),
+            &Warning::UnusedArgument { name, span } =>
+                warning(format!("argument `{}` is never used", interner.lookup_or_anon(name)), span),
+            &Warning::Shadowing { name, span, original } =>
+                warning(format!("`{}` shadows an outer variable", interner.lookup_or_anon(name)), span)
+                    .with_note("the outer variable is defined here", Some(original)),
+            &Warning::TypeMismatch { expected, found, span } =>
+                warning(format!("expected {}, found {}", expected.name(), found.name()), span),
+            &Warning::UnknownType { name, span } =>
+                warning(format!("unknown type `{}`", interner.lookup_or_anon(name)), span),
+            &Warning::InvalidOperands { op, left, right, span } =>
+                warning(format!("`{}` can't be used on {} and {}", op, left.name(), right.name()), span),
+            &Warning::WrongArity { expected, got, span } =>
+                warning(format!("expected {} arguments, got {}", expected, got), span),
+            &Warning::ConstantCondition { value, span } =>
+                warning(format!("this condition is always {}", value), span),
+            &Warning::EmptyBlock { span } =>
+                warning("this block is empty".to_string(), span),
+            &Warning::LambdaComparison { span } =>
+                warning("a lambda is never equal to anything".to_string(), span),
+            &Warning::Lint { lint, ref message, span } =>
+                warning(message.clone(), span).with_note(format!("reported by the `{}` lint", lint), None),
+        }
+    }
+}
+
+/// Collects warnings produced by the compiler, along with any other
+/// diagnostics that are reported while compiling.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    warnings: Vec<Warning>,
+    reported: Vec<Diagnostic>,
+    lints: LintRegistry,
+    check_types: bool,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics {
+            warnings: vec![],
+            reported: vec![],
+            lints: LintRegistry::new(),
+            check_types: false,
+        }
+    }
+
+    /// Shadowing is allowed, so warnings about it are off by default.
+    pub fn set_warn_on_shadowing(&mut self, enabled: bool) {
+        self.lints.set_enabled("shadowing", enabled);
+    }
+
+    pub fn warn_on_shadowing(&self) -> bool {
+        self.lints.is_enabled("shadowing")
+    }
+
+    /// The lints that run over every program that is bound.
+    pub fn lints(&self) -> &LintRegistry {
+        &self.lints
+    }
+
+    pub fn lints_mut(&mut self) -> &mut LintRegistry {
+        &mut self.lints
+    }
+
+    pub(crate) fn run_lints<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], interner: &SymbolIntern) {
+        let Diagnostics { ref mut lints, ref mut warnings, .. } = *self;
+        lints.run(forms, interner, warnings);
+    }
+
+    /// Annotations are ignored, and types aren't inferred, unless this is
+    /// enabled, since checking them is an extra pass over the program.
+    pub fn set_check_types(&mut self, enabled: bool) {
+        self.check_types = enabled;
+    }
+
+    pub fn check_types(&self) -> bool {
+        self.check_types
+    }
+
+    pub fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        self.reported.push(diagnostic);
+    }
+
+    /// Every diagnostic that has been reported, followed by the warnings.
+    pub fn to_diagnostics(&self, interner: &SymbolIntern) -> Vec<Diagnostic> {
+        self.reported.iter()
+                     .cloned()
+                     .chain(self.warnings.iter().map(|w| w.to_diagnostic(interner)))
+                     .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty() && self.reported.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.warnings.clear();
+        self.reported.clear();
+    }
+}
@@ -0,0 +1,45 @@
+use compiler::Constant;
+use compiler::binding::Bound;
+use compiler::parse::Ast;
+use vm::{arith, Arith, Value};
+
+/// Computes the value of `bound` at compile time if it is built only out
+/// of number and string literals, arithmetic on them, and lists of them.
+///
+/// Anything that would fail at runtime (like dividing by zero or
+/// overflowing) is left for the vm so that the error happens when the
+/// code is run.  So are results that a `CompiledProgram` can't store as a
+/// constant, like rationals.
+pub fn evaluate(bound: &Bound) -> Option<Constant> {
+    match bound {
+        &Bound::Literal(&Ast::IntLit(i, _)) => Some(Constant::Int(i)),
+        &Bound::Literal(&Ast::FloatLit(f, _)) => Some(Constant::Float(f)),
+        &Bound::Literal(&Ast::StringLit(ref s, _)) => Some(Constant::String(s.clone().into())),
+        &Bound::Add(l, r, _) => evaluate_arith(Arith::Add, l, r),
+        &Bound::Sub(l, r, _) => evaluate_arith(Arith::Sub, l, r),
+        &Bound::Mul(l, r, _) => evaluate_arith(Arith::Mul, l, r),
+        &Bound::Div(l, r, _) => evaluate_arith(Arith::Div, l, r),
+        &Bound::ListLit(ref elements, _) => {
+            elements.iter().map(|&e| evaluate(e)).collect::<Option<_>>().map(Constant::List)
+        }
+        _ => None,
+    }
+}
+
+fn evaluate_arith(op: Arith, l: &Bound, r: &Bound) -> Option<Constant> {
+    let l = number(evaluate(l)?)?;
+    let r = number(evaluate(r)?)?;
+    match arith(op, &l, &r) {
+        Ok(Value::Int(i)) => Some(Constant::Int(i)),
+        Ok(Value::Float(f)) => Some(Constant::Float(f)),
+        _ => None,
+    }
+}
+
+fn number(constant: Constant) -> Option<Value> {
+    match constant {
+        Constant::Int(i) => Some(Value::Int(i)),
+        Constant::Float(f) => Some(Value::Float(f)),
+        _ => None,
+    }
+}
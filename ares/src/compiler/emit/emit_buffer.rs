@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use util::collections::HashMap;
 
 use vm::Instr;
 
@@ -1,10 +1,13 @@
 mod error;
 mod emit_buffer;
+mod const_eval;
 
 use compiler::parse::Ast;
 use compiler::binding::{Bound, BoundRef, SymbolBindSource, LambdaBindings};
-use compiler::{CompileContext, ShiftMeta};
+use compiler::{CompileContext, Constant, ShiftMeta, ast_to_value};
 use vm::{Instr, ClosureClass};
+#[cfg(feature = "decimal")]
+use vm::Decimal;
 use ares_syntax::SymbolIntern;
 
 pub use self::error::EmitError;
@@ -41,12 +44,32 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
                     out: &mut EmitBuffer,
                     inside_lambda: Option<&LambdaBindings>)
                     -> Result<bool, EmitError> {
+    if let Some(span) = bound.span() {
+        compile_context.add_span(out.offset() as u32, span);
+    }
+
+    // Arithmetic and lists made only of literals are computed here, so
+    // that running the program just loads the result.
+    match bound {
+        &Bound::Add(..) | &Bound::Sub(..) | &Bound::Mul(..) | &Bound::Div(..) | &Bound::ListLit(..) => {
+            if let Some(constant) = const_eval::evaluate(bound) {
+                emit_constant(constant, compile_context, out);
+                return Ok(true);
+            }
+        }
+        _ => {}
+    }
+
     match bound {
         &Bound::BlockExpression(ref bound_bodies, _) => {
-            assert!(emit_all(bound_bodies.iter().map(|&a|a), compile_context, symbol_intern, out, inside_lambda)?);
+            create_cells(bound_bodies, out, inside_lambda);
+            if !emit_all(bound_bodies.iter().map(|&a|a), compile_context, symbol_intern, out, inside_lambda)? {
+                out.push(Instr::NilLit);
+            }
             Ok(true)
         }
         &Bound::BlockStatement(ref bound_bodies, _) => {
+            create_cells(bound_bodies, out, inside_lambda);
             if emit_all(bound_bodies.iter().map(|&a|a), compile_context, symbol_intern, out, inside_lambda)? {
                 out.push(Instr::Pop);
             }
@@ -112,6 +135,17 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
             out.push(Instr::Neq);
             Ok(true)
         }
+        &Bound::Quote(ast) => {
+            let data = match ast {
+                &Ast::Quote(data, _) => data,
+                _ => panic!("non-quote ast found in Bound::Quote {:?}", ast),
+            };
+            // Quoted data is built once, when the code is loaded.  The
+            // parser only puts data inside of a quote.
+            let value = ast_to_value(data).expect("non-data ast found in a quote");
+            out.push(compile_context.add_constant(value));
+            Ok(true)
+        }
         &Bound::Literal(ast) => {
             match ast {
                 &Ast::IntLit(i, _) => {
@@ -119,17 +153,23 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
                     if i >= MIN as i64 && i <= MAX as i64 {
                         out.push(Instr::IntLit(i as i32));
                     } else {
-                        out.push(compile_context.add_constant(i.into()));
+                        out.push(compile_context.add_int_constant(i));
                     }
                 }
                 &Ast::BoolLit(b, _) => {
                     out.push(Instr::BoolLit(b));
                 }
                 &Ast::StringLit(ref s, _) => {
-                    out.push(compile_context.add_constant(s.clone().into()));
+                    out.push(compile_context.add_string_constant(s));
                 }
                 &Ast::FloatLit(f, _) => {
-                    out.push(compile_context.add_constant(f.into()));
+                    out.push(compile_context.add_float_constant(f));
+                }
+                // Binding has already checked that the digits parse.
+                #[cfg(feature = "decimal")]
+                &Ast::DecimalLit(ref digits, _) => {
+                    let decimal = Decimal::parse(digits).expect("unchecked decimal literal");
+                    out.push(compile_context.add_constant(decimal.into_value()));
                 }
                 &Ast::SymbolLit(s, _) => {
                     out.push(Instr::SymbolLit(s));
@@ -198,7 +238,7 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
 
             Ok(false)
         },
-        &Bound::Lambda { ref arg_symbols, ref body, ref bindings, ref upvar_list, ref is_shifter, ..} => {
+        &Bound::Lambda { ref body, ref bindings, ref upvar_list, ref is_shifter, ref name, num_args, has_rest_params, ast, ..} => {
             // Push all needed upvars onto the stack for the closure to take hold of.
             if !upvar_list.is_empty() {
                 let binder = inside_lambda.unwrap();
@@ -214,22 +254,25 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
 
             let closure_class = ClosureClass {
                 code_offset: out.offset() as u32,
-                // TODO: take varargs into account
-                arg_count: arg_symbols.len() as u32,
+                arg_count: num_args,
                 local_defines_count: bindings.num_declarations,
                 upvars_count: bindings.num_upvars,
-                has_rest_params: false,
+                has_rest_params: has_rest_params,
                 namespace: symbol_intern.precomputed.default_namespace,
                 is_shifter: is_shifter.get(),
+                name: name.get(),
+                position: None,
+                span: Some(ast.span()),
+                parent: compile_context.current_lambda(),
             };
 
             let cc_id = compile_context.add_closure_class(closure_class);
             out.fulfill(create_closure_fulfill, Instr::CreateClosure(cc_id));
 
-            // Convert any closed over argument into a cell
+            // Convert any closed over argument that can change into a cell
             for (_, binding) in bindings.bindings.iter() {
-                if let &SymbolBindSource::Arg{ ref upvar, .. } = binding {
-                    if upvar.get() {
+                if let &SymbolBindSource::Arg{ .. } = binding {
+                    if binding.is_cell() {
                         let position = bindings.compute_stack_offset(binding);
                         out.push(Instr::Dup(position));
                         out.push(Instr::WrapCell);
@@ -238,7 +281,10 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
                 }
             }
 
-            if !emit(body, compile_context, symbol_intern, out, Some(bindings))? {
+            compile_context.enter_lambda(cc_id);
+            let body_is_expression = emit(body, compile_context, symbol_intern, out, Some(bindings));
+            compile_context.exit_lambda();
+            if !body_is_expression? {
                 // If the body was a statement, return nil
                 out.push(Instr::NilLit);
             }
@@ -248,11 +294,55 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
             out.fulfill(eol_fulfill, Instr::Jump(next));
             Ok(true)
         }
-        &Bound::FnCall(ref funclike, ref args, _) => {
+        &Bound::Contract { ref requires, ref ensures, body, ref result, .. } => {
+            let checked = compile_context.options().check_contracts;
+            if checked {
+                for clause in requires {
+                    emit(clause, compile_context, symbol_intern, out, inside_lambda)?;
+                    if let Some(span) = clause.span() {
+                        compile_context.add_contract_check(out.offset() as u32, span);
+                    }
+                    out.push(Instr::Require);
+                }
+            }
+
+            let body_is_expression = emit(body, compile_context, symbol_intern, out, inside_lambda)?;
+            if !checked || ensures.is_empty() {
+                return Ok(body_is_expression);
+            }
+            if !body_is_expression {
+                out.push(Instr::NilLit);
+            }
+
+            // Keep the body's value in `result` while the clauses are checked.
+            let binder = inside_lambda.unwrap();
+            let stack_offset = binder.compute_stack_offset(result);
+            let is_cell = result.is_cell();
+            if is_cell {
+                out.push(Instr::WrapCell);
+            }
+            out.push(Instr::Assign(stack_offset));
+
+            for clause in ensures {
+                emit(clause, compile_context, symbol_intern, out, inside_lambda)?;
+                if let Some(span) = clause.span() {
+                    compile_context.add_contract_check(out.offset() as u32, span);
+                }
+                out.push(Instr::Ensure);
+            }
+
+            out.push(Instr::Dup(stack_offset));
+            if is_cell {
+                out.push(Instr::UnwrapCell);
+            }
+            Ok(true)
+        }
+        &Bound::FnCall(ref funclike, ref args, ast, _) => {
             for arg in args {
                 emit(arg, compile_context, symbol_intern, out, inside_lambda)?;
             }
             emit(funclike, compile_context, symbol_intern, out, inside_lambda)?;
+            compile_context.add_call_site(out.offset() as u32, ast.span());
             out.push(Instr::Execute(args.len() as u32));
             Ok(true)
         }
@@ -261,26 +351,21 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
                 &SymbolBindSource::Global(symbol) => {
                     out.push(Instr::GetGlobal(symbol));
                 }
-                &SymbolBindSource::Arg{ref upvar, ..} | &SymbolBindSource::LocalDefine{ref upvar, ..} if upvar.get() => {
-                    let binder = inside_lambda.unwrap();
-                    out.push(Instr::Dup(binder.compute_stack_offset(&source)));
-                    out.push(Instr::UnwrapCell);
-                }
-                &SymbolBindSource::Arg{..} | &SymbolBindSource::LocalDefine{..} => {
-                    let binder = inside_lambda.unwrap();
-                    out.push(Instr::Dup(binder.compute_stack_offset(&source)));
-                }
+                &SymbolBindSource::Arg{..} |
+                &SymbolBindSource::LocalDefine{..} |
                 &SymbolBindSource::Upvar{..} => {
                     let binder = inside_lambda.unwrap();
                     out.push(Instr::Dup(binder.compute_stack_offset(&source)));
-                    out.push(Instr::UnwrapCell);
+                    if source.is_cell() {
+                        out.push(Instr::UnwrapCell);
+                    }
                 }
             }
             Ok(true)
         }
         &Bound::Assign(_, ref source, value, _) => {
             match source {
-                &SymbolBindSource::Arg{ref upvar, ..} | &SymbolBindSource::LocalDefine{ref upvar, ..} if upvar.get() => {
+                &SymbolBindSource::Arg{..} | &SymbolBindSource::LocalDefine{..} if source.is_cell() => {
                     let binder = inside_lambda.unwrap();
                     emit(value, compile_context, symbol_intern, out, inside_lambda)?;
                     //out.push(Instr::WrapCell);
@@ -306,16 +391,16 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
         }
         &Bound::Define(_, ref source, value, _) => {
             match source {
-                &SymbolBindSource::Arg{ref upvar, ..} |
-                &SymbolBindSource::LocalDefine{ref upvar, ..} if upvar.get() => {
+                &SymbolBindSource::Arg{..} |
+                &SymbolBindSource::LocalDefine{..} if source.is_cell() => {
+                    // The block made the cell already, so that the value
+                    // can close over the variable that it is stored in.
                     let binder = inside_lambda.unwrap();
-                    let stack_offset = binder.compute_stack_offset(&source);
                     emit(value, compile_context, symbol_intern, out, inside_lambda)?;
-                    out.push(Instr::WrapCell);
-                    out.push(Instr::Assign(stack_offset));
+                    out.push(Instr::SetCell(binder.compute_stack_offset(&source)));
                 }
-                &SymbolBindSource::Arg{ref upvar, ..} |
-                &SymbolBindSource::LocalDefine{ref upvar, ..} => {
+                &SymbolBindSource::Arg{..} |
+                &SymbolBindSource::LocalDefine{..} => {
                     let binder = inside_lambda.unwrap();
                     emit(value, compile_context, symbol_intern, out, inside_lambda)?;
                     out.push(Instr::Assign(binder.compute_stack_offset(&source)));
@@ -328,6 +413,91 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
             }
             Ok(false)
         }
+        &Bound::Loop { ref variables, body, .. } => {
+            for &(_, ref source, init) in variables {
+                emit(init, compile_context, symbol_intern, out, inside_lambda)?;
+                store_variable(source, out, inside_lambda);
+            }
+
+            let start = out.offset() as u32;
+            compile_context.enter_loop(start);
+            let body_is_expression = emit(body, compile_context, symbol_intern, out, inside_lambda);
+            compile_context.exit_loop();
+            if !body_is_expression? {
+                out.push(Instr::NilLit);
+            }
+            Ok(true)
+        }
+        &Bound::Recur(ref values, ref targets, _) => {
+            // Every value is computed before any variable is replaced, so
+            // the new values can refer to the old ones.
+            for value in values {
+                emit(value, compile_context, symbol_intern, out, inside_lambda)?;
+            }
+            for target in targets.iter().rev() {
+                store_variable(target, out, inside_lambda);
+            }
+            out.push(Instr::Jump(compile_context.current_loop().unwrap()));
+            Ok(true)
+        }
+        &Bound::Letrec { ref bindings, body, .. } => {
+            // Every closed over variable gets its cell before any value is
+            // computed, so that the values can close over each other.
+            for &(_, ref source, _) in bindings {
+                create_cell(source, out, inside_lambda);
+            }
+            for &(_, ref source, value) in bindings {
+                emit(value, compile_context, symbol_intern, out, inside_lambda)?;
+                match source {
+                    &SymbolBindSource::LocalDefine{..} if source.is_cell() => {
+                        let binder = inside_lambda.unwrap();
+                        out.push(Instr::SetCell(binder.compute_stack_offset(source)));
+                    }
+                    _ => store_variable(source, out, inside_lambda),
+                }
+            }
+
+            let body_is_expression = emit(body, compile_context, symbol_intern, out, inside_lambda)?;
+            if !body_is_expression {
+                out.push(Instr::NilLit);
+            }
+            Ok(true)
+        }
+        &Bound::NamedLet { ref source, function, ref inits, ast, .. } => {
+            match source {
+                // The function closes over itself, so its cell has to
+                // exist before the closure is created.
+                &SymbolBindSource::LocalDefine{..} if source.is_cell() => {
+                    let binder = inside_lambda.unwrap();
+                    create_cell(source, out, inside_lambda);
+                    emit(function, compile_context, symbol_intern, out, inside_lambda)?;
+                    out.push(Instr::SetCell(binder.compute_stack_offset(source)));
+                }
+                _ => {
+                    emit(function, compile_context, symbol_intern, out, inside_lambda)?;
+                    store_variable(source, out, inside_lambda);
+                }
+            }
+
+            for init in inits {
+                emit(init, compile_context, symbol_intern, out, inside_lambda)?;
+            }
+            match source {
+                &SymbolBindSource::LocalDefine{..} => {
+                    let binder = inside_lambda.unwrap();
+                    out.push(Instr::Dup(binder.compute_stack_offset(source)));
+                    if source.is_cell() {
+                        out.push(Instr::UnwrapCell);
+                    }
+                }
+                &SymbolBindSource::Global(symbol) => out.push(Instr::GetGlobal(symbol)),
+                &SymbolBindSource::Arg{..} |
+                &SymbolBindSource::Upvar{..} => panic!("a named let always declares its function"),
+            }
+            compile_context.add_call_site(out.offset() as u32, ast.span());
+            out.push(Instr::Execute(inits.len() as u32));
+            Ok(true)
+        }
         &Bound::Reset(ref symbols, ref closure, _) => {
             emit(closure, compile_context, symbol_intern, out, inside_lambda)?;
 
@@ -393,3 +563,71 @@ pub fn emit<'bound, 'ast: 'bound>(bound: &'bound Bound<'bound, 'ast>,
     }
 }
 
+/// Pops the value on top of the stack into a freshly bound variable.
+/// Variables that are closed over get a new cell, so closures created
+/// in earlier iterations of a loop keep the values that they saw.
+fn store_variable(source: &SymbolBindSource, out: &mut EmitBuffer, inside_lambda: Option<&LambdaBindings>) {
+    match source {
+        &SymbolBindSource::LocalDefine{..} if source.is_cell() => {
+            let binder = inside_lambda.unwrap();
+            out.push(Instr::WrapCell);
+            out.push(Instr::Assign(binder.compute_stack_offset(source)));
+        }
+        &SymbolBindSource::LocalDefine{..} => {
+            let binder = inside_lambda.unwrap();
+            out.push(Instr::Assign(binder.compute_stack_offset(source)));
+        }
+        &SymbolBindSource::Global(symbol) => {
+            out.push(Instr::PutGlobal(symbol));
+        }
+        &SymbolBindSource::Arg{..} |
+        &SymbolBindSource::Upvar{..} => panic!("only declarations can be stored to"),
+    }
+}
+
+/// Puts an empty cell in every closed over variable that `statements`
+/// define, before any of them run.  Closures can then capture variables
+/// that are defined later in the block, or by the definition that the
+/// closure is part of.
+fn emit_constant(constant: Constant, compile_context: &mut CompileContext, out: &mut EmitBuffer) {
+    use std::i32::{MIN, MAX};
+    match constant {
+        Constant::Int(i) if i >= MIN as i64 && i <= MAX as i64 => out.push(Instr::IntLit(i as i32)),
+        Constant::Int(i) => out.push(compile_context.add_int_constant(i)),
+        Constant::Float(f) => out.push(compile_context.add_float_constant(f)),
+        Constant::String(s) => out.push(compile_context.add_string_constant(&s)),
+        Constant::Nil => out.push(Instr::NilLit),
+        Constant::Bool(b) => out.push(Instr::BoolLit(b)),
+        Constant::Symbol(s) => out.push(Instr::SymbolLit(s)),
+        data@Constant::List(_) | data@Constant::Map(_) => out.push(compile_context.add_constant(data.to_value())),
+        #[cfg(feature = "decimal")]
+        d@Constant::Decimal(_) => out.push(compile_context.add_constant(d.to_value())),
+    }
+}
+
+fn create_cells(statements: &[BoundRef], out: &mut EmitBuffer, inside_lambda: Option<&LambdaBindings>) {
+    for statement in statements {
+        match *statement {
+            &Bound::Define(_, ref source, _, _) => create_cell(source, out, inside_lambda),
+            &Bound::Import { ref defines, .. } => {
+                for define in defines {
+                    if let &Bound::Define(_, ref source, _, _) = define {
+                        create_cell(source, out, inside_lambda);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn create_cell(source: &SymbolBindSource, out: &mut EmitBuffer, inside_lambda: Option<&LambdaBindings>) {
+    if let &SymbolBindSource::LocalDefine{..} = source {
+        if source.is_cell() {
+            let binder = inside_lambda.unwrap();
+            out.push(Instr::NilLit);
+            out.push(Instr::WrapCell);
+            out.push(Instr::Assign(binder.compute_stack_offset(source)));
+        }
+    }
+}
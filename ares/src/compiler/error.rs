@@ -2,6 +2,13 @@ pub use compiler::parse::ParseError;
 pub use compiler::emit::EmitError;
 pub use compiler::binding::BindingError;
 
+use std::error::Error;
+use std::fmt;
+
+use ares_syntax::{ConversionError, Span, Symbol, SymbolIntern};
+use compiler::diagnostics::{Diagnostic, Severity};
+use compiler::parse::{closing_delimiter, opening_delimiter};
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum CompileError {
     ParseError(ParseError),
@@ -26,3 +33,180 @@ impl From<BindingError> for CompileError {
         CompileError::BindingError(ee)
     }
 }
+
+impl CompileError {
+    /// Describes this error as diagnostics.  There is more than one when
+    /// the binder found several problems at once.
+    pub fn to_diagnostics(&self, interner: &SymbolIntern) -> Vec<Diagnostic> {
+        match self {
+            &CompileError::ParseError(ref e) => vec![parse_diagnostic(e)],
+            &CompileError::BindingError(ref e) => {
+                let mut out = vec![];
+                binding_diagnostics(e, &|name| interner.lookup_or_anon(name), &mut out);
+                out
+            }
+            &CompileError::EmitError(_) => unreachable!(),
+        }
+    }
+
+    /// Describes this error with the names of the symbols in it, one line
+    /// for each problem.
+    pub fn message(&self, interner: &SymbolIntern) -> String {
+        messages(&self.to_diagnostics(interner))
+    }
+}
+
+impl BindingError {
+    /// Describes this error with the names of the symbols in it, one line
+    /// for each problem.
+    pub fn message(&self, interner: &SymbolIntern) -> String {
+        let mut out = vec![];
+        binding_diagnostics(self, &|name| interner.lookup_or_anon(name), &mut out);
+        messages(&out)
+    }
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> String {
+    diagnostics.iter().map(|d| &d.message[..]).collect::<Vec<_>>().join("\n")
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&parse_diagnostic(self).message)
+    }
+}
+
+/// Without an interner, symbols are written as their ids, like `s12`;
+/// `message` writes their names.
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut out = vec![];
+        binding_diagnostics(self, &|name| format!("s{}", name.id()), &mut out);
+        f.write_str(&messages(&out))
+    }
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CompileError::ParseError(ref e) => e.fmt(f),
+            &CompileError::EmitError(ref e) => e.fmt(f),
+            &CompileError::BindingError(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl Error for BindingError {}
+
+impl Error for EmitError {}
+
+impl Error for CompileError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match self {
+            &CompileError::ParseError(ref e) => Some(e),
+            &CompileError::EmitError(ref e) => Some(e),
+            &CompileError::BindingError(ref e) => Some(e),
+        }
+    }
+}
+
+fn parse_diagnostic(error: &ParseError) -> Diagnostic {
+    let error_at = |code, message: String, span| Diagnostic::new(Severity::Error, code, message, span);
+    match error {
+        &ParseError::InvalidToken { location } =>
+            error_at("E0001", "invalid token".to_string(), Some(Span::new(location as u32, location as u32 + 1))),
+        &ParseError::UnrecognizedToken { token: Some((start, (_, ref text), end)), ref expected, open } => {
+            let mut diagnostic = error_at("E0002", format!("unexpected `{}`", text), Some(Span::new(start as u32, end as u32)));
+            if !expected.is_empty() {
+                diagnostic = diagnostic.with_note(format!("expected one of {}", expected.join(", ")), None);
+            }
+            match open {
+                // Only worth pointing out when the token is the wrong
+                // closing delimiter.
+                Some((offset, open)) if is_closing_delimiter(text) =>
+                    diagnostic.with_note(format!("this `{}` is still open; it is closed by `{}`",
+                                                 open, closing_delimiter(open)),
+                                         Some(Span::new(offset as u32, offset as u32 + 1))),
+                _ => diagnostic,
+            }
+        }
+        &ParseError::UnrecognizedToken { token: None, open, .. } => {
+            let diagnostic = error_at("E0003", "unexpected end of input".to_string(), None);
+            match open {
+                Some((offset, open)) =>
+                    diagnostic.with_note(format!("this `{}` is never closed", open),
+                                         Some(Span::new(offset as u32, offset as u32 + 1))),
+                None => diagnostic,
+            }
+        }
+        &ParseError::ExtraToken { token: (start, (_, ref text), end) } => {
+            let diagnostic = error_at("E0004", format!("unexpected `{}` after the end of the program", text),
+                                      Some(Span::new(start as u32, end as u32)));
+            if is_closing_delimiter(text) {
+                let open = opening_delimiter(text.chars().next().unwrap());
+                diagnostic.with_note(format!("there is no `{}` for this `{}` to close", open, text), None)
+            } else {
+                diagnostic
+            }
+        }
+        &ParseError::TooDeep(span) =>
+            error_at("E0005", "brackets are nested too deeply".to_string(), Some(span))
+                .with_note("the limit is `CompileOptions::max_nesting_depth`", None),
+        &ParseError::InvalidNumber(ConversionError::IntegerOverflow(span)) =>
+            error_at("E0006", "integer literal is too large".to_string(), Some(span))
+                .with_note(format!("the largest integer is {}", i64::max_value()), None),
+        &ParseError::InvalidNumber(ConversionError::FloatOverflow(span)) =>
+            error_at("E0007", "float literal is too large".to_string(), Some(span)),
+        &ParseError::InvalidNumber(ConversionError::MalformedExponent(span)) =>
+            error_at("E0008", "exponent has no digits".to_string(), Some(span))
+                .with_note("an exponent is written like the `e9` in `1e9` or the `e-3` in `2.5e-3`", None),
+    }
+}
+
+fn is_closing_delimiter(text: &str) -> bool {
+    text == ")" || text == "]" || text == "}"
+}
+
+fn binding_diagnostics(error: &BindingError, symbol_name: &Fn(Symbol) -> String, out: &mut Vec<Diagnostic>) {
+    let error_at = |code, message: String, span| Diagnostic::new(Severity::Error, code, message, span);
+    out.push(match error {
+        &BindingError::CouldNotBind(name, span, None) =>
+            error_at("E0100", format!("`{}` is not defined", symbol_name(name)), Some(span)),
+        &BindingError::CouldNotBind(name, span, Some(suggestion)) =>
+            error_at("E0100", format!("`{}` is not defined", symbol_name(name)), Some(span))
+                .with_note(format!("did you mean `{}`?", symbol_name(suggestion)), None),
+        &BindingError::AlreadyDefined(name) =>
+            error_at("E0101", format!("`{}` is already defined in this scope", symbol_name(name)), None),
+        &BindingError::SpecialFormCall { name, syntax, span } =>
+            error_at("E0102", format!("`{}` is not a function", symbol_name(name)), Some(span))
+                .with_note(format!("ares writes it as `{}`", syntax), None),
+        &BindingError::RecurOutsideLoop(span) =>
+            error_at("E0103", "`recur` outside of a loop".to_string(), Some(span)),
+        &BindingError::RecurArity { expected, got, span } =>
+            error_at("E0104", format!("`recur` expected {} values, got {}", expected, got), Some(span)),
+        &BindingError::RecurNotInTail(span) =>
+            error_at("E0105", "`recur` must produce the value of its loop".to_string(), Some(span)),
+        &BindingError::DefineNotAllowedHere(span) =>
+            error_at("E0106", "definitions are only allowed as statements".to_string(), Some(span))
+                .with_note("use `letrec` to define names inside of an expression", None),
+        &BindingError::DecimalsUnsupported(span) =>
+            error_at("E0107", "decimal literals aren't supported".to_string(), Some(span))
+                .with_note("the vm was built without the `decimal` feature", None),
+        &BindingError::DecimalOutOfRange(span) =>
+            error_at("E0108", "decimal literal has too many digits".to_string(), Some(span)),
+        &BindingError::Multiple(ref errors) => {
+            for e in errors {
+                binding_diagnostics(e, symbol_name, out);
+            }
+            return;
+        }
+    });
+}
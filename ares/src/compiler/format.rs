@@ -0,0 +1,55 @@
+use typed_arena::Arena;
+use ares_syntax::{SymbolIntern, format_program_source};
+use compiler::parse::{self, Ast, ParseError};
+
+/// Parses `source` and prints it back out with canonical indentation.
+///
+/// Forms that are commented out with `#;` are kept as they were written.
+pub fn format_source(source: &str) -> Result<String, ParseError> {
+    let arena: Arena<Ast> = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let asts = parse::parse(source, &mut interner, &arena)?;
+    // The parser only makes syntax that can be printed.
+    let formatted = format_program_source(&asts, &interner, source).expect("parsed syntax prints");
+    // The parser skips the `#!` line that can start a script, so it is
+    // copied over as it was.
+    if source.starts_with("#!") {
+        let end = source.find('\n').unwrap_or(source.len());
+        Ok(format!("{}\n{}", &source[.. end], formatted))
+    } else {
+        Ok(formatted)
+    }
+}
+
+#[test]
+fn formatting_is_idempotent() {
+    let formatted = format_source("var a = [1,2,  3];fn(x){x+a[0]}(5)").unwrap();
+    assert_eq!(formatted, "var a = [1, 2, 3];\nfn(x) {\n    x + a[0]\n}(5);\n");
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+    assert!(format_source("var = ;").is_err());
+}
+
+#[test]
+fn datum_comments_are_kept() {
+    let formatted = format_source("#;print(1);\nvar a = [1,#;2,  3];f(a, #;g(b));\nfn(){#;var y=1;  2; #;3;}").unwrap();
+    assert_eq!(formatted, "\
+#;print(1);
+var a = [1, #;2, 3];
+f(a, #;g(b));
+fn() {
+    #;var y=1;
+    2;
+    #;3;
+};
+");
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+    assert_eq!(format_source("1;\n#;2;  \n").unwrap(), "1;\n#;2;\n");
+}
+
+#[test]
+fn shebangs_are_kept() {
+    let formatted = format_source("#!/usr/bin/env ares run\nprint( 1 );").unwrap();
+    assert_eq!(formatted, "#!/usr/bin/env ares run\nprint(1);\n");
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+    assert_eq!(format_source("#!ares").unwrap(), "#!ares\n");
+}
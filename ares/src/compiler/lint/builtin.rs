@@ -0,0 +1,392 @@
+use util::collections::HashMap;
+
+use ares_syntax::{Span, Symbol};
+use compiler::Warning;
+use compiler::binding::{Bound, BoundRef, BoundVisitor, SymbolBindSource, walk_bound};
+use compiler::lint::{Lint, LintContext};
+use compiler::parse::Ast;
+
+fn span_of(bound: &Bound) -> Span {
+    bound.span().unwrap_or_else(Span::dummy)
+}
+
+/// Warns about arguments and local variables that are never read.
+/// Globals can be used by code that hasn't been compiled yet, so they
+/// are never reported.
+pub struct UnusedBinding;
+
+impl Lint for UnusedBinding {
+    fn name(&self) -> &'static str {
+        "unused_binding"
+    }
+
+    fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext) {
+        let mut visitor = UnusedVisitor { lambdas: vec![], cx: cx };
+        for form in forms {
+            visitor.visit(form);
+        }
+    }
+}
+
+struct UnusedVisitor<'c, 'a: 'c> {
+    // The bindings defined in each enclosing lambda, innermost last.
+    lambdas: Vec<Vec<(Symbol, Span, SymbolBindSource)>>,
+    cx: &'c mut LintContext<'a>,
+}
+
+impl<'c, 'a: 'c> UnusedVisitor<'c, 'a> {
+    fn define(&mut self, name: Symbol, span: Span, source: &SymbolBindSource) {
+        if let Some(lambda) = self.lambdas.last_mut() {
+            lambda.push((name, span, source.clone()));
+        }
+    }
+}
+
+impl<'c, 'a: 'c, 'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for UnusedVisitor<'c, 'a> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Lambda { ref arg_symbols, ref bindings, body, ast, .. } => {
+                let args = arg_symbols.iter().map(|&arg| (arg, ast.span(), bindings.bindings[&arg].clone()));
+                self.lambdas.push(args.collect());
+                self.visit(body);
+                for (name, span, source) in self.lambdas.pop().unwrap() {
+                    if source.is_used() {
+                        continue;
+                    }
+                    self.cx.warn(match source {
+                        SymbolBindSource::Arg{..} => Warning::UnusedArgument { name: name, span: span },
+                        _ => Warning::UnusedVariable { name: name, span: span },
+                    });
+                }
+            }
+            &Bound::Define(name, ref source, value, ast) => {
+                self.define(name, ast.span(), source);
+                self.visit(value);
+            }
+            &Bound::Loop { ref variables, body, ast } => {
+                for &(_, _, init) in variables {
+                    self.visit(init);
+                }
+                for &(name, ref source, _) in variables {
+                    self.define(name, ast.span(), source);
+                }
+                self.visit(body);
+            }
+            &Bound::Letrec { ref bindings, body, ast } => {
+                for &(name, ref source, _) in bindings {
+                    self.define(name, ast.span(), source);
+                }
+                for &(_, _, value) in bindings {
+                    self.visit(value);
+                }
+                self.visit(body);
+            }
+            &Bound::NamedLet { name, ref source, function, ref inits, ast } => {
+                for init in inits {
+                    self.visit(init);
+                }
+                self.define(name, ast.span(), source);
+                self.visit(function);
+            }
+            // The names that an import defines are for the rest of the
+            // program, whether or not this part of it uses them.
+            &Bound::Import { .. } => {}
+            _ => walk_bound(self, bound),
+        }
+    }
+}
+
+/// Warns about definitions that mask a variable from an outer scope.
+/// Off by default, since shadowing is allowed.
+pub struct Shadowing;
+
+impl Lint for Shadowing {
+    fn name(&self) -> &'static str {
+        "shadowing"
+    }
+
+    fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext) {
+        let mut visitor = ShadowingVisitor {
+            scopes: vec![HashMap::new()],
+            lambda_depth: 0,
+            cx: cx,
+        };
+        for form in forms {
+            visitor.visit(form);
+        }
+    }
+}
+
+struct ShadowingVisitor<'c, 'a: 'c> {
+    // Where each name in scope was defined, innermost scope last.  The
+    // first scope is the top level.
+    scopes: Vec<HashMap<Symbol, Span>>,
+    lambda_depth: u32,
+    cx: &'c mut LintContext<'a>,
+}
+
+impl<'c, 'a: 'c> ShadowingVisitor<'c, 'a> {
+    fn define(&mut self, name: Symbol, span: Span) {
+        self.scopes.last_mut().unwrap().insert(name, span);
+        // Anything that isn't inside of a lambda stays around for the
+        // rest of the program, like a global would.
+        if self.lambda_depth == 0 {
+            self.scopes[0].insert(name, span);
+        }
+    }
+
+    fn scoped<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'c, 'a: 'c, 'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for ShadowingVisitor<'c, 'a> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Lambda { ref arg_symbols, body, ast, .. } => {
+                self.lambda_depth += 1;
+                self.scoped(|this| {
+                    for &arg in arg_symbols {
+                        this.define(arg, ast.span());
+                    }
+                    this.visit(body);
+                });
+                self.lambda_depth -= 1;
+            }
+            &Bound::BlockExpression(..) |
+            &Bound::BlockStatement(..) => self.scoped(|this| walk_bound(this, bound)),
+            &Bound::Define(name, _, value, ast) => {
+                let original = self.scopes.iter().rev().filter_map(|scope| scope.get(&name)).next().cloned();
+                if let Some(original) = original {
+                    self.cx.warn(Warning::Shadowing {
+                        name: name,
+                        span: ast.span(),
+                        original: original,
+                    });
+                }
+                self.define(name, ast.span());
+                self.visit(value);
+            }
+            &Bound::Loop { ref variables, body, ast } => {
+                for &(_, _, init) in variables {
+                    self.visit(init);
+                }
+                self.scoped(|this| {
+                    for &(name, _, _) in variables {
+                        this.define(name, ast.span());
+                    }
+                    this.visit(body);
+                });
+            }
+            &Bound::Letrec { ref bindings, body, ast } => {
+                self.scoped(|this| {
+                    for &(name, _, _) in bindings {
+                        this.define(name, ast.span());
+                    }
+                    for &(_, _, value) in bindings {
+                        this.visit(value);
+                    }
+                    this.visit(body);
+                });
+            }
+            &Bound::NamedLet { name, function, ref inits, ast, .. } => {
+                for init in inits {
+                    self.visit(init);
+                }
+                self.scoped(|this| {
+                    this.define(name, ast.span());
+                    this.visit(function);
+                });
+            }
+            &Bound::Import { .. } => {}
+            _ => walk_bound(self, bound),
+        }
+    }
+}
+
+/// Warns about an `if` whose condition is `true` or `false`, so that one
+/// of its branches can never run.
+pub struct ConstantCondition;
+
+impl Lint for ConstantCondition {
+    fn name(&self) -> &'static str {
+        "constant_condition"
+    }
+
+    fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext) {
+        let mut visitor = ConstantConditionVisitor(cx);
+        for form in forms {
+            visitor.visit(form);
+        }
+    }
+}
+
+struct ConstantConditionVisitor<'c, 'a: 'c>(&'c mut LintContext<'a>);
+
+impl<'c, 'a: 'c, 'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for ConstantConditionVisitor<'c, 'a> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::IfExpression(condition, _, _, _) |
+            &Bound::IfStatement(condition, _, _, _) => {
+                if let &Bound::Literal(&Ast::BoolLit(value, span)) = condition {
+                    self.0.warn(Warning::ConstantCondition { value: value, span: span });
+                }
+            }
+            _ => {}
+        }
+        walk_bound(self, bound);
+    }
+}
+
+/// Warns about blocks with nothing in them, like `if x then {}`.  The empty
+/// body of a lambda, as in `fn() {}`, is a common way to do nothing, so
+/// it is left alone.
+pub struct EmptyBlock;
+
+impl Lint for EmptyBlock {
+    fn name(&self) -> &'static str {
+        "empty_block"
+    }
+
+    fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext) {
+        let mut visitor = EmptyBlockVisitor(cx);
+        for form in forms {
+            visitor.visit(form);
+        }
+    }
+}
+
+fn is_empty_block(bound: &Bound) -> bool {
+    match bound {
+        &Bound::BlockExpression(ref statements, _) |
+        &Bound::BlockStatement(ref statements, _) => statements.is_empty(),
+        _ => false,
+    }
+}
+
+struct EmptyBlockVisitor<'c, 'a: 'c>(&'c mut LintContext<'a>);
+
+impl<'c, 'a: 'c, 'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for EmptyBlockVisitor<'c, 'a> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Lambda { body, .. } if is_empty_block(body) => return,
+            _ if is_empty_block(bound) => self.0.warn(Warning::EmptyBlock { span: span_of(bound) }),
+            _ => {}
+        }
+        walk_bound(self, bound);
+    }
+}
+
+/// Warns about `==` and `!=` with a lambda that is written right there.
+/// Lambdas are never equal to anything, so the result is always known.
+pub struct LambdaComparison;
+
+impl Lint for LambdaComparison {
+    fn name(&self) -> &'static str {
+        "lambda_comparison"
+    }
+
+    fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext) {
+        let mut visitor = LambdaComparisonVisitor(cx);
+        for form in forms {
+            visitor.visit(form);
+        }
+    }
+}
+
+struct LambdaComparisonVisitor<'c, 'a: 'c>(&'c mut LintContext<'a>);
+
+impl<'c, 'a: 'c, 'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for LambdaComparisonVisitor<'c, 'a> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Equal(l, r, _) |
+            &Bound::NotEqual(l, r, _) => {
+                let is_lambda = |b: &Bound| match b {
+                    &Bound::Lambda { .. } => true,
+                    _ => false,
+                };
+                if is_lambda(l) || is_lambda(r) {
+                    self.0.warn(Warning::LambdaComparison { span: span_of(bound) });
+                }
+            }
+            _ => {}
+        }
+        walk_bound(self, bound);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use compiler::{Diagnostics, Warning};
+    use compiler::binding::{Bound, BoundRef};
+    use compiler::lint::{Lint, LintContext};
+    use compiler::binding::test::ok_bind_1_full;
+
+    fn warnings(program: &str) -> Vec<Warning> {
+        let mut diagnostics = Diagnostics::new();
+        ok_bind_1_full(program, &mut diagnostics);
+        diagnostics.warnings().to_vec()
+    }
+
+    #[test]
+    fn constant_conditions() {
+        match &warnings("fn(x) { if true then x else 1 }")[..] {
+            &[Warning::ConstantCondition { value: true, .. }] => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(warnings("fn(x) { if x then 1 else 2 }").is_empty());
+    }
+
+    #[test]
+    fn empty_blocks() {
+        match &warnings("fn(x) { if x then { } }")[..] {
+            &[Warning::EmptyBlock { .. }] => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(warnings("fn() { }").is_empty());
+    }
+
+    #[test]
+    fn lambda_comparisons() {
+        match &warnings("fn(f) { f == fn() { 1 } }")[..] {
+            &[Warning::LambdaComparison { .. }] => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(warnings("fn(f, g) { f == g }").is_empty());
+    }
+
+    struct NoLongNames;
+
+    impl Lint for NoLongNames {
+        fn name(&self) -> &'static str {
+            "no_long_names"
+        }
+
+        fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext) {
+            for form in forms {
+                if let &&Bound::Define(name, _, _, ast) = form {
+                    if cx.interner().lookup_or_anon(name).len() > 5 {
+                        cx.report("name is too long", ast.span());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn registered_lints_run() {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.lints_mut().register(NoLongNames);
+        diagnostics.lints_mut().set_enabled("empty_block", false);
+        ok_bind_1_full("var abcdefg = fn(x) { if false then { } x };", &mut diagnostics);
+
+        let lints: Vec<_> = diagnostics.warnings().iter().map(|w| match w {
+            &Warning::ConstantCondition { .. } => "constant_condition",
+            &Warning::Lint { lint, .. } => lint,
+            other => panic!("unexpected {:?}", other),
+        }).collect();
+        assert_eq!(lints, vec!["constant_condition", "no_long_names"]);
+    }
+}
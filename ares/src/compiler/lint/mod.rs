@@ -0,0 +1,132 @@
+use std::fmt;
+use util::collections::HashSet;
+
+use ares_syntax::{Span, SymbolIntern};
+use compiler::Warning;
+use compiler::binding::BoundRef;
+
+mod builtin;
+
+pub use self::builtin::{Shadowing, UnusedBinding, ConstantCondition, EmptyBlock, LambdaComparison};
+
+/// A check that runs over every program after it is bound, and warns
+/// about code that is allowed but probably a mistake.
+///
+/// Implement this and add it to a `LintRegistry` to check for problems
+/// that are specific to a project.
+pub trait Lint {
+    /// The name that the lint is turned on and off by, like `empty_block`.
+    fn name(&self) -> &'static str;
+
+    /// Checks the top level forms of a program, warning through `cx`.
+    fn check<'bound, 'ast: 'bound>(&mut self, forms: &[BoundRef<'bound, 'ast>], cx: &mut LintContext);
+}
+
+/// Lets a `Lint` report what it finds.
+pub struct LintContext<'a> {
+    name: &'static str,
+    interner: &'a SymbolIntern,
+    warnings: &'a mut Vec<Warning>,
+}
+
+impl<'a> LintContext<'a> {
+    pub fn interner(&self) -> &SymbolIntern {
+        self.interner
+    }
+
+    /// Reports a problem as a `Warning::Lint` with the name of the lint
+    /// that found it.
+    pub fn report<M: Into<String>>(&mut self, message: M, span: Span) {
+        let warning = Warning::Lint {
+            lint: self.name,
+            message: message.into(),
+            span: span,
+        };
+        self.warnings.push(warning);
+    }
+
+    /// Reports a warning that has its own variant.
+    pub fn warn(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+}
+
+/// The lints that run when a program is compiled.  It starts out with the
+/// built in lints, all of which are on other than `shadowing`.
+pub struct LintRegistry {
+    lints: Vec<Box<Lint>>,
+    disabled: HashSet<String>,
+}
+
+impl LintRegistry {
+    pub fn new() -> LintRegistry {
+        let mut registry = LintRegistry {
+            lints: vec![],
+            disabled: HashSet::new(),
+        };
+        registry.register(Shadowing);
+        registry.register(UnusedBinding);
+        registry.register(ConstantCondition);
+        registry.register(EmptyBlock);
+        registry.register(LambdaComparison);
+        // Shadowing is allowed, so warnings about it are off by default.
+        registry.set_enabled("shadowing", false);
+        registry
+    }
+
+    /// Adds a lint that runs after the ones that are already registered.
+    pub fn register<L: Lint + 'static>(&mut self, lint: L) {
+        self.lints.push(Box::new(lint));
+    }
+
+    /// Turns the lint called `name` on or off.  Lints can be turned off
+    /// before they are registered.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(name);
+        } else {
+            self.disabled.insert(name.to_string());
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.contains(name)
+    }
+
+    /// The names of every registered lint, in the order that they run.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.lints.iter().map(|lint| lint.name()).collect()
+    }
+
+    pub(crate) fn run<'bound, 'ast: 'bound>(&mut self,
+                                            forms: &[BoundRef<'bound, 'ast>],
+                                            interner: &SymbolIntern,
+                                            warnings: &mut Vec<Warning>) {
+        for lint in &mut self.lints {
+            if self.disabled.contains(lint.name()) {
+                continue;
+            }
+            let mut cx = LintContext {
+                name: lint.name(),
+                interner: interner,
+                warnings: &mut *warnings,
+            };
+            lint.check(forms, &mut cx);
+        }
+    }
+}
+
+impl Default for LintRegistry {
+    fn default() -> LintRegistry {
+        LintRegistry::new()
+    }
+}
+
+impl fmt::Debug for LintRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LintRegistry")
+         .field("lints", &self.names())
+         .field("disabled", &self.disabled)
+         .finish()
+    }
+}
@@ -3,6 +3,11 @@ pub(crate) mod emit;
 mod error;
 pub(crate) mod compile_context;
 pub(crate) mod binding;
+mod data;
+mod diagnostics;
+mod format;
+mod lint;
+mod program;
 
 
 use typed_arena;
@@ -11,24 +16,80 @@ use compiler::emit::EmitBuffer;
 use ares_syntax::SymbolIntern;
 use vm::{Instr, Modules};
 
-pub use self::compile_context::{CompileContext, ShiftMeta};
+pub use self::compile_context::{CompileContext, CompileOptions, ConstantPoolStats, ShiftMeta};
+pub use self::diagnostics::{Diagnostic, Diagnostics, Note, Severity, Warning, diagnostics_to_json};
+pub use self::binding::{Type, Bound, BoundRef, BoundVisitor, BoundFolder, LambdaBindings, SymbolBindSource,
+                        walk_bound, fold_bound};
+pub use self::lint::{Lint, LintContext, LintRegistry, Shadowing, UnusedBinding, ConstantCondition,
+                     EmptyBlock, LambdaComparison};
+pub use self::format::format_source;
+pub use self::data::{DataError, ast_to_value, value_to_ast};
+pub use self::parse::{Edit, FormIter, ReadError, parse_iter, parse_reader, reparse};
+pub use self::program::{CompiledProgram, Constant, ProgramDecodeError, CompileFileError,
+                        compile_to_bytes, compile_file_to_bytes};
 
 pub fn compile(source: &str,
                compile_context: &mut CompileContext,
                modules: Option<&Modules>,
                interner: &mut SymbolIntern,
+               diagnostics: &mut Diagnostics,
                emit_offset: usize)
                -> Result<Vec<Instr>, CompileError> {
 
     let ast_arena: typed_arena::Arena<parse::Ast> = typed_arena::Arena::new();
     let bound_arena: typed_arena::Arena<binding::Bound> = typed_arena::Arena::new();
 
+    let log_phases = compile_context.options().log_phases;
+
+    if log_phases {
+        debug!(target: "ares::compile", "tokens produced: {}", ::ares_syntax::tokenize(source).count());
+    }
+
     let mut out = EmitBuffer::new(emit_offset);
-    let asts: Vec<parse::Ast> = try!(parse::parse(source, interner, &ast_arena));
+    let max_depth = compile_context.options().max_nesting_depth;
+    let asts: Vec<parse::Ast> = try!(parse::parse_with_max_depth(source, interner, &ast_arena, max_depth));
+    if log_phases {
+        debug!(target: "ares::compile", "forms parsed: {}", asts.len());
+    }
+
     let asts: Vec<&parse::Ast> = asts.into_iter().map(|a| ast_arena.alloc(a) as &_).collect();
-    let bounds = try!(binding::Bound::bind_top(&asts, &bound_arena, modules, interner));
+    let bounds = try!(binding::Bound::bind_top(&asts, &bound_arena, modules, interner, diagnostics));
+    if log_phases {
+        debug!(target: "ares::compile", "bindings resolved: {} forms, {} warnings",
+               bounds.len(), diagnostics.warnings().len());
+    }
+    if diagnostics.check_types() {
+        binding::check_types(&bounds, interner, diagnostics);
+    }
 
     try!(emit::emit_all(bounds, compile_context, interner, &mut out, None));
+    compile_context.locate_closures(source);
+
+    let instructions = out.into_instructions();
+    if log_phases {
+        debug!(target: "ares::compile", "instructions emitted: {}", instructions.len());
+    }
+    Ok(instructions)
+}
 
-    Ok(out.into_instructions())
+/// Parses and binds `source` like `compile` does, and checks its types if
+/// `diagnostics` asks for that, but emits nothing.  Whatever is wrong with
+/// the program is the error, and warnings end up in `diagnostics`.
+pub fn check(source: &str,
+             compile_context: &CompileContext,
+             modules: Option<&Modules>,
+             interner: &mut SymbolIntern,
+             diagnostics: &mut Diagnostics)
+             -> Result<(), CompileError> {
+    let ast_arena: typed_arena::Arena<parse::Ast> = typed_arena::Arena::new();
+    let bound_arena: typed_arena::Arena<binding::Bound> = typed_arena::Arena::new();
+
+    let max_depth = compile_context.options().max_nesting_depth;
+    let asts: Vec<parse::Ast> = try!(parse::parse_with_max_depth(source, interner, &ast_arena, max_depth));
+    let asts: Vec<&parse::Ast> = asts.into_iter().map(|a| ast_arena.alloc(a) as &_).collect();
+    let bounds = try!(binding::Bound::bind_top(&asts, &bound_arena, modules, interner, diagnostics));
+    if diagnostics.check_types() {
+        binding::check_types(&bounds, interner, diagnostics);
+    }
+    Ok(())
 }
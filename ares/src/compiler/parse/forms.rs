@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+
+use ares_syntax::SymbolIntern;
+use typed_arena::Arena;
+
+use super::{Ast, AstRef, ParseError};
+use super::reader::{FormScanner, parse_form};
+
+/// The top-level forms of some source, parsed one at a time as they are
+/// asked for.  Made by `parse_iter`.
+///
+/// After an error there are no more forms.
+pub struct FormIter<'s, 'i, 'a> {
+    source: &'s str,
+    // How far into `source` the forms so far go
+    position: usize,
+    interner: &'i mut SymbolIntern,
+    arena: &'a Arena<Ast<'a>>,
+    parsed: VecDeque<Ast<'a>>,
+    done: bool,
+}
+
+/// Parses the forms of `source` lazily, so that a runner can evaluate
+/// each one before the next is parsed, and stop at the first one that
+/// fails without parsing the rest of the file.  The forms are the same as
+/// the ones that `parse` returns.
+pub fn parse_iter<'s, 'i, 'a>(source: &'s str, interner: &'i mut SymbolIntern, arena: &'a Arena<Ast<'a>>)
+-> FormIter<'s, 'i, 'a> {
+    FormIter {
+        source: source,
+        position: 0,
+        interner: interner,
+        arena: arena,
+        parsed: VecDeque::new(),
+        done: false,
+    }
+}
+
+impl <'s, 'i, 'a> Iterator for FormIter<'s, 'i, 'a> {
+    type Item = Result<AstRef<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Result<AstRef<'a>, ParseError>> {
+        while self.parsed.is_empty() && !self.done {
+            let rest = &self.source[self.position ..];
+            let form = match FormScanner::new().scan(rest, false, self.interner, self.arena) {
+                Some(form) => form,
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+            match parse_form(form, rest, self.position, self.interner, self.arena) {
+                Ok((end, asts)) => {
+                    self.position += end;
+                    self.parsed.extend(asts);
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.parsed.pop_front().map(|ast| Ok(&*self.arena.alloc(ast)))
+    }
+}
+
+#[test]
+fn forms_are_parsed_lazily() {
+    use super::parse;
+
+    let source = "var x = 1;\nif x then { print(x); }\nx = x + 1;\n[x]";
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let expected = parse(source, &mut interner, &arena).unwrap();
+    let forms: Vec<Ast> = parse_iter(source, &mut interner, &arena).map(|ast| ast.unwrap().clone()).collect();
+    assert_eq!(forms, expected);
+
+    // Nothing after the first form is parsed until it is asked for.
+    let mut forms = parse_iter("print(1);\nvar = ;\nprint(2);", &mut interner, &arena);
+    assert!(forms.next().unwrap().is_ok());
+    match forms.next() {
+        Some(Err(ParseError::UnrecognizedToken { token: Some((start, _, _)), .. })) => assert_eq!(start, 14),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+    assert!(forms.next().is_none());
+}
@@ -1,9 +1,19 @@
+use std::borrow::Cow;
+
 pub use ares_syntax::{Span, Ast, AstRef};
-use ares_syntax::SymbolIntern;
-use ares_syntax::parse_Program;
+use ares_syntax::{ConversionError, SymbolIntern};
+use ares_syntax::{parse_Program, tokenize, TokenKind, TriviaKind};
 use typed_arena::Arena;
 use lalrpop_util;
 
+mod forms;
+mod reader;
+mod reparse;
+
+pub use self::forms::{FormIter, parse_iter};
+pub use self::reader::{ReadError, parse_reader};
+pub use self::reparse::{Edit, reparse};
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum ParseError {
    InvalidToken {
@@ -12,14 +22,63 @@ pub enum ParseError {
     UnrecognizedToken {
         token: Option<(usize, (usize, String), usize)>,
         expected: Vec<String>,
+        /// The innermost `(`, `[` or `{` that was still open where
+        /// parsing stopped, with its byte offset.
+        open: Option<(usize, char)>,
     },
     ExtraToken {
         token: (usize, (usize, String), usize),
     },
+    /// Brackets are nested more deeply than the limit.  The span is the
+    /// bracket that went past it.
+    TooDeep(Span),
+    /// A number literal that doesn't stand for a number, like `1e`.
+    InvalidNumber(ConversionError),
+}
+
+impl ParseError {
+    // The same error for a piece of source that starts `base` bytes into
+    // the source that is reported on.
+    fn offset_by(self, base: usize) -> ParseError {
+        let token = |(a, (c, s), b): (usize, (usize, String), usize)| (a + base, (c, s), b + base);
+        match self {
+            ParseError::InvalidToken { location } => ParseError::InvalidToken { location: location + base },
+            ParseError::UnrecognizedToken { token: t, expected, open } => ParseError::UnrecognizedToken {
+                token: t.map(&token),
+                expected: expected,
+                open: open.map(|(offset, c)| (offset + base, c)),
+            },
+            ParseError::ExtraToken { token: t } => ParseError::ExtraToken { token: token(t) },
+            ParseError::TooDeep(span) =>
+                ParseError::TooDeep(Span::new(span.start() + base as u32, span.end() + base as u32)),
+            ParseError::InvalidNumber(e) => {
+                let span = |span: Span| Span::new(span.start() + base as u32, span.end() + base as u32);
+                ParseError::InvalidNumber(match e {
+                    ConversionError::IntegerOverflow(s) => ConversionError::IntegerOverflow(span(s)),
+                    ConversionError::FloatOverflow(s) => ConversionError::FloatOverflow(span(s)),
+                    ConversionError::MalformedExponent(s) => ConversionError::MalformedExponent(span(s)),
+                })
+            }
+        }
+    }
 }
 
+/// The nesting limit used by `parse`.
+pub const DEFAULT_MAX_NESTING_DEPTH: u32 = 256;
+
 pub fn parse<'a>(program: &str, interner: &mut SymbolIntern, arena: &'a Arena<Ast<'a>>)
 -> Result<Vec<Ast<'a>>, ParseError> {
+    parse_with_max_depth(program, interner, arena, DEFAULT_MAX_NESTING_DEPTH)
+}
+
+/// Like `parse`, but rejects programs whose brackets are nested more than
+/// `max_depth` deep.
+pub fn parse_with_max_depth<'a>(program: &str, interner: &mut SymbolIntern, arena: &'a Arena<Ast<'a>>,
+                                max_depth: u32)
+-> Result<Vec<Ast<'a>>, ParseError> {
+    check_depth(program, max_depth)?;
+    let blanked = without_shebang(program);
+    let program = &blanked[..];
     match parse_Program(arena, interner, program) {
         Ok(ast) => Ok(ast),
         Err(e) => {
@@ -29,20 +88,210 @@ pub fn parse<'a>(program: &str, interner: &mut SymbolIntern, arena: &'a Arena<As
                 lalrpop_util::ParseError::UnrecognizedToken{token: Some((a, (c, s), b)), expected} =>
                     ParseError::UnrecognizedToken {
                         token: Some((a, (c, s.into()), b)),
-                        expected: expected
+                        expected: expected,
+                        open: open_delimiter(program, a),
                     },
                 lalrpop_util::ParseError::UnrecognizedToken{token: None, expected} =>
-                    ParseError::UnrecognizedToken{token: None, expected: expected},
+                    ParseError::UnrecognizedToken {
+                        token: None,
+                        expected: expected,
+                        open: open_delimiter(program, program.len()),
+                    },
                 lalrpop_util::ParseError::ExtraToken{token: (a, (c, s), b)} =>
                     ParseError::ExtraToken {
                         token: (a, (c, s.into()), b)
                     },
-                lalrpop_util::ParseError::User{..} => unreachable!()
+                lalrpop_util::ParseError::User{error} =>
+                    ParseError::InvalidNumber(error),
             })
         }
     }
 }
 
+// Later phases recurse over the tree, so the depth is checked before
+// anything is built.
+fn check_depth(program: &str, max_depth: u32) -> Result<(), ParseError> {
+    let mut depth = 0u32;
+    for token in tokenize(program) {
+        if token.kind != TokenKind::Punctuation {
+            continue;
+        }
+        match token.text {
+            "(" | "[" | "{" => {
+                depth += 1;
+                if depth > max_depth {
+                    return Err(ParseError::TooDeep(token.span));
+                }
+            }
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// `program` with its shebang line replaced by spaces, so that the parser
+/// never sees it.  Offsets are the same in both.
+pub(crate) fn without_shebang(program: &str) -> Cow<str> {
+    let shebang = tokenize(program).next().and_then(|token| {
+        token.leading_trivia.iter().find(|trivia| trivia.kind == TriviaKind::Shebang).map(|trivia| trivia.span.end() as usize)
+    });
+    match shebang {
+        Some(end) => {
+            let mut blanked = " ".repeat(end);
+            blanked.push_str(&program[end ..]);
+            Cow::Owned(blanked)
+        }
+        None => Cow::Borrowed(program),
+    }
+}
+
+/// The delimiter that closes `open`.
+pub fn closing_delimiter(open: char) -> char {
+    match open {
+        '(' => ')',
+        '[' => ']',
+        '{' => '}',
+        other => other,
+    }
+}
+
+/// The delimiter that `close` closes.
+pub fn opening_delimiter(close: char) -> char {
+    match close {
+        ')' => '(',
+        ']' => '[',
+        '}' => '{',
+        other => other,
+    }
+}
+
+// The innermost delimiter that is still open at byte offset `end`.  A
+// closing delimiter that doesn't match the innermost one is skipped, so
+// the opener that it should have closed stays open.
+fn open_delimiter(program: &str, end: usize) -> Option<(usize, char)> {
+    let mut open: Vec<(usize, char)> = vec![];
+    for token in tokenize(program) {
+        if token.span.start() as usize >= end {
+            break;
+        }
+        if token.kind != TokenKind::Punctuation {
+            continue;
+        }
+        let c = token.text.chars().next().unwrap();
+        match c {
+            '(' | '[' | '{' => open.push((token.span.start() as usize, c)),
+            ')' | ']' | '}' if open.last().map(|&(_, o)| closing_delimiter(o)) == Some(c) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.pop()
+}
+
+#[test]
+fn errors_find_open_delimiters() {
+    let parse_error = |program: &str| {
+        let arena = Arena::new();
+        parse(program, &mut SymbolIntern::new(), &arena).unwrap_err()
+    };
+
+    match parse_error("f(1, [2, 3)") {
+        ParseError::UnrecognizedToken { open, .. } => assert_eq!(open, Some((5, '['))),
+        other => panic!("expected UnrecognizedToken, got {:?}", other),
+    }
+    match parse_error("fn() {\n  g((1)\n") {
+        ParseError::UnrecognizedToken { token: None, open, .. } => assert_eq!(open, Some((10, '('))),
+        other => panic!("expected UnrecognizedToken, got {:?}", other),
+    }
+}
+
+#[test]
+fn datum_comments_skip_a_form() {
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let cases = [
+        ("#;print(1);\nprint(2);", "print(2);"),
+        ("[1, #;2, 3]", "[1, 3]"),
+        ("f(a, #;g(b))", "f(a)"),
+        ("#;#;a; b;\nc;", "c;"),
+        ("f(#;a, b, #;c,)", "f(b)"),
+        ("fn() { #;var y = 1; 2 }", "fn() { 2 }"),
+        ("{ #;{ a; } }", "{ }"),
+    ];
+    for &(commented, plain) in &cases {
+        let commented_asts = parse(commented, &mut interner, &arena).unwrap();
+        let plain_asts = parse(plain, &mut interner, &arena).unwrap();
+        assert_eq!(commented_asts.len(), plain_asts.len(), "{}", commented);
+        for (a, b) in commented_asts.iter().zip(&plain_asts) {
+            assert!(a.equals_sans_span(b), "{}", commented);
+        }
+    }
+
+    assert_eq!(parse("#;print(1);\n2", &mut interner, &arena).unwrap()[0].span(), Span::new(12, 13));
+    assert!(parse("1; #;", &mut interner, &arena).is_err());
+    assert!(parse("1 + #;2 3;", &mut interner, &arena).is_err());
+}
+
+#[test]
+fn shebangs_are_skipped() {
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let asts = parse("#!/usr/bin/env ares run\nprint(1);", &mut interner, &arena).unwrap();
+    assert_eq!(asts.len(), 1);
+    assert_eq!(asts[0].span(), Span::new(24, 32));
+    assert_eq!(parse("1;\n#!x", &mut interner, &arena).unwrap_err(), ParseError::InvalidToken { location: 3 });
+}
+
+#[test]
+fn number_literals() {
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let asts = parse("1_000_000; 1e9; 2.5e-3; 1_0.5d;", &mut interner, &arena).unwrap();
+    assert_eq!(asts[0], Ast::IntLit(1000000, Span::new(0, 9)));
+    assert_eq!(asts[1], Ast::FloatLit(1e9, Span::new(11, 14)));
+    assert_eq!(asts[2], Ast::FloatLit(2.5e-3, Span::new(16, 22)));
+    assert_eq!(asts[3], Ast::DecimalLit("10.5".into(), Span::new(24, 30)));
+    assert_eq!(asts[3].to_source(&interner).unwrap(), "10.5d");
+
+    assert_eq!(parse("var x = 2.5e-;", &mut interner, &arena).unwrap_err(),
+               ParseError::InvalidNumber(ConversionError::MalformedExponent(Span::new(8, 13))));
+    assert_eq!(parse("99999999999999999999", &mut interner, &arena).unwrap_err(),
+               ParseError::InvalidNumber(ConversionError::IntegerOverflow(Span::new(0, 20))));
+}
+
+#[test]
+fn adjacent_strings_are_joined() {
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let asts = parse("var s = \"a\\\"\"\n        \"bc\" \"\";", &mut interner, &arena).unwrap();
+    match &asts[0] {
+        &Ast::Define(_, value, _) => assert_eq!(*value, Ast::StringLit("a\\\"bc".into(), Span::new(8, 29))),
+        other => panic!("expected a define, got {:?}", other),
+    }
+
+    // Quoted data keeps its strings apart.
+    let asts = parse("'(\"a\" \"b\")", &mut interner, &arena).unwrap();
+    match &asts[0] {
+        &Ast::Quote(&Ast::ListLit(ref items, _), _) => assert_eq!(items.len(), 2),
+        other => panic!("expected quoted data, got {:?}", other),
+    }
+}
+
+#[test]
+fn nesting_is_limited() {
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+    assert!(parse_with_max_depth(&nested(10), &mut interner, &arena, 10).is_ok());
+    assert_eq!(parse_with_max_depth(&nested(11), &mut interner, &arena, 10).unwrap_err(),
+               ParseError::TooDeep(Span::new(10, 11)));
+    assert_eq!(parse(&nested(100000), &mut interner, &arena).unwrap_err(),
+               ParseError::TooDeep(Span::new(256, 257)));
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
@@ -0,0 +1,226 @@
+//! Parsing source that arrives a piece at a time, from a pipe or a file
+//! too large to want in memory all at once.
+//!
+//! The source is split into top-level forms as it is tokenized, and each
+//! form is parsed as soon as it is complete, so only the source of the
+//! form being read is held onto.
+
+use std::io::{self, Read};
+use std::str;
+
+use ares_syntax::{SymbolIntern, TokenIter, TokenKind, parse_Statement};
+use typed_arena::Arena;
+
+use super::{Ast, ParseError, DEFAULT_MAX_NESTING_DEPTH, check_depth, parse, without_shebang};
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> ReadError {
+        ReadError::Io(e)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(e: ParseError) -> ReadError {
+        ReadError::Parse(e)
+    }
+}
+
+/// Parses everything that `reader` produces, like `parse`.  Spans and
+/// the offsets in errors are from the start of the stream.
+pub fn parse_reader<'a, R: Read>(mut reader: R, interner: &mut SymbolIntern, arena: &'a Arena<Ast<'a>>)
+-> Result<Vec<Ast<'a>>, ReadError> {
+    let mut asts = vec![];
+    let mut chunk = [0; CHUNK_SIZE];
+    // Bytes that were read but end partway through a character.
+    let mut undecoded = vec![];
+    // The source of the form being read, which starts `base` bytes into
+    // the stream.
+    let mut source = String::new();
+    let mut base = 0;
+    let mut scanner = FormScanner::new();
+
+    let mut more = true;
+    while more {
+        let read = match reader.read(&mut chunk) {
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        more = read != 0;
+        undecoded.extend_from_slice(&chunk[.. read]);
+        let valid = match str::from_utf8(&undecoded) {
+            Ok(text) => text.len(),
+            Err(ref e) if e.error_len().is_none() && more => e.valid_up_to(),
+            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                "stream did not contain valid UTF-8").into()),
+        };
+        source.push_str(str::from_utf8(&undecoded[.. valid]).unwrap());
+        undecoded.drain(.. valid);
+
+        while let Some(form) = scanner.scan(&source, more, interner, arena) {
+            let (end, parsed) = parse_form(form, &source, base, interner, arena)?;
+            asts.extend(parsed);
+            source.drain(.. end);
+            base += end;
+            scanner = FormScanner::new();
+        }
+    }
+    Ok(asts)
+}
+
+/// Parses the form that `FormScanner` found at the start of `source`,
+/// which starts `base` bytes into the whole source.  Returns where the
+/// form ends in `source`, and what it parsed to.
+pub(super) fn parse_form<'a>(form: FormEnd<'a>,
+                             source: &str,
+                             base: usize,
+                             interner: &mut SymbolIntern,
+                             arena: &'a Arena<Ast<'a>>)
+                             -> Result<(usize, Vec<Ast<'a>>), ParseError> {
+    let with_base = |ast: Ast<'a>| if base == 0 { ast } else { ast.with_offset(base as i64, arena) };
+    match form {
+        FormEnd::Parsed(end, ast) => Ok((end, vec![with_base(ast)])),
+        FormEnd::At(end) => {
+            let parsed = parse(&source[.. end], interner, arena).map_err(|e| e.offset_by(base))?;
+            Ok((end, parsed.into_iter().map(with_base).collect()))
+        }
+    }
+}
+
+pub(super) enum FormEnd<'a> {
+    /// The form ends at this offset, and is this statement.
+    Parsed(usize, Ast<'a>),
+    /// The form ends at this offset, and hasn't been parsed.
+    At(usize),
+}
+
+/// Finds where the top-level form at the start of some source ends, as
+/// more of the source arrives.
+///
+/// Most statements end with a `;` outside of any brackets.  Blocks, `if`
+/// statements and imports don't, so a form that starts like one of those
+/// might also end after a `}` or a string.  Whether it does depends on
+/// the grammar, so the parser is asked, unless the next token is an
+/// `else` that carries on an `if`.
+pub(super) struct FormScanner {
+    // How many bytes of the form's source have been tokenized.
+    scanned: usize,
+    depth: u32,
+    started: bool,
+    ends_at_block: bool,
+    // Where the form ends, unless the next token carries it on.
+    candidate: Option<usize>,
+}
+
+impl FormScanner {
+    pub(super) fn new() -> FormScanner {
+        FormScanner {
+            scanned: 0,
+            depth: 0,
+            started: false,
+            ends_at_block: false,
+            candidate: None,
+        }
+    }
+
+    /// Tokenizes more of `source`, which is the source of the form so far
+    /// and possibly of some forms after it.  Returns where the form ends
+    /// if that is known yet.  If `more` is false, `source` is everything
+    /// that is left, and the form ends with it if it hasn't before.
+    pub(super) fn scan<'a>(&mut self, source: &str, more: bool, interner: &mut SymbolIntern, arena: &'a Arena<Ast<'a>>)
+                -> Option<FormEnd<'a>> {
+        let mut tokens = if more {
+            TokenIter::partial(&source[self.scanned ..])
+        } else {
+            TokenIter::new(&source[self.scanned ..])
+        };
+        for token in tokens.by_ref() {
+            if let Some(candidate) = self.candidate.take() {
+                if token.kind != TokenKind::Keyword || token.text != "else" {
+                    let text = &source[.. candidate];
+                    if check_depth(text, DEFAULT_MAX_NESTING_DEPTH).is_ok() {
+                        if let Ok(ast) = parse_Statement(arena, interner, &without_shebang(text)) {
+                            return Some(FormEnd::Parsed(candidate, ast));
+                        }
+                    }
+                }
+            }
+
+            let end = self.scanned + token.span.end() as usize;
+            if token.kind == TokenKind::Eof {
+                return if self.started { Some(FormEnd::At(end)) } else { None };
+            }
+            if !self.started {
+                self.started = true;
+                self.ends_at_block = match (token.kind, token.text) {
+                    (TokenKind::Punctuation, "{") |
+                    (TokenKind::Keyword, "if") |
+                    (TokenKind::Keyword, "import") => true,
+                    _ => false,
+                };
+            }
+
+            if token.kind == TokenKind::Punctuation {
+                match token.text {
+                    "(" | "[" | "{" => self.depth += 1,
+                    ")" | "]" | "}" => self.depth = self.depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+            if self.depth == 0 {
+                match (token.kind, token.text) {
+                    (TokenKind::Punctuation, ";") => return Some(FormEnd::At(end)),
+                    (TokenKind::Punctuation, "}") |
+                    (TokenKind::StringLit, _) if self.ends_at_block => self.candidate = Some(end),
+                    _ => {}
+                }
+            }
+        }
+        self.scanned += tokens.offset();
+        None
+    }
+}
+
+#[test]
+fn reading_matches_parsing() {
+    // Hands out a few bytes at a time, so that tokens and characters are
+    // split between reads.
+    struct Trickle<'s>(&'s [u8], usize);
+    impl <'s> Read for Trickle<'s> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(self.0.len()).min(buf.len());
+            buf[.. n].copy_from_slice(&self.0[.. n]);
+            self.0 = &self.0[n ..];
+            Ok(n)
+        }
+    }
+
+    let source = "var x = 1;\n{ var y = \"é\"; }\nif x then { print(x); } else { x = 2; }\n\
+                  if x then { 1; }\nvar f = fn(a) { a };\nf(x) + [1, 2][0]";
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let expected = parse(source, &mut interner, &arena).unwrap();
+    assert_eq!(expected.len(), 6);
+    for &step in &[1, 2, 3, 7, 100] {
+        let read = parse_reader(Trickle(source.as_bytes(), step), &mut interner, &arena).unwrap();
+        assert_eq!(read, expected, "reading {} bytes at a time", step);
+    }
+
+    match parse_reader(Trickle(b"var x = 1;\nvar y = ;", 4), &mut interner, &arena) {
+        Err(ReadError::Parse(ParseError::UnrecognizedToken { token: Some((start, _, _)), .. })) =>
+            assert_eq!(start, 19),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+    match parse_reader(Trickle(b"var x = \"\xff\";", 4), &mut interner, &arena) {
+        Err(ReadError::Io(ref e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+        other => panic!("expected an io error, got {:?}", other),
+    }
+}
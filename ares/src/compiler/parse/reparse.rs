@@ -0,0 +1,96 @@
+//! Parsing source again after a small edit, for editors that reparse
+//! a large file on every keystroke.
+
+use ares_syntax::{SymbolIntern, tokenize};
+use typed_arena::Arena;
+
+use super::{Ast, ParseError, Span};
+use super::reader::{FormScanner, parse_form};
+
+/// A change to some source: the bytes in `span` were replaced by `text`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit<'t> {
+    pub span: Span,
+    pub text: &'t str,
+}
+
+/// Parses `source`, which is what the source that parsed to `old` became
+/// after `edit`, giving the same forms as `parse` would.
+///
+/// Only the top-level forms around the edit are parsed again.  The ones
+/// before it are kept as they are, and once the forms being parsed line
+/// up with the old ones again, the rest are kept with their spans moved.
+pub fn reparse<'a>(old: &[Ast<'a>],
+                   edit: Edit,
+                   source: &str,
+                   interner: &mut SymbolIntern,
+                   arena: &'a Arena<Ast<'a>>)
+                   -> Result<Vec<Ast<'a>>, ParseError> {
+    let moved = edit.text.len() as i64 - (edit.span.end() - edit.span.start()) as i64;
+    let edited_end = edit.span.start() as usize + edit.text.len();
+
+    // The form before the first one that the edit touches is parsed
+    // again too, since the edit can carry it on, like an `else` added
+    // after an `if`.
+    let first = old.iter()
+                   .position(|ast| ast.span().end() >= edit.span.start())
+                   .unwrap_or(old.len())
+                   .saturating_sub(1);
+    let mut asts = old[.. first].to_vec();
+    let mut base = if first == 0 { 0 } else { old[first].span().start() as usize };
+
+    let mut scanner = FormScanner::new();
+    while let Some(form) = scanner.scan(&source[base ..], false, interner, arena) {
+        let (end, parsed) = parse_form(form, &source[base ..], base, interner, arena)?;
+        asts.extend(parsed);
+        base += end;
+        scanner = FormScanner::new();
+
+        if base >= edited_end {
+            let next = base + tokenize(&source[base ..]).next().unwrap().span.start() as usize;
+            let old_start = next as i64 - moved;
+            if let Ok(same) = old.binary_search_by_key(&old_start, |ast| ast.span().start() as i64) {
+                asts.extend(old[same ..].iter().map(|ast| ast.with_offset(moved, arena)));
+                break;
+            }
+        }
+    }
+    Ok(asts)
+}
+
+#[test]
+fn reparsing_matches_parsing() {
+    use super::parse;
+
+    let original = "var x = 1;\nif x then { x = 2; }\nvar y = [x, 3];\nprint(y);\n{ var z = y; }\nz";
+    let edits = [
+        ("3", "30"),
+        ("var y", "var yy"),
+        ("print(y);", "print(y);\nprint(x);"),
+        ("}\nvar y", "} else { x = 3; }\nvar y"),
+        ("\nprint(y);", ""),
+        ("var x = 1;", ""),
+        ("\nz", "\nz + 1"),
+    ];
+
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let old = parse(original, &mut interner, &arena).unwrap();
+    for &(from, to) in &edits {
+        let start = original.find(from).unwrap();
+        let edit = Edit {
+            span: Span::new(start as u32, (start + from.len()) as u32),
+            text: to,
+        };
+        let source = original.replacen(from, to, 1);
+        assert_eq!(reparse(&old, edit, &source, &mut interner, &arena).unwrap(),
+                   parse(&source, &mut interner, &arena).unwrap(),
+                   "replacing {:?} with {:?}", from, to);
+    }
+
+    let start = original.find("[x, 3]").unwrap() as u32;
+    let edit = Edit { span: Span::new(start, start + 1), text: "(" };
+    let source = original.replacen("[x, 3]", "(x, 3]", 1);
+    assert_eq!(reparse(&old, edit, &source, &mut interner, &arena).unwrap_err(),
+               parse(&source, &mut interner, &arena).unwrap_err());
+}
@@ -0,0 +1,933 @@
+use std::borrow::Cow;
+use util::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use ares_syntax::{Span, Symbol, SymbolIntern};
+use compiler::{self, CompileContext, CompileError, Diagnostics, ShiftMeta};
+use vm::{ClosureClass, Instr, Modules, Value};
+#[cfg(feature = "decimal")]
+use vm::Decimal;
+
+// Lets a context recognize programs that it has already linked.  This is
+// the only static in the crate, and contexts only rely on the ids being
+// unique.
+static NEXT_PROGRAM_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+const MAGIC: &'static [u8] = b"ARES";
+const FORMAT_VERSION: u32 = 6;
+/// How deeply list and map constants may be nested in a program that is
+/// being decoded, so that a malicious program can't overflow the stack.
+const MAX_CONSTANT_DEPTH: u32 = 256;
+
+/// A constant that compiled code loads with `LoadConstant`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    /// Borrowed when the program was loaded with `load_static`.
+    String(Cow<'static, str>),
+    /// A list literal that was built at compile time.
+    List(Vec<Constant>),
+    /// The rest only appear inside of quoted data.
+    Nil,
+    Bool(bool),
+    Symbol(Symbol),
+    Map(Vec<(Constant, Constant)>),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+}
+
+/// Bytecode that doesn't belong to any `Vm`.  Code positions and
+/// constant, closure and shift ids all start at zero and are relocated
+/// when the program is linked into a `Vm`; symbols are carried along with
+/// their names so that they can be re-interned there.
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    id: usize,
+    code: Vec<Instr>,
+    constants: Vec<Constant>,
+    closure_classes: Vec<ClosureClass>,
+    shift_metadata: Vec<ShiftMeta>,
+    call_sites: Vec<(u32, Span)>,
+    contract_checks: Vec<(u32, Span)>,
+    spans: Vec<(u32, Span)>,
+    /// Every symbol that the code refers to.  Generated symbols have no name.
+    symbols: Vec<(Symbol, Option<String>)>,
+    default_namespace: Symbol,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum ProgramDecodeError {
+    NotAProgram,
+    UnknownVersion(u32),
+    UnexpectedEnd,
+    InvalidUtf8,
+    UnknownInstruction(u8),
+    UnknownConstant(u8),
+    /// List and map constants nested deeper than `MAX_CONSTANT_DEPTH`.
+    ConstantTooDeep,
+    /// A symbol that isn't in the program's symbol table.
+    UnknownSymbol(u32),
+    /// The instruction at this position refers to a constant, closure
+    /// class, shift or code position that the program doesn't have.
+    InvalidOperand(usize),
+    /// The closure class with this id starts outside of the code or has a
+    /// parent that doesn't exist.
+    InvalidClosureClass(usize),
+    /// The shift metadata with this id returns outside of the code.
+    InvalidShiftMeta(usize),
+}
+
+#[derive(Debug)]
+pub enum CompileFileError {
+    Io(io::Error),
+    Compile(CompileError),
+}
+
+impl From<io::Error> for CompileFileError {
+    fn from(e: io::Error) -> CompileFileError {
+        CompileFileError::Io(e)
+    }
+}
+
+impl From<CompileError> for CompileFileError {
+    fn from(e: CompileError) -> CompileFileError {
+        CompileFileError::Compile(e)
+    }
+}
+
+/// Compiles `source` into the bytes that `CompiledProgram::load_static`
+/// reads.  Names in `known_globals` are treated as globals that the host
+/// will define before the program runs.
+///
+/// This is meant to be called from a build script, with the output
+/// embedded using `include_bytes!`.
+pub fn compile_to_bytes(source: &str, known_globals: &[&str]) -> Result<Vec<u8>, CompileError> {
+    let mut interner = SymbolIntern::new();
+    let mut modules = Modules::new();
+    let namespace = interner.precomputed.default_namespace;
+    for name in known_globals {
+        let name = interner.intern(*name);
+        modules.set(namespace, name, Value::Nil);
+    }
+    let program = CompiledProgram::compile(source, Some(&modules), &mut interner)?;
+    Ok(program.to_bytes())
+}
+
+/// `compile_to_bytes` for the contents of a file.
+pub fn compile_file_to_bytes<P: AsRef<Path>>(path: P, known_globals: &[&str]) -> Result<Vec<u8>, CompileFileError> {
+    let source = fs::read_to_string(path)?;
+    Ok(compile_to_bytes(&source, known_globals)?)
+}
+
+fn next_id() -> usize {
+    NEXT_PROGRAM_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Constant {
+    pub(crate) fn to_value(&self) -> Value {
+        self.to_value_with(&|s| s)
+    }
+
+    /// Like `to_value`, but passes every symbol through `sym`.
+    fn to_value_with<F: Fn(Symbol) -> Symbol>(&self, sym: &F) -> Value {
+        match self {
+            &Constant::Int(i) => Value::Int(i),
+            &Constant::Float(f) => Value::Float(f),
+            &Constant::String(ref s) => (&s[..]).into(),
+            &Constant::List(ref elements) => {
+                elements.iter().map(|e| e.to_value_with(sym)).collect::<Vec<_>>().into()
+            }
+            &Constant::Nil => Value::Nil,
+            &Constant::Bool(b) => Value::Bool(b),
+            &Constant::Symbol(s) => Value::Symbol(sym(s)),
+            &Constant::Map(ref pairs) => {
+                Value::map(pairs.iter().map(|&(ref k, ref v)| (k.to_value_with(sym), v.to_value_with(sym))))
+            }
+            #[cfg(feature = "decimal")]
+            &Constant::Decimal(d) => d.into_value(),
+        }
+    }
+
+    fn from_value(value: &Value) -> Constant {
+        match value {
+            &Value::Int(i) => Constant::Int(i),
+            &Value::Float(f) => Constant::Float(f),
+            &Value::List(ref elements) => Constant::List(elements.iter().map(Constant::from_value).collect()),
+            &Value::Nil => Constant::Nil,
+            &Value::Bool(b) => Constant::Bool(b),
+            &Value::Symbol(s) => Constant::Symbol(s),
+            // Sorted so that a program always encodes to the same bytes.
+            &Value::Map(ref pairs) => Constant::Map(pairs.borrow().sorted().into_iter().map(|(k, v)| {
+                (Constant::from_value(k), Constant::from_value(v))
+            }).collect()),
+            #[cfg(feature = "decimal")]
+            &Value::Decimal(ref d) => Constant::Decimal(**d),
+            other => Constant::String(other.expect_str().expect("unexpected constant").to_string().into()),
+        }
+    }
+
+    fn symbols(&self, out: &mut HashSet<Symbol>) {
+        match self {
+            &Constant::Symbol(s) => { out.insert(s); }
+            &Constant::List(ref elements) => {
+                for element in elements {
+                    element.symbols(out);
+                }
+            }
+            &Constant::Map(ref pairs) => {
+                for &(ref k, ref v) in pairs {
+                    k.symbols(out);
+                    v.symbols(out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl CompiledProgram {
+    /// Compiles `source` on its own.  `globals` are the globals that the
+    /// code may refer to without defining them.
+    pub fn compile(source: &str, globals: Option<&Modules>, interner: &mut SymbolIntern)
+    -> Result<CompiledProgram, CompileError> {
+        let mut compile_context = CompileContext::new();
+        let mut diagnostics = Diagnostics::new();
+        let code = compiler::compile(source, &mut compile_context, globals, interner, &mut diagnostics, 0)?;
+
+        let constants: Vec<Constant> = compile_context.constants().iter().map(Constant::from_value).collect();
+
+        let mut symbols = HashSet::new();
+        for constant in &constants {
+            constant.symbols(&mut symbols);
+        }
+        for instr in &code {
+            match instr {
+                &Instr::SymbolLit(s) | &Instr::GetGlobal(s) | &Instr::PutGlobal(s) => { symbols.insert(s); }
+                _ => {}
+            }
+        }
+        for class in compile_context.closure_classes() {
+            symbols.insert(class.namespace);
+            if let Some(name) = class.name {
+                symbols.insert(name);
+            }
+        }
+        let mut symbols: Vec<(Symbol, Option<String>)> = symbols.into_iter()
+            .map(|s| (s, interner.lookup(s).map(String::from)))
+            .collect();
+        symbols.sort();
+
+        Ok(CompiledProgram {
+            id: next_id(),
+            code: code,
+            constants: constants,
+            closure_classes: compile_context.closure_classes().to_vec(),
+            shift_metadata: compile_context.shift_metadata().to_vec(),
+            call_sites: compile_context.call_sites(),
+            contract_checks: compile_context.contract_checks(),
+            spans: compile_context.spans().to_vec(),
+            symbols: symbols,
+            default_namespace: interner.precomputed.default_namespace,
+        })
+    }
+
+    /// Identifies this program among every program created by this
+    /// process.  Clones share the id of the original.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn instructions(&self) -> &[Instr] {
+        &self.code
+    }
+
+    pub fn constants(&self) -> &[Constant] {
+        &self.constants
+    }
+
+    /// Appends this program to `code`, relocating it and adding its
+    /// constants and closure classes to `compile_context`.  Returns the
+    /// code position that the program starts at; it ends with `Halt`.
+    pub(crate) fn link(&self, code: &mut Vec<Instr>, compile_context: &mut CompileContext,
+                       interner: &mut SymbolIntern) -> usize {
+        let base = code.len() as u32;
+
+        let mut symbol_map = HashMap::new();
+        symbol_map.insert(self.default_namespace, interner.precomputed.default_namespace);
+        for &(symbol, ref name) in &self.symbols {
+            if symbol == self.default_namespace {
+                continue;
+            }
+            let linked = match name {
+                &Some(ref name) => interner.intern(&name[..]),
+                &None => interner.gensym(),
+            };
+            symbol_map.insert(symbol, linked);
+        }
+        let sym = |s: Symbol| symbol_map.get(&s).cloned().unwrap_or(s);
+
+        let constant_base = compile_context.constants().len() as u32;
+        for constant in &self.constants {
+            compile_context.add_constant(constant.to_value_with(&sym));
+        }
+
+        let class_base = compile_context.closure_classes().len() as u32;
+        for class in &self.closure_classes {
+            let mut class = class.clone();
+            class.code_offset += base;
+            class.namespace = sym(class.namespace);
+            class.name = class.name.map(&sym);
+            class.parent = class.parent.map(|p| p + class_base);
+            compile_context.add_closure_class(class);
+        }
+
+        let shift_base = compile_context.shift_metadata().len() as u32;
+        for meta in &self.shift_metadata {
+            compile_context.add_shift_meta(ShiftMeta {
+                num_symbols: meta.num_symbols,
+                return_pos: meta.return_pos + base,
+            });
+        }
+
+        for &(pos, span) in &self.call_sites {
+            compile_context.add_call_site(pos + base, span);
+        }
+        for &(pos, span) in &self.contract_checks {
+            compile_context.add_contract_check(pos + base, span);
+        }
+        for &(pos, span) in &self.spans {
+            compile_context.add_span(pos + base, span);
+        }
+
+        code.extend(self.code.iter().map(|instr| match instr {
+            &Instr::Jump(p) => Instr::Jump(p + base),
+            &Instr::Call(p) => Instr::Call(p + base),
+            &Instr::LoadConstant(id) => Instr::LoadConstant(id + constant_base),
+            &Instr::CreateClosure(id) => Instr::CreateClosure(id + class_base),
+            &Instr::Shift(id) => Instr::Shift(id + shift_base),
+            &Instr::SymbolLit(s) => Instr::SymbolLit(sym(s)),
+            &Instr::GetGlobal(s) => Instr::GetGlobal(sym(s)),
+            &Instr::PutGlobal(s) => Instr::PutGlobal(sym(s)),
+            other => other.clone(),
+        }));
+        code.push(Instr::Halt);
+        base as usize
+    }
+
+    /// Encodes this program in the format read by `from_bytes` and
+    /// `load_static`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer { out: MAGIC.to_vec() };
+        w.u32(FORMAT_VERSION);
+        w.u32(self.default_namespace.id());
+
+        w.u32(self.symbols.len() as u32);
+        for &(symbol, ref name) in &self.symbols {
+            w.u32(symbol.id());
+            match name {
+                &Some(ref name) => {
+                    w.u8(1);
+                    w.str(name);
+                }
+                &None => w.u8(0),
+            }
+        }
+
+        w.u32(self.constants.len() as u32);
+        for constant in &self.constants {
+            write_constant(&mut w, constant);
+        }
+
+        w.u32(self.closure_classes.len() as u32);
+        for class in &self.closure_classes {
+            w.u32(class.code_offset);
+            w.u32(class.arg_count);
+            w.u32(class.local_defines_count);
+            w.u32(class.upvars_count);
+            w.u8(class.has_rest_params as u8);
+            w.u32(class.namespace.id());
+            w.u8(class.is_shifter as u8);
+            match class.name {
+                Some(name) => {
+                    w.u8(1);
+                    w.u32(name.id());
+                }
+                None => w.u8(0),
+            }
+            match class.position {
+                Some((line, column)) => {
+                    w.u8(1);
+                    w.u32(line);
+                    w.u32(column);
+                }
+                None => w.u8(0),
+            }
+            match class.span {
+                Some(span) => {
+                    w.u8(1);
+                    w.u32(span.start());
+                    w.u32(span.end());
+                }
+                None => w.u8(0),
+            }
+            match class.parent {
+                Some(parent) => {
+                    w.u8(1);
+                    w.u32(parent);
+                }
+                None => w.u8(0),
+            }
+        }
+
+        w.u32(self.shift_metadata.len() as u32);
+        for meta in &self.shift_metadata {
+            w.u32(meta.num_symbols);
+            w.u32(meta.return_pos);
+        }
+
+        for positions in &[&self.call_sites, &self.contract_checks, &self.spans] {
+            w.u32(positions.len() as u32);
+            for &(pos, span) in positions.iter() {
+                w.u32(pos);
+                w.u32(span.start());
+                w.u32(span.end());
+            }
+        }
+
+        w.u32(self.code.len() as u32);
+        for instr in &self.code {
+            w.instr(instr);
+        }
+        w.out
+    }
+
+    /// Decodes a program, copying its string constants.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompiledProgram, ProgramDecodeError> {
+        decode(bytes, |s| Cow::Owned(s.to_string()))
+    }
+
+    /// Decodes a program that is embedded in the binary (usually with
+    /// `include_bytes!`).  String constants borrow from `bytes` instead
+    /// of being copied.
+    pub fn load_static(bytes: &'static [u8]) -> Result<CompiledProgram, ProgramDecodeError> {
+        decode(bytes, Cow::Borrowed)
+    }
+}
+
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.out.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.u32(v as u32);
+        self.u32((v >> 32) as u32);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    fn instr(&mut self, instr: &Instr) {
+        use vm::Instr::*;
+
+        // Instructions without an operand are written as just their tag.
+        let (tag, operand) = match instr {
+            &Halt => (0, None),
+            &Nop => (1, None),
+            &Print => (2, None),
+            &Dbg => (3, None),
+            &Dup(n) => (4, Some(n)),
+            &DupTop => (5, None),
+            &Pop => (6, None),
+            &Swap => (7, None),
+            &Reset(n) => (8, Some(n)),
+            &Shift(n) => (9, Some(n)),
+            &NilLit => (10, None),
+            &BoolLit(b) => (11, Some(b as u32)),
+            &SymbolLit(s) => (12, Some(s.id())),
+            &IntLit(i) => (13, Some(i as u32)),
+            &LoadConstant(n) => (14, Some(n)),
+            &GetGlobal(s) => (15, Some(s.id())),
+            &PutGlobal(s) => (16, Some(s.id())),
+            &SetCell(n) => (17, Some(n)),
+            &Assign(n) => (18, Some(n)),
+            &UnwrapCell => (19, None),
+            &WrapCell => (20, None),
+            &Call(n) => (21, Some(n)),
+            &Jump(n) => (22, Some(n)),
+            &JumpTo => (23, None),
+            &Ret => (24, None),
+            &CreateClosure(n) => (25, Some(n)),
+            &AddInt => (26, None),
+            &SubInt => (27, None),
+            &DivInt => (28, None),
+            &MulInt => (29, None),
+            &And => (30, None),
+            &Or => (31, None),
+            &Lt => (32, None),
+            &Lte => (33, None),
+            &Gt => (34, None),
+            &Gte => (35, None),
+            &Eq => (36, None),
+            &Neq => (37, None),
+            &ConstructList(n) => (38, Some(n)),
+            &ListIndex => (39, None),
+            &Execute(n) => (40, Some(n)),
+            &ExecuteN => (41, None),
+            &If => (42, None),
+            &Ifn => (43, None),
+            &Require => (44, None),
+            &Ensure => (45, None),
+        };
+        self.u8(tag);
+        if let Some(operand) = operand {
+            self.u32(operand);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl <'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ProgramDecodeError> {
+        if self.bytes.len() < n {
+            return Err(ProgramDecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ProgramDecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ProgramDecodeError> {
+        let b = self.take(4)?;
+        Ok((b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+    }
+
+    fn u64(&mut self) -> Result<u64, ProgramDecodeError> {
+        let lo = self.u32()? as u64;
+        let hi = self.u32()? as u64;
+        Ok(lo | hi << 32)
+    }
+
+    fn str(&mut self) -> Result<&'a str, ProgramDecodeError> {
+        let len = self.u32()? as usize;
+        str::from_utf8(self.take(len)?).map_err(|_| ProgramDecodeError::InvalidUtf8)
+    }
+
+    fn symbol(&mut self) -> Result<Symbol, ProgramDecodeError> {
+        Ok(Symbol::from_id(self.u32()?))
+    }
+
+    fn instr(&mut self) -> Result<Instr, ProgramDecodeError> {
+        use vm::Instr::*;
+
+        Ok(match self.u8()? {
+            0 => Halt,
+            1 => Nop,
+            2 => Print,
+            3 => Dbg,
+            4 => Dup(self.u32()?),
+            5 => DupTop,
+            6 => Pop,
+            7 => Swap,
+            8 => Reset(self.u32()?),
+            9 => Shift(self.u32()?),
+            10 => NilLit,
+            11 => BoolLit(self.u32()? != 0),
+            12 => SymbolLit(self.symbol()?),
+            13 => IntLit(self.u32()? as i32),
+            14 => LoadConstant(self.u32()?),
+            15 => GetGlobal(self.symbol()?),
+            16 => PutGlobal(self.symbol()?),
+            17 => SetCell(self.u32()?),
+            18 => Assign(self.u32()?),
+            19 => UnwrapCell,
+            20 => WrapCell,
+            21 => Call(self.u32()?),
+            22 => Jump(self.u32()?),
+            23 => JumpTo,
+            24 => Ret,
+            25 => CreateClosure(self.u32()?),
+            26 => AddInt,
+            27 => SubInt,
+            28 => DivInt,
+            29 => MulInt,
+            30 => And,
+            31 => Or,
+            32 => Lt,
+            33 => Lte,
+            34 => Gt,
+            35 => Gte,
+            36 => Eq,
+            37 => Neq,
+            38 => ConstructList(self.u32()?),
+            39 => ListIndex,
+            40 => Execute(self.u32()?),
+            41 => ExecuteN,
+            42 => If,
+            43 => Ifn,
+            44 => Require,
+            45 => Ensure,
+            other => return Err(ProgramDecodeError::UnknownInstruction(other)),
+        })
+    }
+}
+
+fn write_constant(w: &mut Writer, constant: &Constant) {
+    match constant {
+        &Constant::Int(i) => {
+            w.u8(0);
+            w.u64(i as u64);
+        }
+        &Constant::Float(f) => {
+            w.u8(1);
+            w.u64(f.to_bits());
+        }
+        &Constant::String(ref s) => {
+            w.u8(2);
+            w.str(s);
+        }
+        &Constant::List(ref elements) => {
+            w.u8(3);
+            w.u32(elements.len() as u32);
+            for element in elements {
+                write_constant(w, element);
+            }
+        }
+        &Constant::Nil => w.u8(4),
+        &Constant::Bool(b) => {
+            w.u8(5);
+            w.u8(b as u8);
+        }
+        &Constant::Symbol(s) => {
+            w.u8(6);
+            w.u32(s.id());
+        }
+        &Constant::Map(ref pairs) => {
+            w.u8(7);
+            w.u32(pairs.len() as u32);
+            for &(ref k, ref v) in pairs {
+                write_constant(w, k);
+                write_constant(w, v);
+            }
+        }
+        #[cfg(feature = "decimal")]
+        &Constant::Decimal(d) => {
+            w.u8(8);
+            w.str(&d.digits());
+        }
+    }
+}
+
+fn read_constant<'a, F>(r: &mut Reader<'a>, string: &F, depth: u32) -> Result<Constant, ProgramDecodeError>
+where F: Fn(&'a str) -> Cow<'static, str> {
+    if depth > MAX_CONSTANT_DEPTH {
+        return Err(ProgramDecodeError::ConstantTooDeep);
+    }
+    Ok(match r.u8()? {
+        0 => Constant::Int(r.u64()? as i64),
+        1 => Constant::Float(f64::from_bits(r.u64()?)),
+        2 => Constant::String(string(r.str()?)),
+        3 => {
+            let mut elements = vec![];
+            for _ in 0 .. r.u32()? {
+                elements.push(read_constant(r, string, depth + 1)?);
+            }
+            Constant::List(elements)
+        }
+        4 => Constant::Nil,
+        5 => Constant::Bool(r.u8()? != 0),
+        6 => Constant::Symbol(r.symbol()?),
+        7 => {
+            let mut pairs = vec![];
+            for _ in 0 .. r.u32()? {
+                let k = read_constant(r, string, depth + 1)?;
+                let v = read_constant(r, string, depth + 1)?;
+                pairs.push((k, v));
+            }
+            Constant::Map(pairs)
+        }
+        #[cfg(feature = "decimal")]
+        8 => Constant::Decimal(Decimal::parse(r.str()?).ok_or(ProgramDecodeError::UnknownConstant(8))?),
+        other => return Err(ProgramDecodeError::UnknownConstant(other)),
+    })
+}
+
+fn decode<'a, F>(bytes: &'a [u8], string: F) -> Result<CompiledProgram, ProgramDecodeError>
+where F: Fn(&'a str) -> Cow<'static, str> {
+    let mut r = Reader { bytes: bytes };
+    if r.take(MAGIC.len()).ok() != Some(MAGIC) {
+        return Err(ProgramDecodeError::NotAProgram);
+    }
+    let version = r.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(ProgramDecodeError::UnknownVersion(version));
+    }
+    let default_namespace = r.symbol()?;
+
+    let mut symbols = vec![];
+    for _ in 0 .. r.u32()? {
+        let symbol = r.symbol()?;
+        let name = if r.u8()? == 1 { Some(r.str()?.to_string()) } else { None };
+        symbols.push((symbol, name));
+    }
+
+    let mut constants = vec![];
+    for _ in 0 .. r.u32()? {
+        constants.push(read_constant(&mut r, &string, 0)?);
+    }
+
+    let mut closure_classes = vec![];
+    for _ in 0 .. r.u32()? {
+        let mut class = ClosureClass {
+            code_offset: r.u32()?,
+            arg_count: r.u32()?,
+            local_defines_count: r.u32()?,
+            upvars_count: r.u32()?,
+            has_rest_params: r.u8()? != 0,
+            namespace: r.symbol()?,
+            is_shifter: r.u8()? != 0,
+            name: None,
+            position: None,
+            span: None,
+            parent: None,
+        };
+        if r.u8()? == 1 {
+            class.name = Some(r.symbol()?);
+        }
+        if r.u8()? == 1 {
+            class.position = Some((r.u32()?, r.u32()?));
+        }
+        if r.u8()? == 1 {
+            class.span = Some(Span::new(r.u32()?, r.u32()?));
+        }
+        if r.u8()? == 1 {
+            class.parent = Some(r.u32()?);
+        }
+        closure_classes.push(class);
+    }
+
+    let mut shift_metadata = vec![];
+    for _ in 0 .. r.u32()? {
+        shift_metadata.push(ShiftMeta {
+            num_symbols: r.u32()?,
+            return_pos: r.u32()?,
+        });
+    }
+
+    let mut positions = vec![];
+    for _ in 0 .. 3 {
+        let mut spans = vec![];
+        for _ in 0 .. r.u32()? {
+            let pos = r.u32()?;
+            let start = r.u32()?;
+            let end = r.u32()?;
+            spans.push((pos, Span::new(start, end)));
+        }
+        positions.push(spans);
+    }
+    let spans = positions.pop().unwrap();
+    let contract_checks = positions.pop().unwrap();
+    let call_sites = positions.pop().unwrap();
+
+    let mut code = vec![];
+    for _ in 0 .. r.u32()? {
+        code.push(r.instr()?);
+    }
+
+    let program = CompiledProgram {
+        id: next_id(),
+        code: code,
+        constants: constants,
+        closure_classes: closure_classes,
+        shift_metadata: shift_metadata,
+        call_sites: call_sites,
+        contract_checks: contract_checks,
+        spans: spans,
+        symbols: symbols,
+        default_namespace: default_namespace,
+    };
+    validate(&program)?;
+    Ok(program)
+}
+
+/// Checks that every id and code position in a decoded program refers to
+/// something in it, so that linking and running it can't index out of
+/// bounds.
+fn validate(program: &CompiledProgram) -> Result<(), ProgramDecodeError> {
+    let mut known: HashSet<Symbol> = program.symbols.iter().map(|&(s, _)| s).collect();
+    known.insert(program.default_namespace);
+    let check_symbol = |s: Symbol| if known.contains(&s) {
+        Ok(())
+    } else {
+        Err(ProgramDecodeError::UnknownSymbol(s.id()))
+    };
+
+    // `link` puts a `Halt` right after the code, which jumps may go to.
+    let code_len = program.code.len() as u32;
+    let mut constant_symbols = HashSet::new();
+    for constant in &program.constants {
+        constant.symbols(&mut constant_symbols);
+    }
+    for &symbol in &constant_symbols {
+        check_symbol(symbol)?;
+    }
+
+    for (id, class) in program.closure_classes.iter().enumerate() {
+        let parent_exists = class.parent.map_or(true, |p| (p as usize) < program.closure_classes.len());
+        if class.code_offset > code_len || !parent_exists {
+            return Err(ProgramDecodeError::InvalidClosureClass(id));
+        }
+        check_symbol(class.namespace)?;
+        if let Some(name) = class.name {
+            check_symbol(name)?;
+        }
+    }
+
+    for (id, meta) in program.shift_metadata.iter().enumerate() {
+        if meta.return_pos > code_len {
+            return Err(ProgramDecodeError::InvalidShiftMeta(id));
+        }
+    }
+
+    for (position, instr) in program.code.iter().enumerate() {
+        let valid = match instr {
+            &Instr::LoadConstant(id) => (id as usize) < program.constants.len(),
+            &Instr::CreateClosure(id) => (id as usize) < program.closure_classes.len(),
+            &Instr::Shift(id) => (id as usize) < program.shift_metadata.len(),
+            &Instr::Jump(p) | &Instr::Call(p) => p <= code_len,
+            &Instr::SymbolLit(s) | &Instr::GetGlobal(s) | &Instr::PutGlobal(s) => {
+                check_symbol(s)?;
+                true
+            }
+            _ => true,
+        };
+        if !valid {
+            return Err(ProgramDecodeError::InvalidOperand(position));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn bytes_round_trip() {
+    use host::Context;
+
+    let source = "var greet = fn(name) { [\"hello\", name, 'sym, 10000000000, 1.5] }; greet(target)";
+    let bytes = compile_to_bytes(source, &["target"]).unwrap();
+    let program = CompiledProgram::from_bytes(&bytes).unwrap();
+    assert_eq!(program.to_bytes(), bytes);
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var unrelated = 'a; var target = 5;").unwrap();
+    let result = ctx.run(&mut state, &program).unwrap();
+    let expected = ctx.eval(&mut state, "[\"hello\", 5, 'sym, 10000000000, 1.5]").unwrap();
+    assert_eq!(result, expected);
+
+    assert_eq!(CompiledProgram::from_bytes(b"nope").unwrap_err(), ProgramDecodeError::NotAProgram);
+
+    let mut old = bytes.clone();
+    old[MAGIC.len()] = 5;
+    assert_eq!(CompiledProgram::from_bytes(&old).unwrap_err(), ProgramDecodeError::UnknownVersion(5));
+}
+
+#[test]
+fn folded_constants_round_trip() {
+    let mut interner = SymbolIntern::new();
+    let program = CompiledProgram::compile("var xs = [1, 2 * 1.5, \"a\", [10000000000]];", None, &mut interner).unwrap();
+    assert_eq!(program.constants(), &[
+        Constant::List(vec![
+            Constant::Int(1),
+            Constant::Float(3.0),
+            Constant::String("a".into()),
+            Constant::List(vec![Constant::Int(10000000000)]),
+        ]),
+    ]);
+    let bytes = program.to_bytes();
+    assert_eq!(CompiledProgram::from_bytes(&bytes).unwrap().constants(), program.constants());
+}
+
+#[test]
+fn quoted_data_is_relinked() {
+    use host::{Context, ContextLike};
+
+    let mut interner = SymbolIntern::new();
+    interner.intern("taken");
+    let program = CompiledProgram::compile("'(a {k 1} \"s\")", None, &mut interner).unwrap();
+    let bytes = program.to_bytes();
+    let decoded = CompiledProgram::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.constants(), program.constants());
+
+    // The symbols in the data are re-interned in the context that runs it.
+    let mut ctx: Context<()> = Context::new();
+    let result = ctx.run(&mut (), &decoded).unwrap().unwrap();
+    let items = result.expect_list_ref().unwrap();
+    let a = Value::Symbol(ctx.interner_mut().intern("a"));
+    let k = Value::Symbol(ctx.interner_mut().intern("k"));
+    assert_eq!(items[0], a);
+    assert_eq!(items[1], Value::map(vec![(k, Value::Int(1))]));
+    assert_eq!(items[2], Value::string("s"));
+}
+
+#[test]
+fn malformed_programs_are_rejected() {
+    let mut interner = SymbolIntern::new();
+    let program = CompiledProgram::compile("var f = fn(x) { [x, 'a] }; f(1)", None, &mut interner).unwrap();
+    let decode = |program: &CompiledProgram| CompiledProgram::from_bytes(&program.to_bytes());
+    assert!(decode(&program).is_ok());
+
+    let mut broken = program.clone();
+    let position = broken.code.iter().position(|i| match i { &Instr::CreateClosure(_) => true, _ => false }).unwrap();
+    broken.code[position] = Instr::CreateClosure(99);
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::InvalidOperand(position));
+
+    let mut broken = program.clone();
+    broken.code.push(Instr::LoadConstant(99));
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::InvalidOperand(program.code.len()));
+
+    let mut broken = program.clone();
+    broken.code.push(Instr::Jump(10000));
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::InvalidOperand(program.code.len()));
+
+    let mut broken = program.clone();
+    broken.code.push(Instr::Shift(0));
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::InvalidOperand(program.code.len()));
+
+    let mut broken = program.clone();
+    broken.code.push(Instr::GetGlobal(Symbol::from_id(10000)));
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::UnknownSymbol(10000));
+
+    let mut broken = program.clone();
+    broken.closure_classes[0].parent = Some(7);
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::InvalidClosureClass(0));
+
+    let mut deep = Constant::Nil;
+    for _ in 0 .. MAX_CONSTANT_DEPTH + 1 {
+        deep = Constant::List(vec![deep]);
+    }
+    let mut broken = program.clone();
+    broken.constants.push(deep);
+    assert_eq!(decode(&broken).unwrap_err(), ProgramDecodeError::ConstantTooDeep);
+}
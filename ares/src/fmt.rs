@@ -0,0 +1,66 @@
+extern crate ares;
+extern crate ares_syntax;
+
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+use ares::compiler::{CompileError, format_source};
+use ares_syntax::SymbolIntern;
+
+fn exit_with(name: &str, error: &Display) -> ! {
+    let _ = writeln!(io::stderr(), "{}: {}", name, error);
+    process::exit(1);
+}
+
+fn format_or_exit(name: &str, source: &str) -> String {
+    match format_source(source) {
+        Ok(formatted) => formatted,
+        Err(e) => {
+            for diagnostic in CompileError::from(e).to_diagnostics(&SymbolIntern::new()) {
+                let _ = write!(io::stderr(), "{}: {}", name, diagnostic.render(source));
+            }
+            process::exit(1);
+        }
+    }
+}
+
+/// Formats each file that is passed in, or stdin if there are none.
+/// With `--write`, files are rewritten in place instead of printed.
+fn main() {
+    let mut write = false;
+    let mut paths = vec![];
+    for arg in env::args().skip(1) {
+        if arg == "--write" {
+            write = true;
+        } else {
+            paths.push(arg);
+        }
+    }
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut source) {
+            exit_with("<stdin>", &e);
+        }
+        print!("{}", format_or_exit("<stdin>", &source));
+        return;
+    }
+
+    for path in paths {
+        let mut source = String::new();
+        if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut source)) {
+            exit_with(&path, &e);
+        }
+        let formatted = format_or_exit(&path, &source);
+        if write {
+            if let Err(e) = File::create(&path).and_then(|mut f| f.write_all(formatted.as_bytes())) {
+                exit_with(&path, &e);
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+}
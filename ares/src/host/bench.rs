@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use host::{Context, State, AresResult};
+use vm::ExecStats;
+
+/// The measurements taken for a single run of a benchmarked program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BenchIteration {
+    pub wall_time: Duration,
+    pub instructions: u64,
+    pub allocations: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BenchReport {
+    pub iterations: Vec<BenchIteration>,
+}
+
+impl BenchReport {
+    pub fn total_wall_time(&self) -> Duration {
+        self.iterations.iter().fold(Duration::new(0, 0), |acc, it| acc + it.wall_time)
+    }
+
+    /// The average wall time of an iteration, or zero if nothing was run.
+    pub fn mean_wall_time(&self) -> Duration {
+        if self.iterations.is_empty() {
+            Duration::new(0, 0)
+        } else {
+            self.total_wall_time() / self.iterations.len() as u32
+        }
+    }
+
+    pub fn total_instructions(&self) -> u64 {
+        self.iterations.iter().map(|it| it.instructions).sum()
+    }
+
+    pub fn total_allocations(&self) -> u64 {
+        self.iterations.iter().map(|it| it.allocations).sum()
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Counters for everything that this context has executed so far.
+    pub fn exec_stats(&self) -> ExecStats {
+        self.vm.stats
+    }
+
+    /// Compiles `source` once and runs it `iterations` times, measuring
+    /// each run.  Wall times are read from the context's clock (see
+    /// `set_clock`) and don't include compiling or linking `source`.
+    pub fn bench(&mut self, state: &mut S, source: &str, iterations: usize)
+    -> AresResult<BenchReport> {
+        let program = try!(self.compile(source));
+        let start = self.link(&program);
+        let mut report = BenchReport { iterations: Vec::with_capacity(iterations) };
+        for _ in 0 .. iterations {
+            let before = self.vm.stats;
+            let started_at = self.vm.io.clock.now();
+            try!(self.execute_linked(state, start));
+            let finished_at = self.vm.io.clock.now();
+            let after = self.vm.stats;
+            // The system clock can go backwards.
+            let wall_time = finished_at.checked_sub(started_at).unwrap_or_default();
+
+            report.iterations.push(BenchIteration {
+                wall_time: wall_time,
+                instructions: after.instructions - before.instructions,
+                allocations: after.allocations - before.allocations,
+            });
+        }
+        Ok(report)
+    }
+}
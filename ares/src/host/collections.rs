@@ -0,0 +1,115 @@
+use util::collections::HashMap;
+
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function};
+
+/// The `[first, second]` of a two element list.
+fn pair(value: &Value) -> Option<(Value, Value)> {
+    match value {
+        &Value::List(ref items) if items.len() == 2 => Some((items[0].clone(), items[1].clone())),
+        _ => None,
+    }
+}
+
+fn pairs(value: Option<&Value>) -> Option<Vec<(Value, Value)>> {
+    match value {
+        Some(&Value::List(ref items)) => items.iter().map(pair).collect(),
+        _ => None,
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines the builtins for moving between lists and maps.  A "pair"
+    /// is a two element list `[key, value]`.
+    ///
+    /// * `zip(a, b)` returns a list of pairs of the items of two lists,
+    ///   stopping at the end of the shorter one.
+    /// * `unzip(pairs)` returns `[firsts, seconds]`.
+    /// * `pairs_to_map(pairs)` returns a map of the pairs.  Later pairs win
+    ///   when keys repeat.
+    /// * `map_to_pairs(map)` returns the pairs of a map, sorted by key.
+    /// * `group_by(pairs)` returns a map from each key to the list of the
+    ///   values paired with it, in order.
+    /// * `frequencies(list)` returns a map from each item to the number of
+    ///   times that it appears.
+    ///
+    /// Each returns `nil` if its arguments are the wrong shape.
+    pub fn install_collection_builtins(&mut self) {
+        self.set_global("zip", user_function::<S, _>(Some("zip".into()), |args, _, _| {
+            match (args.get(0), args.get(1)) {
+                (Some(&Value::List(ref a)), Some(&Value::List(ref b))) => {
+                    let zipped: Vec<Value> = a.iter().zip(b.iter())
+                        .map(|(a, b)| Value::list(vec![a.clone(), b.clone()]))
+                        .collect();
+                    zipped.into()
+                }
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("unzip", user_function::<S, _>(Some("unzip".into()), |args, _, _| {
+            match pairs(args.get(0)) {
+                Some(pairs) => {
+                    let (firsts, seconds): (Vec<Value>, Vec<Value>) = pairs.into_iter().unzip();
+                    Value::list(vec![Value::list(firsts), Value::list(seconds)])
+                }
+                None => Value::Nil,
+            }
+        }));
+
+        self.set_global("pairs_to_map", user_function::<S, _>(Some("pairs_to_map".into()), |args, _, _| {
+            pairs(args.get(0)).map_or(Value::Nil, Value::map)
+        }));
+
+        self.set_global("map_to_pairs", user_function::<S, _>(Some("map_to_pairs".into()), |args, _, _| {
+            match args.get(0) {
+                Some(&Value::Map(ref map)) => {
+                    let pairs: Vec<Value> = map.borrow().sorted().into_iter()
+                        .map(|(k, v)| Value::list(vec![k.clone(), v.clone()]))
+                        .collect();
+                    pairs.into()
+                }
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("group_by", user_function::<S, _>(Some("group_by".into()), |args, _, _| {
+            let pairs = match pairs(args.get(0)) {
+                Some(pairs) => pairs,
+                None => return Value::Nil,
+            };
+            let mut groups: HashMap<Value, Vec<Value>> = HashMap::new();
+            for (key, value) in pairs {
+                groups.entry(key).or_insert_with(Vec::new).push(value);
+            }
+            Value::map(groups.into_iter().map(|(k, values)| (k, Value::list(values))))
+        }));
+
+        self.set_global("frequencies", user_function::<S, _>(Some("frequencies".into()), |args, _, _| {
+            let items = match args.get(0) {
+                Some(&Value::List(ref items)) => items,
+                _ => return Value::Nil,
+            };
+            let mut counts: HashMap<Value, i64> = HashMap::new();
+            for item in items.iter() {
+                *counts.entry(item.clone()).or_insert(0) += 1;
+            }
+            Value::map(counts)
+        }));
+    }
+}
+
+#[test]
+fn lists_and_maps() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_collection_builtins();
+    let mut eval = |source: &str| ctx.eval(&mut state, source).unwrap().unwrap();
+
+    assert_eq!(eval("zip([1, 2, 3], ['a, 'b])"), eval("[[1, 'a], [2, 'b]]"));
+    assert_eq!(eval("unzip(zip([1, 2], [3, 4]))"), eval("[[1, 2], [3, 4]]"));
+    assert_eq!(eval("map_to_pairs(pairs_to_map([[2, 'b], [1, 'a], [2, 'c]]))"), eval("[[1, 'a], [2, 'c]]"));
+    assert_eq!(eval("map_to_pairs(group_by([['x, 1], ['y, 2], ['x, 3]]))"), eval("[['x, [1, 3]], ['y, [2]]]"));
+    assert_eq!(eval("map_to_pairs(frequencies([\"a\", \"b\", \"a\"]))"), eval("[[\"a\", 2], [\"b\", 1]]"));
+    assert_eq!(eval("unzip([[1, 2], [3]])"), Value::Nil);
+}
@@ -0,0 +1,181 @@
+use host::{AresResult, Context, ContextLike, State};
+use vm::{Value, user_function};
+
+/// The handlers and restarts that are established.  They are kept in the
+/// vm rather than in globals so that the vm can put them back when a
+/// script fails while some are established.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Conditions {
+    handlers: Value,
+    restarts: Value,
+    /// The value of the restart that is unwinding, wrapped in a list.
+    pending: Value,
+}
+
+impl Default for Conditions {
+    fn default() -> Conditions {
+        Conditions { handlers: Value::Nil, restarts: Value::Nil, pending: Value::Nil }
+    }
+}
+
+// Handlers and restarts are kept in linked lists of `[head, rest]` pairs
+// so that installing one never changes a list that an outer call saved.
+// An invoked restart leaves its value pending and unwinds to its `reset`,
+// where `with_restart` finds it.
+const CONDITIONS: &'static str = r#"
+var __ares_signal_from = fn(handlers, condition) {
+    if handlers == nil then nil else {
+        __ares_set_handlers(handlers[1]);
+        handlers[0](condition);
+        __ares_signal_from(handlers[1], condition)
+    }
+};
+
+var __ares_find_restart = fn(restarts, name) {
+    if restarts == nil then false
+    else if restarts[0] == name then true
+    else __ares_find_restart(restarts[1], name)
+};
+
+var signal = fn(condition) {
+    var saved = __ares_handlers();
+    __ares_signal_from(saved, condition);
+    __ares_set_handlers(saved);
+    nil
+};
+
+var with_handler = fn(handler, body) {
+    var saved = __ares_handlers();
+    __ares_set_handlers([handler, saved]);
+    var value = body();
+    __ares_set_handlers(saved);
+    value
+};
+
+var with_restart = fn(name, body, recover) {
+    var handlers = __ares_handlers();
+    var restarts = __ares_restarts();
+    __ares_set_restarts([name, restarts]);
+    var value = reset(name) { body() };
+    __ares_set_handlers(handlers);
+    __ares_set_restarts(restarts);
+    var pending = __ares_pending();
+    if pending == nil then value else {
+        __ares_set_pending(nil);
+        recover(pending[0])
+    }
+};
+
+var find_restart = fn(name) {
+    __ares_find_restart(__ares_restarts(), name)
+};
+
+var invoke_restart = fn(name, value) {
+    if find_restart(name) then {
+        __ares_set_pending([value]);
+        shift(name) k { nil }
+    } else false
+};
+"#;
+
+// Defines `__ares_<name>()`, which reads a field of the vm's
+// `Conditions`, and `__ares_set_<name>(value)`, which replaces it.
+fn install_field<S: State>(ctx: &mut Context<S>, name: &str, field: fn(&mut Conditions) -> &mut Value) {
+    let conditions = ctx.vm.conditions.clone();
+    ctx.set_global(&format!("__ares_{}", name)[..], user_function::<S, _>(None, move |_, _, _| {
+        field(&mut conditions.borrow_mut()).clone()
+    }));
+    let conditions = ctx.vm.conditions.clone();
+    ctx.set_global(&format!("__ares_set_{}", name)[..], user_function::<S, _>(None, move |args, _, ctx| {
+        if ctx.check_arity(&args, 1) {
+            *field(&mut conditions.borrow_mut()) = args[0].clone();
+        }
+        Value::Nil
+    }));
+}
+
+impl <S: State> Context<S> {
+    /// Defines a condition system in the style of Common Lisp, built on
+    /// `reset` and `shift`:
+    ///
+    /// * `with_handler(handler, body)` calls `body()` with `handler`
+    ///   installed.
+    /// * `signal(condition)` calls every installed handler with
+    ///   `condition`, innermost first, without unwinding the stack.  While
+    ///   a handler runs only the handlers outside of it are installed.  A
+    ///   handler that returns declines the condition, and `signal` returns
+    ///   `nil` once every handler has declined.
+    /// * `with_restart(name, body, recover)` calls `body()`.  If
+    ///   `invoke_restart(name, value)` is called while it runs, the stack
+    ///   is unwound to the innermost `with_restart` for the symbol `name`,
+    ///   which returns `recover(value)`.
+    /// * `find_restart(name)` returns whether a restart is established.
+    ///   `invoke_restart` returns `false` instead of unwinding if it isn't.
+    ///
+    /// The handlers and restarts that were established when a script
+    /// fails are dropped along with it.
+    ///
+    /// The definitions are written in Ares, so unlike the other builtins
+    /// they have to be run with a state.
+    pub fn install_conditions(&mut self, state: &mut S) -> AresResult<()> {
+        install_field(self, "handlers", |c| &mut c.handlers);
+        install_field(self, "restarts", |c| &mut c.restarts);
+        install_field(self, "pending", |c| &mut c.pending);
+        self.eval(state, CONDITIONS).map(|_| ())
+    }
+}
+
+#[test]
+fn handlers_choose_restarts() {
+    use vm::Value;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_conditions(&mut state).unwrap();
+    ctx.eval(&mut state, "
+        var seen = nil;
+        var risky = fn(x) {
+            with_restart('use_value, fn() {
+                signal('bad_input);
+                x * 10
+            }, fn(v) { v })
+        };
+    ").unwrap();
+
+    let mut eval = |program: &str| ctx.eval(&mut state, program).unwrap();
+
+    // Without a handler, or with one that declines, signal returns.
+    assert_eq!(eval("risky(2)"), Some(Value::Int(20)));
+    assert_eq!(eval("with_handler(fn(c) { seen = c; }, fn() { risky(2) + 1 })"), Some(Value::Int(21)));
+    assert_eq!(eval("seen == 'bad_input"), Some(Value::Bool(true)));
+
+    // A handler that invokes the restart unwinds back to `risky`.
+    assert_eq!(eval("with_handler(fn(c) { invoke_restart('use_value, 7) }, fn() { risky(2) + 1 })"),
+               Some(Value::Int(8)));
+    assert_eq!(eval("with_handler(fn(c) { invoke_restart('use_value, 7) }, fn() { \
+                         with_handler(fn(c) { seen = 'inner; }, fn() { risky(3) }) \
+                     })"),
+               Some(Value::Int(7)));
+    assert_eq!(eval("seen == 'inner"), Some(Value::Bool(true)));
+
+    // Unwinding puts the handlers and restarts back.
+    assert_eq!(eval("__ares_handlers() == nil"), Some(Value::Bool(true)));
+    assert_eq!(eval("find_restart('use_value)"), Some(Value::Bool(false)));
+    assert_eq!(eval("invoke_restart('use_value, 1)"), Some(Value::Bool(false)));
+}
+
+#[test]
+fn failures_drop_their_handlers() {
+    use vm::Value;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_conditions(&mut state).unwrap();
+    ctx.eval(&mut state, "var seen = nil;").unwrap();
+
+    assert!(ctx.eval(&mut state, "with_handler(fn(c) { seen = c; }, fn() { 1 / 0 })").is_err());
+    assert!(ctx.eval(&mut state, "with_restart('retry, fn() { 1 / 0 }, fn(v) { v })").is_err());
+
+    assert_eq!(ctx.eval(&mut state, "signal('later); seen").unwrap(), Some(Value::Nil));
+    assert_eq!(ctx.eval(&mut state, "find_restart('retry)").unwrap(), Some(Value::Bool(false)));
+}
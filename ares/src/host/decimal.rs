@@ -0,0 +1,68 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, Decimal, user_function};
+
+impl <S: State> Context<S> {
+    /// Defines the builtins for decimals:
+    ///
+    /// * `decimal(x)` makes a decimal from an int, or from a string of
+    ///   digits like `"1.50"`.
+    /// * `round_decimal(d, places)` rounds to `places` digits after the
+    ///   point, sending ties to the even digit.
+    ///
+    /// Each returns `nil` if its arguments are invalid.
+    pub fn install_decimal_builtins(&mut self) {
+        self.set_global("decimal", user_function::<S, _>(Some("decimal".into()), |args, _, _| {
+            let decimal = match args.get(0) {
+                Some(&Value::Int(i)) => Some(Decimal::from_int(i)),
+                Some(&Value::Decimal(ref d)) => Some(**d),
+                Some(other) => other.expect_str().ok().and_then(Decimal::parse),
+                None => None,
+            };
+            decimal.map_or(Value::Nil, Decimal::into_value)
+        }));
+
+        self.set_global("round_decimal", user_function::<S, _>(Some("round_decimal".into()), |args, _, _| {
+            match (args.get(0), args.get(1)) {
+                (Some(&Value::Decimal(ref d)), Some(&Value::Int(places))) if places >= 0 =>
+                    d.round(places as u32).into_value(),
+                _ => Value::Nil,
+            }
+        }));
+    }
+}
+
+#[test]
+fn decimal_literals() {
+    use compiler::CompileError;
+    use compiler::binding::BindingError;
+    use host::AresError;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_decimal_builtins();
+    ctx.install_numeric_builtins();
+
+    let eval = |ctx: &mut Context<()>, program: &str| {
+        let value = ctx.eval(&mut (), program).unwrap().unwrap();
+        ::host::ContextLike::format_value(ctx, &value)
+    };
+    assert_eq!(eval(&mut ctx, "0.10d + 0.20d"), "0.30d");
+    assert_eq!(eval(&mut ctx, "1.50d * 3"), "4.50d");
+    assert_eq!(eval(&mut ctx, "round_decimal(10d / 3, 2)"), "3.33d");
+    assert_eq!(eval(&mut ctx, "decimal(\"19.99\") - decimal(5)"), "14.99d");
+    assert_eq!(eval(&mut ctx, "to_float(2.5d)"), "2.5");
+    assert_eq!(ctx.eval(&mut state, "0.10d + 0.20d == 0.3d"), Ok(Some(Value::Bool(true))));
+    assert_eq!(ctx.eval(&mut state, "1.5d < 2"), Ok(Some(Value::Bool(true))));
+    assert!(ctx.eval(&mut state, "1.5d + 0.5").is_err());
+
+    // Literals are constants, so they don't depend on any global.
+    assert_eq!(eval(&mut ctx, "var __ares_decimal = nil; 2.25d"), "2.25d");
+    let program = ctx.compile("[1.50d, 0.001d]").unwrap();
+    let program = ::compiler::CompiledProgram::from_bytes(&program.to_bytes()).unwrap();
+    let value = ctx.run(&mut state, &program).unwrap().unwrap();
+    assert_eq!(::host::ContextLike::format_value(&ctx, &value), "[1.50d, 0.001d]");
+    match ctx.eval(&mut state, "123456789012345678901234567890d") {
+        Err(AresError::CompileError(CompileError::BindingError(BindingError::DecimalOutOfRange(_)))) => {}
+        other => panic!("expected DecimalOutOfRange, got {:?}", other),
+    }
+}
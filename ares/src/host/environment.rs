@@ -0,0 +1,47 @@
+use std::env;
+
+use host::{Context, ContextLike, State};
+use vm::{Value, VmOptions, user_function};
+
+fn allowed(options: &VmOptions) -> bool {
+    options.allow_environment && !options.deterministic
+}
+
+impl <S: State> Context<S> {
+    /// Defines `env(name)`, which returns the value of an environment
+    /// variable, and `argv()`, which returns the arguments that the
+    /// process was started with.  Both return `nil` unless
+    /// `VmOptions::allow_environment` is set (and `deterministic` isn't),
+    /// and `env` also returns `nil` for variables that aren't set.
+    pub fn install_environment_builtins(&mut self) {
+        self.set_global("env", user_function::<S, _>(Some("env".into()), |args, _, ctx| {
+            if !allowed(ctx.vm_options_mut()) {
+                return Value::Nil;
+            }
+            match args.get(0).map(Value::expect_str) {
+                Some(Ok(name)) => env::var(name).map_or(Value::Nil, Value::from),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("argv", user_function::<S, _>(Some("argv".into()), |_, _, ctx| {
+            if !allowed(ctx.vm_options_mut()) {
+                return Value::Nil;
+            }
+            env::args().collect::<Vec<String>>().into()
+        }));
+    }
+}
+
+#[test]
+fn environment_needs_permission() {
+    env::set_var("ARES_ENVIRONMENT_TEST", "yes");
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_environment_builtins();
+    assert_eq!(ctx.eval(&mut state, "env(\"ARES_ENVIRONMENT_TEST\")").unwrap(), Some(Value::Nil));
+
+    ctx.set_vm_options(VmOptions { allow_environment: true, .. VmOptions::default() });
+    assert_eq!(ctx.eval(&mut state, "env(\"ARES_ENVIRONMENT_TEST\")").unwrap(), Some("yes".into()));
+    assert_eq!(ctx.eval(&mut state, "argv() == nil").unwrap(), Some(Value::Bool(false)));
+}
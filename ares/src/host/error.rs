@@ -1,8 +1,15 @@
-use compiler::CompileError;
+use std::error::Error;
+use std::fmt;
+
+use ares_syntax::SymbolIntern;
+use compiler::{BindingError, CompileError, Diagnostic, ParseError};
 use vm::InterpError;
 
 pub type AresResult<A> = Result<A, AresError>;
 
+/// Anything that can go wrong while compiling or running a script.  It
+/// converts from each of the errors that it holds, so `?` works on all of
+/// them, and into a `Box<Error>`.
 #[derive(Eq, PartialEq, Debug)]
 pub enum AresError {
     CompileError(CompileError),
@@ -20,3 +27,75 @@ impl From<InterpError> for AresError {
         AresError::InterpError(ie)
     }
 }
+
+impl From<ParseError> for AresError {
+    fn from(pe: ParseError) -> AresError {
+        AresError::CompileError(pe.into())
+    }
+}
+
+impl From<BindingError> for AresError {
+    fn from(be: BindingError) -> AresError {
+        AresError::CompileError(be.into())
+    }
+}
+
+impl AresError {
+    /// Describes this error with the names of the symbols in it and the
+    /// values that it holds.  Use the interner of the context that the
+    /// error came from.
+    pub fn message(&self, interner: &SymbolIntern) -> String {
+        match self {
+            &AresError::CompileError(ref e) => e.message(interner),
+            &AresError::InterpError(ref e) => e.message(interner),
+        }
+    }
+
+    /// Describes this error as diagnostics, which can be rendered or
+    /// written as JSON with `diagnostics_to_json`.
+    pub fn to_diagnostics(&self, interner: &SymbolIntern) -> Vec<Diagnostic> {
+        match self {
+            &AresError::CompileError(ref e) => e.to_diagnostics(interner),
+            &AresError::InterpError(ref e) => vec![e.to_diagnostic(interner)],
+        }
+    }
+}
+
+impl fmt::Display for AresError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &AresError::CompileError(ref e) => e.fmt(f),
+            &AresError::InterpError(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for AresError {
+    fn source(&self) -> Option<&(Error + 'static)> {
+        match self {
+            &AresError::CompileError(ref e) => Some(e),
+            &AresError::InterpError(ref e) => Some(e),
+        }
+    }
+}
+
+#[test]
+fn errors_chain() {
+    use host::{Context, ContextLike};
+
+    let mut ctx: Context<()> = Context::new();
+    let error = ctx.eval(&mut (), "1 +").unwrap_err();
+    assert_eq!(error.to_string(), "unexpected end of input");
+    assert!(error.source().unwrap().downcast_ref::<CompileError>().is_some());
+    assert!(error.source().unwrap().source().unwrap().downcast_ref::<ParseError>().is_some());
+
+    let error = ctx.eval(&mut (), "[1]['b]").unwrap_err();
+    assert_eq!(error.to_string(), "expected an int, got a symbol");
+    assert_eq!(error.message(ctx.interner()), "expected an int, got a symbol `'b`");
+
+    fn run(ctx: &mut Context<()>) -> Result<(), Box<Error>> {
+        ctx.eval(&mut (), "[1][5]")?;
+        Ok(())
+    }
+    assert_eq!(run(&mut ctx).unwrap_err().to_string(), "index 5 is out of bounds for length 1");
+}
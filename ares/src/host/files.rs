@@ -0,0 +1,165 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use host::{Context, ContextLike, State};
+use vm::{Value, VmOptions, user_function};
+
+/// Allows scripts to use the file builtins.  Without a policy in
+/// `VmOptions::file_system` every file builtin returns `nil`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FileSystemPolicy {
+    /// When set, paths are resolved relative to this directory and may
+    /// not leave it.
+    pub root: Option<PathBuf>,
+    pub allow_writes: bool,
+}
+
+impl FileSystemPolicy {
+    /// Allows reading anything that the host process can read.
+    pub fn read_only() -> FileSystemPolicy {
+        FileSystemPolicy { root: None, allow_writes: false }
+    }
+
+    /// Allows reading and writing inside of `root` only.
+    pub fn jailed<P: Into<PathBuf>>(root: P) -> FileSystemPolicy {
+        FileSystemPolicy { root: Some(root.into()), allow_writes: true }
+    }
+
+    /// The path that a script's `path` refers to, or `None` if the policy
+    /// doesn't allow access to it.
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let root = match self.root {
+            Some(ref root) => root,
+            None => return Some(PathBuf::from(path)),
+        };
+
+        let relative = Path::new(path);
+        let escapes = relative.components().any(|c| match c {
+            Component::Normal(_) | Component::CurDir => false,
+            _ => true,
+        });
+        if escapes {
+            return None;
+        }
+
+        // Symlinks inside of the root could still point outside of it.
+        let joined = root.join(relative);
+        let canonical_root = root.canonicalize().ok()?;
+        let existing = match joined.symlink_metadata() {
+            // A symlink to nothing can't be canonicalized, and writing
+            // through it would create whatever it points at.
+            Ok(ref metadata) if metadata.file_type().is_symlink() && !joined.exists() => return None,
+            Ok(_) => joined.clone(),
+            Err(_) => joined.parent()?.to_path_buf(),
+        };
+        if existing.canonicalize().ok()?.starts_with(&canonical_root) {
+            Some(joined)
+        } else {
+            None
+        }
+    }
+}
+
+fn resolve(options: &VmOptions, args: &[Value], write: bool) -> Option<PathBuf> {
+    let path = args.get(0)?.expect_str().ok()?;
+    let policy = options.file_system.as_ref()?;
+    if options.deterministic {
+        return None;
+    }
+    if write && !policy.allow_writes {
+        return None;
+    }
+    policy.resolve(path)
+}
+
+impl <S: State> Context<S> {
+    /// Defines `read_file(path)`, `write_file(path, text)`,
+    /// `file_exists(path)` and `list_dir(path)`.  They only work when
+    /// `VmOptions::file_system` has a policy that allows the access and
+    /// `VmOptions::deterministic` isn't set, and return `nil` otherwise or if the operation fails.  `write_file`
+    /// returns `true` when it succeeds.
+    pub fn install_file_builtins(&mut self) {
+        self.set_global("read_file", user_function::<S, _>(Some("read_file".into()), |args, _, ctx| {
+            resolve(ctx.vm_options_mut(), &args, false)
+                .and_then(|path| fs::read_to_string(path).ok())
+                .map_or(Value::Nil, Value::from)
+        }));
+
+        self.set_global("write_file", user_function::<S, _>(Some("write_file".into()), |args, _, ctx| {
+            let text = match args.get(1).map(Value::expect_str) {
+                Some(Ok(text)) => text.to_string(),
+                _ => return Value::Nil,
+            };
+            resolve(ctx.vm_options_mut(), &args, true)
+                .and_then(|path| fs::write(path, text).ok())
+                .map_or(Value::Nil, |()| Value::Bool(true))
+        }));
+
+        self.set_global("file_exists", user_function::<S, _>(Some("file_exists".into()), |args, _, ctx| {
+            resolve(ctx.vm_options_mut(), &args, false).map_or(Value::Nil, |path| Value::Bool(path.exists()))
+        }));
+
+        self.set_global("list_dir", user_function::<S, _>(Some("list_dir".into()), |args, _, ctx| {
+            let entries = resolve(ctx.vm_options_mut(), &args, false).and_then(|path| fs::read_dir(path).ok());
+            let mut names: Vec<String> = match entries {
+                Some(entries) => entries
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .collect(),
+                None => return Value::Nil,
+            };
+            names.sort();
+            names.into()
+        }));
+    }
+}
+
+#[test]
+fn jailed_access() {
+    use std::env;
+
+    let root = env::temp_dir().join(format!("ares-files-{}", ::std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_file_builtins();
+    assert_eq!(ctx.eval(&mut state, "file_exists(\"a.txt\")").unwrap(), Some(Value::Nil));
+
+    ctx.set_vm_options(VmOptions {
+        file_system: Some(FileSystemPolicy::jailed(root.clone())),
+        .. VmOptions::default()
+    });
+    let result = ctx.eval(&mut state, "
+        write_file(\"a.txt\", \"hello\");
+        [read_file(\"a.txt\"), file_exists(\"a.txt\"), list_dir(\".\"), read_file(\"../a.txt\")]
+    ").unwrap();
+    let expected: Value = vec!["hello".into(), Value::Bool(true), vec!["a.txt"].into(), Value::Nil].into();
+    assert_eq!(result, Some(expected));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_stay_jailed() {
+    use std::env;
+    use std::os::unix::fs::symlink;
+
+    let root = env::temp_dir().join(format!("ares-symlink-{}", ::std::process::id()));
+    let outside = env::temp_dir().join(format!("ares-symlink-{}-outside.txt", ::std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    symlink(&outside, root.join("link.txt")).unwrap();
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_file_builtins();
+    ctx.set_vm_options(VmOptions {
+        file_system: Some(FileSystemPolicy::jailed(root.clone())),
+        .. VmOptions::default()
+    });
+    assert_eq!(ctx.eval(&mut state, "write_file(\"link.txt\", \"escaped\")").unwrap(), Some(Value::Nil));
+    assert!(!outside.exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
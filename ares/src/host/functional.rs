@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use host::{Context, ContextLike, State};
+use util::collections::HashMap;
+use vm::{Value, user_function, forwarding_function};
+
+fn identity<S: State>() -> Value {
+    user_function::<S, _>(Some("identity".into()), |args, _, _| {
+        args.into_iter().next().unwrap_or(Value::Nil)
+    })
+}
+
+/// A function that calls `g` and then `f` with the result.
+fn compose<S: State>(f: Value, g: Value) -> Value {
+    forwarding_function::<S, _>(Some("comp".into()), move |args, _, ctx| {
+        ctx.call_then(g.clone(), args, f.clone());
+        Value::Nil
+    })
+}
+
+/// The results of a memoized function, keyed by the list of arguments that
+/// gave them.  Past `capacity` results, the oldest are dropped.
+struct Memo {
+    results: HashMap<Value, Value>,
+    order: VecDeque<Value>,
+    capacity: Option<usize>,
+}
+
+impl Memo {
+    fn insert(&mut self, key: Value, result: Value) {
+        if self.results.insert(key.clone(), result).is_none() {
+            self.order.push_back(key);
+        }
+        if let Some(capacity) = self.capacity {
+            while self.order.len() > capacity {
+                let oldest = self.order.pop_front().unwrap();
+                self.results.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines the builtins that make new functions out of old ones.
+    ///
+    /// * `identity(x)` returns `x`.
+    /// * `constantly(x)` returns a function that ignores its arguments and
+    ///   returns `x`.
+    /// * `flip(f)` returns a function that calls `f` with its first two
+    ///   arguments swapped.
+    /// * `partial(f, a, b, ...)` returns a function that calls `f` with
+    ///   `a, b, ...` followed by its own arguments.
+    /// * `comp(f, g, ...)` returns a function that calls the last function
+    ///   with its arguments, and then each function before it with the
+    ///   result of the one after, so `comp(f, g)(x)` is `f(g(x))`.
+    ///   `comp()` is `identity`.
+    /// * `apply(f, list)` calls `f` with the items of `list`.
+    /// * `memoize(f)` returns a function that calls `f` once for each
+    ///   list of arguments and remembers the result.  `memoize(f, size)`
+    ///   only remembers the last `size` results.
+    ///
+    /// Each returns `nil` if it is given something that can't be called.
+    pub fn install_functional_builtins(&mut self) {
+        self.set_global("identity", identity::<S>());
+
+        self.set_global("constantly", user_function::<S, _>(Some("constantly".into()), |args, _, _| {
+            let value = args.into_iter().next().unwrap_or(Value::Nil);
+            user_function::<S, _>(Some("constantly".into()), move |_, _, _| value.clone())
+        }));
+
+        self.set_global("flip", user_function::<S, _>(Some("flip".into()), |args, _, _| {
+            let f = match args.into_iter().next() {
+                Some(ref f) if f.is_callable() => f.clone(),
+                _ => return Value::Nil,
+            };
+            forwarding_function::<S, _>(Some("flip".into()), move |mut args, _, ctx| {
+                if args.len() >= 2 {
+                    args.swap(0, 1);
+                }
+                ctx.tail_call(f.clone(), args);
+                Value::Nil
+            })
+        }));
+
+        self.set_global("partial", user_function::<S, _>(Some("partial".into()), |args, _, _| {
+            let mut args = args.into_iter();
+            let f = match args.next() {
+                Some(ref f) if f.is_callable() => f.clone(),
+                _ => return Value::Nil,
+            };
+            let bound: Vec<Value> = args.collect();
+            forwarding_function::<S, _>(Some("partial".into()), move |args, _, ctx| {
+                let mut all = bound.clone();
+                all.extend(args);
+                ctx.tail_call(f.clone(), all);
+                Value::Nil
+            })
+        }));
+
+        self.set_global("comp", user_function::<S, _>(Some("comp".into()), |mut args, _, _| {
+            if !args.iter().all(Value::is_callable) {
+                return Value::Nil;
+            }
+            let last = match args.pop() {
+                Some(last) => last,
+                None => return identity::<S>(),
+            };
+            args.into_iter().rev().fold(last, |g, f| compose::<S>(f, g))
+        }));
+
+        self.set_global("apply", forwarding_function::<S, _>(Some("apply".into()), |args, _, ctx| {
+            match (args.get(0), args.get(1)) {
+                (Some(f), Some(&Value::List(ref items))) if f.is_callable() => {
+                    ctx.tail_call(f.clone(), items.to_vec());
+                }
+                _ => {}
+            }
+            Value::Nil
+        }));
+
+        self.set_global("memoize", user_function::<S, _>(Some("memoize".into()), |args, _, _| {
+            let f = match args.get(0) {
+                Some(f) if f.is_callable() => f.clone(),
+                _ => return Value::Nil,
+            };
+            let capacity = match args.get(1) {
+                None => None,
+                Some(&Value::Int(size)) if size >= 0 => Some(size as usize),
+                Some(_) => return Value::Nil,
+            };
+            let memo = Rc::new(RefCell::new(Memo {
+                results: HashMap::new(),
+                order: VecDeque::new(),
+                capacity: capacity,
+            }));
+            forwarding_function::<S, _>(Some("memoize".into()), move |args, _, ctx| {
+                let key = Value::list(args.clone());
+                if let Some(result) = memo.borrow().results.get(&key) {
+                    return result.clone();
+                }
+                // Storing the result is left out of replay logs as well, so
+                // that a replay fills in the same results.
+                let memo = memo.clone();
+                let store = forwarding_function::<S, _>(Some("memoize".into()), move |args, _, _| {
+                    let result = args.into_iter().next().unwrap_or(Value::Nil);
+                    memo.borrow_mut().insert(key.clone(), result.clone());
+                    result
+                });
+                ctx.call_then(f.clone(), args, store);
+                Value::Nil
+            })
+        }));
+    }
+}
+
+#[test]
+fn functions_from_functions() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_functional_builtins();
+    ctx.install_collection_builtins();
+    let mut eval = |source: &str| ctx.eval(&mut state, source).unwrap().unwrap_or(Value::Nil);
+
+    eval("var sub = fn(a, b) { a - b };");
+    eval("var inc = fn(x) { x + 1 };");
+    eval("var double = fn(x) { x * 2 };");
+    assert_eq!(eval("identity(5)"), Value::Int(5));
+    assert_eq!(eval("constantly(3)(1, 2)"), Value::Int(3));
+    assert_eq!(eval("flip(sub)(1, 10)"), Value::Int(9));
+    assert_eq!(eval("partial(sub, 10)(4)"), Value::Int(6));
+    assert_eq!(eval("comp(inc, double)(5)"), Value::Int(11));
+    assert_eq!(eval("comp(double, inc, partial(sub, 10))(4)"), Value::Int(14));
+    assert_eq!(eval("comp()(7)"), Value::Int(7));
+    assert_eq!(eval("apply(sub, [7, 2])"), Value::Int(5));
+    // Natives can be forwarded to, and forwarded calls nest.
+    assert_eq!(eval("apply(partial(zip, [1, 2]), [['a, 'b]])"), eval("[[1, 'a], [2, 'b]]"));
+    assert_eq!(eval("1 + apply(partial(apply, sub), [[10, 3]])"), Value::Int(8));
+    assert_eq!(eval("partial(5, 1)"), Value::Nil);
+}
+
+#[test]
+fn memoized_functions() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_functional_builtins();
+    let mut eval = |source: &str| ctx.eval(&mut state, source).unwrap().unwrap_or(Value::Nil);
+
+    eval("var calls = 0;");
+    eval("var double = memoize(fn(x) { calls = calls + 1; x * 2 });");
+    assert_eq!(eval("[double(2), double(2), double(3), calls]"), eval("[4, 4, 6, 2]"));
+
+    eval("calls = 0;");
+    eval("var last = memoize(fn(x) { calls = calls + 1; x }, 1);");
+    assert_eq!(eval("[last(1), last(1), last(2), last(1), calls]"), eval("[1, 1, 2, 1, 3]"));
+
+    eval("var fib = memoize(fn(n) { if n < 2 then n else fib(n - 1) + fib(n - 2) });");
+    assert_eq!(eval("fib(80)"), Value::Int(23416728348467685));
+    assert_eq!(eval("memoize(fn(x) { x }, 0 - 1)"), Value::Nil);
+}
@@ -0,0 +1,155 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{self, Poll};
+
+use host::{Conditions, Context, State, AresResult};
+use vm::Value;
+
+/// A script that may be waiting on async native functions.  Created by
+/// `Context::eval_async`.
+pub struct EvalFuture<'a, S: State + 'a> {
+    context: &'a mut Context<S>,
+    state: &'a mut S,
+    /// The code to load the first time that this is polled.
+    source: Option<String>,
+    previous_stack_size: u32,
+    previous_frame_count: usize,
+    /// The condition handlers and restarts to put back if the script
+    /// fails or is dropped.
+    previous_conditions: Conditions,
+}
+
+impl <S: State> Context<S> {
+    /// Evaluates `program`, suspending whenever it calls a function made
+    /// with `async_function` until the returned future completes.
+    pub fn eval_async<'a>(&'a mut self, state: &'a mut S, program: &str) -> EvalFuture<'a, S> {
+        let previous_frame_count = self.vm.frames.len();
+        let previous_conditions = self.vm.conditions.borrow().clone();
+        EvalFuture {
+            context: self,
+            state: state,
+            source: Some(program.to_string()),
+            previous_stack_size: 0,
+            previous_frame_count: previous_frame_count,
+            previous_conditions: previous_conditions,
+        }
+    }
+}
+
+impl <'a, S: State> Future for EvalFuture<'a, S> {
+    type Output = AresResult<Option<Value>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Some(source) = this.source.take() {
+            let instrs = this.context.compile_in_place(&source)?;
+            let vm = &mut this.context.vm;
+            let start = vm.code.len();
+            vm.code.extend(instrs);
+            vm.global_caches.grow_to(vm.code.len());
+
+            this.previous_stack_size = vm.stack.len();
+            if let Err(e) = vm.start_at(start, this.state) {
+                *vm.conditions.borrow_mut() = this.previous_conditions.clone();
+                return Poll::Ready(Err(e.into()));
+            }
+        }
+
+        while this.context.vm.is_suspended() {
+            match this.context.vm.poll_suspension(this.state, cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => {
+                    *this.context.vm.conditions.borrow_mut() = this.previous_conditions.clone();
+                    return Poll::Ready(Err(e.into()));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(this.context.take_result(this.previous_stack_size))
+    }
+}
+
+// A future that is dropped before it finishes leaves its script
+// suspended, so everything that the script pushed is thrown away.
+impl <'a, S: State + 'a> Drop for EvalFuture<'a, S> {
+    fn drop(&mut self) {
+        let vm = &mut self.context.vm;
+        if vm.suspension.take().is_some() {
+            vm.frames.truncate(self.previous_frame_count);
+            let _ = vm.stack.truncate(self.previous_stack_size as usize);
+            *vm.conditions.borrow_mut() = self.previous_conditions.clone();
+        }
+    }
+}
+
+#[test]
+fn resumes_after_native_future() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::task::Waker;
+    use host::ContextLike;
+    use vm::async_function;
+
+    // Completes on the second poll.
+    struct Delayed {
+        value: i64,
+        polled: Rc<Cell<u32>>,
+    }
+
+    impl Future for Delayed {
+        type Output = Value;
+
+        fn poll(self: Pin<&mut Self>, _: &mut task::Context) -> Poll<Value> {
+            let polls = self.polled.get() + 1;
+            self.polled.set(polls);
+            if polls % 2 == 0 { Poll::Ready(Value::Int(self.value)) } else { Poll::Pending }
+        }
+    }
+
+    let polled = Rc::new(Cell::new(0));
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    {
+        let polled = polled.clone();
+        ctx.set_global("fetch", async_function::<(), _, _>(Some("fetch".into()), move |args, _, _| {
+            Delayed { value: *args[0].expect_int_ref().unwrap() * 10, polled: polled.clone() }
+        }));
+    }
+
+    assert!(ctx.eval(&mut state, "fetch(1)").is_err());
+
+    let result = {
+        let mut future = ctx.eval_async(&mut state, "var f = fn(x) { fetch(x) + 1 }; f(1) + f(2)");
+        let mut cx = task::Context::from_waker(Waker::noop());
+        let mut result = Pin::new(&mut future).poll(&mut cx);
+        while result.is_pending() {
+            result = Pin::new(&mut future).poll(&mut cx);
+        }
+        result
+    };
+    assert_eq!(result, Poll::Ready(Ok(Some(Value::Int(32)))));
+    assert_eq!(polled.get(), 4);
+}
+
+#[test]
+fn dropping_a_pending_future() {
+    use std::future;
+    use std::task::Waker;
+    use host::ContextLike;
+    use vm::async_function;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_global("never", async_function::<(), _, _>(Some("never".into()), |_, _, _| {
+        future::pending::<Value>()
+    }));
+
+    {
+        let mut future = ctx.eval_async(&mut state, "var f = fn(x) { never() + x }; [1, f(2)]");
+        let mut cx = task::Context::from_waker(Waker::noop());
+        assert!(Pin::new(&mut future).poll(&mut cx).is_pending());
+    }
+    assert_eq!(ctx.eval(&mut state, "1 + 1").unwrap(), Some(Value::Int(2)));
+}
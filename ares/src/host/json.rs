@@ -0,0 +1,139 @@
+use util::collections::HashMap;
+
+use serde_json::{self, Map, Number};
+use serde_json::Value as Json;
+
+use ares_syntax::SymbolIntern;
+use host::{Context, ContextLike, State};
+use vm::{Value, ValueKind, user_function};
+
+// Deeper values are assumed to be cyclic through a cell.
+const MAX_DEPTH: u32 = 512;
+
+#[derive(Debug, PartialEq)]
+pub enum JsonError {
+    /// The text wasn't valid JSON.
+    Parse(String),
+    /// Functions, closures and continuations have no JSON form.
+    Unsupported(ValueKind),
+    /// Object keys have to be symbols or strings.
+    BadKey(Value),
+    /// NaN and the infinities can't be written as JSON numbers.
+    BadFloat(f64),
+    TooDeep,
+}
+
+/// Encodes `value` as JSON text.  Map keys and symbols are written as
+/// strings; cells are written as the value that they contain.
+pub fn value_to_json(value: &Value, interner: &SymbolIntern) -> Result<String, JsonError> {
+    let json = to_json(value, interner, 0)?;
+    Ok(json.to_string())
+}
+
+/// Decodes JSON text.  Numbers that fit in an `i64` become ints and
+/// everything else becomes a float.  Object keys become symbols.
+pub fn json_to_value(text: &str, interner: &mut SymbolIntern) -> Result<Value, JsonError> {
+    let json: Json = serde_json::from_str(text).map_err(|e| JsonError::Parse(e.to_string()))?;
+    Ok(from_json(json, interner))
+}
+
+fn to_json(value: &Value, interner: &SymbolIntern, depth: u32) -> Result<Json, JsonError> {
+    if depth > MAX_DEPTH {
+        return Err(JsonError::TooDeep);
+    }
+
+    Ok(match value {
+        &Value::Nil => Json::Null,
+        &Value::Bool(b) => Json::Bool(b),
+        &Value::Int(i) => Json::Number(i.into()),
+        &Value::Float(f) => Json::Number(Number::from_f64(f).ok_or(JsonError::BadFloat(f))?),
+        // JSON has no fractions, so rationals are written as the nearest float.
+        &Value::Rational(ref r) => Json::Number(Number::from_f64(r.to_f64()).ok_or(JsonError::BadFloat(r.to_f64()))?),
+        // Decimals are written as strings so that their digits survive.
+        #[cfg(feature = "decimal")]
+        &Value::Decimal(ref d) => Json::String(d.digits()),
+        &Value::String(_) | &Value::Substring(_) => Json::String(value.expect_str().unwrap().to_string()),
+        &Value::Symbol(s) => Json::String(interner.lookup_or_anon(s)),
+        &Value::Cell(ref c) => to_json(&*c.borrow(), interner, depth + 1)?,
+        &Value::List(ref items) => {
+            let items: Result<Vec<Json>, JsonError> =
+                items.iter().map(|v| to_json(v, interner, depth + 1)).collect();
+            Json::Array(items?)
+        }
+        &Value::Map(ref pairs) => {
+            let mut object = Map::new();
+            for (k, v) in pairs.borrow().iter() {
+                let key = match k {
+                    &Value::Symbol(s) => interner.lookup_or_anon(s),
+                    &Value::String(_) | &Value::Substring(_) => k.expect_str().unwrap().to_string(),
+                    other => return Err(JsonError::BadKey(other.clone())),
+                };
+                object.insert(key, to_json(v, interner, depth + 1)?);
+            }
+            Json::Object(object)
+        }
+        &Value::Closure(_) => return Err(JsonError::Unsupported(ValueKind::Closure)),
+        &Value::UserFn(_) => return Err(JsonError::Unsupported(ValueKind::UserFn)),
+        &Value::Continuation(_) => return Err(JsonError::Unsupported(ValueKind::Continuation)),
+        &Value::Handle(_) => return Err(JsonError::Unsupported(ValueKind::Handle)),
+    })
+}
+
+fn from_json(json: Json, interner: &mut SymbolIntern) -> Value {
+    match json {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::Bool(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::Float(n.as_f64().unwrap_or(::std::f64::NAN)),
+        },
+        Json::String(s) => s.into(),
+        Json::Array(items) => items.into_iter().map(|j| from_json(j, interner)).collect::<Vec<_>>().into(),
+        Json::Object(object) => {
+            let map: HashMap<Value, Value> = object.into_iter()
+                .map(|(k, v)| (Value::Symbol(interner.intern(k)), from_json(v, interner)))
+                .collect();
+            map.into()
+        }
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines `json_parse(text)` and `json_stringify(value)`.  Both
+    /// return `nil` if the conversion fails.
+    pub fn install_json_builtins(&mut self) {
+        self.set_global("json_parse", user_function::<S, _>(Some("json_parse".into()), |args, _, ctx| {
+            let text = match args.get(0).map(Value::expect_str) {
+                Some(Ok(text)) => text.to_string(),
+                _ => return Value::Nil,
+            };
+            json_to_value(&text, ctx.interner_mut()).unwrap_or(Value::Nil)
+        }));
+
+        self.set_global("json_stringify", user_function::<S, _>(Some("json_stringify".into()), |args, _, ctx| {
+            match args.get(0).map(|v| value_to_json(v, ctx.interner())) {
+                Some(Ok(text)) => text.into(),
+                _ => Value::Nil,
+            }
+        }));
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_json_builtins();
+
+    let text = r#"{"a":[1,2.5,"x",null,true],"b":{}}"#;
+    let value = json_to_value(text, ctx.interner_mut()).unwrap();
+    let back: Json = serde_json::from_str(&value_to_json(&value, ctx.interner()).unwrap()).unwrap();
+    assert_eq!(back, serde_json::from_str::<Json>(text).unwrap());
+
+    let result = ctx.eval(&mut state, "json_stringify(json_parse(\"[1, [2]]\"))").unwrap();
+    assert_eq!(result, Some("[1,[2]]".into()));
+    match value_to_json(&Value::Float(::std::f64::NAN), ctx.interner()) {
+        Err(JsonError::BadFloat(_)) => {}
+        other => panic!("expected BadFloat, got {:?}", other),
+    }
+}
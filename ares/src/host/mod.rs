@@ -1,20 +1,92 @@
-use vm::{Vm, Value, Modules};
+use vm::{Vm, Value, Modules, VmOptions, HostIo, Clock, Rng, Resources, InterpError};
+use compiler::{CompileError, Diagnostic, Diagnostics, Warning};
 use ares_syntax::{Symbol, SymbolIntern};
+use util::collections::HashMap;
 use std::marker::PhantomData;
+use std::rc::Rc;
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::io::Write;
 
+mod bench;
+mod collections;
+mod conditions;
+#[cfg(feature = "decimal")]
+mod decimal;
+#[cfg(feature = "std")]
+mod environment;
 mod error;
+#[cfg(feature = "std")]
+mod files;
+mod functional;
+#[cfg(feature = "async")]
+mod future;
+#[cfg(feature = "serde_json")]
+mod json;
+mod module;
+mod numeric;
+#[cfg(feature = "std")]
+mod print;
+mod random;
+mod record;
+mod sandbox;
+mod scheduler;
+mod sexpr;
+mod snapshot;
 mod state;
+mod string_builder;
+mod strings;
+mod symbols;
+mod template;
+mod testing;
+#[cfg(feature = "std")]
+mod time;
 
+pub use self::bench::{BenchIteration, BenchReport};
+pub(crate) use self::conditions::Conditions;
 pub use self::error::*;
+#[cfg(feature = "std")]
+pub use self::files::FileSystemPolicy;
+pub use self::module::{NativeModule, ModuleDefinitions};
+#[cfg(feature = "async")]
+pub use self::future::EvalFuture;
+#[cfg(feature = "serde_json")]
+pub use self::json::{JsonError, value_to_json, json_to_value};
+pub use self::sexpr::{WriteError, ReadError, write_value, read_value};
+pub use self::record::{IntoAres, FromAres, FromAresError, record_to_map, record_field};
+pub use self::sandbox::SandboxPolicy;
+pub use self::scheduler::Scheduler;
+pub use self::snapshot::Snapshot;
 pub use self::state::State;
+pub use self::template::{Template, TemplateError};
+pub use self::testing::{TestRegistry, TestReport, run_tests};
 
+/// Compiles and runs scripts.  Every context has its own symbols,
+/// globals and options, so contexts never see each other's state; see
+/// `notes/isolation.md` for what is shared.
 pub struct Context<S: State> {
     pub(crate) vm: Vm<S>,
+    pub(crate) diagnostics: Diagnostics,
+    pub(crate) tests: Rc<RefCell<TestRegistry>>,
+    /// Where each program that has been `run` was linked, by program id.
+    pub(crate) linked_programs: HashMap<usize, usize>,
+    pub(crate) scheduler: Rc<RefCell<Scheduler>>,
 }
 
 pub struct EphemeralContext<'a, S: ?Sized + State + 'a> {
     pub(crate) globals: &'a mut Modules,
     pub(crate) interner: &'a mut SymbolIntern,
+    pub(crate) options: &'a mut VmOptions,
+    pub(crate) io: &'a mut HostIo,
+    pub(crate) rng: &'a mut Rng,
+    pub(crate) resources: &'a mut Resources,
+    /// A call for the vm to make once the native function returns.
+    pub(crate) forward: Option<::vm::Forward>,
+    /// An error for the call to the native function to fail with.
+    pub(crate) error: Option<InterpError>,
+    /// A future returned by an async native function.
+    #[cfg(feature = "async")]
+    pub(crate) pending: Option<::vm::NativeFuture>,
     _phantom: PhantomData<S>
 }
 
@@ -25,6 +97,12 @@ pub trait GlobalPath {
 pub trait ContextLike<S: State> {
     fn internals(&self) -> (&Modules, &SymbolIntern);
     fn internals_mut(&mut self) -> (&mut Modules, &mut SymbolIntern);
+    fn vm_options_mut(&mut self) -> &mut VmOptions;
+    /// Where the builtins write their output and read the time from.
+    fn io_mut(&mut self) -> &mut HostIo;
+    fn rng_mut(&mut self) -> &mut Rng;
+    /// Host objects that scripts refer to by `Value::Handle`.
+    fn resources_mut(&mut self) -> &mut Resources;
 
     fn modules(&self) -> &Modules {
         let (m, _) = self.internals();
@@ -80,15 +158,42 @@ pub trait ContextLike<S: State> {
         ::vm::to_string_helper(&value, self.interner())
     }
 
+    fn format_warning(&self, warning: &Warning) -> String {
+        match warning {
+            &Warning::UnusedVariable{name, span} =>
+                format!("UnusedVariable({}) at {:?}", self.interner().lookup_or_anon(name), span),
+            &Warning::UnusedArgument{name, span} =>
+                format!("UnusedArgument({}) at {:?}", self.interner().lookup_or_anon(name), span),
+            &Warning::Shadowing{name, span, original} =>
+                format!("Shadowing({}) at {:?}, originally defined at {:?}",
+                        self.interner().lookup_or_anon(name), span, original),
+            &Warning::TypeMismatch{expected, found, span} =>
+                format!("TypeMismatch(expected {}, found {}) at {:?}", expected.name(), found.name(), span),
+            &Warning::UnknownType{name, span} =>
+                format!("UnknownType({}) at {:?}", self.interner().lookup_or_anon(name), span),
+            &Warning::InvalidOperands{op, left, right, span} =>
+                format!("InvalidOperands({} {} {}) at {:?}", left.name(), op, right.name(), span),
+            &Warning::WrongArity{expected, got, span} =>
+                format!("WrongArity(expected {}, got {}) at {:?}", expected, got, span),
+            &Warning::ConstantCondition{value, span} =>
+                format!("ConstantCondition({}) at {:?}", value, span),
+            &Warning::EmptyBlock{span} => format!("EmptyBlock at {:?}", span),
+            &Warning::LambdaComparison{span} => format!("LambdaComparison at {:?}", span),
+            &Warning::Lint{lint, ref message, span} => format!("{}: {} at {:?}", lint, message, span),
+        }
+    }
+
     fn format_error(&self, error: AresError) -> String {
-        use ::vm::InterpError;
         use ::compiler::CompileError;
         use compiler::binding::BindingError;
 
         match error {
             AresError::CompileError(CompileError::ParseError(pe)) => format!("{:?}", pe),
-            AresError::CompileError(CompileError::BindingError(BindingError::CouldNotBind(s, sp))) =>
+            AresError::CompileError(CompileError::BindingError(BindingError::CouldNotBind(s, sp, None))) =>
                 format!("CouldNotBind({}) at {:?}", self.interner().lookup_or_anon(s), sp),
+            AresError::CompileError(CompileError::BindingError(BindingError::CouldNotBind(s, sp, Some(close)))) =>
+                format!("CouldNotBind({}) at {:?}; did you mean {}?", self.interner().lookup_or_anon(s), sp,
+                        self.interner().lookup_or_anon(close)),
             AresError::CompileError(CompileError::BindingError(BindingError::Multiple(es))) => {
                 let mut s = String::new();
                 for e in es {
@@ -99,6 +204,21 @@ pub trait ContextLike<S: State> {
             }
             AresError::CompileError(CompileError::BindingError(BindingError::AlreadyDefined(s))) =>
                 format!("AlreadyDefined({})", self.interner().lookup_or_anon(s)),
+            AresError::CompileError(CompileError::BindingError(BindingError::SpecialFormCall{name, syntax, span})) =>
+                format!("{} is not a function; ares writes it as `{}` (at {:?})",
+                        self.interner().lookup_or_anon(name), syntax, span),
+            AresError::CompileError(CompileError::BindingError(BindingError::RecurOutsideLoop(span))) =>
+                format!("RecurOutsideLoop at {:?}", span),
+            AresError::CompileError(CompileError::BindingError(BindingError::RecurArity{expected, got, span})) =>
+                format!("RecurArity(expected {}, got {}) at {:?}", expected, got, span),
+            AresError::CompileError(CompileError::BindingError(BindingError::RecurNotInTail(span))) =>
+                format!("RecurNotInTail at {:?}", span),
+            AresError::CompileError(CompileError::BindingError(BindingError::DefineNotAllowedHere(span))) =>
+                format!("DefineNotAllowedHere at {:?}", span),
+            AresError::CompileError(CompileError::BindingError(BindingError::DecimalsUnsupported(span))) =>
+                format!("DecimalsUnsupported at {:?}", span),
+            AresError::CompileError(CompileError::BindingError(BindingError::DecimalOutOfRange(span))) =>
+                format!("DecimalOutOfRange at {:?}", span),
             AresError::CompileError(CompileError::EmitError(_)) => unreachable!(),
 
             AresError::InterpError(InterpError::InternalInterpError(s)) =>
@@ -111,27 +231,103 @@ pub trait ContextLike<S: State> {
             AresError::InterpError(InterpError::StackOutOfBounds) => "StackOutOfBounds".to_string(),
             AresError::InterpError(InterpError::IncomparableValues(a, b)) =>
                 format!("Could not compare {} with {}", self.format_value(&a), self.format_value(&b)),
-            AresError::InterpError(InterpError::BadArity{got, expected}) =>
-                format!("BadArity{{got: {}, expected: {}}}", got, expected),
-            AresError::InterpError(InterpError::UserFnWithWrongStateType) => "UserFnWithWrongStateType".to_string()
+            AresError::InterpError(InterpError::StringIndexOutOfBounds{start, end, len}) =>
+                format!("StringIndexOutOfBounds{{start: {}, end: {}, len: {}}}", start, end, len),
+            AresError::InterpError(InterpError::ListIndexOutOfBounds{index, len}) =>
+                format!("ListIndexOutOfBounds{{index: {}, len: {}}}", index, len),
+            AresError::InterpError(InterpError::DivideByZero) => "DivideByZero".to_string(),
+            AresError::InterpError(InterpError::IntegerOverflow) => "IntegerOverflow".to_string(),
+            AresError::InterpError(InterpError::NumericCoercion{left, right}) =>
+                format!("Can't combine a {:?} with a {:?} without rounding", left, right),
+            AresError::InterpError(InterpError::WrongArity{got, expected, span: Some(span)}) =>
+                format!("WrongArity{{got: {}, expected: {}}} at {:?}", got, expected, span),
+            AresError::InterpError(InterpError::WrongArity{got, expected, span: None}) =>
+                format!("WrongArity{{got: {}, expected: {}}}", got, expected),
+            AresError::InterpError(InterpError::UserFnWithWrongStateType) => "UserFnWithWrongStateType".to_string(),
+            AresError::InterpError(InterpError::NativePanic{name: Some(name), message}) =>
+                format!("NativePanic in {}: {}", name, message),
+            AresError::InterpError(InterpError::NativePanic{name: None, message}) =>
+                format!("NativePanic: {}", message),
+            #[cfg(feature = "async")]
+            AresError::InterpError(InterpError::SuspendedOutsideOfAsync) => "SuspendedOutsideOfAsync".to_string(),
+            AresError::InterpError(InterpError::ContractViolation{kind, span: Some(span)}) =>
+                format!("ContractViolation({:?}) at {:?}", kind, span),
+            AresError::InterpError(InterpError::ContractViolation{kind, span: None}) =>
+                format!("ContractViolation({:?})", kind),
+            AresError::InterpError(InterpError::ReplayDiverged{expected, got}) =>
+                format!("ReplayDiverged{{expected: {:?}, got: {:?}}}", expected, got),
+            AresError::InterpError(InterpError::MutatedMapKey) => "MutatedMapKey".to_string(),
+            AresError::InterpError(InterpError::ShiftWithoutReset) => "ShiftWithoutReset".to_string(),
         }
     }
 }
 
 impl <'a, S: State> EphemeralContext<'a, S> {
-    pub fn new(globals: &'a mut Modules, interner: &'a mut SymbolIntern) -> EphemeralContext<'a, S> {
+    pub fn new(globals: &'a mut Modules, interner: &'a mut SymbolIntern, options: &'a mut VmOptions,
+               io: &'a mut HostIo, rng: &'a mut Rng, resources: &'a mut Resources) -> EphemeralContext<'a, S> {
         EphemeralContext {
             globals: globals,
             interner: interner,
+            options: options,
+            io: io,
+            rng: rng,
+            resources: resources,
+            forward: None,
+            error: None,
+            #[cfg(feature = "async")]
+            pending: None,
             _phantom: PhantomData,
         }
     }
+
+    /// Has the vm call `callee` with `args` once the native function
+    /// returns, and makes the result of that call the result of the native
+    /// function.  The value that the native function returns is ignored.
+    ///
+    /// Native functions that do this should be made with
+    /// `forwarding_function`.
+    pub fn tail_call(&mut self, callee: Value, args: Vec<Value>) {
+        self.forward = Some(::vm::Forward::Tail { callee: callee, args: args });
+    }
+
+    /// Like `tail_call`, but then calls `then` with the result, and makes
+    /// what `then` returns the result of the native function.
+    pub fn call_then(&mut self, callee: Value, args: Vec<Value>, then: Value) {
+        self.forward = Some(::vm::Forward::Then { callee: callee, args: args, then: then });
+    }
+
+    /// Makes the call to the native function fail with `error` once it
+    /// returns.  The value that it returns is ignored.
+    pub fn fail(&mut self, error: InterpError) {
+        self.error = Some(error);
+    }
+
+    /// Fails the call with `WrongArity` unless there are `expected`
+    /// arguments, and returns whether there are.
+    pub fn check_arity(&mut self, args: &[Value], expected: u32) -> bool {
+        if args.len() == expected as usize {
+            return true;
+        }
+        self.fail(InterpError::WrongArity { got: args.len() as u32, expected: expected, span: None });
+        false
+    }
+
+    /// Suspends the calling script until `future` completes.  The value
+    /// that the native function returns is ignored.
+    #[cfg(feature = "async")]
+    pub fn suspend(&mut self, future: ::vm::NativeFuture) {
+        self.pending = Some(future);
+    }
 }
 
 impl <S: State> Context<S> {
     pub fn new() -> Context<S> {
         Context {
             vm: Vm::new(),
+            diagnostics: Diagnostics::new(),
+            tests: Rc::new(RefCell::new(TestRegistry::default())),
+            linked_programs: HashMap::new(),
+            scheduler: Rc::new(RefCell::new(Scheduler::default())),
         }
     }
 
@@ -143,16 +339,207 @@ impl <S: State> Context<S> {
         (stack, instructions, instruction_pointer)
     }
 
+    /// Statistics about the constants that have been compiled into this context.
+    pub fn constant_pool_stats(&self) -> ::compiler::ConstantPoolStats {
+        self.vm.compile_context.pool_stats()
+    }
+
+    /// The lambdas that `closure` was written inside of, innermost first.
+    pub fn enclosing_lambdas(&self, closure: &::vm::Closure) -> Vec<&::vm::ClosureClass> {
+        self.vm.compile_context.enclosing_classes(&closure.class)
+    }
+
+    /// Starts or stops counting how many times each piece of code runs.
+    /// Turning coverage off discards the counts.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        match (enabled, self.vm.coverage.is_some()) {
+            (true, false) => self.vm.coverage = Some(::vm::Coverage::new()),
+            (false, true) => self.vm.coverage = None,
+            _ => {}
+        }
+    }
+
+    /// Reports how many times the code for each span that has been
+    /// evaluated has run.  Returns `None` if coverage isn't turned on.
+    pub fn coverage_report(&self) -> Option<::vm::CoverageReport> {
+        self.vm.coverage.as_ref().map(|c| c.report(self.vm.compile_context.spans()))
+    }
+
+    /// Changes how programs passed to `eval` are compiled.
+    pub fn set_compile_options(&mut self, options: ::compiler::CompileOptions) {
+        self.vm.compile_context.set_options(options);
+    }
+
+    /// Changes how the vm runs code.  The random number generator is
+    /// reseeded if `options.rng_seed` is set, or with 0 if
+    /// `options.deterministic` is.
+    pub fn set_vm_options(&mut self, options: VmOptions) {
+        let seed = match options.rng_seed {
+            None if options.deterministic => Some(0),
+            seed => seed,
+        };
+        if let Some(seed) = seed {
+            self.vm.rng = Rng::new(seed);
+        }
+        self.vm.options = options;
+    }
+
+    /// Sends what the print builtins write to `stdout`, and what `eprint`
+    /// writes to `stderr`, instead of to the process's own.
+    #[cfg(feature = "std")]
+    pub fn set_output(&mut self, stdout: Box<Write>, stderr: Box<Write>) {
+        self.vm.io.stdout = stdout;
+        self.vm.io.stderr = stderr;
+    }
+
+    /// Replaces the clock that `now` reads the time from.
+    pub fn set_clock(&mut self, clock: Box<Clock>) {
+        self.vm.io.clock = clock;
+    }
+
+    /// Starts recording the result of every native function call into a
+    /// `ReplayLog`, replacing any recording or replay in progress.
+    pub fn start_recording(&mut self) {
+        self.vm.replay = ::vm::Replay::Recording(::vm::ReplayLog::new());
+    }
+
+    /// Feeds the results in `log` back to the script instead of calling
+    /// native functions, so a recorded run can be reproduced exactly.  A
+    /// call that doesn't match the log fails with
+    /// `InterpError::ReplayDiverged`.
+    pub fn start_replaying(&mut self, log: ::vm::ReplayLog) {
+        self.vm.replay = ::vm::Replay::Replaying { log: log, next: 0 };
+    }
+
+    /// Stops recording or replaying, returning the log that was recorded
+    /// or replayed.
+    pub fn stop_replay(&mut self) -> Option<::vm::ReplayLog> {
+        match ::std::mem::replace(&mut self.vm.replay, ::vm::Replay::Off) {
+            ::vm::Replay::Off => None,
+            ::vm::Replay::Recording(log) | ::vm::Replay::Replaying { log, .. } => Some(log),
+        }
+    }
+
+    /// Replaces the callbacks that run as code executes.  See `Hooks`.
+    pub fn set_hooks(&mut self, hooks: ::vm::Hooks) {
+        self.vm.set_hooks(hooks);
+    }
+
+    pub fn collect_garbage(&mut self) {
+        self.vm.collect_garbage();
+    }
+
+    /// Turns on warnings for definitions that shadow an outer variable.
+    pub fn warn_on_shadowing(&mut self, enabled: bool) {
+        self.diagnostics.set_warn_on_shadowing(enabled);
+    }
+
+    /// The lints that run when code is compiled.  Register a `Lint` here
+    /// to add checks that are specific to a project.
+    pub fn lints_mut(&mut self) -> &mut ::compiler::LintRegistry {
+        self.diagnostics.lints_mut()
+    }
+
+    /// Turns on checking type annotations, like `fn(x: int)`, when
+    /// programs are compiled.  Mismatches are reported as warnings.
+    pub fn check_types(&mut self, enabled: bool) {
+        self.diagnostics.set_check_types(enabled);
+    }
+
+    /// The warnings produced while compiling the most recently evaluated program.
+    pub fn warnings(&self) -> &[Warning] {
+        self.diagnostics.warnings()
+    }
+
+    /// Everything that was found while compiling the most recently
+    /// evaluated or compiled program: the errors that stopped it, if it
+    /// failed, followed by the warnings.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.to_diagnostics(&self.vm.interner)
+    }
+
+    /// Lists the variables that are in scope at `cursor_offset` in
+    /// `source`, including the globals defined in this context.
+    pub fn completions(&mut self, source: &str, cursor_offset: u32)
+    -> Result<Vec<::tooling::Completion>, ::compiler::CompileError> {
+        let &mut Vm{ ref mut interner, ref globals, .. } = &mut self.vm;
+        ::tooling::completions_with_globals(source, cursor_offset, Some(globals), interner)
+    }
+
     pub fn eval(&mut self, state: &mut S, program: &str) -> AresResult<Option<Value>> {
+        let instrs = try!(self.compile_in_place(program));
+        let previous_stack_size = self.vm.stack.len();
+        try!(self.vm.load_and_execute(&instrs[..], 0, state));
+        self.take_result(previous_stack_size)
+    }
+
+    // Compiles code that will be loaded at the end of this vm's code.
+    fn compile_in_place(&mut self, program: &str) -> AresResult<Vec<::vm::Instr>> {
         let emitted_code_size = self.vm.code.len();
+        self.diagnostics.clear();
+
+        let &mut Vm{ ref mut compile_context, ref mut interner, ref globals, .. } = &mut self.vm;
+        let result = ::compiler::compile(program, compile_context, Some(globals), interner,
+                                         &mut self.diagnostics, emitted_code_size);
+        Ok(try!(report_errors(result, interner, &mut self.diagnostics)))
+    }
+
+    /// Compiles `source` against the globals of this context without
+    /// running it.  The program can be run any number of times with
+    /// `run`, here or in any other context; it is `Send` and `Sync`, so
+    /// it can be shared between threads in an `Arc`.
+    pub fn compile(&mut self, source: &str) -> AresResult<::compiler::CompiledProgram> {
+        self.diagnostics.clear();
+        let &mut Vm{ ref mut interner, ref globals, .. } = &mut self.vm;
+        let result = ::compiler::CompiledProgram::compile(source, Some(globals), interner);
+        Ok(try!(report_errors(result, interner, &mut self.diagnostics)))
+    }
+
+    /// Parses and binds `source` against the globals of this context
+    /// without compiling or running it, to find the problems with it.
+    /// They are in `diagnostics` afterwards, along with any warnings.
+    pub fn check(&mut self, source: &str) -> AresResult<()> {
+        self.diagnostics.clear();
+        let &mut Vm{ ref compile_context, ref mut interner, ref globals, .. } = &mut self.vm;
+        let result = ::compiler::check(source, compile_context, Some(globals), interner, &mut self.diagnostics);
+        Ok(try!(report_errors(result, interner, &mut self.diagnostics)))
+    }
+
+    /// Runs a compiled program.  A program is only linked into a context
+    /// the first time that it is run there.
+    pub fn run(&mut self, state: &mut S, program: &::compiler::CompiledProgram) -> AresResult<Option<Value>> {
+        let start = self.link(program);
+        self.execute_linked(state, start)
+    }
 
-        let instrs = {
-            let &mut Vm{ ref mut compile_context, ref mut interner, ref globals, .. } = &mut self.vm;
-            try!(::compiler::compile(program, compile_context, Some(globals), interner, emitted_code_size))
+    // Links `program` if it hasn't been already, and returns where it starts.
+    pub(crate) fn link(&mut self, program: &::compiler::CompiledProgram) -> usize {
+        if let Some(start) = self.linked_programs.get(&program.id()).cloned() {
+            return start;
+        }
+        let start = {
+            let &mut Vm{ ref mut code, ref mut compile_context, ref mut interner, .. } = &mut self.vm;
+            program.link(code, compile_context, interner)
         };
+        self.vm.global_caches.grow_to(self.vm.code.len());
+        self.linked_programs.insert(program.id(), start);
+        start
+    }
 
+    pub(crate) fn execute_linked(&mut self, state: &mut S, start: usize) -> AresResult<Option<Value>> {
         let previous_stack_size = self.vm.stack.len();
-        try!(self.vm.load_and_execute(&instrs[..], 0, state));
+        try!(self.vm.execute_at(start, 0, state));
+        self.take_result(previous_stack_size)
+    }
+
+    /// Calls a function value, like a closure that a script handed to a
+    /// native function, with `args`, and returns its result.
+    pub fn call(&mut self, state: &mut S, function: Value, args: Vec<Value>) -> AresResult<Value> {
+        Ok(try!(self.vm.call(function, args, state)))
+    }
+
+    // A program leaves at most one value on the stack.
+    fn take_result(&mut self, previous_stack_size: u32) -> AresResult<Option<Value>> {
         let new_stack_size = self.vm.stack.len();
         assert!(new_stack_size == previous_stack_size ||
                 new_stack_size == previous_stack_size + 1);
@@ -174,6 +561,22 @@ impl <S: State> ContextLike<S> for Context<S> {
     fn internals_mut(&mut self) -> (&mut Modules, &mut SymbolIntern) {
         (&mut self.vm.globals, &mut self.vm.interner)
     }
+
+    fn vm_options_mut(&mut self) -> &mut VmOptions {
+        &mut self.vm.options
+    }
+
+    fn io_mut(&mut self) -> &mut HostIo {
+        &mut self.vm.io
+    }
+
+    fn rng_mut(&mut self) -> &mut Rng {
+        &mut self.vm.rng
+    }
+
+    fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.vm.resources
+    }
 }
 
 impl <'a, S: State> ContextLike<S> for EphemeralContext<'a, S> {
@@ -184,6 +587,22 @@ impl <'a, S: State> ContextLike<S> for EphemeralContext<'a, S> {
     fn internals_mut(&mut self) -> (&mut Modules, &mut SymbolIntern) {
         (self.globals, self.interner)
     }
+
+    fn vm_options_mut(&mut self) -> &mut VmOptions {
+        &mut *self.options
+    }
+
+    fn io_mut(&mut self) -> &mut HostIo {
+        &mut *self.io
+    }
+
+    fn rng_mut(&mut self) -> &mut Rng {
+        &mut *self.rng
+    }
+
+    fn resources_mut(&mut self) -> &mut Resources {
+        &mut *self.resources
+    }
 }
 
 impl GlobalPath for (Symbol, Symbol) {
@@ -210,6 +629,17 @@ impl <'a> GlobalPath for &'a str {
     }
 }
 
+// Records a failed compilation's errors as diagnostics before passing it on.
+fn report_errors<T>(result: Result<T, CompileError>, interner: &SymbolIntern, diagnostics: &mut Diagnostics)
+-> Result<T, CompileError> {
+    if let Err(ref e) = result {
+        for diagnostic in e.to_diagnostics(interner) {
+            diagnostics.report(diagnostic);
+        }
+    }
+    result
+}
+
 #[test]
 fn basic_context() {
     let mut state = ();
@@ -230,6 +660,141 @@ fn test_globals() {
     assert_eq!(ctx.get_global(("ns", "foo")).cloned().unwrap(), 10.into());
 }
 
+#[test]
+fn repeated_string_literals_share_constants() {
+    let mut state = ();
+    let mut ctx = Context::new();
+    ctx.eval(&mut state, "var a = \"hi\"; var b = \"hi\"; var c = \"bye\";").unwrap();
+    ctx.eval(&mut state, "\"hi\"").unwrap();
+    let stats = ctx.constant_pool_stats();
+    assert_eq!(stats.strings, 2);
+    assert_eq!(stats.string_hits, 2);
+}
+
+#[test]
+fn constants_are_shared_across_forms() {
+    let mut state = ();
+    let mut ctx = Context::new();
+    ctx.eval(&mut state, "var a = 10000000000; var b = 2.5;").unwrap();
+    // Arithmetic and lists of constants are computed when compiling, so
+    // each of these mixes in a variable.
+    ctx.eval(&mut state, "var c = a + 10000000000 + 2.5;").unwrap();
+    ctx.eval(&mut state, "fn(x) { [x, \"hi\", 2.5] }").unwrap();
+    ctx.eval(&mut state, "\"hi\"").unwrap();
+    let stats = ctx.constant_pool_stats();
+    assert_eq!(stats.constants, 3);
+    assert_eq!(stats.hits, 4);
+}
+
+#[test]
+fn unused_variable_warnings() {
+    let mut state = ();
+    let mut ctx = Context::new();
+    ctx.eval(&mut state, "fn(a, b) { var c = 1; var d = 2; a + d }").unwrap();
+
+    let names: Vec<String> = ctx.warnings().iter().map(|w| ctx.format_warning(w)).collect();
+    assert_eq!(names.len(), 2);
+    assert!(names[0].starts_with("UnusedArgument(b)"));
+    assert!(names[1].starts_with("UnusedVariable(c)"));
+
+    // Captured variables are used
+    ctx.eval(&mut state, "fn(a) { fn() { a } }").unwrap();
+    assert!(ctx.warnings().is_empty());
+}
+
+#[test]
+fn shadowing_warnings() {
+    let mut state = ();
+    let mut ctx = Context::new();
+    let program = "fn(x) { var y = x; fn() { var x = y; x } }";
+
+    ctx.eval(&mut state, program).unwrap();
+    assert!(ctx.warnings().is_empty());
+    // Only a lambda can mask a name; blocks in the same lambda can't.
+    assert!(ctx.eval(&mut state, "fn(x) { var y = x; { var x = y; x } }").is_err());
+
+    ctx.warn_on_shadowing(true);
+    ctx.eval(&mut state, program).unwrap();
+    assert_eq!(ctx.warnings().len(), 1);
+    assert!(ctx.format_warning(&ctx.warnings()[0]).starts_with("Shadowing(x)"));
+}
+
+#[test]
+fn type_annotation_warnings() {
+    let warnings = |check: bool, program: &str| {
+        let mut ctx: Context<()> = Context::new();
+        ctx.check_types(check);
+        ctx.eval(&mut (), program).unwrap();
+        ctx.warnings().iter().map(|w| ctx.format_warning(w)).collect::<Vec<_>>()
+    };
+
+    let greet = "var greet = fn(name: string, times: int): string { if times > 0 then name else \"\" };";
+    assert!(warnings(false, &format!("{} greet(1, 2)", greet)).is_empty());
+    assert!(warnings(true, &format!("{} greet(\"hi\", 2)", greet)).is_empty());
+
+    let mismatches = warnings(true, &format!("{} greet(1, 2)", greet));
+    assert_eq!(mismatches.len(), 1);
+    assert!(mismatches[0].starts_with("TypeMismatch(expected string, found int)"));
+
+    let mismatches = warnings(true, "var f = fn(x: int): string { x + 1 }; f(1)");
+    assert!(mismatches[0].starts_with("TypeMismatch(expected string, found int)"));
+    let mismatches = warnings(true, "fn(x: int) { x = \"s\"; x }");
+    assert!(mismatches[0].starts_with("TypeMismatch(expected int, found string)"));
+    assert!(warnings(true, "fn(x: integer) { x }")[0].starts_with("UnknownType(integer)"));
+
+    // Unannotated code is left alone.
+    assert!(warnings(true, "var g = fn(x) { x }; g(1); g(\"a\")").is_empty());
+}
+
+#[test]
+fn inferred_type_warnings() {
+    let warnings = |program: &str| {
+        let mut ctx: Context<()> = Context::new();
+        ctx.check_types(true);
+        // Programs that are wrong at runtime still compile.
+        let _ = ctx.eval(&mut (), program);
+        ctx.warnings().iter().map(|w| ctx.format_warning(w)).collect::<Vec<_>>()
+    };
+
+    assert!(warnings("\"a\" + 1")[0].starts_with("InvalidOperands(string + int)"));
+    assert!(warnings("var s = \"a\"; s < 2")[0].starts_with("InvalidOperands(string < int)"));
+    assert!(warnings("var f = fn() { \"a\" }; f() * 2")[0].starts_with("InvalidOperands(string * int)"));
+    assert!(warnings("var f = fn(x) { x }; f(1, 2)")[0].starts_with("WrongArity(expected 1, got 2)"));
+    assert!(warnings("fn(a, b) { a + b }(1)")[0].starts_with("WrongArity(expected 2, got 1)"));
+    assert!(warnings("var f = fn(x: int) { x }; f(\"a\")")[0].starts_with("TypeMismatch(expected int, found string)"));
+
+    // Nothing is known about reassigned variables, arguments or globals.
+    assert!(warnings("var s = \"a\"; s = 1; s + 1").is_empty());
+    assert!(warnings("var f = fn(x) { x }; f = fn(a, b) { a + b }; f(1, 2)").is_empty());
+    assert!(warnings("fn(x, y) { x + y }").is_empty());
+    assert!(warnings("var f = fn(x) { x }; f(1) + 2").is_empty());
+}
+
+#[test]
+fn lambdas_print_name_and_arity() {
+    let mut state = ();
+    let mut ctx = Context::new();
+    let add = ctx.eval(&mut state, "var add = fn(a, b) { a + b };\nadd").unwrap().unwrap();
+    assert_eq!(ctx.format_value(&add), "<lambda add/2 @ 1:11>");
+
+    let anonymous = ctx.eval(&mut state, "  fn(x) { x }").unwrap().unwrap();
+    assert_eq!(ctx.format_value(&anonymous), "<lambda/1 @ 1:3>");
+}
+
+#[test]
+fn lambdas_know_their_source() {
+    let mut state = ();
+    let mut ctx = Context::new();
+    let source = "var outer = fn() { fn(x) { x } }; outer()";
+    let inner = ctx.eval(&mut state, source).unwrap().unwrap();
+    let inner = inner.expect_closure_ref().unwrap();
+    assert_eq!(&source[inner.span().unwrap().range()], "fn(x) { x }");
+
+    let enclosing = ctx.enclosing_lambdas(inner);
+    assert_eq!(enclosing.len(), 1);
+    assert_eq!(ctx.interner().lookup(enclosing[0].name.unwrap()), Some("outer"));
+}
+
 #[test]
 fn context_with_user_fn() {
     use vm::user_function;
@@ -253,3 +818,556 @@ fn context_with_user_fn() {
     }
     assert_eq!(state, 2);
 }
+
+#[test]
+fn natives_check_their_arity() {
+    use vm::user_function;
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_global("show", user_function::<(), _>(None, |args, _, ctx| {
+        if ctx.check_arity(&args, 1) {
+            Value::from(ctx.format_value(&args[0]))
+        } else {
+            Value::Nil
+        }
+    }));
+
+    assert_eq!(ctx.eval(&mut state, "show(1)").unwrap(), Some(Value::from("1")));
+    match ctx.eval(&mut state, "show()") {
+        Err(AresError::InterpError(InterpError::WrongArity { got: 0, expected: 1, span: Some(_) })) => {}
+        other => panic!("expected WrongArity, got {:?}", other),
+    }
+}
+
+#[test]
+fn coverage() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_coverage(true);
+
+    let program = "var f = fn(x) { if x then 10 else 20 }; f(true); f(true);";
+    ctx.eval(&mut state, program).unwrap();
+    let report = ctx.coverage_report().unwrap();
+
+    let count_of = |needle: &str| {
+        let start = program.find(needle).unwrap() as u32;
+        report.spans.iter().find(|&&(span, _)| span.start() == start).unwrap().1
+    };
+    assert_eq!(count_of("10"), 2);
+    assert_eq!(count_of("20"), 0);
+    assert_eq!(report.lines(program).get(&1), Some(&2));
+}
+
+#[test]
+fn bench() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+
+    let report = ctx.bench(&mut state, "var f = fn(x) { [x, x] }; f(1);", 3).unwrap();
+    assert_eq!(report.iterations.len(), 3);
+    for iteration in &report.iterations {
+        assert!(iteration.instructions > 0);
+        // one closure and one list
+        assert_eq!(iteration.allocations, 2);
+    }
+    assert_eq!(report.total_instructions(), ctx.exec_stats().instructions);
+
+    // Wall times come from the context's clock.
+    ctx.set_clock(Box::new(::vm::FixedClock(::std::time::Duration::from_secs(5))));
+    let report = ctx.bench(&mut state, "1 + 2", 2).unwrap();
+    assert_eq!(report.total_wall_time(), ::std::time::Duration::from_secs(0));
+}
+
+#[test]
+fn compile_once_run_many() {
+    use std::sync::Arc;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var total = 0;").unwrap();
+    let program = Arc::new(ctx.compile("total = total + 1; total").unwrap());
+
+    assert_eq!(ctx.run(&mut state, &program).unwrap(), Some(Value::Int(1)));
+    let code_size = ctx.vm.code.len();
+    assert_eq!(ctx.run(&mut state, &program).unwrap(), Some(Value::Int(2)));
+    assert_eq!(ctx.vm.code.len(), code_size);
+
+    let shared = program.clone();
+    let mut other: Context<()> = Context::new();
+    other.eval(&mut state, "var total = 10;").unwrap();
+    assert_eq!(other.run(&mut state, &shared).unwrap(), Some(Value::Int(11)));
+    assert_eq!(ctx.eval(&mut state, "total").unwrap(), Some(Value::Int(2)));
+}
+
+#[test]
+fn calling_function_values() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let add = ctx.eval(&mut state, "fn(a, b) { a + b }").unwrap().unwrap();
+
+    assert_eq!(ctx.call(&mut state, add.clone(), vec![1.into(), 2.into()]).unwrap(), Value::Int(3));
+    let code_size = ctx.vm.code.len();
+    let stack_size = ctx.vm.stack.len();
+    assert_eq!(ctx.call(&mut state, add.clone(), vec![3.into(), 4.into()]).unwrap(), Value::Int(7));
+    assert!(ctx.call(&mut state, add, vec![1.into()]).is_err());
+    assert_eq!(ctx.vm.code.len(), code_size);
+    assert_eq!(ctx.vm.stack.len(), stack_size);
+}
+
+#[test]
+fn hooks_observe_calls() {
+    use vm::{Hooks, user_function};
+
+    let events = Rc::new(RefCell::new(vec![]));
+    let mut hooks = Hooks::default();
+    {
+        let events = events.clone();
+        hooks.on_call = Some(Box::new(move |name, args| {
+            events.borrow_mut().push(format!("call {:?} {}", name, args.len()));
+        }));
+    }
+    {
+        let events = events.clone();
+        hooks.on_return = Some(Box::new(move |name, value| {
+            events.borrow_mut().push(format!("return {:?} {:?}", name, value));
+        }));
+    }
+    {
+        let events = events.clone();
+        hooks.on_error = Some(Box::new(move |_| events.borrow_mut().push("error".to_string())));
+    }
+    {
+        let events = events.clone();
+        hooks.on_gc_end = Some(Box::new(move || events.borrow_mut().push("gc".to_string())));
+    }
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_global("double", user_function::<(), _>(Some("double".to_string()), |args, _, _| {
+        Value::Int(*args[0].expect_int_ref().unwrap() * 2)
+    }));
+    ctx.set_hooks(hooks);
+
+    ctx.eval(&mut state, "var f = fn(a, b) { double(a) + b }; f(1, 2); fn() { 5 }()").unwrap();
+    assert!(ctx.eval(&mut state, "f(1)").is_err());
+    ctx.collect_garbage();
+
+    assert_eq!(*events.borrow(), vec![
+        "call Some(\"f\") 2".to_string(),
+        "call Some(\"double\") 1".to_string(),
+        "return Some(\"double\") 2".to_string(),
+        "return Some(\"f\") 4".to_string(),
+        "call None 0".to_string(),
+        "return None 5".to_string(),
+        "call Some(\"f\") 1".to_string(),
+        "error".to_string(),
+        "gc".to_string(),
+    ]);
+}
+
+#[test]
+fn native_panics_become_errors() {
+    use vm::{InterpError, user_function};
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_vm_options(VmOptions { catch_native_panics: true, .. VmOptions::default() });
+    ctx.set_global("boom", user_function::<(), _>(Some("boom".to_string()), |_, _, _| panic!("kaboom")));
+
+    assert_eq!(ctx.eval(&mut state, "boom()"), Err(AresError::InterpError(InterpError::NativePanic {
+        name: Some("boom".to_string()),
+        message: "kaboom".to_string(),
+    })));
+    assert_eq!(ctx.eval(&mut state, "1 + 1"), Ok(Some(Value::Int(2))));
+}
+
+#[test]
+fn contracts_raise_violations() {
+    use vm::{InterpError, ContractKind};
+    use compiler::CompileOptions;
+    use ares_syntax::Span;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let program = "var dec = fn(x) requires x > 0 ensures result >= 0 { x - 1 }; \
+                   var bad = fn(x) ensures result > x { x };";
+    ctx.eval(&mut state, program).unwrap();
+
+    let clause = |text: &str| {
+        let start = program.find(text).unwrap() as u32;
+        Some(Span::new(start, start + text.len() as u32))
+    };
+    assert_eq!(ctx.eval(&mut state, "dec(5)"), Ok(Some(Value::Int(4))));
+    assert_eq!(ctx.eval(&mut state, "dec(0)"), Err(AresError::InterpError(InterpError::ContractViolation {
+        kind: ContractKind::Requires,
+        span: clause("x > 0"),
+    })));
+    assert_eq!(ctx.eval(&mut state, "bad(1)"), Err(AresError::InterpError(InterpError::ContractViolation {
+        kind: ContractKind::Ensures,
+        span: clause("result > x"),
+    })));
+
+    ctx.set_compile_options(CompileOptions { check_contracts: false, .. CompileOptions::default() });
+    ctx.eval(&mut state, program).unwrap();
+    assert_eq!(ctx.eval(&mut state, "dec(0)"), Ok(Some(Value::Int(-1))));
+    assert_eq!(ctx.eval(&mut state, "bad(1)"), Ok(Some(Value::Int(1))));
+
+    // The clause keywords can still name variables.
+    let names = "var requires = 2; var ensures = fn(requires) requires requires > 0 { requires }; ensures(requires)";
+    assert_eq!(ctx.eval(&mut state, names), Ok(Some(Value::Int(2))));
+}
+
+#[test]
+fn loops_run_in_constant_space() {
+    use compiler::CompileError;
+    use compiler::binding::BindingError;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var count = fn(n) { \
+                              loop(i = 0, acc = 0) { if i < n then recur(i + 1, acc + 2) else acc } \
+                          };").unwrap();
+    assert_eq!(ctx.eval(&mut state, "count(100000)"), Ok(Some(Value::Int(200000))));
+    assert_eq!(ctx.eval(&mut state, "loop(a = 1, b = 1, n = 0) { if n < 10 then recur(b, a + b, n + 1) else a }"),
+               Ok(Some(Value::Int(89))));
+
+    let binding_error = |result: AresResult<Option<Value>>| match result {
+        Err(AresError::CompileError(CompileError::BindingError(e))) => e,
+        other => panic!("expected a binding error, got {:?}", other),
+    };
+    match binding_error(ctx.eval(&mut state, "recur(1)")) {
+        BindingError::RecurOutsideLoop(_) => {}
+        other => panic!("{:?}", other),
+    }
+    match binding_error(ctx.eval(&mut state, "loop(i = 0, j = 0) { recur(1) }")) {
+        BindingError::RecurArity { expected: 2, got: 1, .. } => {}
+        other => panic!("{:?}", other),
+    }
+    match binding_error(ctx.eval(&mut state, "loop(i = 0) { 1 + recur(i) }")) {
+        BindingError::RecurNotInTail(_) => {}
+        other => panic!("{:?}", other),
+    }
+    match binding_error(ctx.eval(&mut state, "loop(i = 0) { fn() { recur(i) } }")) {
+        BindingError::RecurOutsideLoop(_) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[cfg(not(feature = "decimal"))]
+#[test]
+fn decimal_literals_need_the_feature() {
+    use compiler::CompileError;
+    use compiler::binding::BindingError;
+
+    let mut ctx: Context<()> = Context::new();
+    match ctx.eval(&mut (), "var x = 1.50d;") {
+        Err(AresError::CompileError(CompileError::BindingError(BindingError::DecimalsUnsupported(span)))) =>
+            assert_eq!(span, ::ares_syntax::Span::new(8, 13)),
+        other => panic!("expected DecimalsUnsupported, got {:?}", other),
+    }
+}
+
+#[test]
+fn top_level_loops_leave_globals_alone() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var total = 0;").unwrap();
+    let namespace = ctx.vm.interner.precomputed.default_namespace;
+    let before = ctx.vm.globals.names_in(namespace).len();
+    assert_eq!(ctx.eval(&mut state, "loop(i = 0) { if i < 5 then { total = total + i; recur(i + 1) } else total }"),
+               Ok(Some(Value::Int(10))));
+    assert_eq!(ctx.eval(&mut state, "loop(i = 0) { var doubled = i * 2; if i < 3 then recur(i + 1) else doubled }"),
+               Ok(Some(Value::Int(6))));
+    assert_eq!(ctx.vm.globals.names_in(namespace).len(), before);
+    assert_eq!(ctx.eval(&mut state, "total"), Ok(Some(Value::Int(10))));
+}
+
+
+#[test]
+fn named_lets_call_themselves() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    assert_eq!(ctx.eval(&mut state, "let fact(n = 5) { if n == 0 then 1 else n * fact(n - 1) }"),
+               Ok(Some(Value::Int(120))));
+
+    // The initial values are outside of the function's scope.
+    ctx.eval(&mut state, "var go = 10; \
+                          var sum = fn(xs, n) { \
+                              let go(i = go - 10, acc = 0) { \
+                                  if i < n then go(i + 1, acc + xs[i]) else acc \
+                              } \
+                          };").unwrap();
+    assert_eq!(ctx.eval(&mut state, "sum([1, 2, 3, 4], 4)"), Ok(Some(Value::Int(10))));
+}
+
+#[test]
+fn do_blocks_are_scoped_expressions() {
+    use compiler::CompileError;
+    use compiler::binding::BindingError;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var x = 1; var y = do { var t = 10; x + t };").unwrap();
+    assert_eq!(ctx.eval(&mut state, "x"), Ok(Some(Value::Int(1))));
+    assert_eq!(ctx.eval(&mut state, "y"), Ok(Some(Value::Int(11))));
+    match ctx.eval(&mut state, "var v = 1; do { var v = 2; v }") {
+        Err(AresError::CompileError(CompileError::BindingError(BindingError::AlreadyDefined(..)))) => {}
+        other => panic!("expected AlreadyDefined, got {:?}", other),
+    }
+    assert_eq!(ctx.eval(&mut state, "var w = do { var z = 2; }; w"), Ok(Some(Value::Nil)));
+    match ctx.eval(&mut state, "z") {
+        Err(AresError::CompileError(CompileError::BindingError(BindingError::CouldNotBind(..)))) => {}
+        other => panic!("expected CouldNotBind, got {:?}", other),
+    }
+}
+
+#[test]
+fn letrec_values_see_each_other() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var parity = fn(n) { \
+                              letrec(even = fn(k) { if k == 0 then true else odd(k - 1) }, \
+                                     odd = fn(k) { if k == 0 then false else even(k - 1) }) { \
+                                  even(n) \
+                              } \
+                          };").unwrap();
+    assert_eq!(ctx.eval(&mut state, "parity(10)"), Ok(Some(Value::Bool(true))));
+    assert_eq!(ctx.eval(&mut state, "parity(7)"), Ok(Some(Value::Bool(false))));
+    assert_eq!(ctx.eval(&mut state, "letrec(x = 2, y = 3) { x * y }"), Ok(Some(Value::Int(6))));
+}
+
+#[test]
+fn block_definitions_can_refer_forward() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let program = "var r = do { \
+                       var even = fn(n) { if n == 0 then true else odd(n - 1) }; \
+                       var odd = fn(n) { if n == 0 then false else even(n - 1) }; \
+                       even(10) \
+                   }; r";
+    assert_eq!(ctx.eval(&mut state, program), Ok(Some(Value::Bool(true))));
+}
+
+#[test]
+fn local_definitions_can_recurse() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var fact5 = fn() { \
+                              var fact = fn(n) { if n == 0 then 1 else n * fact(n - 1) }; \
+                              fact(5) \
+                          }; \
+                          var parity = fn(n) { \
+                              var even = fn(k) { if k == 0 then true else odd(k - 1) }; \
+                              var odd = fn(k) { if k == 0 then false else even(k - 1) }; \
+                              even(n) \
+                          };").unwrap();
+    assert_eq!(ctx.eval(&mut state, "fact5()"), Ok(Some(Value::Int(120))));
+    assert_eq!(ctx.eval(&mut state, "parity(9)"), Ok(Some(Value::Bool(false))));
+}
+
+#[test]
+fn only_mutated_captures_use_cells() {
+    use vm::Instr;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let source = "var f = fn(x) { var y = x + 1; fn() { x + y } };";
+    let program = ctx.compile(source).unwrap();
+    assert!(!program.instructions().contains(&Instr::WrapCell));
+    ctx.eval(&mut state, source).unwrap();
+
+    ctx.eval(&mut state, "var counter = fn() { \
+                              var n = 0; \
+                              fn() { n = n + 1; n } \
+                          }; \
+                          var c = counter();").unwrap();
+    assert_eq!(ctx.eval(&mut state, "c(); c()"), Ok(Some(Value::Int(2))));
+    assert_eq!(ctx.eval(&mut state, "f(1)()"), Ok(Some(Value::Int(3))));
+}
+
+#[test]
+fn pure_definitions_are_computed_at_compile_time() {
+    use vm::Instr;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let source = "var tau = 2 * 3.25; var sizes = [1, 2 + 2, \"big\"]; var bad = 1 / 0;";
+    let program = ctx.compile(source).unwrap();
+    assert!(!program.instructions().contains(&Instr::MulInt));
+    assert!(!program.instructions().contains(&Instr::ConstructList(3)));
+    // Errors still happen when the code is run.
+    assert!(program.instructions().contains(&Instr::DivInt));
+
+    assert!(ctx.eval(&mut state, "var tau = 2 * 3.25; var sizes = [1, 2 + 2, \"big\"];").is_ok());
+    assert_eq!(ctx.get_global("tau").cloned(), Some(Value::Float(2.0 * 3.25)));
+    assert_eq!(ctx.eval(&mut state, "sizes[1]"), Ok(Some(Value::Int(4))));
+}
+
+#[test]
+fn compile_problems_are_diagnostics() {
+    use compiler::Severity;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.warn_on_shadowing(true);
+    ctx.eval(&mut state, "fn(x, a) { var y = x; fn() { var x = y; x } }").unwrap();
+    let diagnostics = ctx.diagnostics();
+    let codes: Vec<_> = diagnostics.iter().map(|d| (d.severity, d.code)).collect();
+    assert_eq!(codes, vec![(Severity::Warning, "W0003"), (Severity::Warning, "W0002")]);
+    assert!(diagnostics[0].notes[0].span.is_some());
+
+    let source = "var y = 1;\nvar z = y + missing;";
+    assert!(ctx.eval(&mut state, source).is_err());
+    let diagnostics = ctx.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].render(source),
+               "error[E0100]: `missing` is not defined\n \
+                --> 2:13\n  \
+                |\n\
+                2 | var z = y + missing;\n  \
+                |             ^^^^^^^\n");
+}
+
+#[test]
+fn checking_does_not_run() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.warn_on_shadowing(true);
+    assert!(ctx.check("var z = 1;").is_ok());
+    assert!(ctx.get_global("z").is_none());
+    assert!(ctx.check("fn(x, a) { var y = x; fn() { var x = y; x } }").is_ok());
+    assert_eq!(ctx.diagnostics().len(), 2);
+
+    assert!(ctx.check("var y = missing;").is_err());
+    let codes: Vec<_> = ctx.diagnostics().iter().map(|d| d.code).collect();
+    assert_eq!(codes, vec!["E0100"]);
+    assert_eq!(ctx.eval(&mut state, "1 + 2"), Ok(Some(3.into())));
+}
+
+#[test]
+fn diagnostics_as_json() {
+    use compiler::diagnostics_to_json;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let source = "var y = missing;";
+    assert!(ctx.eval(&mut state, source).is_err());
+    assert_eq!(diagnostics_to_json(&ctx.diagnostics(), source),
+               "[{\"type\":\"diagnostic\",\"severity\":\"error\",\"code\":\"E0100\",\
+                \"message\":\"`missing` is not defined\",\
+                \"span\":{\"type\":\"span\",\"start\":8,\"end\":15,\"line\":1,\"column\":9,\
+                \"end_line\":1,\"end_column\":16},\"notes\":[]}]");
+
+    let error = ctx.eval(&mut state, "1 / 0").unwrap_err();
+    let diagnostics = error.to_diagnostics(ctx.interner());
+    assert_eq!(diagnostics[0].code, "E0206");
+    assert_eq!(diagnostics[0].to_json(""),
+               "{\"type\":\"diagnostic\",\"severity\":\"error\",\"code\":\"E0206\",\
+                \"message\":\"division by zero\",\"span\":null,\"notes\":[]}");
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn deterministic_runs_repeat() {
+    use std::time::Duration;
+    use vm::FixedClock;
+
+    let run = |options: VmOptions| {
+        let mut ctx: Context<()> = Context::new();
+        ctx.set_vm_options(options);
+        ctx.install_random_builtins();
+        ctx.install_time_builtins();
+        ctx.install_environment_builtins();
+        ctx.eval(&mut (), "[rand(), rand_int(100), now(), argv()]").unwrap().unwrap()
+    };
+    let deterministic = || VmOptions { deterministic: true, allow_environment: true, .. VmOptions::default() };
+
+    let first = run(deterministic());
+    assert_eq!(first, run(deterministic()));
+    assert_eq!(first.expect_list_ref().unwrap()[2..], [Value::Nil, Value::Nil]);
+
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_vm_options(deterministic());
+    ctx.set_clock(Box::new(FixedClock(Duration::from_secs(5))));
+    ctx.install_time_builtins();
+    assert_eq!(ctx.eval(&mut (), "now()").unwrap(), Some(Value::Float(5.0)));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn replays_reproduce_recorded_runs() {
+    use vm::{InterpError, ReplayLog};
+
+    let program = "[rand_int(1000), now(), rand()]";
+    let context = |seed| {
+        let mut ctx: Context<()> = Context::new();
+        ctx.set_vm_options(VmOptions { rng_seed: Some(seed), .. VmOptions::default() });
+        ctx.install_random_builtins();
+        ctx.install_time_builtins();
+        ctx
+    };
+
+    let mut production = context(1);
+    production.start_recording();
+    let recorded = production.eval(&mut (), program).unwrap();
+    let log = production.stop_replay().unwrap();
+    let names: Vec<_> = log.entries.iter().map(|e| e.name.clone().unwrap()).collect();
+    assert_eq!(names, vec!["rand_int", "now", "rand"]);
+
+    let saved = log.to_value();
+    let mut local = context(2);
+    local.start_replaying(ReplayLog::from_value(&saved).unwrap());
+    assert_eq!(local.eval(&mut (), program).unwrap(), recorded);
+
+    local.start_replaying(log);
+    assert_eq!(local.eval(&mut (), "now()"), Err(AresError::InterpError(InterpError::ReplayDiverged {
+        expected: Some("rand_int".to_string()),
+        got: Some("now".to_string()),
+    })));
+}
+
+#[test]
+fn contexts_share_no_state() {
+    let mut first: Context<()> = Context::new();
+    let mut second: Context<()> = Context::new();
+    first.interner_mut().intern("only in first");
+
+    first.eval(&mut (), "var shared = 'a; var count = 1;").unwrap();
+    assert!(second.eval(&mut (), "count").is_err());
+    second.eval(&mut (), "var shared = 'b; var count = 2;").unwrap();
+
+    let symbol = |ctx: &mut Context<()>| match ctx.eval(&mut (), "shared").unwrap() {
+        Some(Value::Symbol(s)) => ctx.interner().lookup_or_anon(s),
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(symbol(&mut first), "a");
+    assert_eq!(symbol(&mut second), "b");
+    assert_eq!(first.get_global("count").cloned(), Some(Value::Int(1)));
+    assert_eq!(second.get_global("count").cloned(), Some(Value::Int(2)));
+
+    first.warn_on_shadowing(true);
+    assert!(!second.diagnostics.warn_on_shadowing());
+}
+
+#[test]
+fn isolated_contexts_run_concurrently() {
+    use std::thread;
+
+    let threads: Vec<_> = (0 .. 8).map(|n| thread::spawn(move || {
+        let mut ctx: Context<()> = Context::new();
+        for i in 0 .. n {
+            ctx.interner_mut().intern(&format!("padding {}", i));
+        }
+        ctx.eval(&mut (), &format!("var n = {}; var total = 0;", n)).unwrap();
+        for _ in 0 .. 100 {
+            ctx.eval(&mut (), "total = total + n; var scratch = [total, 'key];").unwrap();
+        }
+        // Values aren't `Send`, so only plain numbers leave the thread.
+        let result = ctx.eval(&mut (), "scratch[0] - total").unwrap().unwrap();
+        (ctx.get_global("total").cloned().unwrap().expect_int().unwrap(), result.expect_int().unwrap())
+    })).collect();
+
+    for (n, thread) in threads.into_iter().enumerate() {
+        assert_eq!(thread.join().unwrap(), (100 * n as i64, 0));
+    }
+}
@@ -0,0 +1,90 @@
+use std::marker::PhantomData;
+
+use host::{Context, ContextLike, EphemeralContext, State};
+use vm::{Value, user_function};
+
+/// A bundle of native functions and constants that a host crate can ship
+/// as a unit, installed with `Context::install`.
+pub trait NativeModule<S: State> {
+    /// The namespace that the module's definitions go in.  It has to be a
+    /// valid identifier.
+    fn namespace(&self) -> &str;
+
+    /// Adds the module's functions and constants to `definitions`.
+    fn define(&mut self, definitions: &mut ModuleDefinitions<S>);
+}
+
+/// The functions and constants that a `NativeModule` defines.
+pub struct ModuleDefinitions<S: State> {
+    namespace: String,
+    entries: Vec<(String, Value)>,
+    _phantom: PhantomData<S>,
+}
+
+impl <S: State> ModuleDefinitions<S> {
+    pub fn function<F>(&mut self, name: &str, f: F) -> &mut ModuleDefinitions<S>
+    where F: FnMut(Vec<Value>, &mut S, &mut EphemeralContext<S>) -> Value + 'static {
+        let full_name = format!("{}_{}", self.namespace, name);
+        self.entries.push((name.to_string(), user_function::<S, _>(Some(full_name), f)));
+        self
+    }
+
+    pub fn constant<V: Into<Value>>(&mut self, name: &str, value: V) -> &mut ModuleDefinitions<S> {
+        self.entries.push((name.to_string(), value.into()));
+        self
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Installs every definition of `module`.  Each one is stored under
+    /// its own name in the module's namespace, and scripts see it as the
+    /// global `<namespace>_<name>`, e.g. `http_get`.
+    pub fn install<M: NativeModule<S>>(&mut self, mut module: M) {
+        let mut definitions = ModuleDefinitions {
+            namespace: module.namespace().to_string(),
+            entries: vec![],
+            _phantom: PhantomData,
+        };
+        module.define(&mut definitions);
+
+        let namespace = definitions.namespace;
+        for (name, value) in definitions.entries {
+            self.set_global((&namespace[..], &name[..]), value.clone());
+            self.set_global(&format!("{}_{}", namespace, name)[..], value);
+        }
+    }
+}
+
+#[test]
+fn install_module() {
+    struct Geometry {
+        scale: i64,
+    }
+
+    impl NativeModule<()> for Geometry {
+        fn namespace(&self) -> &str {
+            "geometry"
+        }
+
+        fn define(&mut self, definitions: &mut ModuleDefinitions<()>) {
+            let scale = self.scale;
+            definitions
+                .constant("unit", 1)
+                .function("area", move |args, _, _| {
+                    match (&args[0], &args[1]) {
+                        (&Value::Int(w), &Value::Int(h)) => Value::Int(w * h * scale),
+                        _ => Value::Nil,
+                    }
+                });
+        }
+    }
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install(Geometry { scale: 2 });
+
+    assert_eq!(ctx.eval(&mut state, "geometry_area(3, geometry_unit + 1)").unwrap(), Some(Value::Int(12)));
+    assert!(ctx.has_global(("geometry", "area")));
+    let area = ctx.get_global("geometry_area").cloned().unwrap();
+    assert_eq!(ctx.format_value(&area), "<UserFn geometry_area>");
+}
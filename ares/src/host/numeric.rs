@@ -0,0 +1,69 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, Rational, user_function};
+
+impl <S: State> Context<S> {
+    /// Defines the builtins for converting between kinds of numbers:
+    ///
+    /// * `rational(n, d)` returns the exact fraction `n / d`.
+    /// * `numerator(x)` and `denominator(x)` return the parts of an int or
+    ///   rational in lowest terms.
+    /// * `to_float(x)` returns the float nearest to an int, rational or
+    ///   decimal.
+    ///
+    /// Each returns `nil` if its arguments are invalid.
+    pub fn install_numeric_builtins(&mut self) {
+        self.set_global("rational", user_function::<S, _>(Some("rational".into()), |args, _, _| {
+            match (args.get(0), args.get(1)) {
+                (Some(&Value::Int(n)), Some(&Value::Int(d))) =>
+                    Rational::new(n, d).map(Rational::into_value).unwrap_or(Value::Nil),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("numerator", user_function::<S, _>(Some("numerator".into()), |args, _, _| {
+            match args.get(0) {
+                Some(&Value::Int(i)) => Value::Int(i),
+                Some(&Value::Rational(ref r)) => Value::Int(r.numer()),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("denominator", user_function::<S, _>(Some("denominator".into()), |args, _, _| {
+            match args.get(0) {
+                Some(&Value::Int(_)) => Value::Int(1),
+                Some(&Value::Rational(ref r)) => Value::Int(r.denom()),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("to_float", user_function::<S, _>(Some("to_float".into()), |args, _, _| {
+            match args.get(0) {
+                Some(&Value::Int(i)) => Value::Float(i as f64),
+                Some(&Value::Float(f)) => Value::Float(f),
+                Some(&Value::Rational(ref r)) => Value::Float(r.to_f64()),
+                #[cfg(feature = "decimal")]
+                Some(&Value::Decimal(ref d)) => Value::Float(d.to_f64()),
+                _ => Value::Nil,
+            }
+        }));
+    }
+}
+
+#[test]
+fn exact_arithmetic() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_numeric_builtins();
+
+    let eval = |ctx: &mut Context<()>, program: &str| {
+        let value = ctx.eval(&mut (), program).unwrap().unwrap();
+        ::host::ContextLike::format_value(ctx, &value)
+    };
+    assert_eq!(eval(&mut ctx, "1 / 3"), "1/3");
+    assert_eq!(eval(&mut ctx, "1/3 + 1/6"), "1/2");
+    assert_eq!(eval(&mut ctx, "1/3 * 3"), "1");
+    assert_eq!(eval(&mut ctx, "1/2 + 0.25"), "0.75");
+    assert_eq!(eval(&mut ctx, "[numerator(6/4), denominator(6/4), to_float(3/4)]"), "[3, 2, 0.75]");
+    assert_eq!(ctx.eval(&mut state, "1/3 < 1/2"), Ok(Some(Value::Bool(true))));
+    assert_eq!(ctx.eval(&mut state, "rational(2, 0)"), Ok(Some(Value::Nil)));
+}
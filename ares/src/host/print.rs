@@ -0,0 +1,69 @@
+use std::io::Write;
+
+use ares_syntax::SymbolIntern;
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function, to_string_helper, to_string_ordered};
+
+/// Maps are printed in key order in deterministic mode.
+fn join_args(args: &[Value], interner: &SymbolIntern, ordered: bool) -> String {
+    let format = if ordered { to_string_ordered } else { to_string_helper };
+    let parts: Vec<String> = args.iter().map(|v| format(v, interner)).collect();
+    parts.join(" ")
+}
+
+impl <S: State> Context<S> {
+    /// Defines `print`, `println` and `eprint`.  Each one writes its
+    /// arguments separated by spaces to the context's stdout (or
+    /// its stderr for `eprint`), which `set_output` replaces.  Write errors are ignored.
+    pub fn install_print_builtins(&mut self) {
+        self.set_global("print", user_function::<S, _>(Some("print".into()), |args, _, ctx| {
+            let ordered = ctx.vm_options_mut().deterministic;
+            let text = join_args(&args, ctx.interner(), ordered);
+            let _ = ctx.io_mut().stdout.write_all(text.as_bytes());
+            Value::Nil
+        }));
+
+        self.set_global("println", user_function::<S, _>(Some("println".into()), |args, _, ctx| {
+            let ordered = ctx.vm_options_mut().deterministic;
+            let text = join_args(&args, ctx.interner(), ordered);
+            let _ = writeln!(ctx.io_mut().stdout, "{}", text);
+            Value::Nil
+        }));
+
+        self.set_global("eprint", user_function::<S, _>(Some("eprint".into()), |args, _, ctx| {
+            let ordered = ctx.vm_options_mut().deterministic;
+            let text = join_args(&args, ctx.interner(), ordered);
+            let _ = ctx.io_mut().stderr.write_all(text.as_bytes());
+            Value::Nil
+        }));
+    }
+}
+
+#[test]
+fn output_is_captured() {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let out = Shared::default();
+    let err = Shared::default();
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.set_output(Box::new(out.clone()), Box::new(err.clone()));
+    ctx.install_print_builtins();
+    ctx.eval(&mut state, "print(1, \"a\"); println([2]); eprint('oops);").unwrap();
+
+    assert_eq!(&out.0.borrow()[..], b"1 a[2]\n");
+    assert_eq!(&err.0.borrow()[..], b"'oops");
+}
@@ -0,0 +1,77 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function};
+
+impl <S: State> Context<S> {
+    /// Defines the random builtins, which all draw from the generator
+    /// seeded by `VmOptions::rng_seed`:
+    ///
+    /// * `rand()` returns a float in `[0, 1)`.
+    /// * `rand_int(n)` returns an int in `[0, n)`.
+    /// * `rand_range(a, b)` returns an int in `[a, b)`.
+    /// * `shuffle(list)` returns a shuffled copy of `list`.
+    ///
+    /// Each returns `nil` if its arguments are invalid or the range is empty.
+    pub fn install_random_builtins(&mut self) {
+        self.set_global("rand", user_function::<S, _>(Some("rand".into()), |_, _, ctx| {
+            Value::Float(ctx.rng_mut().next_f64())
+        }));
+
+        self.set_global("rand_int", user_function::<S, _>(Some("rand_int".into()), |args, _, ctx| {
+            match args.get(0) {
+                Some(&Value::Int(n)) if n > 0 => Value::Int(ctx.rng_mut().below(n as u64) as i64),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("rand_range", user_function::<S, _>(Some("rand_range".into()), |args, _, ctx| {
+            match (args.get(0), args.get(1)) {
+                (Some(&Value::Int(lo)), Some(&Value::Int(hi))) if lo < hi => {
+                    let width = hi.wrapping_sub(lo) as u64;
+                    Value::Int(lo.wrapping_add(ctx.rng_mut().below(width) as i64))
+                }
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("shuffle", user_function::<S, _>(Some("shuffle".into()), |args, _, ctx| {
+            let mut items = match args.get(0) {
+                Some(&Value::List(ref items)) => (**items).clone(),
+                _ => return Value::Nil,
+            };
+            let rng = ctx.rng_mut();
+            for i in (1 .. items.len()).rev() {
+                let j = rng.below(i as u64 + 1) as usize;
+                items.swap(i, j);
+            }
+            items.into()
+        }));
+    }
+}
+
+#[test]
+fn seeded_builtins_are_reproducible() {
+    use vm::VmOptions;
+
+    let program = "[rand(), rand_int(10), rand_range(0 - 5, 5), shuffle([1, 2, 3, 4])]";
+    let run = || {
+        let mut ctx: Context<()> = Context::new();
+        ctx.set_vm_options(VmOptions { rng_seed: Some(42), .. VmOptions::default() });
+        ctx.install_random_builtins();
+        ctx.eval(&mut (), program).unwrap().unwrap()
+    };
+
+    let first = run();
+    assert_eq!(first, run());
+
+    let items = first.expect_list().unwrap();
+    let f = *items[0].expect_float_ref().unwrap();
+    assert!(f >= 0.0 && f < 1.0);
+    let n = *items[1].expect_int_ref().unwrap();
+    assert!(n >= 0 && n < 10);
+    let r = *items[2].expect_int_ref().unwrap();
+    assert!(r >= -5 && r < 5);
+    let mut shuffled = items[3].expect_list_ref().unwrap().iter()
+        .map(|v| *v.expect_int_ref().unwrap()).collect::<Vec<_>>();
+    shuffled.sort();
+    assert_eq!(shuffled, vec![1, 2, 3, 4]);
+}
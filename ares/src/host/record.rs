@@ -0,0 +1,218 @@
+use util::collections::HashMap;
+use vm::{Value, ValueKind, SymbolIntern};
+
+/// Converts a host value into a script value.
+pub trait IntoAres {
+    fn into_ares(self, interner: &mut SymbolIntern) -> Value;
+}
+
+/// Converts a script value back into a host value.
+pub trait FromAres: Sized {
+    fn from_ares(value: &Value, interner: &SymbolIntern) -> Result<Self, FromAresError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FromAresError {
+    Mismatched {
+        expected: ValueKind,
+        got: Value,
+    },
+    MissingField(&'static str),
+    /// A field was present but couldn't be converted.
+    Field(&'static str, Box<FromAresError>),
+}
+
+macro_rules! gen_conversions {
+    ($t: ty, $selector: path, $kind: expr) => {
+        impl IntoAres for $t {
+            fn into_ares(self, _: &mut SymbolIntern) -> Value {
+                $selector(self)
+            }
+        }
+
+        impl FromAres for $t {
+            fn from_ares(value: &Value, _: &SymbolIntern) -> Result<$t, FromAresError> {
+                match value {
+                    &$selector(v) => Ok(v),
+                    other => Err(FromAresError::Mismatched { expected: $kind, got: other.clone() }),
+                }
+            }
+        }
+    }
+}
+
+gen_conversions!(i64, Value::Int, ValueKind::Int);
+gen_conversions!(f64, Value::Float, ValueKind::Float);
+gen_conversions!(bool, Value::Bool, ValueKind::Bool);
+
+impl IntoAres for Value {
+    fn into_ares(self, _: &mut SymbolIntern) -> Value {
+        self
+    }
+}
+
+impl FromAres for Value {
+    fn from_ares(value: &Value, _: &SymbolIntern) -> Result<Value, FromAresError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoAres for String {
+    fn into_ares(self, _: &mut SymbolIntern) -> Value {
+        self.into()
+    }
+}
+
+impl FromAres for String {
+    fn from_ares(value: &Value, _: &SymbolIntern) -> Result<String, FromAresError> {
+        value.expect_str().map(String::from).map_err(|_| FromAresError::Mismatched {
+            expected: ValueKind::String,
+            got: value.clone(),
+        })
+    }
+}
+
+impl <T: IntoAres> IntoAres for Vec<T> {
+    fn into_ares(self, interner: &mut SymbolIntern) -> Value {
+        let items: Vec<Value> = self.into_iter().map(|v| v.into_ares(interner)).collect();
+        items.into()
+    }
+}
+
+impl <T: FromAres> FromAres for Vec<T> {
+    fn from_ares(value: &Value, interner: &SymbolIntern) -> Result<Vec<T>, FromAresError> {
+        match value {
+            &Value::List(ref items) => items.iter().map(|v| T::from_ares(v, interner)).collect(),
+            other => Err(FromAresError::Mismatched { expected: ValueKind::List, got: other.clone() }),
+        }
+    }
+}
+
+/// `None` is nil.
+impl <T: IntoAres> IntoAres for Option<T> {
+    fn into_ares(self, interner: &mut SymbolIntern) -> Value {
+        match self {
+            Some(v) => v.into_ares(interner),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl <T: FromAres> FromAres for Option<T> {
+    fn from_ares(value: &Value, interner: &SymbolIntern) -> Result<Option<T>, FromAresError> {
+        match value {
+            &Value::Nil => Ok(None),
+            other => T::from_ares(other, interner).map(Some),
+        }
+    }
+}
+
+/// Builds a map keyed by the symbols named in `fields`.  Used by
+/// `ares_record!`.
+#[doc(hidden)]
+pub fn record_to_map(fields: Vec<(&'static str, Value)>, interner: &mut SymbolIntern) -> Value {
+    let map: HashMap<Value, Value> = fields.into_iter()
+        .map(|(name, value)| (Value::Symbol(interner.intern(name)), value))
+        .collect();
+    map.into()
+}
+
+/// Converts the field called `name` out of a map made by `record_to_map`.
+/// Missing fields are treated as nil, so `Option` fields may be left out.
+#[doc(hidden)]
+pub fn record_field<T: FromAres>(map: &Value, name: &'static str, interner: &SymbolIntern)
+-> Result<T, FromAresError> {
+    let pairs = match map {
+        &Value::Map(ref pairs) => pairs,
+        other => return Err(FromAresError::Mismatched { expected: ValueKind::Map, got: other.clone() }),
+    };
+    let value = interner.symbol_for_name(name).and_then(|s| pairs.get(&Value::Symbol(s)));
+    match value {
+        Some(value) => T::from_ares(&value, interner),
+        None => T::from_ares(&Value::Nil, interner).map_err(|_| FromAresError::MissingField(name)),
+    }.map_err(|e| match e {
+        e@FromAresError::MissingField(_) => e,
+        e => FromAresError::Field(name, Box::new(e)),
+    })
+}
+
+/// Declares a struct along with `IntoAres` and `FromAres` impls that
+/// convert it to and from a map keyed by the field names as symbols.
+///
+/// ```ignore
+/// ares_record! {
+///     pub struct Config {
+///         width: i64,
+///         title: String,
+///     }
+/// }
+/// ```
+///
+/// Every field has to implement both traits.
+#[macro_export]
+macro_rules! ares_record {
+    ($(#[$attr: meta])* pub struct $name: ident { $($field: ident : $t: ty),* $(,)* }) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(pub $field: $t),*
+        }
+        ares_record!(@impls $name { $($field: $t),* });
+    };
+    ($(#[$attr: meta])* struct $name: ident { $($field: ident : $t: ty),* $(,)* }) => {
+        $(#[$attr])*
+        struct $name {
+            $($field: $t),*
+        }
+        ares_record!(@impls $name { $($field: $t),* });
+    };
+    (@impls $name: ident { $($field: ident : $t: ty),* }) => {
+        impl $crate::host::IntoAres for $name {
+            fn into_ares(self, interner: &mut $crate::vm::SymbolIntern) -> $crate::vm::Value {
+                let fields = vec![
+                    $((stringify!($field), $crate::host::IntoAres::into_ares(self.$field, interner))),*
+                ];
+                $crate::host::record_to_map(fields, interner)
+            }
+        }
+
+        impl $crate::host::FromAres for $name {
+            fn from_ares(value: &$crate::vm::Value, interner: &$crate::vm::SymbolIntern)
+            -> Result<$name, $crate::host::FromAresError> {
+                Ok($name {
+                    $($field: $crate::host::record_field(value, stringify!($field), interner)?),*
+                })
+            }
+        }
+    };
+}
+
+#[test]
+fn records_round_trip() {
+    ares_record! {
+        #[derive(Debug, PartialEq)]
+        struct Config {
+            width: i64,
+            title: String,
+            tags: Vec<String>,
+            scale: Option<f64>,
+        }
+    }
+
+    let mut interner = SymbolIntern::new();
+    let config = Config { width: 80, title: "main".into(), tags: vec!["a".into()], scale: None };
+    let value = config.into_ares(&mut interner);
+    let width = Value::Symbol(interner.intern("width"));
+    assert_eq!(value.expect_map_ref().unwrap().get(&width), Some(Value::Int(80)));
+    assert_eq!(Config::from_ares(&value, &interner).unwrap(),
+               Config { width: 80, title: "main".into(), tags: vec!["a".into()], scale: None });
+
+    let mut map = HashMap::new();
+    map.insert(width, Value::from("wide"));
+    let value: Value = map.into();
+    assert_eq!(Config::from_ares(&value, &interner), Err(FromAresError::Field("width", Box::new(
+        FromAresError::Mismatched { expected: ValueKind::Int, got: "wide".into() }))));
+
+    let empty: HashMap<Value, Value> = HashMap::new();
+    let value: Value = empty.into();
+    assert_eq!(Config::from_ares(&value, &interner), Err(FromAresError::MissingField("width")));
+}
@@ -0,0 +1,184 @@
+use host::{Context, ContextLike, State};
+#[cfg(feature = "std")]
+use host::FileSystemPolicy;
+use util::collections::HashSet;
+
+/// Decides which parts of the standard library `install_standard_library`
+/// gives to scripts.  Every capability starts out denied; the builtins
+/// that only work with data (`read`, `write`, `template`, the list, map,
+/// function, string and symbol helpers, the string builders and the json
+/// builtins) are always installed unless an allowlist excludes them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SandboxPolicy {
+    io: bool,
+    time: bool,
+    random: bool,
+    environment: bool,
+    #[cfg(feature = "std")]
+    file_system: Option<FileSystemPolicy>,
+    allowlist: Option<HashSet<String>>,
+}
+
+impl SandboxPolicy {
+    /// A policy that denies every capability.
+    pub fn new() -> SandboxPolicy {
+        SandboxPolicy::default()
+    }
+
+    /// A policy that allows every capability, with unrestricted file access.
+    pub fn permissive() -> SandboxPolicy {
+        let policy = SandboxPolicy::new()
+            .allow_io(true)
+            .allow_time(true)
+            .allow_random(true)
+            .allow_environment(true);
+        #[cfg(feature = "std")]
+        let policy = policy.file_system(FileSystemPolicy { root: None, allow_writes: true });
+        policy
+    }
+
+    /// The print builtins, plus the file builtins if a `file_system`
+    /// policy is also given.
+    pub fn allow_io(mut self, allow: bool) -> SandboxPolicy {
+        self.io = allow;
+        self
+    }
+
+    /// `now()`.
+    pub fn allow_time(mut self, allow: bool) -> SandboxPolicy {
+        self.time = allow;
+        self
+    }
+
+    /// `rand`, `rand_int`, `rand_range` and `shuffle`.
+    pub fn allow_random(mut self, allow: bool) -> SandboxPolicy {
+        self.random = allow;
+        self
+    }
+
+    /// `env` and `argv`.
+    pub fn allow_environment(mut self, allow: bool) -> SandboxPolicy {
+        self.environment = allow;
+        self
+    }
+
+    /// Which files the file builtins may touch.  Only used with `allow_io`.
+    #[cfg(feature = "std")]
+    pub fn file_system(mut self, policy: FileSystemPolicy) -> SandboxPolicy {
+        self.file_system = Some(policy);
+        self
+    }
+
+    /// Only installs the builtins named here (that the capabilities also
+    /// allow).
+    pub fn allow_only<I, N>(mut self, names: I) -> SandboxPolicy
+    where I: IntoIterator<Item = N>, N: Into<String> {
+        self.allowlist = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Whether the builtin called `name` passes the allowlist.
+    pub fn permits_name(&self, name: &str) -> bool {
+        self.allowlist.as_ref().map_or(true, |names| names.contains(name))
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Installs the builtins that `policy` allows, and configures the
+    /// vm options that those builtins check.
+    pub fn install_standard_library(&mut self, policy: &SandboxPolicy) {
+        // Builtins are installed into a scratch context first so that the
+        // allowlist can be applied by name.
+        let mut scratch: Context<S> = Context::new();
+        scratch.install_sexpr_builtins();
+        scratch.install_numeric_builtins();
+        scratch.install_string_builtins();
+        scratch.install_symbol_builtins();
+        scratch.install_string_builder_builtins();
+        scratch.install_collection_builtins();
+        scratch.install_functional_builtins();
+        scratch.install_template_builtins();
+        install_json(&mut scratch);
+        install_decimal(&mut scratch);
+        install_system(&mut scratch, policy);
+        if policy.random {
+            scratch.install_random_builtins();
+        }
+
+        let namespace = scratch.interner().precomputed.default_namespace;
+        for symbol in scratch.modules().names_in(namespace) {
+            let name = scratch.interner().lookup_or_anon(symbol);
+            if policy.permits_name(&name) {
+                let value = scratch.modules().get(namespace, symbol).cloned().unwrap();
+                self.set_global(&name[..], value);
+            }
+        }
+
+        let options = self.vm_options_mut();
+        #[cfg(feature = "std")]
+        {
+            options.file_system = if policy.io { policy.file_system.clone() } else { None };
+        }
+        options.allow_environment = policy.environment;
+    }
+}
+
+/// The builtins that reach the operating system, which only exist with
+/// the `std` feature.
+#[cfg(feature = "std")]
+fn install_system<S: State>(ctx: &mut Context<S>, policy: &SandboxPolicy) {
+    if policy.io {
+        ctx.install_print_builtins();
+        if policy.file_system.is_some() {
+            ctx.install_file_builtins();
+        }
+    }
+    if policy.time {
+        ctx.install_time_builtins();
+    }
+    if policy.environment {
+        ctx.install_environment_builtins();
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn install_system<S: State>(_: &mut Context<S>, _: &SandboxPolicy) {}
+
+#[cfg(feature = "serde_json")]
+fn install_json<S: State>(ctx: &mut Context<S>) {
+    ctx.install_json_builtins();
+}
+
+#[cfg(not(feature = "serde_json"))]
+fn install_json<S: State>(_: &mut Context<S>) {}
+
+#[test]
+fn policy_limits_builtins() {
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_standard_library(&SandboxPolicy::new().allow_random(true).allow_only(vec!["rand", "write"]));
+
+    assert!(ctx.has_global("rand"));
+    assert!(ctx.has_global("write"));
+    assert!(!ctx.has_global("rand_int"));
+    assert!(!ctx.has_global("read"));
+    assert!(!ctx.has_global("print"));
+    assert!(!ctx.has_global("read_file"));
+    assert!(!ctx.vm_options_mut().allow_environment);
+
+    if !cfg!(feature = "std") {
+        return;
+    }
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_standard_library(&SandboxPolicy::permissive());
+    for name in &["print", "read_file", "now", "shuffle", "env", "read"] {
+        assert!(ctx.has_global(*name), "{} was not installed", name);
+    }
+}
+
+#[cfg(feature = "decimal")]
+fn install_decimal<S: State>(ctx: &mut Context<S>) {
+    ctx.install_decimal_builtins();
+}
+
+#[cfg(not(feature = "decimal"))]
+fn install_decimal<S: State>(_: &mut Context<S>) {}
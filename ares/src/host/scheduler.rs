@@ -0,0 +1,193 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use host::{Context, ContextLike, State, AresResult};
+use vm::{Value, user_function};
+
+/// Timers registered by `set_timeout` and `set_interval`.  Time only
+/// passes when the host calls `Context::tick`.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// Milliseconds that have been ticked so far.
+    now: u64,
+    next_id: i64,
+    timers: Vec<Timer>,
+}
+
+#[derive(Debug)]
+struct Timer {
+    id: i64,
+    due: u64,
+    interval: Option<u64>,
+    callback: Value,
+}
+
+impl Scheduler {
+    /// The time that has been ticked since the scheduler was created.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.now)
+    }
+
+    /// The number of timers that have not finished or been cancelled.
+    pub fn pending(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// Cancels the timer with the id returned by `set_timeout` or
+    /// `set_interval`.  Returns false if there was no such timer.
+    pub fn cancel(&mut self, id: i64) -> bool {
+        let before = self.timers.len();
+        self.timers.retain(|t| t.id != id);
+        self.timers.len() != before
+    }
+
+    fn schedule(&mut self, delay: u64, interval: Option<u64>, callback: Value) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.timers.push(Timer {
+            id: id,
+            due: self.now + delay,
+            interval: interval,
+            callback: callback,
+        });
+        id
+    }
+
+    // The ids of the timers that are due, earliest first.
+    fn due(&self) -> Vec<i64> {
+        let mut due: Vec<(u64, i64)> = self.timers.iter()
+            .filter(|t| t.due <= self.now)
+            .map(|t| (t.due, t.id))
+            .collect();
+        due.sort();
+        due.into_iter().map(|(_, id)| id).collect()
+    }
+
+    // Takes the callback of a due timer, rescheduling it if it repeats.
+    fn fire(&mut self, id: i64) -> Option<Value> {
+        let now = self.now;
+        let index = match self.timers.iter().position(|t| t.id == id) {
+            Some(index) => index,
+            None => return None,
+        };
+        match self.timers[index].interval {
+            Some(interval) => {
+                let timer = &mut self.timers[index];
+                // An interval that fell behind skips the runs it missed.
+                timer.due = ::std::cmp::max(timer.due + interval, now + 1);
+                Some(timer.callback.clone())
+            }
+            None => Some(self.timers.remove(index).callback),
+        }
+    }
+}
+
+fn millis(value: Option<&Value>) -> Option<u64> {
+    match value {
+        Some(&Value::Int(i)) => Some(if i < 0 { 0 } else { i as u64 }),
+        Some(&Value::Float(f)) => Some(if f < 0.0 { 0 } else { f as u64 }),
+        _ => None,
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines the timer builtins:
+    ///
+    /// * `set_timeout(ms, callback)` calls `callback` once, `ms`
+    ///   milliseconds from now.
+    /// * `set_interval(ms, callback)` calls `callback` every `ms`
+    ///   milliseconds.
+    /// * `clear_timer(id)` cancels a timer and returns whether it existed.
+    ///
+    /// Both `set_` builtins return the id of the new timer, or nil if the
+    /// arguments are wrong.  Callbacks are run by `tick`.
+    pub fn install_timer_builtins(&mut self) {
+        let scheduler = self.scheduler.clone();
+        self.set_global("set_timeout", user_function::<S, _>(Some("set_timeout".into()), move |args, _, _| {
+            match (millis(args.get(0)), args.get(1)) {
+                (Some(delay), Some(callback)) =>
+                    Value::Int(scheduler.borrow_mut().schedule(delay, None, callback.clone())),
+                _ => Value::Nil,
+            }
+        }));
+
+        let scheduler = self.scheduler.clone();
+        self.set_global("set_interval", user_function::<S, _>(Some("set_interval".into()), move |args, _, _| {
+            match (millis(args.get(0)), args.get(1)) {
+                // A zero interval would fire on every tick forever.
+                (Some(interval), Some(callback)) => {
+                    let interval = ::std::cmp::max(interval, 1);
+                    Value::Int(scheduler.borrow_mut().schedule(interval, Some(interval), callback.clone()))
+                }
+                _ => Value::Nil,
+            }
+        }));
+
+        let scheduler = self.scheduler.clone();
+        self.set_global("clear_timer", user_function::<S, _>(Some("clear_timer".into()), move |args, _, _| {
+            match args.get(0) {
+                Some(&Value::Int(id)) => Value::Bool(scheduler.borrow_mut().cancel(id)),
+                _ => Value::Bool(false),
+            }
+        }));
+    }
+
+    pub fn scheduler(&self) -> Rc<RefCell<Scheduler>> {
+        self.scheduler.clone()
+    }
+
+    /// Advances the scheduler by `elapsed` and runs every timer that is
+    /// due, earliest first.  Each timer runs at most once per tick, and
+    /// timers created by the callbacks wait for a later tick.  Returns the
+    /// number of callbacks that were run.
+    ///
+    /// If a callback fails, the error is returned and the timers that
+    /// were still due run on the next tick.
+    pub fn tick(&mut self, state: &mut S, elapsed: Duration) -> AresResult<usize> {
+        let due = {
+            let mut scheduler = self.scheduler.borrow_mut();
+            scheduler.now += elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+            scheduler.due()
+        };
+
+        let mut ran = 0;
+        for id in due {
+            let callback = match self.scheduler.borrow_mut().fire(id) {
+                Some(callback) => callback,
+                // Cancelled by an earlier callback.
+                None => continue,
+            };
+            try!(self.call(state, callback, vec![]));
+            ran += 1;
+        }
+        Ok(ran)
+    }
+}
+
+#[test]
+fn timers_fire_on_tick() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_timer_builtins();
+    ctx.eval(&mut state, "
+        var once = 0;
+        var repeats = 0;
+        set_timeout(50, fn() { once = once + 1; });
+        var interval = set_interval(20, fn() { repeats = repeats + 1; });
+        var cancelled = set_timeout(10, fn() { once = 100; });
+        clear_timer(cancelled);
+    ").unwrap();
+
+    assert_eq!(ctx.tick(&mut state, Duration::from_millis(10)).unwrap(), 0);
+    let code_size = ctx.vm.code.len();
+    assert_eq!(ctx.tick(&mut state, Duration::from_millis(10)).unwrap(), 1);
+    assert_eq!(ctx.tick(&mut state, Duration::from_millis(40)).unwrap(), 2);
+    assert_eq!(ctx.vm.code.len(), code_size);
+    assert_eq!(ctx.eval(&mut state, "once").unwrap(), Some(Value::Int(1)));
+    assert_eq!(ctx.eval(&mut state, "repeats").unwrap(), Some(Value::Int(2)));
+
+    ctx.eval(&mut state, "clear_timer(interval)").unwrap();
+    assert_eq!(ctx.scheduler().borrow().pending(), 0);
+    assert_eq!(ctx.scheduler().borrow().elapsed(), Duration::from_millis(60));
+}
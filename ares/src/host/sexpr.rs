@@ -0,0 +1,344 @@
+use util::collections::HashMap;
+
+use ares_syntax::SymbolIntern;
+use host::{Context, ContextLike, State};
+use vm::{Value, ValueKind, Rational, user_function};
+#[cfg(feature = "decimal")]
+use vm::Decimal;
+
+// Deeper values are assumed to be cyclic through a cell.
+const MAX_DEPTH: u32 = 512;
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum WriteError {
+    /// Functions, closures and continuations can't be read back in.
+    Unsupported(ValueKind),
+    TooDeep,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ReadError {
+    pub message: String,
+    /// The byte offset in the input where reading failed.
+    pub offset: usize,
+}
+
+/// Writes `value` as an s-expression that `read_value` turns back into
+/// an equal value.
+///
+/// * `nil`, `true`, `false`, numbers, and `"strings"` are written as
+///   literals.  Floats always contain a `.` or an exponent, except for
+///   `nan`, `inf` and `-inf`.
+/// * Rationals are written as `numerator/denominator`, like `-1/3`.
+/// * Decimals are written the way scripts write them, like `1.50d`.
+/// * Symbols are written as `'name`, or `'|odd name|` if the name has
+///   characters that aren't allowed in an identifier.
+/// * Lists are written as `(a b c)` and maps as `{key value ...}`, with
+///   the keys sorted so that equal maps are always written the same way.
+/// * Cells are written as the value that they contain.
+pub fn write_value(value: &Value, interner: &SymbolIntern) -> Result<String, WriteError> {
+    let mut out = String::new();
+    write_into(value, interner, &mut out, 0)?;
+    Ok(out)
+}
+
+/// Reads one value written by `write_value`.  Anything other than
+/// whitespace after the value is an error.
+pub fn read_value(text: &str, interner: &mut SymbolIntern) -> Result<Value, ReadError> {
+    let mut reader = Reader { text: text, pos: 0, interner: interner };
+    let value = reader.value(0)?;
+    reader.skip_whitespace();
+    if reader.pos != text.len() {
+        return Err(reader.error("unexpected text after value"));
+    }
+    Ok(value)
+}
+
+fn is_plain_symbol_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-+*/<>=!?".contains(c)
+}
+
+fn write_escaped(s: &str, delimiter: char, out: &mut String) {
+    out.push(delimiter);
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c == delimiter => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(delimiter);
+}
+
+fn write_float(f: f64, out: &mut String) {
+    if f.is_nan() {
+        out.push_str("nan");
+    } else if f.is_infinite() {
+        out.push_str(if f > 0.0 { "inf" } else { "-inf" });
+    } else {
+        // Debug formatting is the shortest text that parses back to `f`
+        out.push_str(&format!("{:?}", f));
+    }
+}
+
+fn write_into(value: &Value, interner: &SymbolIntern, out: &mut String, depth: u32) -> Result<(), WriteError> {
+    if depth > MAX_DEPTH {
+        return Err(WriteError::TooDeep);
+    }
+
+    match value {
+        &Value::Nil => out.push_str("nil"),
+        &Value::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+        &Value::Int(i) => out.push_str(&i.to_string()),
+        &Value::Float(f) => write_float(f, out),
+        &Value::String(_) | &Value::Substring(_) => write_escaped(value.expect_str().unwrap(), '"', out),
+        &Value::Rational(ref r) => out.push_str(&r.to_string()),
+        #[cfg(feature = "decimal")]
+        &Value::Decimal(ref d) => out.push_str(&d.to_string()),
+        &Value::Symbol(s) => {
+            let name = interner.lookup_or_anon(s);
+            out.push('\'');
+            let plain = name.chars().all(is_plain_symbol_char) &&
+                        name.chars().next().map_or(false, |c| !c.is_digit(10));
+            if plain {
+                out.push_str(&name);
+            } else {
+                write_escaped(&name, '|', out);
+            }
+        }
+        &Value::Cell(ref c) => write_into(&*c.borrow(), interner, out, depth + 1)?,
+        &Value::List(ref items) => {
+            out.push('(');
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    out.push(' ');
+                }
+                write_into(item, interner, out, depth + 1)?;
+            }
+            out.push(')');
+        }
+        &Value::Map(ref pairs) => {
+            out.push('{');
+            for (i, (k, v)) in pairs.borrow().sorted().into_iter().enumerate() {
+                if i != 0 {
+                    out.push(' ');
+                }
+                write_into(k, interner, out, depth + 1)?;
+                out.push(' ');
+                write_into(v, interner, out, depth + 1)?;
+            }
+            out.push('}');
+        }
+        &Value::Closure(_) => return Err(WriteError::Unsupported(ValueKind::Closure)),
+        &Value::UserFn(_) => return Err(WriteError::Unsupported(ValueKind::UserFn)),
+        &Value::Continuation(_) => return Err(WriteError::Unsupported(ValueKind::Continuation)),
+        &Value::Handle(_) => return Err(WriteError::Unsupported(ValueKind::Handle)),
+    }
+    Ok(())
+}
+
+struct Reader<'a, 'i> {
+    text: &'a str,
+    pos: usize,
+    interner: &'i mut SymbolIntern,
+}
+
+impl <'a, 'i> Reader<'a, 'i> {
+    fn error(&self, message: &str) -> ReadError {
+        ReadError { message: message.into(), offset: self.pos }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.pos += c.len_utf8();
+        }
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().map_or(false, char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self, depth: u32) -> Result<Value, ReadError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("value is nested too deeply"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("expected a value")),
+            Some('(') => {
+                self.bump();
+                let mut items = vec![];
+                while !self.at_close(')')? {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(items.into())
+            }
+            Some('{') => {
+                self.bump();
+                let mut map = HashMap::new();
+                while !self.at_close('}')? {
+                    let k = self.value(depth + 1)?;
+                    if self.at_close('}')? {
+                        return Err(self.error("map is missing a value for its last key"));
+                    }
+                    let v = self.value(depth + 1)?;
+                    map.insert(k, v);
+                }
+                Ok(map.into())
+            }
+            Some('"') => Ok(self.escaped('"')?.into()),
+            Some('\'') => {
+                self.bump();
+                let name = if self.peek() == Some('|') {
+                    self.escaped('|')?
+                } else {
+                    self.word().to_string()
+                };
+                if name.is_empty() {
+                    return Err(self.error("expected a symbol name"));
+                }
+                Ok(Value::Symbol(self.interner.intern(name)))
+            }
+            Some(_) => self.atom(),
+        }
+    }
+
+    /// Skips whitespace and consumes `close` if it is next.
+    fn at_close(&mut self, close: char) -> Result<bool, ReadError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error(&format!("expected `{}`", close))),
+            Some(c) if c == close => {
+                self.bump();
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+        }
+    }
+
+    fn word(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.peek().map_or(false, |c| is_plain_symbol_char(c) || c == '.') {
+            self.bump();
+        }
+        &self.text[start .. self.pos]
+    }
+
+    fn escaped(&mut self, delimiter: char) -> Result<String, ReadError> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('\\') => match self.bump() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some(c) if c == '\\' || c == delimiter => out.push(c),
+                    _ => return Err(self.error("unknown escape sequence")),
+                },
+                Some(c) if c == delimiter => return Ok(out),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Value, ReadError> {
+        let start = self.pos;
+        let word = self.word();
+        let value = match word {
+            "nil" => Value::Nil,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "nan" => Value::Float(::std::f64::NAN),
+            "inf" => Value::Float(::std::f64::INFINITY),
+            "-inf" => Value::Float(::std::f64::NEG_INFINITY),
+            #[cfg(feature = "decimal")]
+            w if w.ends_with('d') =>
+                Decimal::parse(&w[.. w.len() - 1]).map(Decimal::into_value)
+                                                  .ok_or_else(|| self.error_at(start, "invalid decimal"))?,
+            w if w.contains('/') => {
+                let mut parts = w.splitn(2, '/').map(|p| p.parse::<i64>().ok());
+                match (parts.next(), parts.next()) {
+                    (Some(Some(n)), Some(Some(d))) if d != 0 =>
+                        Rational::new(n, d).map(Rational::into_value)
+                                           .map_err(|_| self.error_at(start, "rational out of range"))?,
+                    _ => return Err(self.error_at(start, "invalid rational")),
+                }
+            }
+            w if w.contains(|c| c == '.' || c == 'e' || c == 'E') =>
+                w.parse::<f64>().map(Value::Float).map_err(|_| self.error_at(start, "invalid float"))?,
+            w => w.parse::<i64>().map(Value::Int).map_err(|_| self.error_at(start, "expected a value"))?,
+        };
+        Ok(value)
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> ReadError {
+        ReadError { message: message.into(), offset: offset }
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines `write(value)`, which returns the s-expression for a
+    /// value, and `read(string)`, which parses one back.  Both return
+    /// `nil` if they fail.
+    pub fn install_sexpr_builtins(&mut self) {
+        self.set_global("write", user_function::<S, _>(Some("write".into()), |args, _, ctx| {
+            match args.get(0).map(|v| write_value(v, ctx.interner())) {
+                Some(Ok(text)) => text.into(),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("read", user_function::<S, _>(Some("read".into()), |args, _, ctx| {
+            let text = match args.get(0).map(Value::expect_str) {
+                Some(Ok(text)) => text.to_string(),
+                _ => return Value::Nil,
+            };
+            read_value(&text, ctx.interner_mut()).unwrap_or(Value::Nil)
+        }));
+    }
+}
+
+#[test]
+fn write_then_read() {
+    let mut interner = SymbolIntern::new();
+    let mut map = HashMap::new();
+    map.insert(Value::Symbol(interner.intern("odd name|")), Value::Float(0.1));
+    map.insert("quote\"d\n".into(), Value::Float(1e300));
+    let value: Value = vec![
+        Value::Nil, Value::Bool(true), Value::Int(-12), Value::Float(2.0),
+        Rational::new(-1, 3).unwrap().into_value(), Value::Symbol(interner.intern("plain")), map.into(), Vec::<Value>::new().into(),
+    ].into();
+
+    let text = write_value(&value, &interner).unwrap();
+    assert!(text.starts_with("(nil true -12 2.0 -1/3 'plain {"));
+    assert_eq!(read_value(&text, &mut interner).unwrap(), value);
+
+    assert!(read_value("(1 2", &mut interner).is_err());
+    assert!(read_value("{1}", &mut interner).is_err());
+}
+
+#[test]
+fn builtins() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_sexpr_builtins();
+    let result = ctx.eval(&mut state, "read(write([1, 'a, \"b\"])) == [1, 'a, \"b\"]").unwrap();
+    assert_eq!(result, Some(Value::Bool(true)));
+}
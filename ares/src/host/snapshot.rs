@@ -0,0 +1,77 @@
+use host::{Context, State};
+use vm::Modules;
+
+/// A copy of a context's globals, and a mark of which resources existed.
+#[derive(Debug)]
+pub struct Snapshot {
+    globals: Modules,
+    next_resource: u32,
+}
+
+impl <S: State> Context<S> {
+    /// Deep copies the globals.  Later changes to the context, including
+    /// mutations of cells that are reachable from globals, don't affect
+    /// the snapshot.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            globals: self.vm.globals.deep_copy(),
+            next_resource: self.vm.resources.next_id(),
+        }
+    }
+
+    /// Puts the globals back the way they were when `snapshot` was taken,
+    /// and removes the resources that were inserted since then.  The same
+    /// snapshot can be restored any number of times.
+    ///
+    /// Symbols are never forgotten, because the host may still hold
+    /// values with symbols that were interned after the snapshot.
+    /// Resources that were removed after the snapshot can't be brought
+    /// back, so handles to them in the restored globals stay invalid.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.vm.globals.restore_from(&snapshot.globals);
+        self.vm.resources.remove_since(snapshot.next_resource);
+    }
+}
+
+#[test]
+fn restore_rolls_back_globals() {
+    use host::ContextLike;
+    use vm::Value;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.eval(&mut state, "var count = 0; var bump = fn() { count = count + 1; count };").unwrap();
+    let snapshot = ctx.snapshot();
+
+    ctx.eval(&mut state, "bump(); bump(); var extra = 5;").unwrap();
+    assert_eq!(ctx.eval(&mut state, "count").unwrap(), Some(Value::Int(2)));
+
+    ctx.restore(&snapshot);
+    assert_eq!(ctx.eval(&mut state, "count").unwrap(), Some(Value::Int(0)));
+    assert!(!ctx.has_global("extra"));
+    assert_eq!(ctx.eval(&mut state, "bump()").unwrap(), Some(Value::Int(1)));
+
+    ctx.restore(&snapshot);
+    assert_eq!(ctx.eval(&mut state, "bump()").unwrap(), Some(Value::Int(1)));
+}
+
+#[test]
+fn restore_keeps_symbols_and_drops_new_resources() {
+    use host::ContextLike;
+
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    let kept = ctx.resources_mut().insert(1u8);
+    let snapshot = ctx.snapshot();
+
+    let symbol = ctx.eval(&mut state, "'after_snapshot").unwrap().unwrap();
+    let added = ctx.resources_mut().insert(2u8);
+    ctx.restore(&snapshot);
+
+    assert_eq!(ctx.eval(&mut state, "'after_snapshot").unwrap(), Some(symbol.clone()));
+    assert!(ctx.eval(&mut state, "'something_else").unwrap() != Some(symbol));
+    assert!(ctx.resources_mut().contains(&kept));
+    assert!(!ctx.resources_mut().contains(&added));
+    assert!(ctx.resources_mut().insert(3u8) != added);
+    assert_eq!(ctx.resources_mut().len(), 2);
+}
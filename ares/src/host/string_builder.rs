@@ -0,0 +1,63 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function, to_string_helper};
+
+/// The buffer behind a string builder handle.
+struct StringBuilder(String);
+
+impl <S: State> Context<S> {
+    /// Defines the builtins for building up a large string without copying
+    /// it on every concatenation:
+    ///
+    /// * `string_builder()` returns a handle to a new, empty buffer.
+    /// * `append(builder, values...)` adds each value to the end of the
+    ///   buffer and returns the builder.  Strings are added as they are;
+    ///   anything else is added the way `print` would show it.
+    /// * `build(builder)` returns the contents of the buffer as a string.
+    ///   The builder can keep being appended to afterwards.
+    ///
+    /// `append` and `build` return `nil` if `builder` isn't a builder.
+    pub fn install_string_builder_builtins(&mut self) {
+        self.set_global("string_builder", user_function::<S, _>(Some("string_builder".into()), |_, _, ctx| {
+            ctx.resources_mut().insert(StringBuilder(String::new()))
+        }));
+
+        self.set_global("append", user_function::<S, _>(Some("append".into()), |args, _, ctx| {
+            let (builder, values) = match args.split_first() {
+                Some(split) => split,
+                None => return Value::Nil,
+            };
+            let text: Vec<String> = values.iter().map(|v| match v.expect_str() {
+                Ok(s) => s.to_string(),
+                Err(_) => to_string_helper(v, ctx.interner()),
+            }).collect();
+            match ctx.resources_mut().get_mut::<StringBuilder>(builder) {
+                Some(&mut StringBuilder(ref mut buffer)) => {
+                    for piece in text {
+                        buffer.push_str(&piece);
+                    }
+                    builder.clone()
+                }
+                None => Value::Nil,
+            }
+        }));
+
+        self.set_global("build", user_function::<S, _>(Some("build".into()), |args, _, ctx| {
+            match args.get(0).and_then(|b| ctx.resources_mut().get::<StringBuilder>(b)) {
+                Some(&StringBuilder(ref buffer)) => Value::string(buffer.clone()),
+                None => Value::Nil,
+            }
+        }));
+    }
+}
+
+#[test]
+fn builders_accumulate() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_string_builder_builtins();
+    ctx.eval(&mut state, "var b = string_builder(); var i = 0;").unwrap();
+    ctx.eval(&mut state, "append(append(b, \"x = \"), 1, \", \");").unwrap();
+    assert_eq!(ctx.eval(&mut state, "build(b)").unwrap(), Some(Value::string("x = 1, ")));
+    assert_eq!(ctx.eval(&mut state, "build(append(b, 'y, [2])) ").unwrap(), Some(Value::string("x = 1, 'y[2]")));
+    assert_eq!(ctx.eval(&mut state, "build(\"not a builder\")").unwrap(), Some(Value::Nil));
+}
@@ -0,0 +1,122 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function};
+
+/// Whether `value` is a non-empty string whose every character passes
+/// `test`.  A character is just a string of length one.
+fn all_chars<F: Fn(char) -> bool>(value: Option<&Value>, test: F) -> Value {
+    match value.map(Value::expect_str) {
+        Some(Ok(text)) => Value::Bool(!text.is_empty() && text.chars().all(test)),
+        _ => Value::Nil,
+    }
+}
+
+/// Applies `test` to the text of two strings.
+fn both_strs<F: Fn(&str, &str) -> bool>(args: &[Value], test: F) -> Value {
+    match (args.get(0).map(Value::expect_str), args.get(1).map(Value::expect_str)) {
+        (Some(Ok(a)), Some(Ok(b))) => Value::Bool(test(a, b)),
+        _ => Value::Nil,
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines the builtins for looking at strings.  There is no separate
+    /// character type, so a character is a string of length one.
+    ///
+    /// * `is_digit(s)`, `is_alpha(s)` and `is_whitespace(s)` check that
+    ///   every character of `s` is a numeric, alphabetic or whitespace
+    ///   character, by their Unicode properties.  They are false for the
+    ///   empty string.
+    /// * `starts_with(s, prefix)`, `ends_with(s, suffix)` and
+    ///   `contains(s, part)` look for one string in another.
+    /// * `str_len(s)` returns the number of characters in `s`, and
+    ///   `str_bytes_len(s)` the number of bytes in its utf-8 encoding.
+    /// * `substring(s, start, end)` returns the characters from `start` up
+    ///   to `end`, or to the end of `s` if `end` is left out.
+    ///
+    /// Each returns `nil` if it isn't given strings, or if a range is out
+    /// of bounds.  `notes/strings.md` describes what a character is.
+    pub fn install_string_builtins(&mut self) {
+        self.set_global("is_digit", user_function::<S, _>(Some("is_digit".into()), |args, _, _| {
+            all_chars(args.get(0), char::is_numeric)
+        }));
+        self.set_global("is_alpha", user_function::<S, _>(Some("is_alpha".into()), |args, _, _| {
+            all_chars(args.get(0), char::is_alphabetic)
+        }));
+        self.set_global("is_whitespace", user_function::<S, _>(Some("is_whitespace".into()), |args, _, _| {
+            all_chars(args.get(0), char::is_whitespace)
+        }));
+
+        self.set_global("starts_with", user_function::<S, _>(Some("starts_with".into()), |args, _, _| {
+            both_strs(&args, |s, prefix| s.starts_with(prefix))
+        }));
+        self.set_global("ends_with", user_function::<S, _>(Some("ends_with".into()), |args, _, _| {
+            both_strs(&args, |s, suffix| s.ends_with(suffix))
+        }));
+        self.set_global("contains", user_function::<S, _>(Some("contains".into()), |args, _, _| {
+            both_strs(&args, |s, part| s.contains(part))
+        }));
+
+        self.set_global("str_len", user_function::<S, _>(Some("str_len".into()), |args, _, _| {
+            match args.get(0).map(Value::char_len) {
+                Some(Ok(len)) => Value::Int(len as i64),
+                _ => Value::Nil,
+            }
+        }));
+        self.set_global("str_bytes_len", user_function::<S, _>(Some("str_bytes_len".into()), |args, _, _| {
+            match args.get(0).map(Value::expect_str) {
+                Some(Ok(text)) => Value::Int(text.len() as i64),
+                _ => Value::Nil,
+            }
+        }));
+        self.set_global("substring", user_function::<S, _>(Some("substring".into()), |args, _, _| {
+            let text = match args.get(0) {
+                Some(text) => text,
+                None => return Value::Nil,
+            };
+            let end = match args.get(2) {
+                Some(&Value::Int(end)) => end,
+                None => match text.char_len() {
+                    Ok(len) => len as i64,
+                    Err(_) => return Value::Nil,
+                },
+                Some(_) => return Value::Nil,
+            };
+            match args.get(1) {
+                Some(&Value::Int(start)) if start >= 0 && end >= 0 =>
+                    text.char_substring(start as usize, end as usize).unwrap_or(Value::Nil),
+                _ => Value::Nil,
+            }
+        }));
+    }
+}
+
+#[test]
+fn string_predicates() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_string_builtins();
+    let mut eval = |source: &str| ctx.eval(&mut state, source).unwrap().unwrap();
+
+    assert_eq!(eval("[is_digit(\"042\"), is_digit(\"٣\"), is_digit(\"4a\"), is_digit(\"\")]"),
+               eval("[true, true, false, false]"));
+    assert_eq!(eval("[is_alpha(\"héllo\"), is_alpha(\"日本\"), is_alpha(\"a1\")]"), eval("[true, true, false]"));
+    assert_eq!(eval("[is_whitespace(\" \t\"), is_whitespace(\"\u{3000}\"), is_whitespace(\"x\")]"),
+               eval("[true, true, false]"));
+    assert_eq!(eval("[starts_with(\"héllo\", \"hé\"), ends_with(\"héllo\", \"lo\"), contains(\"héllo\", \"él\")]"),
+               eval("[true, true, true]"));
+    assert_eq!(eval("[contains(\"abc\", \"d\"), starts_with(\"abc\", 1)]"), eval("[false, nil]"));
+}
+
+#[test]
+fn strings_count_characters() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_string_builtins();
+    let mut eval = |source: &str| ctx.eval(&mut state, source).unwrap().unwrap();
+
+    assert_eq!(eval("[str_len(\"héllo\"), str_bytes_len(\"héllo\")]"), eval("[5, 6]"));
+    assert_eq!(eval("\"héllo\"[1]"), Value::string("é"));
+    assert_eq!(eval("[substring(\"héllo\", 1, 3), substring(\"héllo\", 3)]"), eval("[\"él\", \"lo\"]"));
+    assert_eq!(eval("[substring(\"héllo\", 3, 9), substring(\"héllo\", 3, 1), substring(\"héllo\", 0 - 1)]"),
+               eval("[nil, nil, nil]"));
+}
@@ -0,0 +1,51 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function};
+
+impl <S: State> Context<S> {
+    /// Defines the builtins for making symbols while a script runs:
+    ///
+    /// * `symbol_to_string('name)` returns `"name"`.
+    /// * `string_to_symbol("name")` returns `'name`, interning the name if
+    ///   nothing has used it yet.
+    /// * `gensym()` returns a new symbol that is different from every
+    ///   other symbol, including any that `string_to_symbol` makes later.
+    ///   Its name is only for printing.
+    ///
+    /// The conversions return `nil` if they are given the wrong kind of
+    /// value.
+    pub fn install_symbol_builtins(&mut self) {
+        self.set_global("symbol_to_string", user_function::<S, _>(Some("symbol_to_string".into()), |args, _, ctx| {
+            match args.get(0) {
+                Some(&Value::Symbol(symbol)) => Value::string(ctx.interner().lookup_or_anon(symbol)),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("string_to_symbol", user_function::<S, _>(Some("string_to_symbol".into()), |args, _, ctx| {
+            match args.get(0).map(Value::expect_str) {
+                Some(Ok(name)) => Value::Symbol(ctx.interner_mut().intern(name)),
+                _ => Value::Nil,
+            }
+        }));
+
+        self.set_global("gensym", user_function::<S, _>(Some("gensym".into()), |_, _, ctx| {
+            Value::Symbol(ctx.interner_mut().gensym())
+        }));
+    }
+}
+
+#[test]
+fn symbols_from_strings() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_symbol_builtins();
+    let mut eval = |source: &str| ctx.eval(&mut state, source).unwrap().unwrap();
+
+    assert_eq!(eval("symbol_to_string('hello)"), Value::string("hello"));
+    assert_eq!(eval("string_to_symbol(\"hello\") == 'hello"), Value::Bool(true));
+    assert_eq!(eval("string_to_symbol(\"never seen before\") == string_to_symbol(\"never seen before\")"),
+               Value::Bool(true));
+    assert_eq!(eval("gensym() == gensym()"), Value::Bool(false));
+    assert_eq!(eval("string_to_symbol(symbol_to_string(gensym())) == gensym()"), Value::Bool(false));
+    assert_eq!(eval("[symbol_to_string(\"hello\"), string_to_symbol(1)]"), eval("[nil, nil]"));
+}
@@ -0,0 +1,173 @@
+use ares_syntax::SymbolIntern;
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function, to_string_helper};
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum TemplateError {
+    /// A `{` at this byte offset has no matching `}`.
+    Unclosed(usize),
+    /// A `}` at this byte offset has no matching `{`.
+    Unopened(usize),
+    /// The hole at this byte offset is `{}`.
+    EmptyHole(usize),
+    /// The values given to `render` have nothing under this name.
+    Missing(String),
+    /// The values given to `render` aren't a map.
+    NotAMap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Hole(String),
+}
+
+/// Text with `{name}` holes that are filled in from a map.  `{{` and `}}`
+/// stand for literal braces.
+///
+/// Parsing a template once and rendering it many times saves looking for
+/// the holes on every render.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Template, TemplateError> {
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        let mut chars = text.char_indices().peekable();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(TemplateError::Unopened(offset)),
+                '{' => {
+                    let rest = &text[offset + 1 ..];
+                    let close = rest.find('}').ok_or(TemplateError::Unclosed(offset))?;
+                    let name = rest[.. close].trim();
+                    if name.is_empty() {
+                        return Err(TemplateError::EmptyHole(offset));
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Text(literal.split_off(0)));
+                    }
+                    pieces.push(Piece::Hole(name.to_string()));
+                    while chars.peek().map_or(false, |&(i, _)| i <= offset + 1 + close) {
+                        chars.next();
+                    }
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Text(literal));
+        }
+        Ok(Template { pieces: pieces })
+    }
+
+    /// The names of the holes, in the order that they appear.
+    pub fn holes(&self) -> Vec<&str> {
+        self.pieces.iter().filter_map(|p| match p {
+            &Piece::Hole(ref name) => Some(&name[..]),
+            &Piece::Text(_) => None,
+        }).collect()
+    }
+
+    /// Fills in each hole with the value stored in `values` under a symbol
+    /// or string with the hole's name.  Strings are inserted as they are
+    /// and other values the way `print` would show them.
+    pub fn render(&self, values: &Value, interner: &SymbolIntern) -> Result<String, TemplateError> {
+        self.render_with(values, interner, |s| s.to_string())
+    }
+
+    /// Like `render`, but passes every inserted value through `escape`
+    /// first, to make it safe for HTML or whatever the text is for.  The
+    /// text of the template itself is not escaped.
+    pub fn render_with<F>(&self, values: &Value, interner: &SymbolIntern, escape: F) -> Result<String, TemplateError>
+    where F: Fn(&str) -> String {
+        let values = values.clone().decell();
+        let pairs = match values {
+            Value::Map(ref pairs) => pairs.borrow(),
+            _ => return Err(TemplateError::NotAMap),
+        };
+        let mut out = String::new();
+        for piece in &self.pieces {
+            match piece {
+                &Piece::Text(ref text) => out.push_str(text),
+                &Piece::Hole(ref name) => {
+                    let by_symbol = interner.symbol_for_name(name).and_then(|s| pairs.get(&Value::Symbol(s)));
+                    let value = by_symbol.or_else(|| pairs.get(&Value::string(name.clone())))
+                                         .ok_or_else(|| TemplateError::Missing(name.clone()))?;
+                    let text = match value.expect_str() {
+                        Ok(s) => s.to_string(),
+                        Err(_) => to_string_helper(value, interner),
+                    };
+                    out.push_str(&escape(&text));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines `template(text, values)`, which fills in the `{name}` holes
+    /// of `text` from the map `values` (see `Template`).  It returns `nil`
+    /// if the template is malformed or a value is missing.
+    pub fn install_template_builtins(&mut self) {
+        self.install_template_builtins_with_escape(|s| s.to_string());
+    }
+
+    /// Like `install_template_builtins`, but every value that `template`
+    /// inserts is passed through `escape` first.
+    pub fn install_template_builtins_with_escape<F>(&mut self, escape: F)
+    where F: Fn(&str) -> String + 'static {
+        self.set_global("template", user_function::<S, _>(Some("template".into()), move |args, _, ctx| {
+            let template = match args.get(0).map(Value::expect_str) {
+                Some(Ok(text)) => Template::parse(text),
+                _ => return Value::Nil,
+            };
+            let values = args.get(1).cloned().unwrap_or(Value::Nil);
+            template.and_then(|t| t.render_with(&values, ctx.interner(), &escape))
+                    .map_or(Value::Nil, Value::string)
+        }));
+    }
+}
+
+#[test]
+fn templates_fill_holes() {
+    let mut interner = SymbolIntern::new();
+    let values = Value::map(vec![(Value::Symbol(interner.intern("name")), Value::string("<Ty>")),
+                                 (Value::string("count"), Value::Int(3))]);
+
+    let template = Template::parse("Hello {name}, you have { count } items {{}}").unwrap();
+    assert_eq!(template.holes(), vec!["name", "count"]);
+    assert_eq!(template.render(&values, &interner), Ok("Hello <Ty>, you have 3 items {}".to_string()));
+    assert_eq!(template.render_with(&values, &interner, |s| s.replace("<", "&lt;").replace(">", "&gt;")),
+               Ok("Hello &lt;Ty&gt;, you have 3 items {}".to_string()));
+
+    assert_eq!(Template::parse("{name").unwrap_err(), TemplateError::Unclosed(0));
+    assert_eq!(Template::parse("a {} b").unwrap_err(), TemplateError::EmptyHole(2));
+    assert_eq!(Template::parse("a } b").unwrap_err(), TemplateError::Unopened(2));
+    assert_eq!(Template::parse("{missing}").unwrap().render(&values, &interner),
+               Err(TemplateError::Missing("missing".to_string())));
+}
+
+#[test]
+fn template_builtin() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_template_builtins_with_escape(|s| s.to_uppercase());
+    let values = Value::map(vec![(Value::string("who"), Value::string("world"))]);
+    ctx.set_global("values", values);
+    assert_eq!(ctx.eval(&mut state, "template(\"hello {who}\", values)").unwrap(),
+               Some(Value::string("hello WORLD")));
+    assert_eq!(ctx.eval(&mut state, "template(\"{nobody}\", values)").unwrap(), Some(Value::Nil));
+}
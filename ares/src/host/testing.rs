@@ -0,0 +1,121 @@
+use std::mem;
+
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function};
+
+/// Tests and assertion results collected by the test builtins.
+#[derive(Default)]
+pub struct TestRegistry {
+    tests: Vec<(String, Value)>,
+    failures: Vec<String>,
+    expected_throws: Vec<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TestReport {
+    pub passed: Vec<String>,
+    /// The name of every failed test along with why it failed.
+    pub failed: Vec<(String, Vec<String>)>,
+}
+
+impl TestReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl <S: State> Context<S> {
+    /// Defines the builtins that scripts use to declare tests:
+    ///
+    /// * `deftest(name, fn() { ... })` registers a test.
+    /// * `assert_eq(a, b)` fails the running test if `a != b`.
+    /// * `assert_throws(fn() { ... })` fails the running test unless
+    ///   calling the function produces an error.  The function is called
+    ///   once the body of the test has finished.
+    pub fn install_test_builtins(&mut self) {
+        let registry = self.tests.clone();
+        self.set_global("deftest", user_function::<S, _>(Some("deftest".into()), move |args, _, ctx| {
+            if args.len() != 2 {
+                registry.borrow_mut().failures.push("deftest expects a name and a function".into());
+                return Value::Nil;
+            }
+            let name = ctx.format_value(&args[0]);
+            registry.borrow_mut().tests.push((name, args[1].clone()));
+            Value::Nil
+        }));
+
+        let registry = self.tests.clone();
+        self.set_global("assert_eq", user_function::<S, _>(Some("assert_eq".into()), move |args, _, ctx| {
+            let equal = args.len() == 2 && args[0] == args[1];
+            if !equal {
+                let values: Vec<String> = args.iter().map(|v| ctx.format_value(v)).collect();
+                registry.borrow_mut().failures.push(format!("assert_eq failed: {}", values.join(" != ")));
+            }
+            Value::Bool(equal)
+        }));
+
+        let registry = self.tests.clone();
+        self.set_global("assert_throws", user_function::<S, _>(Some("assert_throws".into()), move |args, _, _| {
+            registry.borrow_mut().expected_throws.extend(args);
+            Value::Nil
+        }));
+    }
+}
+
+/// Runs every test registered with `deftest` so far.
+pub fn run_tests<S: State>(ctx: &mut Context<S>, state: &mut S) -> TestReport {
+    let tests = ctx.tests.borrow().tests.clone();
+    let mut report = TestReport::default();
+
+    for (name, test) in tests {
+        {
+            let mut registry = ctx.tests.borrow_mut();
+            registry.failures.clear();
+            registry.expected_throws.clear();
+        }
+
+        let result = ctx.call(state, test, vec![]);
+
+        let (mut failures, expected_throws) = {
+            let mut registry = ctx.tests.borrow_mut();
+            (mem::replace(&mut registry.failures, vec![]),
+             mem::replace(&mut registry.expected_throws, vec![]))
+        };
+        if let Err(e) = result {
+            failures.push(format!("threw {}", ctx.format_error(e)));
+        }
+        for f in expected_throws {
+            if ctx.call(state, f, vec![]).is_ok() {
+                failures.push("assert_throws failed: the function returned normally".into());
+            }
+        }
+
+        if failures.is_empty() {
+            report.passed.push(name);
+        } else {
+            report.failed.push((name, failures));
+        }
+    }
+
+    report
+}
+
+#[test]
+fn passing_and_failing_tests() {
+    let mut state = ();
+    let mut ctx: Context<()> = Context::new();
+    ctx.install_test_builtins();
+    ctx.eval(&mut state, "
+        deftest(\"adds\", fn() { assert_eq(1 + 1, 2); });
+        deftest(\"wrong\", fn() { assert_eq(1, 2); });
+        deftest(\"throws\", fn() { assert_throws(fn() { fn(a) { a }() }); });
+        deftest(\"does not throw\", fn() { assert_throws(fn() { 1 }); });
+    ").unwrap();
+
+    let report = run_tests(&mut ctx, &mut state);
+    assert_eq!(report.passed, vec!["adds".to_string(), "throws".to_string()]);
+    assert_eq!(report.failed.len(), 2);
+    assert_eq!(report.failed[0].0, "wrong");
+    assert_eq!(report.failed[0].1, vec!["assert_eq failed: 1 != 2".to_string()]);
+    assert_eq!(report.failed[1].0, "does not throw");
+}
@@ -0,0 +1,20 @@
+use host::{Context, ContextLike, State};
+use vm::{Value, user_function};
+
+impl <S: State> Context<S> {
+    /// Defines `now()`, which returns the number of seconds since the
+    /// unix epoch as a float, according to the clock given to
+    /// `set_clock`.  It returns `nil` in deterministic mode unless the
+    /// clock is deterministic.
+    pub fn install_time_builtins(&mut self) {
+        self.set_global("now", user_function::<S, _>(Some("now".into()), |_, _, ctx| {
+            let deterministic = ctx.vm_options_mut().deterministic;
+            let clock = &ctx.io_mut().clock;
+            if deterministic && !clock.is_deterministic() {
+                return Value::Nil;
+            }
+            let now = clock.now();
+            Value::Float(now.as_secs() as f64 + now.subsec_nanos() as f64 / 1e9)
+        }));
+    }
+}
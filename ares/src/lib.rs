@@ -11,6 +11,25 @@ extern crate libc;
 extern crate typed_arena;
 extern crate itertools;
 extern crate lalrpop_util;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+#[cfg(feature = "serialize")]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "hashbrown")]
+extern crate hashbrown;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+extern crate js_sys;
+#[cfg(feature = "decimal")]
+extern crate rust_decimal;
+#[cfg(feature = "graphemes")]
+extern crate unicode_segmentation;
 
 #[cfg(test)]
 extern crate latin;
@@ -18,5 +37,13 @@ extern crate latin;
 pub mod compiler;
 pub mod vm;
 pub mod host;
+pub mod pool;
+pub mod tooling;
 pub mod test;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 mod util;
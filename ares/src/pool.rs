@@ -0,0 +1,324 @@
+//! Runs many compiled programs at once, each on a fresh `Context` on one
+//! of a set of worker threads.
+//!
+//! Values can't leave the thread that made them, so results are copied
+//! back to the calling thread.  Data (numbers, strings, symbols, lists and
+//! maps) comes back as it was; closures, continuations, native functions
+//! and handles only mean something inside the context that made them, and
+//! come back as `nil`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use ares_syntax::{Span, SymbolIntern};
+use compiler::CompiledProgram;
+use host::{AresError, Context, ContextLike};
+use vm::{ContractKind, InterpError, Rational, Value, ValueKind};
+#[cfg(feature = "decimal")]
+use vm::Decimal;
+
+/// The results of `run_parallel`, in the same order as the programs.
+#[derive(Debug)]
+pub struct ParallelResults {
+    pub results: Vec<Result<Value, InterpError>>,
+    /// The symbols in `results` are interned here.
+    pub interner: SymbolIntern,
+}
+
+/// Runs every program on `worker_count` threads and gathers what each one
+/// returns.  A program that evaluates to nothing returns `nil`.
+pub fn run_parallel(programs: Vec<CompiledProgram>, worker_count: usize) -> ParallelResults {
+    run_parallel_with(programs, worker_count, |_| {})
+}
+
+/// Like `run_parallel`, but calls `setup` on each worker's context before
+/// it runs anything, to install builtins or define globals.
+pub fn run_parallel_with<F>(programs: Vec<CompiledProgram>, worker_count: usize, setup: F) -> ParallelResults
+where F: Fn(&mut Context<()>) + Send + Sync + 'static {
+    let count = programs.len();
+    let queue = Arc::new(Mutex::new(programs.into_iter().enumerate().collect::<VecDeque<_>>()));
+    let setup = Arc::new(setup);
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0 .. worker_count.max(1)).map(|_| {
+        let queue = queue.clone();
+        let setup = setup.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let mut ctx: Context<()> = Context::new();
+            setup(&mut ctx);
+            loop {
+                let next = queue.lock().unwrap().pop_front();
+                let (index, program) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = match ctx.run(&mut (), &program) {
+                    Ok(value) => Ok(Plain::from_value(&value.unwrap_or(Value::Nil), ctx.interner())),
+                    Err(AresError::InterpError(e)) => Err(PlainError::from_error(e, ctx.interner())),
+                    Err(AresError::CompileError(e)) =>
+                        Err(PlainError::from_error(InterpError::InternalInterpError(format!("{:?}", e)),
+                                                   ctx.interner())),
+                };
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            }
+        })
+    }).collect();
+    drop(sender);
+
+    let mut interner = SymbolIntern::new();
+    let mut results: Vec<Option<Result<Value, InterpError>>> = (0 .. count).map(|_| None).collect();
+    for (index, result) in receiver {
+        results[index] = Some(match result {
+            Ok(plain) => Ok(plain.into_value(&mut interner)),
+            Err(error) => Err(error.into_error(&mut interner)),
+        });
+    }
+    for worker in workers {
+        // A worker that panicked loses the program it was running, which
+        // is reported below.
+        let _ = worker.join();
+    }
+
+    let results = results.into_iter().map(|result| result.unwrap_or_else(|| {
+        Err(InterpError::InternalInterpError("the worker running this program panicked".into()))
+    })).collect();
+    ParallelResults { results: results, interner: interner }
+}
+
+/// A copy of a value that can be sent between threads.
+enum Plain {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Rational(i64, i64),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+    String(String),
+    Symbol(String),
+    List(Vec<Plain>),
+    Map(Vec<(Plain, Plain)>),
+}
+
+impl Plain {
+    /// Copies `value`.  Closures and the other values that can't be sent
+    /// become `Nil`, and so does a map or cell inside of itself.
+    fn from_value(value: &Value, interner: &SymbolIntern) -> Plain {
+        Plain::from_value_within(value, interner, &mut Vec::new())
+    }
+
+    /// `from_value`, where `within` holds the addresses of the maps and
+    /// cells that `value` is inside of.
+    fn from_value_within(value: &Value, interner: &SymbolIntern, within: &mut Vec<usize>) -> Plain {
+        let address = match value {
+            &Value::Cell(ref c) => &**c as *const _ as usize,
+            &Value::Map(ref m) => &**m as *const _ as usize,
+            _ => 0,
+        };
+        if address != 0 {
+            if within.contains(&address) {
+                return Plain::Nil;
+            }
+            within.push(address);
+        }
+        let plain = match value {
+            &Value::Bool(b) => Plain::Bool(b),
+            &Value::Int(i) => Plain::Int(i),
+            &Value::Float(f) => Plain::Float(f),
+            &Value::Rational(ref r) => Plain::Rational(r.numer(), r.denom()),
+            #[cfg(feature = "decimal")]
+            &Value::Decimal(ref d) => Plain::Decimal(**d),
+            &Value::String(_) | &Value::Substring(_) => Plain::String(value.expect_str().unwrap().to_string()),
+            &Value::Symbol(s) => Plain::Symbol(interner.lookup_or_anon(s)),
+            &Value::Cell(ref c) => Plain::from_value_within(&*c.borrow(), interner, within),
+            &Value::List(ref items) =>
+                Plain::List(items.iter().map(|v| Plain::from_value_within(v, interner, within)).collect()),
+            &Value::Map(ref pairs) => Plain::Map(pairs.borrow().iter().map(|(k, v)| {
+                (Plain::from_value_within(k, interner, within), Plain::from_value_within(v, interner, within))
+            }).collect()),
+            _ => Plain::Nil,
+        };
+        if address != 0 {
+            within.pop();
+        }
+        plain
+    }
+
+    fn into_value(self, interner: &mut SymbolIntern) -> Value {
+        match self {
+            Plain::Nil => Value::Nil,
+            Plain::Bool(b) => Value::Bool(b),
+            Plain::Int(i) => Value::Int(i),
+            Plain::Float(f) => Value::Float(f),
+            Plain::Rational(numer, denom) => Rational::new(numer, denom).map_or(Value::Nil, Rational::into_value),
+            #[cfg(feature = "decimal")]
+            Plain::Decimal(d) => Value::Decimal(::vm::Gc::new(d)),
+            Plain::String(s) => Value::string(s),
+            Plain::Symbol(name) => Value::Symbol(interner.intern(name)),
+            Plain::List(items) => Value::list(items.into_iter().map(|p| p.into_value(interner)).collect::<Vec<_>>()),
+            Plain::Map(pairs) => {
+                let pairs: Vec<(Value, Value)> = pairs.into_iter()
+                    .map(|(k, v)| (k.into_value(interner), v.into_value(interner)))
+                    .collect();
+                Value::map(pairs)
+            }
+        }
+    }
+}
+
+/// An error that can be sent between threads: `InterpError` with the
+/// values that it holds copied out.  Both conversions match every
+/// variant, so a new variant has to be added here before it can cross.
+enum PlainError {
+    InternalInterpError(String),
+    MismatchedType {
+        value: Plain,
+        expected: ValueKind,
+    },
+    IncomparableValues(Plain, Plain),
+    StringIndexOutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    ListIndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
+    DivideByZero,
+    IntegerOverflow,
+    NumericCoercion {
+        left: ValueKind,
+        right: ValueKind,
+    },
+    VariableNotFound(String),
+    StackOverflow,
+    StackUnderflow,
+    StackOutOfBounds,
+    WrongArity {
+        got: u32,
+        expected: u32,
+        span: Option<Span>,
+    },
+    UserFnWithWrongStateType,
+    NativePanic {
+        name: Option<String>,
+        message: String,
+    },
+    #[cfg(feature = "async")]
+    SuspendedOutsideOfAsync,
+    ContractViolation {
+        kind: ContractKind,
+        span: Option<Span>,
+    },
+    ReplayDiverged {
+        expected: Option<String>,
+        got: Option<String>,
+    },
+    MutatedMapKey,
+    ShiftWithoutReset,
+}
+
+impl PlainError {
+    fn from_error(error: InterpError, interner: &SymbolIntern) -> PlainError {
+        match error {
+            InterpError::InternalInterpError(message) => PlainError::InternalInterpError(message),
+            InterpError::MismatchedType { value, expected } => PlainError::MismatchedType {
+                value: Plain::from_value(&value, interner),
+                expected: expected,
+            },
+            InterpError::IncomparableValues(a, b) =>
+                PlainError::IncomparableValues(Plain::from_value(&a, interner), Plain::from_value(&b, interner)),
+            InterpError::StringIndexOutOfBounds { start, end, len } =>
+                PlainError::StringIndexOutOfBounds { start: start, end: end, len: len },
+            InterpError::ListIndexOutOfBounds { index, len } =>
+                PlainError::ListIndexOutOfBounds { index: index, len: len },
+            InterpError::DivideByZero => PlainError::DivideByZero,
+            InterpError::IntegerOverflow => PlainError::IntegerOverflow,
+            InterpError::NumericCoercion { left, right } => PlainError::NumericCoercion { left: left, right: right },
+            InterpError::VariableNotFound(name) => PlainError::VariableNotFound(name),
+            InterpError::StackOverflow => PlainError::StackOverflow,
+            InterpError::StackUnderflow => PlainError::StackUnderflow,
+            InterpError::StackOutOfBounds => PlainError::StackOutOfBounds,
+            InterpError::WrongArity { got, expected, span } =>
+                PlainError::WrongArity { got: got, expected: expected, span: span },
+            InterpError::UserFnWithWrongStateType => PlainError::UserFnWithWrongStateType,
+            InterpError::NativePanic { name, message } => PlainError::NativePanic { name: name, message: message },
+            #[cfg(feature = "async")]
+            InterpError::SuspendedOutsideOfAsync => PlainError::SuspendedOutsideOfAsync,
+            InterpError::ContractViolation { kind, span } => PlainError::ContractViolation { kind: kind, span: span },
+            InterpError::ReplayDiverged { expected, got } =>
+                PlainError::ReplayDiverged { expected: expected, got: got },
+            InterpError::MutatedMapKey => PlainError::MutatedMapKey,
+            InterpError::ShiftWithoutReset => PlainError::ShiftWithoutReset,
+        }
+    }
+
+    fn into_error(self, interner: &mut SymbolIntern) -> InterpError {
+        match self {
+            PlainError::InternalInterpError(message) => InterpError::InternalInterpError(message),
+            PlainError::MismatchedType { value, expected } => InterpError::MismatchedType {
+                value: value.into_value(interner),
+                expected: expected,
+            },
+            PlainError::IncomparableValues(a, b) =>
+                InterpError::IncomparableValues(a.into_value(interner), b.into_value(interner)),
+            PlainError::StringIndexOutOfBounds { start, end, len } =>
+                InterpError::StringIndexOutOfBounds { start: start, end: end, len: len },
+            PlainError::ListIndexOutOfBounds { index, len } =>
+                InterpError::ListIndexOutOfBounds { index: index, len: len },
+            PlainError::DivideByZero => InterpError::DivideByZero,
+            PlainError::IntegerOverflow => InterpError::IntegerOverflow,
+            PlainError::NumericCoercion { left, right } => InterpError::NumericCoercion { left: left, right: right },
+            PlainError::VariableNotFound(name) => InterpError::VariableNotFound(name),
+            PlainError::StackOverflow => InterpError::StackOverflow,
+            PlainError::StackUnderflow => InterpError::StackUnderflow,
+            PlainError::StackOutOfBounds => InterpError::StackOutOfBounds,
+            PlainError::WrongArity { got, expected, span } =>
+                InterpError::WrongArity { got: got, expected: expected, span: span },
+            PlainError::UserFnWithWrongStateType => InterpError::UserFnWithWrongStateType,
+            PlainError::NativePanic { name, message } => InterpError::NativePanic { name: name, message: message },
+            #[cfg(feature = "async")]
+            PlainError::SuspendedOutsideOfAsync => InterpError::SuspendedOutsideOfAsync,
+            PlainError::ContractViolation { kind, span } => InterpError::ContractViolation { kind: kind, span: span },
+            PlainError::ReplayDiverged { expected, got } =>
+                InterpError::ReplayDiverged { expected: expected, got: got },
+            PlainError::MutatedMapKey => InterpError::MutatedMapKey,
+            PlainError::ShiftWithoutReset => InterpError::ShiftWithoutReset,
+        }
+    }
+}
+
+#[test]
+fn programs_run_on_workers() {
+    use vm::Modules;
+
+    let mut interner = SymbolIntern::new();
+    let globals = Modules::new();
+    let sources = ["1 + 2", "[\"a\", 'b, 1.5]", "1 / 0", "fn(x) { x }"];
+    let programs: Vec<CompiledProgram> = sources.iter()
+        .map(|source| CompiledProgram::compile(source, Some(&globals), &mut interner).unwrap())
+        .collect();
+
+    let ParallelResults { results, mut interner } = run_parallel(programs, 3);
+    let b = Value::Symbol(interner.intern("b"));
+    assert_eq!(results, vec![
+        Ok(Value::Int(3)),
+        Ok(Value::list(vec![Value::string("a"), b, Value::Float(1.5)])),
+        Err(InterpError::DivideByZero),
+        Ok(Value::Nil),
+    ]);
+}
+
+#[test]
+fn maps_inside_themselves_become_nil() {
+    let mut interner = SymbolIntern::new();
+    let map = Value::map(vec![("a", 1)]);
+    map.expect_map_ref().unwrap().insert("self".into(), map.clone()).unwrap();
+    let copy = Plain::from_value(&map, &interner).into_value(&mut interner);
+    assert_eq!(copy, Value::map(vec![("a", Value::Int(1)), ("self", Value::Nil)]));
+}
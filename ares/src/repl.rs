@@ -1,5 +1,10 @@
+// The line editor is a C library that doesn't build for wasm32, where
+// there is no terminal to run the repl in anyway.
+#![cfg_attr(target_arch = "wasm32", allow(unused_imports))]
+
 extern crate ares;
 extern crate colored;
+#[cfg(not(target_arch = "wasm32"))]
 extern crate linenoise;
 
 use ares::host::*;
@@ -9,6 +14,10 @@ use ares::compiler::CompileError;
 use ares::compiler::ParseError;
 use colored::*;
 
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() {
     linenoise::set_multiline(3);
 
@@ -62,6 +71,10 @@ fn main() {
                 }
             }
 
+            for warning in ctx.warnings() {
+                println!("{}", ctx.format_warning(warning).yellow());
+            }
+
             if exit_requested {
                 break;
             }
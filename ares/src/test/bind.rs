@@ -30,7 +30,7 @@ where F: ::std::fmt::Write {
         f: &mut F) -> Result<(), ::std::fmt::Error> {
 
         match *source {
-            SymbolBindSource::Arg{position, ref upvar} => {
+            SymbolBindSource::Arg{position, ref upvar, ..} => {
                 label("ARG", level, f)?;
                 f.write_str(&gen_indent(level + 1))?;
                 f.write_str(&position.to_string())?;
@@ -40,7 +40,7 @@ where F: ::std::fmt::Write {
                     f.write_str("Is an upvar")?;
                 }
             }
-            SymbolBindSource::Upvar{position, ref upvar} => {
+            SymbolBindSource::Upvar{position, ref upvar, ..} => {
                 label("UPVAR", level, f)?;
                 f.write_str(&gen_indent(level + 1))?;
                 f.write_str(&position.to_string())?;
@@ -50,7 +50,7 @@ where F: ::std::fmt::Write {
                     f.write_str("Is an upvar")?;
                 }
             }
-            SymbolBindSource::LocalDefine{position, ref upvar} => {
+            SymbolBindSource::LocalDefine{position, ref upvar, ..} => {
                 label("LOCAL-DEFINE", level, f)?;
                 f.write_str(&gen_indent(level + 1))?;
                 f.write_str(&position.to_string())?;
@@ -77,12 +77,18 @@ where F: ::std::fmt::Write {
                 &Ast::BoolLit(b, _)  => f.write_str(&format!("{}\n", b)),
                 &Ast::StringLit(ref s, _)  => f.write_str(&format!("\"{}\"\n", s)),
                 &Ast::FloatLit(fl, _)  => f.write_str(&format!("{}\n", fl)),
+                &Ast::DecimalLit(ref d, _)  => f.write_str(&format!("{}d\n", d)),
                 &Ast::SymbolLit(s, _)  => f.write_str(&format!("'{}\n", interner.lookup_or_anon(s))),
                 &Ast::IntLit(i, _)  => f.write_str(&format!("{}\n", i)),
                 &Ast::NilLit(_)  => f.write_str(&format!("nil\n")),
                 _ => panic!("non-literal found in Bound::Literal")
             }
         }
+        &Quote(q) => {
+            label("QUOTE", level, f)?;
+            f.write_str(&gen_indent(level + 1))?;
+            f.write_str(&format!("{}\n", q.to_source(interner).unwrap()))
+        }
         &Symbol { symbol, ref source, .. } => {
             label("SYMBOL", level, f)?;
 
@@ -170,7 +176,7 @@ where F: ::std::fmt::Write {
             format(r, level + 1, interner, f)?;
             Ok(())
         },
-        &FnCall(ref rec, ref args, _) => {
+        &FnCall(ref rec, ref args, _, _) => {
             label("FN-CALL", level, f)?;
 
             label("RECEIVER", level + 1, f)?;
@@ -278,6 +284,26 @@ where F: ::std::fmt::Write {
                 format(idx, level + 2, interner, f)?;
             Ok(())
         }
+        &Contract { ref requires, ref ensures, ref body, ref result, .. } => {
+            label("CONTRACT", level, f)?;
+
+            label("REQUIRES", level + 1, f)?;
+            for clause in requires {
+                format(clause, level + 2, interner, f)?;
+            }
+
+            label("BODY", level + 1, f)?;
+            format(body, level + 2, interner, f)?;
+
+            label("RESULT", level + 1, f)?;
+            print_source(result, level + 2, interner, f)?;
+
+            label("ENSURES", level + 1, f)?;
+            for clause in ensures {
+                format(clause, level + 2, interner, f)?;
+            }
+            Ok(())
+        }
         &BlockStatement(ref bodies, _) => {
             label("BLOCK-STATEMENT", level, f)?;
             for body in bodies {
@@ -315,6 +341,72 @@ where F: ::std::fmt::Write {
             format(value, level + 2, interner, f)?;
             Ok(())
         }
+        &Loop { ref variables, ref body, .. } => {
+            label("LOOP", level, f)?;
+
+            for &(ref name, ref source, ref init) in variables {
+                label("VARIABLE", level + 1, f)?;
+                f.write_str(&gen_indent(level + 2))?;
+                f.write_str(&interner.lookup_or_anon(*name))?;
+                f.write_str("\n")?;
+                print_source(source, level + 2, interner, f)?;
+                format(init, level + 2, interner, f)?;
+            }
+
+            label("BODY", level + 1, f)?;
+            format(body, level + 2, interner, f)?;
+            Ok(())
+        }
+        &Recur(ref values, ref targets, _) => {
+            label("RECUR", level, f)?;
+
+            label("VALUES", level + 1, f)?;
+            for value in values {
+                format(value, level + 2, interner, f)?;
+            }
+
+            label("TARGETS", level + 1, f)?;
+            for target in targets {
+                print_source(target, level + 2, interner, f)?;
+            }
+            Ok(())
+        }
+        &Letrec { ref bindings, ref body, .. } => {
+            label("LETREC", level, f)?;
+
+            for &(ref name, ref source, ref value) in bindings {
+                label("BINDING", level + 1, f)?;
+                f.write_str(&gen_indent(level + 2))?;
+                f.write_str(&interner.lookup_or_anon(*name))?;
+                f.write_str("\n")?;
+                print_source(source, level + 2, interner, f)?;
+                format(value, level + 2, interner, f)?;
+            }
+
+            label("BODY", level + 1, f)?;
+            format(body, level + 2, interner, f)?;
+            Ok(())
+        }
+        &NamedLet { ref name, ref source, ref function, ref inits, .. } => {
+            label("NAMED-LET", level, f)?;
+
+            label("NAME", level + 1, f)?;
+            f.write_str(&gen_indent(level + 2))?;
+            f.write_str(&interner.lookup_or_anon(*name))?;
+            f.write_str("\n")?;
+
+            label("SOURCE", level + 1, f)?;
+            print_source(source, level + 2, interner, f)?;
+
+            label("FUNCTION", level + 1, f)?;
+            format(function, level + 2, interner, f)?;
+
+            label("INITS", level + 1, f)?;
+            for init in inits {
+                format(init, level + 2, interner, f)?;
+            }
+            Ok(())
+        }
         &Shift(ref symbols, ref lambda, _) => {
             label("SHIFT", level, f)?;
 
@@ -55,12 +55,12 @@ pub fn assert_compilation_steps(
 
     // Binding
     if let Some(bound) = bound {
-        let Context{vm: Vm {ref mut interner, ref mut globals, ..}} = get_vm();
+        let Context{vm: Vm {ref mut interner, ref mut globals, ..}, ..} = get_vm();
         test_run_results.binding_test = test_binding(program, &bound, Some(globals), interner);
     }
 
     if let Some(instr) = instr {
-        let Context{vm: Vm {ref mut interner, ref mut globals, ..}} = get_vm();
+        let Context{vm: Vm {ref mut interner, ref mut globals, ..}, ..} = get_vm();
         test_run_results.emit_test = test_emit(program, &instr, interner, Some(globals));
     }
 
@@ -9,7 +9,7 @@ use ::ares_syntax::SymbolIntern;
 use ::typed_arena::Arena;
 use ::vm::Modules;
 use ::host::{AresResult, AresError};
-use ::compiler::{CompileError, CompileContext};
+use ::compiler::{CompileError, CompileContext, Diagnostics};
 use ::compiler::binding::{Bound, BoundRef};
 use ::compiler::emit::{EmitBuffer, emit_all};
 
@@ -44,7 +44,8 @@ fn do_binding<'bound, 'ast: 'bound>(
     let asts = do_parsing(program, parse_arena, interner)?;
     let asts = asts.into_iter().map(|ast| parse_arena.alloc(ast) as &_);
     let asts: Vec<_> = asts.collect();
-    let bound = match Bound::bind_top(&asts, bind_arena, modules, interner) {
+    let mut diagnostics = Diagnostics::new();
+    let bound = match Bound::bind_top(&asts, bind_arena, modules, interner, &mut diagnostics) {
         Ok(bound) => bound,
         Err(error) => return Err(From::<CompileError>::from(From::from(error)))
     };
@@ -65,6 +66,7 @@ fn do_emitting<'bound, 'ast: 'bound>(
     if let Err(error) = emit_all(bound, compile_context, interner, emit_buffer, None) {
         return Err(From::<CompileError>::from(From::from(error)));
     }
+    compile_context.locate_closures(program);
 
     Ok(())
 }
@@ -0,0 +1,53 @@
+extern crate ares;
+extern crate colored;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::process;
+
+use ares::host::*;
+use ares::vm::{Value, user_function};
+use colored::*;
+
+/// Runs the tests declared with `deftest` in every file that is passed in.
+fn main() {
+    let mut all_passed = true;
+
+    for path in env::args().skip(1) {
+        let mut source = String::new();
+        File::open(&path).and_then(|mut f| f.read_to_string(&mut source)).unwrap();
+
+        let mut state = ();
+        let mut ctx = Context::<()>::new();
+        ctx.install_test_builtins();
+        ctx.set_global("print", user_function::<(), _>(None, |args, _, ctx| {
+            if ctx.check_arity(&args, 1) {
+                println!("{}", ctx.format_value(&args[0]));
+            }
+            Value::Nil
+        }));
+
+        if let Err(e) = ctx.eval(&mut state, &source) {
+            println!("{} {}", path, ctx.format_error(e).red());
+            all_passed = false;
+            continue;
+        }
+
+        let report = run_tests(&mut ctx, &mut state);
+        for name in &report.passed {
+            println!("{} {} ... {}", path, name, "ok".green());
+        }
+        for &(ref name, ref failures) in &report.failed {
+            println!("{} {} ... {}", path, name, "FAILED".red());
+            for failure in failures {
+                println!("    {}", failure);
+            }
+        }
+        all_passed &= report.is_success();
+    }
+
+    if !all_passed {
+        process::exit(1);
+    }
+}
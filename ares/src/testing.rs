@@ -0,0 +1,239 @@
+//! Helpers for fuzzing the compiler and vm.  Enabled by the `testing`
+//! feature.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use typed_arena::Arena;
+use ares_syntax::{Ast, AstOwned, Span, Symbol, SymbolIntern, format_program};
+
+use host::Context;
+
+/// A small seedable xorshift generator so that failures are reproducible.
+pub struct Gen {
+    state: u64,
+    size: u32,
+}
+
+impl Gen {
+    /// `size` bounds how deeply expressions are nested.
+    pub fn new(seed: u64, size: u32) -> Gen {
+        Gen {
+            // xorshift gets stuck on a zero state
+            state: if seed == 0 { 0x2545f4914f6cdd1d } else { seed },
+            size: size,
+        }
+    }
+
+    pub fn next(&mut self, bound: u64) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state % bound
+    }
+
+    fn chance(&mut self, one_in: u64) -> bool {
+        self.next(one_in) == 0
+    }
+}
+
+struct Builder<'g, 'i> {
+    gen: &'g mut Gen,
+    interner: &'i mut SymbolIntern,
+    scopes: Vec<Vec<Symbol>>,
+    names_made: u32,
+}
+
+/// Generates a program where every identifier refers to a variable that
+/// is in scope and no variable is defined twice.
+///
+/// Running the program may still fail (calling a number, adding a list
+/// to a string, ...) but it will always terminate: closures are never
+/// assigned to existing variables, so nothing can recurse.
+pub fn arbitrary_ast(gen: &mut Gen, interner: &mut SymbolIntern) -> Vec<AstOwned> {
+    let size = gen.size;
+    let mut builder = Builder {
+        gen: gen,
+        interner: interner,
+        scopes: vec![vec![]],
+        names_made: 0,
+    };
+    let count = builder.gen.next(8) + 1;
+    (0 .. count).map(|_| builder.statement(size)).collect()
+}
+
+/// Generates a program with `arbitrary_ast` and formats it as source code.
+pub fn arbitrary_program(gen: &mut Gen, interner: &mut SymbolIntern) -> String {
+    let owned = arbitrary_ast(gen, interner);
+    let arena = Arena::new();
+    let asts: Vec<Ast> = owned.iter().map(|ast| ast.to_ast(&arena)).collect();
+    format_program(&asts, interner).expect("generated programs print")
+}
+
+/// Parses, binds and evaluates `source` in a fresh context.  Errors are
+/// expected and ignored; a panic anywhere in the pipeline is returned as
+/// `Err` with the panic message.
+pub fn check_pipeline(source: &str) -> Result<(), String> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut ctx: Context<()> = Context::new();
+        let _ = ctx.eval(&mut (), source);
+    }));
+
+    result.map_err(|payload| {
+        if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic".to_string()
+        }
+    })
+}
+
+impl <'g, 'i> Builder<'g, 'i> {
+    fn fresh_name(&mut self) -> Symbol {
+        let name = format!("v{}", self.names_made);
+        self.names_made += 1;
+        self.interner.intern(name)
+    }
+
+    fn pick_in_scope(&mut self) -> Option<Symbol> {
+        let count = self.scopes.iter().map(Vec::len).sum::<usize>() as u64;
+        if count == 0 {
+            return None;
+        }
+        let idx = self.gen.next(count) as usize;
+        self.scopes.iter().flat_map(|s| s.iter()).nth(idx).cloned()
+    }
+
+    fn define(&mut self, name: Symbol) {
+        self.scopes.last_mut().unwrap().push(name);
+    }
+
+    fn statement(&mut self, depth: u32) -> AstOwned {
+        let s = Span::dummy();
+        match self.gen.next(6) {
+            0 | 1 => {
+                // The name isn't in scope until its value has been generated.
+                let value = self.expression(depth, true);
+                let name = self.fresh_name();
+                self.define(name);
+                AstOwned::Define(name, Box::new(value), s)
+            }
+            2 if depth > 0 => {
+                let cond = self.expression(depth - 1, true);
+                let then = self.block_statement(depth - 1);
+                let els = if self.gen.chance(2) {
+                    Some(Box::new(self.block_statement(depth - 1)))
+                } else {
+                    None
+                };
+                AstOwned::IfStatement(Box::new(cond), Box::new(then), els, s)
+            }
+            3 if depth > 0 => self.block_statement(depth - 1),
+            _ => self.expression(depth, true),
+        }
+    }
+
+    fn block_statement(&mut self, depth: u32) -> AstOwned {
+        self.scopes.push(vec![]);
+        let count = self.gen.next(3);
+        let body = (0 .. count).map(|_| self.statement(depth)).collect();
+        self.scopes.pop();
+        AstOwned::BlockStatement(body, Span::dummy())
+    }
+
+    fn block_expression(&mut self, depth: u32) -> AstOwned {
+        self.scopes.push(vec![]);
+        let count = self.gen.next(3);
+        let mut body: Vec<AstOwned> = (0 .. count).map(|_| self.statement(depth)).collect();
+        body.push(self.expression(depth, true));
+        self.scopes.pop();
+        AstOwned::BlockExpression(body, Span::dummy())
+    }
+
+    fn literal(&mut self) -> AstOwned {
+        let s = Span::dummy();
+        let words = ["a", "b", "foo", ""];
+        match self.gen.next(6) {
+            0 => AstOwned::BoolLit(self.gen.chance(2), s),
+            1 => AstOwned::StringLit(words[self.gen.next(4) as usize].to_string(), s),
+            2 => AstOwned::IntLit(self.gen.next(100) as i64, s),
+            3 => AstOwned::FloatLit(self.gen.next(100) as f64 / 4.0, s),
+            4 => AstOwned::SymbolLit(self.interner.intern(words[self.gen.next(3) as usize]), s),
+            _ => AstOwned::NilLit(s),
+        }
+    }
+
+    /// When `can_escape` is false, the generated expression can't evaluate
+    /// to a closure that has been defined elsewhere, so it is safe to
+    /// assign to an existing variable.
+    fn expression(&mut self, depth: u32, can_escape: bool) -> AstOwned {
+        let s = Span::dummy();
+        if depth == 0 || self.gen.chance(4) {
+            if can_escape && self.gen.chance(2) {
+                if let Some(name) = self.pick_in_scope() {
+                    return AstOwned::Identifier(name, s);
+                }
+            }
+            return self.literal();
+        }
+
+        let depth = depth - 1;
+        macro_rules! sub {
+            () => { Box::new(self.expression(depth, can_escape)) }
+        }
+
+        let choice = if can_escape { self.gen.next(12) } else { self.gen.next(8) };
+        match choice {
+            0 => {
+                let count = self.gen.next(4);
+                AstOwned::ListLit((0 .. count).map(|_| self.expression(depth, can_escape)).collect(), s)
+            }
+            1 => AstOwned::Add(sub!(), sub!(), s),
+            2 => AstOwned::Sub(sub!(), sub!(), s),
+            3 => AstOwned::Mul(sub!(), sub!(), s),
+            4 => AstOwned::Div(sub!(), sub!(), s),
+            5 => AstOwned::LessThan(sub!(), sub!(), s),
+            6 => AstOwned::Equal(sub!(), sub!(), s),
+            7 => AstOwned::IfExpression(sub!(), sub!(), sub!(), s),
+            8 => AstOwned::ListAccess(sub!(), sub!(), s),
+            9 => {
+                let count = self.gen.next(3);
+                let callee = sub!();
+                let args = (0 .. count).map(|_| self.expression(depth, true)).collect();
+                AstOwned::FnCall(callee, args, s)
+            }
+            10 => {
+                self.scopes.push(vec![]);
+                let params: Vec<Symbol> = (0 .. self.gen.next(3)).map(|_| self.fresh_name()).collect();
+                for &param in &params {
+                    self.define(param);
+                }
+                let body = self.block_expression(depth);
+                self.scopes.pop();
+                AstOwned::Closure(None, vec![params], None, Box::new(body), s)
+            }
+            _ => match self.pick_in_scope() {
+                Some(name) => AstOwned::Assign(name, Box::new(self.expression(depth, false)), s),
+                None => self.block_expression(depth),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ares_syntax::SymbolIntern;
+    use super::{Gen, arbitrary_program, check_pipeline};
+
+    #[test]
+    fn pipeline_never_panics() {
+        for seed in 1 .. 300 {
+            let mut interner = SymbolIntern::new();
+            let program = arbitrary_program(&mut Gen::new(seed, 4), &mut interner);
+            if let Err(message) = check_pipeline(&program) {
+                panic!("seed {} panicked with {:?} on:\n{}", seed, message, program);
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use util::collections::HashSet;
+use typed_arena::Arena;
+use ares_syntax::{Symbol, SymbolIntern};
+use compiler::CompileError;
+use compiler::Diagnostics;
+use compiler::parse::{self, Ast};
+use compiler::binding::{Bound, BoundRef, BoundVisitor, walk_bound};
+use vm::Modules;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Argument,
+    /// A variable defined in a block that encloses the cursor.
+    Local,
+    /// A variable defined at the top level of the source.
+    Global,
+    /// A global that the host defined before the source was compiled.
+    Builtin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub name: String,
+    pub kind: CompletionKind,
+}
+
+/// Lists every variable that is in scope at `cursor_offset`, with the
+/// innermost bindings first.
+pub fn completions(source: &str, cursor_offset: u32) -> Result<Vec<Completion>, CompileError> {
+    let mut interner = SymbolIntern::new();
+    completions_with_globals(source, cursor_offset, None, &mut interner)
+}
+
+/// Like `completions`, but also includes the globals in the default
+/// namespace of `modules` as builtins.
+pub fn completions_with_globals(source: &str,
+                                cursor_offset: u32,
+                                modules: Option<&Modules>,
+                                interner: &mut SymbolIntern)
+                                -> Result<Vec<Completion>, CompileError> {
+    let ast_arena: Arena<Ast> = Arena::new();
+    let bound_arena: Arena<Bound> = Arena::new();
+    let mut diagnostics = Diagnostics::new();
+
+    let asts = parse::parse(source, interner, &ast_arena)?;
+    let asts: Vec<&Ast> = asts.into_iter().map(|a| ast_arena.alloc(a) as &_).collect();
+    let bounds = Bound::bind_top(&asts, &bound_arena, modules, interner, &mut diagnostics)?;
+
+    let mut collector = Collector {
+        cursor: cursor_offset,
+        found: vec![],
+    };
+    if let Some(modules) = modules {
+        let default_namespace = interner.precomputed.default_namespace;
+        for name in modules.names_in(default_namespace) {
+            collector.found.push((name, CompletionKind::Builtin));
+        }
+    }
+    for &bound in &bounds {
+        if let Some(name) = defined_name(bound) {
+            collector.found.push((name, CompletionKind::Global));
+        }
+    }
+    for bound in bounds {
+        collector.visit(bound);
+    }
+
+    // Inner bindings were found last, and shadow the outer ones.
+    let mut seen = HashSet::new();
+    let mut out = vec![];
+    for (name, kind) in collector.found.into_iter().rev() {
+        let name = match interner.lookup(name) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        if seen.insert(name.clone()) {
+            out.push(Completion { name: name, kind: kind });
+        }
+    }
+    Ok(out)
+}
+
+/// The name as written in the source, before the binder masked it.
+fn defined_name(bound: &Bound) -> Option<Symbol> {
+    match bound {
+        &Bound::Define(_, _, _, &Ast::Define(name, _, _)) => Some(name),
+        _ => None,
+    }
+}
+
+struct Collector {
+    cursor: u32,
+    found: Vec<(Symbol, CompletionKind)>,
+}
+
+impl Collector {
+    fn encloses_cursor(&self, ast: &Ast) -> bool {
+        let span = ast.span();
+        span.start() <= self.cursor && self.cursor <= span.end()
+    }
+}
+
+impl<'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for Collector {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Lambda { ref arg_symbols, body, ast, .. } => {
+                if self.encloses_cursor(ast) {
+                    for &arg in arg_symbols {
+                        self.found.push((arg, CompletionKind::Argument));
+                    }
+                    self.visit(body);
+                }
+            }
+            &Bound::BlockExpression(ref children, ast) |
+            &Bound::BlockStatement(ref children, ast) => {
+                if self.encloses_cursor(ast) {
+                    // Only the definitions that come before the cursor
+                    // are in scope.
+                    for child in children {
+                        match (defined_name(child), child) {
+                            (Some(name), &Bound::Define(_, _, _, define)) if define.span().end() <= self.cursor => {
+                                self.found.push((name, CompletionKind::Local));
+                            }
+                            _ => {}
+                        }
+                    }
+                    walk_bound(self, bound);
+                }
+            }
+            _ => walk_bound(self, bound),
+        }
+    }
+}
+
+#[test]
+fn scopes() {
+    let source = "var g = 1; fn(a) { var b = a; var c = b; }";
+    let cursor = source.find("var c").unwrap() as u32;
+    let names: Vec<(String, CompletionKind)> = completions(source, cursor).unwrap()
+                                                    .into_iter()
+                                                    .map(|c| (c.name, c.kind))
+                                                    .collect();
+    assert_eq!(names, vec![
+        ("b".to_string(), CompletionKind::Local),
+        ("a".to_string(), CompletionKind::Argument),
+        ("g".to_string(), CompletionKind::Global),
+    ]);
+
+    // Outside of the lambda, only the global is visible
+    let names: Vec<String> = completions(source, 2).unwrap().into_iter().map(|c| c.name).collect();
+    assert_eq!(names, vec!["g".to_string()]);
+}
@@ -0,0 +1,7 @@
+//! Queries over ares source code for editor integrations.
+
+mod completions;
+mod references;
+
+pub use self::completions::{completions, completions_with_globals, Completion, CompletionKind};
+pub use self::references::{find_references, References};
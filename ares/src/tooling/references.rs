@@ -0,0 +1,222 @@
+use std::cell::Cell;
+use typed_arena::Arena;
+use ares_syntax::{Span, Symbol, SymbolIntern, TokenKind, tokenize};
+use compiler::CompileError;
+use compiler::Diagnostics;
+use compiler::parse::{self, Ast};
+use compiler::binding::{Bound, BoundRef, BoundVisitor, SymbolBindSource, walk_bound};
+
+/// A binding along with every place in the source that refers to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct References {
+    pub name: String,
+    /// Where the binding was introduced.  This is `None` for globals
+    /// that are defined outside of the source.
+    pub definition: Option<Span>,
+    /// Every place that the binding is read from or assigned to.
+    pub references: Vec<Span>,
+}
+
+/// Finds the binding that the identifier at `offset` refers to, and
+/// every other reference to that same binding.
+///
+/// Returns `None` if there is no identifier at `offset`.
+pub fn find_references(source: &str, offset: u32) -> Result<Option<References>, CompileError> {
+    let ast_arena: Arena<Ast> = Arena::new();
+    let bound_arena: Arena<Bound> = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let mut diagnostics = Diagnostics::new();
+
+    let asts = parse::parse(source, &mut interner, &ast_arena)?;
+    let asts: Vec<&Ast> = asts.into_iter().map(|a| ast_arena.alloc(a) as &_).collect();
+    let bounds = Bound::bind_top(&asts, &bound_arena, None, &mut interner, &mut diagnostics)?;
+
+    let mut collector = Collector {
+        source: source,
+        interner: &interner,
+        upvars: vec![],
+        occurrences: vec![],
+    };
+    for bound in bounds {
+        collector.visit(bound);
+    }
+
+    let occurrences = collector.occurrences;
+    let target = match occurrences.iter().find(|o| o.span.start() <= offset && offset <= o.span.end()) {
+        Some(target) => target,
+        None => return Ok(None),
+    };
+
+    let mut references = References {
+        name: target.name.clone(),
+        definition: None,
+        references: vec![],
+    };
+    for occurrence in occurrences.iter().filter(|o| o.key == target.key) {
+        if occurrence.is_definition {
+            references.definition = references.definition.or(Some(occurrence.span));
+        } else {
+            references.references.push(occurrence.span);
+        }
+    }
+    Ok(Some(references))
+}
+
+/// Identifies a binding regardless of which closure it is viewed from.
+#[derive(Debug, PartialEq)]
+enum Key {
+    Local(*const Cell<bool>),
+    Global(Symbol),
+}
+
+struct Occurrence {
+    key: Key,
+    name: String,
+    span: Span,
+    is_definition: bool,
+}
+
+struct Collector<'a, 'bound> {
+    source: &'a str,
+    interner: &'a SymbolIntern,
+    // The upvar lists of every lambda that is currently being visited
+    upvars: Vec<&'bound [SymbolBindSource]>,
+    occurrences: Vec<Occurrence>,
+}
+
+impl<'a, 'bound> Collector<'a, 'bound> {
+    /// Follows upvars back out to the lambda that actually owns the
+    /// binding.
+    fn key(&self, source: &SymbolBindSource) -> Key {
+        let mut source = source;
+        let mut depth = self.upvars.len();
+        loop {
+            match source {
+                &SymbolBindSource::Upvar { position, .. } if depth > 0 => {
+                    depth -= 1;
+                    source = &self.upvars[depth][position as usize];
+                }
+                &SymbolBindSource::Arg { ref used, .. } |
+                &SymbolBindSource::Upvar { ref used, .. } |
+                &SymbolBindSource::LocalDefine { ref used, .. } => return Key::Local(&**used),
+                &SymbolBindSource::Global(symbol) => return Key::Global(symbol),
+            }
+        }
+    }
+
+    /// Finds the identifier token that names `name` between `from` and
+    /// `to`, falling back to the whole range.  Going by tokens means that
+    /// a name inside a string or a longer identifier is never matched.
+    fn name_span(&self, name: &str, from: u32, to: u32) -> Span {
+        let end = (to as usize).min(self.source.len());
+        let start = (from as usize).min(end);
+        tokenize(&self.source[start .. end])
+            .find(|token| token.kind == TokenKind::Identifier && token.text == name)
+            .map(|token| Span::new(from + token.span.start(), from + token.span.end()))
+            .unwrap_or(Span::new(from, to))
+    }
+
+    fn record(&mut self, source: &SymbolBindSource, name: Symbol, span: Span, is_definition: bool) {
+        // Symbols that the parser generated don't appear in the source
+        let name = match self.interner.lookup(name) {
+            Some(name) => name.to_string(),
+            None => return,
+        };
+        let key = self.key(source);
+        self.occurrences.push(Occurrence {
+            key: key,
+            name: name,
+            span: span,
+            is_definition: is_definition,
+        });
+    }
+}
+
+impl<'a, 'bound, 'ast: 'bound> BoundVisitor<'bound, 'ast> for Collector<'a, 'bound> {
+    fn visit(&mut self, bound: BoundRef<'bound, 'ast>) {
+        match bound {
+            &Bound::Symbol { ref source, ast, .. } => {
+                if let &Ast::Identifier(name, span) = ast {
+                    self.record(source, name, span, false);
+                }
+            }
+            &Bound::Assign(_, ref source, value, ast) => {
+                if let &Ast::Assign(name, _, span) = ast {
+                    let name_str = self.interner.lookup_or_anon(name);
+                    let span = self.name_span(&name_str, span.start(), span.end());
+                    self.record(source, name, span, false);
+                }
+                self.visit(value);
+            }
+            &Bound::Define(_, ref source, value, ast) => {
+                if let &Ast::Define(name, _, span) = ast {
+                    let name_str = self.interner.lookup_or_anon(name);
+                    let span = self.name_span(&name_str, span.start(), span.end());
+                    self.record(source, name, span, true);
+                }
+                self.visit(value);
+            }
+            &Bound::Lambda { ref arg_symbols, body, ast, ref bindings, ref upvar_list, .. } => {
+                self.upvars.push(upvar_list);
+                // Arguments are named before the body starts.
+                let params_end = body.span().map_or(ast.span().end(), |span| span.start());
+                for &arg in arg_symbols {
+                    let arg_str = self.interner.lookup_or_anon(arg);
+                    let span = self.name_span(&arg_str, ast.span().start(), params_end);
+                    if let Some(source) = bindings.bindings.get(&arg) {
+                        self.record(source, arg, span, true);
+                    }
+                }
+                self.visit(body);
+                self.upvars.pop();
+            }
+            _ => walk_bound(self, bound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spans_of(source: &str, needle: &str) -> Vec<Span> {
+        source.match_indices(needle)
+              .map(|(i, _)| Span::new(i as u32, (i + needle.len()) as u32))
+              .collect()
+    }
+
+    #[test]
+    fn locals_through_closures() {
+        let source = "fn(abc) { var xyz = abc; fn() { xyz = abc; xyz } }";
+        let refs = find_references(source, source.rfind("xyz").unwrap() as u32).unwrap().unwrap();
+        let xyz = spans_of(source, "xyz");
+        assert_eq!(refs.name, "xyz");
+        assert_eq!(refs.definition, Some(xyz[0]));
+        assert_eq!(refs.references, &xyz[1..]);
+
+        let refs = find_references(source, source.find("abc").unwrap() as u32).unwrap().unwrap();
+        let abc = spans_of(source, "abc");
+        assert_eq!(refs.definition, Some(abc[0]));
+        assert_eq!(refs.references, &abc[1..]);
+    }
+
+    #[test]
+    fn globals() {
+        let source = "var g = 1; var h = g + g;";
+        let refs = find_references(source, source.rfind('g').unwrap() as u32).unwrap().unwrap();
+        let g = spans_of(source, "g");
+        assert_eq!(refs.definition, Some(g[0]));
+        assert_eq!(refs.references, &g[1..]);
+
+        assert!(find_references(source, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn names_in_strings_are_skipped() {
+        let source = "reset { shift k { \"(k)\"; k(1) } }";
+        let refs = find_references(source, source.rfind('k').unwrap() as u32).unwrap().unwrap();
+        let k = spans_of(source, "k");
+        assert_eq!(refs.definition, Some(k[0]));
+        assert_eq!(refs.references, vec![k[2]]);
+    }
+}
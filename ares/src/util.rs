@@ -18,3 +18,38 @@ pub fn iterators_same<'a, T: Debug + 'a, A, B, F>(mut iter1: A, mut iter2: B, eq
         }
     }
 }
+
+/// The hash collections used by the compiler and vm.  With the
+/// `hashbrown` feature they come from the hashbrown crate, which only
+/// needs `alloc`.
+pub mod collections {
+    #[cfg(feature = "hashbrown")]
+    pub use hashbrown::{HashMap, HashSet};
+    #[cfg(not(feature = "hashbrown"))]
+    pub use std::collections::{HashMap, HashSet};
+}
+
+/// The number of single character insertions, deletions and substitutions
+/// that turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0 .. b.len() + 1).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[test]
+fn edit_distances() {
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("lenght", "length"), 2);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("same", "same"), 0);
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::str::FromStr;
+
+use gc::{Finalize, Gc, Trace};
+use rust_decimal;
+use rust_decimal::prelude::ToPrimitive;
+use vm::{Value, InterpError};
+
+/// A base-ten fixed-point number, for amounts like money where
+/// `0.10d + 0.20d` has to be exactly `0.30d`.  Scripts write decimals
+/// with a `d` suffix, and the digits after the point set the scale, so
+/// `1.50d` keeps both of its digits when printed.
+///
+/// Decimals mix with ints but not with floats or rationals, since either
+/// would round the result somewhere other than where the script asked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Decimal {
+    value: rust_decimal::Decimal,
+}
+
+// Like `Rational`, a `Copy` type can't derive `Trace`.
+impl Finalize for Decimal {}
+
+unsafe impl Trace for Decimal {
+    unsafe_empty_trace!();
+}
+
+impl Decimal {
+    /// Parses the digits of a literal, like `"1.50"`, without the suffix.
+    pub fn parse(text: &str) -> Option<Decimal> {
+        rust_decimal::Decimal::from_str(text).ok().map(|value| Decimal { value: value })
+    }
+
+    pub fn from_int(i: i64) -> Decimal {
+        Decimal { value: rust_decimal::Decimal::from(i) }
+    }
+
+    /// The digits without the `d` suffix, which `parse` reads back.
+    pub fn digits(&self) -> String {
+        self.value.to_string()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.value.to_f64().unwrap_or(::std::f64::NAN)
+    }
+
+    /// The number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.value.scale()
+    }
+
+    /// Rounds to `places` digits after the point, with ties going to the
+    /// even digit the way bankers round.
+    pub fn round(&self, places: u32) -> Decimal {
+        Decimal { value: self.value.round_dp(places) }
+    }
+
+    pub fn into_value(self) -> Value {
+        Value::Decimal(Gc::new(self))
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Result<Decimal, InterpError> {
+        Decimal::wrap(self.value.checked_add(other.value))
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Result<Decimal, InterpError> {
+        Decimal::wrap(self.value.checked_sub(other.value))
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Result<Decimal, InterpError> {
+        Decimal::wrap(self.value.checked_mul(other.value))
+    }
+
+    pub fn checked_div(&self, other: &Decimal) -> Result<Decimal, InterpError> {
+        if other.value.is_zero() {
+            return Err(InterpError::DivideByZero);
+        }
+        Decimal::wrap(self.value.checked_div(other.value))
+    }
+
+    fn wrap(value: Option<rust_decimal::Decimal>) -> Result<Decimal, InterpError> {
+        value.map(|value| Decimal { value: value }).ok_or(InterpError::IntegerOverflow)
+    }
+}
+
+/// Written the same way as the literal that makes it, like `1.50d`.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}d", self.value)
+    }
+}
+
+#[test]
+fn decimal_arithmetic() {
+    let dime = Decimal::parse("0.10").unwrap();
+    let twenty = Decimal::parse("0.20").unwrap();
+    assert_eq!(dime.checked_add(&twenty).unwrap(), Decimal::parse("0.3").unwrap());
+    assert_eq!(dime.checked_add(&twenty).unwrap().to_string(), "0.30d");
+    assert_eq!(Decimal::parse("2.675").unwrap().round(2).to_string(), "2.68d");
+    assert_eq!(Decimal::parse("2.665").unwrap().round(2).to_string(), "2.66d");
+    assert_eq!(dime.checked_div(&Decimal::from_int(0)), Err(InterpError::DivideByZero));
+    assert_eq!(Decimal::parse("1.5x"), None);
+}
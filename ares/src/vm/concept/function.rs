@@ -12,6 +12,24 @@ pub struct UserFunction<S: State + ?Sized> {
     f: Box<FnMut(Vec<Value>, &mut S, &mut EphemeralContext<S>) -> Value + 'static>,
     #[unsafe_ignore_trace]
     state_typeid: TypeId,
+    /// Set for functions made with `forwarding_function`.
+    forwards: bool,
+}
+
+/// A call that a native function has asked the vm to make once it returns.
+pub(crate) enum Forward {
+    /// Calls `callee` with `args`, and returns what it returns.
+    Tail {
+        callee: Value,
+        args: Vec<Value>,
+    },
+    /// Calls `callee` with `args` and then `then` with the result, and
+    /// returns what `then` returns.
+    Then {
+        callee: Value,
+        args: Vec<Value>,
+        then: Value,
+    },
 }
 
 pub fn user_function<S: ?Sized, F>(name: Option<String>, f: F) -> Value where
@@ -19,7 +37,25 @@ S: State, F: FnMut(Vec<Value>, &mut S, &mut EphemeralContext<S>)->Value + 'stati
     let user_function = UserFunction {
         name: name,
         f: Box::new(f),
-        state_typeid: TypeId::of::<S>()
+        state_typeid: TypeId::of::<S>(),
+        forwards: false,
+    };
+    Value::UserFn(Gc::new(GcCell::new(user_function.erase())))
+}
+
+/// Creates a native function that hands its arguments on to other
+/// functions with `EphemeralContext::tail_call` or `call_then`, and so can
+/// call closures.
+///
+/// These functions are left out of replay logs and are called again when
+/// a log is replayed; the calls that they make are recorded instead.
+pub fn forwarding_function<S: ?Sized, F>(name: Option<String>, f: F) -> Value where
+S: State, F: FnMut(Vec<Value>, &mut S, &mut EphemeralContext<S>)->Value + 'static {
+    let user_function = UserFunction {
+        name: name,
+        f: Box::new(f),
+        state_typeid: TypeId::of::<S>(),
+        forwards: true,
     };
     Value::UserFn(Gc::new(GcCell::new(user_function.erase())))
 }
@@ -29,6 +65,10 @@ impl <S: State + ?Sized> UserFunction<S> {
         self.name.as_ref().map(|s| &s[..])
     }
 
+    pub fn forwards(&self) -> bool {
+        self.forwards
+    }
+
     pub fn call(&mut self, state: &mut S, args: Vec<Value>, ctx: &mut EphemeralContext<S>) -> Value {
         (self.f)(args, state, ctx)
     }
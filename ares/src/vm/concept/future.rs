@@ -0,0 +1,45 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+
+use ::host::{State, EphemeralContext};
+use ::vm::{Value, user_function};
+
+/// A value that a native function is waiting on.
+pub type NativeFuture = Pin<Box<Future<Output = Value>>>;
+
+/// Where a script that is waiting on a `NativeFuture` stopped.
+pub(crate) struct Suspension {
+    pub(crate) future: NativeFuture,
+    /// The name of the native function that returned the future.
+    pub(crate) name: Option<String>,
+    /// The instruction after the call that suspended.
+    pub(crate) resume_at: usize,
+    /// The number of frames to go back to once the script finishes.
+    pub(crate) frame_count: usize,
+}
+
+impl fmt::Debug for Suspension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Suspension")
+         .field("resume_at", &self.resume_at)
+         .field("frame_count", &self.frame_count)
+         .finish()
+    }
+}
+
+/// Creates a native function that returns a future.  Calling it suspends
+/// the script until the future completes, and the future's output becomes
+/// the result of the call.
+///
+/// Scripts that call these functions have to be run with
+/// `Context::eval_async`.
+pub fn async_function<S, F, Fut>(name: Option<String>, mut f: F) -> Value where
+S: State, F: FnMut(Vec<Value>, &mut S, &mut EphemeralContext<S>) -> Fut + 'static,
+Fut: Future<Output = Value> + 'static {
+    user_function::<S, _>(name, move |args, state, ctx| {
+        let future = f(args, state, ctx);
+        ctx.suspend(Box::pin(future));
+        Value::Nil
+    })
+}
@@ -1,5 +1,5 @@
 use vm::Value;
-use ares_syntax::Symbol;
+use ares_syntax::{Span, Symbol};
 use std::cell::RefCell;
 
 #[derive(Debug, PartialEq, Clone, Trace, Finalize)]
@@ -12,6 +12,16 @@ pub struct ClosureClass {
     pub namespace: Symbol,
 
     pub is_shifter: bool,
+    /// The name the lambda was given with `fn name(..)` or `var name = ..`.
+    pub name: Option<Symbol>,
+    /// The line and column that the lambda starts at.
+    #[unsafe_ignore_trace]
+    pub position: Option<(u32, u32)>,
+    /// The source code of the lambda.
+    #[unsafe_ignore_trace]
+    pub span: Option<Span>,
+    /// The closure class of the lambda that this one was written inside.
+    pub parent: Option<u32>,
 }
 
 #[derive(Debug, PartialEq, Clone, Trace, Finalize)]
@@ -21,3 +31,14 @@ pub struct Closure {
     #[unsafe_ignore_trace]
     pub reset_symbols: RefCell<Option<Vec<Symbol>>>,
 }
+
+impl Closure {
+    /// The source code that this closure was compiled from, if it is known.
+    pub fn span(&self) -> Option<Span> {
+        self.class.span
+    }
+
+    pub fn name(&self) -> Option<Symbol> {
+        self.class.name
+    }
+}
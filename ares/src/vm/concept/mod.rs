@@ -1,3 +1,9 @@
 pub mod continuation;
 pub mod lambda;
 pub mod function;
+pub mod rational;
+#[cfg(feature = "decimal")]
+pub mod decimal;
+#[cfg(feature = "async")]
+pub mod future;
+pub mod string;
@@ -0,0 +1,144 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use gc::{Finalize, Gc, Trace};
+use vm::{Value, InterpError};
+
+/// An exact fraction.  Always kept in lowest terms with a positive
+/// denominator, so two rationals are equal exactly when their fields are.
+///
+/// Dividing two ints makes a rational, so `1/3` in a script is exactly
+/// one third, and arithmetic between ints and rationals stays exact.
+/// Anything involving a float becomes a float.  A rational whose
+/// denominator is 1 is turned back into an int by `Rational::into_value`,
+/// so `4 / 2` is the int `2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numer: i64,
+    denom: i64,
+}
+
+// Deriving `Trace` adds a `Drop` impl, which a `Copy` type can't have.
+impl Finalize for Rational {}
+
+unsafe impl Trace for Rational {
+    unsafe_empty_trace!();
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a.abs()
+}
+
+impl Rational {
+    /// `numer / denom` in lowest terms.  Fails if `denom` is zero.
+    pub fn new(numer: i64, denom: i64) -> Result<Rational, InterpError> {
+        Rational::reduce(numer as i128, denom as i128)
+    }
+
+    pub fn from_int(i: i64) -> Rational {
+        Rational { numer: i, denom: 1 }
+    }
+
+    fn reduce(mut numer: i128, mut denom: i128) -> Result<Rational, InterpError> {
+        if denom == 0 {
+            return Err(InterpError::DivideByZero);
+        }
+        if denom < 0 {
+            numer = -numer;
+            denom = -denom;
+        }
+        let divisor = gcd(numer, denom);
+        let (numer, denom) = (numer / divisor, denom / divisor);
+        if numer < i64::min_value() as i128 || numer > i64::max_value() as i128 ||
+           denom > i64::max_value() as i128 {
+            return Err(InterpError::IntegerOverflow);
+        }
+        Ok(Rational { numer: numer as i64, denom: denom as i64 })
+    }
+
+    pub fn numer(&self) -> i64 {
+        self.numer
+    }
+
+    pub fn denom(&self) -> i64 {
+        self.denom
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    /// An int if the denominator is 1, and a `Value::Rational` otherwise.
+    pub fn into_value(self) -> Value {
+        if self.denom == 1 {
+            Value::Int(self.numer)
+        } else {
+            Value::Rational(Gc::new(self))
+        }
+    }
+
+    fn parts(&self) -> (i128, i128) {
+        (self.numer as i128, self.denom as i128)
+    }
+
+    pub fn checked_add(&self, other: &Rational) -> Result<Rational, InterpError> {
+        let ((a, b), (c, d)) = (self.parts(), other.parts());
+        Rational::reduce(a * d + c * b, b * d)
+    }
+
+    pub fn checked_sub(&self, other: &Rational) -> Result<Rational, InterpError> {
+        let ((a, b), (c, d)) = (self.parts(), other.parts());
+        Rational::reduce(a * d - c * b, b * d)
+    }
+
+    pub fn checked_mul(&self, other: &Rational) -> Result<Rational, InterpError> {
+        let ((a, b), (c, d)) = (self.parts(), other.parts());
+        Rational::reduce(a * c, b * d)
+    }
+
+    pub fn checked_div(&self, other: &Rational) -> Result<Rational, InterpError> {
+        let ((a, b), (c, d)) = (self.parts(), other.parts());
+        Rational::reduce(a * d, b * c)
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        let ((a, b), (c, d)) = (self.parts(), other.parts());
+        (a * d).cmp(&(c * b))
+    }
+}
+
+/// Written the same way as the division that makes it, like `1/3`.
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numer, self.denom)
+    }
+}
+
+#[test]
+fn rational_arithmetic() {
+    let third = Rational::new(1, 3).unwrap();
+    let half = Rational::new(-2, -4).unwrap();
+    assert_eq!((half.numer(), half.denom()), (1, 2));
+
+    assert_eq!(third.checked_add(&half).unwrap(), Rational::new(5, 6).unwrap());
+    assert_eq!(third.checked_sub(&half).unwrap().to_string(), "-1/6");
+    assert_eq!(third.checked_mul(&Rational::from_int(3)).unwrap().into_value(), Value::Int(1));
+    assert!(third < half);
+
+    assert_eq!(Rational::new(1, 0), Err(InterpError::DivideByZero));
+    let big = Rational::from_int(i64::max_value());
+    assert_eq!(big.checked_add(&big), Err(InterpError::IntegerOverflow));
+}
@@ -0,0 +1,72 @@
+use std::ops::Range;
+
+use gc::Gc;
+#[cfg(feature = "graphemes")]
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The byte offset where each character of `text` starts, followed by
+/// `text.len()`.  A character is a grapheme cluster with the `graphemes`
+/// feature, and a `char` without it.
+pub(crate) fn char_boundaries(text: &str) -> Vec<usize> {
+    #[cfg(feature = "graphemes")]
+    let starts = text.grapheme_indices(true).map(|(i, _)| i);
+    #[cfg(not(feature = "graphemes"))]
+    let starts = text.char_indices().map(|(i, _)| i);
+    starts.chain(Some(text.len())).collect()
+}
+
+/// A view into a range of a garbage collected string.
+///
+/// Substrings keep a reference to the string that they were taken from
+/// instead of copying it, so slicing and splitting a large string only
+/// allocates the (small) substring header.
+#[derive(Debug, Clone, Trace, Finalize)]
+pub struct Substring {
+    source: Gc<String>,
+    start: usize,
+    end: usize,
+}
+
+impl Substring {
+    /// Creates a substring of `source` covering `range`.
+    ///
+    /// Returns None if the range is out of bounds or doesn't fall on
+    /// utf-8 character boundaries.
+    pub fn new(source: Gc<String>, range: Range<usize>) -> Option<Substring> {
+        if range.start > range.end || range.end > source.len() {
+            return None;
+        }
+        if !source.is_char_boundary(range.start) || !source.is_char_boundary(range.end) {
+            return None;
+        }
+
+        Some(Substring {
+            source: source,
+            start: range.start,
+            end: range.end,
+        })
+    }
+
+    /// Takes a substring of this substring.  `range` is relative to the
+    /// start of this substring, but the result still points at the
+    /// original backing string.
+    pub fn slice(&self, range: Range<usize>) -> Option<Substring> {
+        if range.end > self.len() {
+            return None;
+        }
+        Substring::new(self.source.clone(), self.start + range.start .. self.start + range.end)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source[self.start .. self.end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// The string that this substring is a view into.
+    pub fn source(&self) -> &Gc<String> {
+        &self.source
+    }
+}
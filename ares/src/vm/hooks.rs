@@ -0,0 +1,71 @@
+use std::fmt;
+
+use vm::{InterpError, Value};
+
+/// Callbacks that the vm runs as it executes, for embedders that want to
+/// watch a script without changing the interpreter.  Every hook is
+/// optional.
+///
+/// Functions are identified by the name that they were given when they
+/// were created, with `fn name(..)` or `var name = ..` for closures.
+/// Functions without a name are reported as `None`.
+#[derive(Default)]
+pub struct Hooks {
+    /// Runs when a function is entered, with its arguments.
+    pub on_call: Option<Box<FnMut(Option<&str>, &[Value])>>,
+    /// Runs when a function returns, with its return value.
+    pub on_return: Option<Box<FnMut(Option<&str>, &Value)>>,
+    /// Runs before a collection that is asked for with
+    /// `Context::collect_garbage`.  The collector also runs on its own
+    /// as values are allocated, and those collections aren't reported.
+    pub on_gc_start: Option<Box<FnMut()>>,
+    /// Runs after a collection that is asked for with
+    /// `Context::collect_garbage`.
+    pub on_gc_end: Option<Box<FnMut()>>,
+    /// Runs when execution stops with an error.
+    pub on_error: Option<Box<FnMut(&InterpError)>>,
+}
+
+impl Hooks {
+    pub(crate) fn call(&mut self, name: Option<&str>, args: &[Value]) {
+        if let Some(ref mut f) = self.on_call {
+            f(name, args);
+        }
+    }
+
+    pub(crate) fn ret(&mut self, name: Option<&str>, value: &Value) {
+        if let Some(ref mut f) = self.on_return {
+            f(name, value);
+        }
+    }
+
+    pub(crate) fn gc_start(&mut self) {
+        if let Some(ref mut f) = self.on_gc_start {
+            f();
+        }
+    }
+
+    pub(crate) fn gc_end(&mut self) {
+        if let Some(ref mut f) = self.on_gc_end {
+            f();
+        }
+    }
+
+    pub(crate) fn error(&mut self, error: &InterpError) {
+        if let Some(ref mut f) = self.on_error {
+            f(error);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks")
+         .field("on_call", &self.on_call.is_some())
+         .field("on_return", &self.on_return.is_some())
+         .field("on_gc_start", &self.on_gc_start.is_some())
+         .field("on_gc_end", &self.on_gc_end.is_some())
+         .field("on_error", &self.on_error.is_some())
+         .finish()
+    }
+}
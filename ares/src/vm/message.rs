@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fmt;
+
+use vm::{ContractKind, InterpError, Value, ValueKind, to_string_helper};
+use ares_syntax::SymbolIntern;
+use compiler::{Diagnostic, Severity};
+
+impl InterpError {
+    /// Describes this error, printing the values that it holds.  Use the
+    /// interner that the values' symbols were interned in.
+    pub fn message(&self, interner: &SymbolIntern) -> String {
+        self.describe(Some(interner))
+    }
+
+    /// A short identifier that stays the same between releases.  Errors
+    /// found while running a script are numbered from `E0201`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            &InterpError::InternalInterpError(_) => "E0201",
+            &InterpError::MismatchedType { .. } => "E0202",
+            &InterpError::IncomparableValues(..) => "E0203",
+            &InterpError::StringIndexOutOfBounds { .. } => "E0204",
+            &InterpError::ListIndexOutOfBounds { .. } => "E0205",
+            &InterpError::DivideByZero => "E0206",
+            &InterpError::IntegerOverflow => "E0207",
+            &InterpError::NumericCoercion { .. } => "E0208",
+            &InterpError::VariableNotFound(_) => "E0209",
+            &InterpError::StackOverflow => "E0210",
+            &InterpError::StackUnderflow => "E0211",
+            &InterpError::StackOutOfBounds => "E0212",
+            &InterpError::WrongArity { .. } => "E0213",
+            &InterpError::UserFnWithWrongStateType => "E0214",
+            &InterpError::NativePanic { .. } => "E0215",
+            #[cfg(feature = "async")]
+            &InterpError::SuspendedOutsideOfAsync => "E0216",
+            &InterpError::ContractViolation { .. } => "E0217",
+            &InterpError::ReplayDiverged { .. } => "E0218",
+            &InterpError::MutatedMapKey => "E0219",
+            &InterpError::ShiftWithoutReset => "E0220",
+        }
+    }
+
+    /// Describes this error as a diagnostic, pointing at the code that
+    /// caused it when the vm knows where that is.
+    pub fn to_diagnostic(&self, interner: &SymbolIntern) -> Diagnostic {
+        let span = match self {
+            &InterpError::WrongArity { span, .. } | &InterpError::ContractViolation { span, .. } => span,
+            _ => None,
+        };
+        Diagnostic::new(Severity::Error, self.code(), self.message(interner), span)
+    }
+
+    /// Without an interner, values are described only by their kind.
+    fn describe(&self, interner: Option<&SymbolIntern>) -> String {
+        let value = |v: &Value| match interner {
+            Some(interner) => format!("{} `{}`", kind_name(v.kind()), to_string_helper(v, interner)),
+            None => kind_name(v.kind()).to_string(),
+        };
+        match self {
+            &InterpError::InternalInterpError(ref message) => format!("internal error: {}", message),
+            &InterpError::MismatchedType { value: ref v, expected } =>
+                format!("expected {}, got {}", kind_name(expected), value(v)),
+            &InterpError::IncomparableValues(ref a, ref b) => format!("can't compare {} with {}", value(a), value(b)),
+            &InterpError::StringIndexOutOfBounds { start, end, len } =>
+                format!("string range {}..{} is out of bounds for length {}", start, end, len),
+            &InterpError::ListIndexOutOfBounds { index, len } =>
+                format!("index {} is out of bounds for length {}", index, len),
+            &InterpError::DivideByZero => "division by zero".to_string(),
+            &InterpError::IntegerOverflow => "integer overflow".to_string(),
+            &InterpError::NumericCoercion { left, right } =>
+                format!("can't combine {} with {} without rounding", kind_name(left), kind_name(right)),
+            &InterpError::VariableNotFound(ref name) => format!("`{}` is not defined", name),
+            &InterpError::StackOverflow => "stack overflow".to_string(),
+            &InterpError::StackUnderflow => "stack underflow".to_string(),
+            &InterpError::StackOutOfBounds => "stack access out of bounds".to_string(),
+            &InterpError::WrongArity { got, expected, .. } =>
+                format!("expected {} arguments, got {}", expected, got),
+            &InterpError::UserFnWithWrongStateType =>
+                "a native function was called with the wrong state type".to_string(),
+            &InterpError::NativePanic { ref name, ref message } => match name {
+                &Some(ref name) => format!("native function `{}` panicked: {}", name, message),
+                &None => format!("a native function panicked: {}", message),
+            },
+            #[cfg(feature = "async")]
+            &InterpError::SuspendedOutsideOfAsync =>
+                "a native function suspended while the script was run synchronously".to_string(),
+            &InterpError::ContractViolation { kind: ContractKind::Requires, .. } =>
+                "a `requires` clause was false".to_string(),
+            &InterpError::ContractViolation { kind: ContractKind::Ensures, .. } =>
+                "an `ensures` clause was false".to_string(),
+            &InterpError::ReplayDiverged { ref expected, ref got } => {
+                let call = |name: &Option<String>| name.as_ref().map_or("an unnamed function".to_string(),
+                                                                        |n| format!("`{}`", n));
+                match expected {
+                    &Some(_) => format!("replay expected a call to {}, got {}", call(expected), call(got)),
+                    &None => format!("replay expected no more calls, got {}", call(got)),
+                }
+            }
+            &InterpError::MutatedMapKey =>
+                "a map that is a key of another map can't be changed".to_string(),
+            &InterpError::ShiftWithoutReset => "`shift` was used outside of a matching `reset`".to_string(),
+        }
+    }
+}
+
+/// Describes the kind of a value with its article, like "an int".
+fn kind_name(kind: ValueKind) -> &'static str {
+    match kind {
+        ValueKind::Nil => "nil",
+        ValueKind::List => "a list",
+        ValueKind::Map => "a map",
+        ValueKind::String => "a string",
+        ValueKind::Float => "a float",
+        ValueKind::Int => "an int",
+        ValueKind::Rational => "a rational",
+        #[cfg(feature = "decimal")]
+        ValueKind::Decimal => "a decimal",
+        ValueKind::Bool => "a bool",
+        ValueKind::Symbol => "a symbol",
+        ValueKind::Closure => "a lambda",
+        ValueKind::UserFn => "a native function",
+        ValueKind::Cell => "a cell",
+        ValueKind::Continuation => "a continuation",
+        ValueKind::Handle => "a handle",
+    }
+}
+
+/// Describes values by their kind; `message` prints them as well.
+impl fmt::Display for InterpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.describe(None))
+    }
+}
+
+impl Error for InterpError {}
+
+#[test]
+fn interp_error_messages() {
+    let mut interner = SymbolIntern::new();
+    let error = InterpError::MismatchedType {
+        value: Value::Symbol(interner.intern("abc")),
+        expected: ValueKind::Int,
+    };
+    assert_eq!(error.to_string(), "expected an int, got a symbol");
+    assert_eq!(error.message(&interner), "expected an int, got a symbol `'abc`");
+    assert_eq!(error.to_diagnostic(&interner).code, "E0202");
+    assert_eq!(InterpError::ReplayDiverged { expected: None, got: Some("now".into()) }.to_string(),
+               "replay expected no more calls, got `now`");
+}
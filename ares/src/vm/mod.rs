@@ -1,22 +1,56 @@
 mod value;
+#[macro_use]
+mod view;
+mod hooks;
 mod util;
 mod concept;
+mod message;
+#[cfg(feature = "serialize")]
+mod serialize;
 #[cfg(test)]
 mod test;
 
 use std::marker::PhantomData;
 use std::cell::RefCell;
+use std::rc::Rc;
+use std::any::Any;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+use std::panic;
 
 use compiler::{CompileContext, ShiftMeta};
-use host::{State, EphemeralContext};
+use host::{State, EphemeralContext, Conditions};
+#[cfg(feature = "std")]
+use host::FileSystemPolicy;
 
 pub use vm::value::*;
+pub use vm::view::ValueRef;
+pub use vm::hooks::Hooks;
 pub use vm::concept::lambda::*;
 pub use vm::util::stack::*;
 pub use vm::util::module::*;
+pub use vm::util::inline_cache::*;
+pub use vm::util::coverage::*;
+pub use vm::util::random::Rng;
+pub use vm::util::clock::{Clock, SystemClock, FixedClock};
+pub use vm::util::replay::{ReplayLog, ReplayEntry};
+pub(crate) use vm::util::replay::Replay;
+pub use vm::util::resources::Resources;
 pub use vm::concept::function::*;
+pub(crate) use vm::concept::function::Forward;
+#[cfg(feature = "async")]
+pub use vm::concept::future::{NativeFuture, async_function};
+#[cfg(feature = "async")]
+pub(crate) use vm::concept::future::Suspension;
 pub use vm::concept::continuation::*;
+pub use vm::concept::string::*;
+pub use vm::concept::rational::Rational;
+#[cfg(feature = "decimal")]
+pub use vm::concept::decimal::Decimal;
+#[cfg(feature = "serialize")]
+pub use vm::serialize::{WithInterner, ValueSeed};
 pub use gc::Gc;
+pub use ares_syntax::{Symbol, SymbolIntern};
 
 use ares_syntax::*;
 
@@ -30,15 +64,72 @@ pub enum InterpError {
         expected: ValueKind,
     },
     IncomparableValues(Value, Value),
+    StringIndexOutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    /// Also used for indexing strings, with `len` counted in characters.
+    ListIndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
+    DivideByZero,
+    IntegerOverflow,
+    /// Two numbers whose kinds can't be combined without rounding one of
+    /// them, like a decimal and a float.
+    NumericCoercion {
+        left: ValueKind,
+        right: ValueKind,
+    },
     VariableNotFound(String),
     StackOverflow,
     StackUnderflow,
     StackOutOfBounds,
-    BadArity {
+    WrongArity {
         got: u32,
         expected: u32,
+        /// The span of the call that supplied the wrong number of arguments.
+        span: Option<Span>,
     },
     UserFnWithWrongStateType,
+    /// A native function panicked while `VmOptions::catch_native_panics`
+    /// was set.
+    NativePanic {
+        name: Option<String>,
+        message: String,
+    },
+    /// A native function returned a future while the script was being
+    /// run synchronously.
+    #[cfg(feature = "async")]
+    SuspendedOutsideOfAsync,
+    /// A `requires` or `ensures` clause of a lambda was false.
+    ContractViolation {
+        kind: ContractKind,
+        /// The span of the clause that failed.
+        span: Option<Span>,
+    },
+    /// A native function was called while replaying that doesn't match
+    /// the next call in the `ReplayLog`.  `expected` is `None` if the log
+    /// has run out (or the recorded function has no name).
+    ReplayDiverged {
+        expected: Option<String>,
+        got: Option<String>,
+    },
+    /// A map was changed after it was used as a key in another map.
+    MutatedMapKey,
+    /// A `shift` ran without a `reset` around it that names one of its
+    /// symbols.
+    ShiftWithoutReset,
+}
+
+/// The clauses of a lambda's contract.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ContractKind {
+    /// Checked before the body runs.
+    Requires,
+    /// Checked after the body returns, with its value bound to `result`.
+    Ensures,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Trace, Finalize)]
@@ -46,7 +137,75 @@ pub struct Frame {
     resume_code_pos: usize,
     stack_frame: u32,
     namespace: Symbol,
-    reset_symbols: Option<Vec<Symbol>>
+    reset_symbols: Option<Vec<Symbol>>,
+    /// Set for the frame of a closure call, to the closure's name, so
+    /// that its return can be reported to the hooks.
+    closure: Option<Option<Symbol>>,
+}
+
+/// Settings that control how the vm runs code.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct VmOptions {
+    /// Logs every function call under the `ares::vm` target.
+    pub log_calls: bool,
+    /// Lets scripts use the file builtins.  `None` disables them.
+    #[cfg(feature = "std")]
+    pub file_system: Option<FileSystemPolicy>,
+    /// Seeds the generator used by the random builtins.  When this is
+    /// `None` the generator is seeded from the current time.
+    pub rng_seed: Option<u64>,
+    /// Lets scripts read environment variables and the process's
+    /// arguments through `env` and `argv`.
+    pub allow_environment: bool,
+    /// Turns panics in native functions into `InterpError::NativePanic`
+    /// instead of unwinding through the vm.
+    pub catch_native_panics: bool,
+    /// Makes running a script reproducible, for replays and lockstep
+    /// simulations.  The random generator is seeded with `rng_seed` (or
+    /// 0), maps are printed in key order, `now` only reads a clock that
+    /// is deterministic, and the builtins that read the environment or the
+    /// file system return `nil`.
+    pub deterministic: bool,
+}
+
+/// Where the builtins that reach outside of the vm write to and read the
+/// time from.  Hosts replace these with `Context::set_output` and
+/// `Context::set_clock`.
+pub struct HostIo {
+    /// Where the print builtins write to.  Defaults to the process's stdout.
+    #[cfg(feature = "std")]
+    pub stdout: Box<Write>,
+    /// Where `eprint` writes to.  Defaults to the process's stderr.
+    #[cfg(feature = "std")]
+    pub stderr: Box<Write>,
+    /// The time that `now` reports.
+    pub clock: Box<Clock>,
+}
+
+impl Default for HostIo {
+    fn default() -> HostIo {
+        HostIo {
+            #[cfg(feature = "std")]
+            stdout: Box::new(io::stdout()),
+            #[cfg(feature = "std")]
+            stderr: Box::new(io::stderr()),
+            clock: Box::new(SystemClock),
+        }
+    }
+}
+
+impl ::std::fmt::Debug for HostIo {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("HostIo").finish()
+    }
+}
+
+/// Counters that are updated as the vm runs.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct ExecStats {
+    pub instructions: u64,
+    /// The number of instructions executed that allocate on the gc heap.
+    pub allocations: u64,
 }
 
 #[derive(Debug)]
@@ -56,11 +215,49 @@ pub struct Vm<S: State = ()> {
     pub(crate) interner: SymbolIntern,
     pub(crate) globals: Modules,
     pub(crate) code: Vec<Instr>,
+    pub(crate) global_caches: InlineCaches,
     pub(crate) compile_context: CompileContext,
     pub(crate) last_code_position: usize,
+    pub(crate) options: VmOptions,
+    pub(crate) io: HostIo,
+    /// Only tracked while coverage is turned on.
+    pub(crate) coverage: Option<Coverage>,
+    pub(crate) stats: ExecStats,
+    pub(crate) rng: Rng,
+    pub(crate) hooks: Hooks,
+    pub(crate) resources: Resources,
+    pub(crate) replay: Replay,
+    /// Shared with the condition builtins, which is why it's behind an `Rc`.
+    pub(crate) conditions: Rc<RefCell<Conditions>>,
+    /// Where `FORWARD_STUB` was loaded, once it has been.
+    pub(crate) forward_stub: Option<usize>,
+    /// Set while a script is waiting on a native future.
+    #[cfg(feature = "async")]
+    pub(crate) suspension: Option<Suspension>,
     _phantom: PhantomData<S>,
 }
 
+/// The code that makes the calls that native functions ask for with
+/// `EphemeralContext::tail_call` and `call_then`, and that the host makes
+/// with `Vm::call`.  It runs in a frame of its own with the arguments, the
+/// function and the argument count on the stack, and `then` under them
+/// for `call_then`.  It starts with a `Halt` so that code loaded before it
+/// doesn't run on into it.
+const FORWARD_STUB: [Instr; 9] = [
+    Instr::Halt,
+    // tail_call
+    Instr::ExecuteN,
+    Instr::Ret,
+    // call_then
+    Instr::ExecuteN,
+    Instr::Swap,
+    Instr::Execute(1),
+    Instr::Ret,
+    // Vm::call
+    Instr::ExecuteN,
+    Instr::Halt,
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Instr {
     /// Stop execution
@@ -166,7 +363,8 @@ pub enum Instr {
     ConstructList(u32),
 
     /// Pops a number off the stack.  Pops a list off the stack.
-    /// Uses the number to index the list.
+    /// Uses the number to index the list, or the characters of a
+    /// string.
     ListIndex,
 
     /// Execute a lambda on the top of the stack with
@@ -185,6 +383,12 @@ pub enum Instr {
     /// Read a bool off the stack, if false, continue executing,
     /// else skip the next instruction.
     Ifn,
+
+    /// Pops a bool off the stack and raises a contract violation
+    /// if it is false.
+    Require,
+    /// See Require, but for a postcondition.
+    Ensure,
 }
 
 impl Instr {
@@ -206,6 +410,25 @@ where I: FnOnce(i64, i64) -> bool,
         (&Value::Float(af), Value::Float(bf)) => f(af, bf),
         (&Value::Int(ai), Value::Float(bf)) => f(ai as f64, bf),
         (&Value::Float(af), Value::Int(bi)) => f(af, bi as f64),
+        (&Value::Float(af), Value::Rational(ref br)) => f(af, br.to_f64()),
+        (&Value::Rational(ref ar), Value::Float(bf)) => f(ar.to_f64(), bf),
+        // Exact comparisons compare the ordering (-1, 0 or 1) against zero
+        // so that they can reuse the int comparison.
+        (&Value::Rational(ref ar), Value::Rational(ref br)) => i((**ar).cmp(&**br) as i64, 0),
+        (&Value::Rational(ref ar), Value::Int(bi)) => i((**ar).cmp(&Rational::from_int(bi)) as i64, 0),
+        (&Value::Int(ai), Value::Rational(ref br)) => i(Rational::from_int(ai).cmp(&**br) as i64, 0),
+        #[cfg(feature = "decimal")]
+        (&Value::Decimal(_), ref other @ Value::Float(_)) |
+        (&Value::Decimal(_), ref other @ Value::Rational(_)) |
+        (&Value::Float(_), ref other @ Value::Decimal(_)) |
+        (&Value::Rational(_), ref other @ Value::Decimal(_)) =>
+            return Err(InterpError::NumericCoercion { left: a.kind(), right: other.kind() }),
+        #[cfg(feature = "decimal")]
+        (&Value::Decimal(ref ad), Value::Decimal(ref bd)) => i((**ad).cmp(&**bd) as i64, 0),
+        #[cfg(feature = "decimal")]
+        (&Value::Decimal(ref ad), Value::Int(bi)) => i((**ad).cmp(&Decimal::from_int(bi)) as i64, 0),
+        #[cfg(feature = "decimal")]
+        (&Value::Int(ai), Value::Decimal(ref bd)) => i(Decimal::from_int(ai).cmp(&**bd) as i64, 0),
         (a, b) => return Err(InterpError::IncomparableValues(a.clone(), b)),
     })
 }
@@ -217,15 +440,101 @@ impl <S: State> Vm<S> {
             frames: vec![],
             globals: Modules::new(),
             code: Vec::new(),
+            global_caches: InlineCaches::new(),
             interner: SymbolIntern::new(),
             compile_context: ::compiler::CompileContext::new(),
             last_code_position: 0,
+            options: VmOptions::default(),
+            io: HostIo::default(),
+            coverage: None,
+            stats: ExecStats::default(),
+            rng: Rng::from_time(),
+            hooks: Hooks::default(),
+            resources: Resources::new(),
+            replay: Replay::Off,
+            conditions: Rc::new(RefCell::new(Conditions::default())),
+            forward_stub: None,
+            #[cfg(feature = "async")]
+            suspension: None,
             _phantom: PhantomData,
         }
     }
 
     pub fn load_and_execute(&mut self, code: &[Instr], arg_count: u32, state: &mut S) -> Result<(), InterpError> {
-        let start = self.code.len() as u32;
+        let start = self.code.len();
+        self.code.extend(code.iter().cloned());
+        self.global_caches.grow_to(self.code.len());
+        self.execute_at(start, arg_count, state)
+    }
+
+    /// Replaces the callbacks that run as code executes.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
+    }
+
+    /// Runs a garbage collection, running the gc hooks around it.
+    pub fn collect_garbage(&mut self) {
+        self.hooks.gc_start();
+        ::gc::force_collect();
+        self.hooks.gc_end();
+    }
+
+    /// Runs code that has already been loaded, starting at `start`, in a
+    /// new top level frame.
+    pub(crate) fn execute_at(&mut self, start: usize, arg_count: u32, state: &mut S) -> Result<(), InterpError> {
+        let frame_count = self.push_base_frame(arg_count);
+        let conditions = self.conditions.borrow().clone();
+        let r = self.execute(start as u32, state);
+        // An error can leave the frames of the functions that were
+        // running behind, so drop everything that this call pushed, and
+        // the handlers and restarts that they established.
+        self.frames.truncate(frame_count);
+        if r.is_err() {
+            *self.conditions.borrow_mut() = conditions;
+        }
+        #[cfg(feature = "async")]
+        {
+            if self.suspension.take().is_some() {
+                return Err(InterpError::SuspendedOutsideOfAsync);
+            }
+        }
+        r
+    }
+
+    /// Calls `function` with `args` in a new top level frame, without
+    /// loading any code to do it.
+    pub fn call(&mut self, function: Value, args: Vec<Value>, state: &mut S) -> Result<Value, InterpError> {
+        let start = self.forward_stub() + 7;
+        let previous_stack_size = self.stack.len();
+        let count = args.len() as i64;
+        for arg in args {
+            self.stack.push(arg)?;
+        }
+        self.stack.push(function)?;
+        self.stack.push(Value::Int(count))?;
+        match self.execute_at(start, 0, state) {
+            Ok(()) => self.stack.pop(),
+            Err(e) => {
+                self.stack.truncate(previous_stack_size as usize)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Where `FORWARD_STUB` starts, loading it after the rest of the code
+    /// the first time that it's needed.
+    fn forward_stub(&mut self) -> usize {
+        if let Some(start) = self.forward_stub {
+            return start;
+        }
+        let start = self.code.len();
+        self.code.extend(FORWARD_STUB.iter().cloned());
+        self.global_caches.grow_to(self.code.len());
+        self.forward_stub = Some(start);
+        start
+    }
+
+    fn push_base_frame(&mut self, arg_count: u32) -> usize {
         let default_ns = self.interner.precomputed.default_namespace;
 
         let base_frame = Frame {
@@ -233,15 +542,60 @@ impl <S: State> Vm<S> {
             stack_frame: self.stack.len() - arg_count,
             namespace: default_ns,
             reset_symbols: None,
+            closure: None,
         };
+        let frame_count = self.frames.len();
         self.frames.push(base_frame);
+        frame_count
+    }
 
-        self.code.extend(code.iter().cloned());
-        let r = self.execute(start, state);
-        self.frames.pop();
+    /// Like `execute_at`, but the code may suspend on a native future,
+    /// in which case it is continued by `poll_suspension`.
+    #[cfg(feature = "async")]
+    pub(crate) fn start_at(&mut self, start: usize, state: &mut S) -> Result<(), InterpError> {
+        let frame_count = self.push_base_frame(0);
+        let r = self.execute(start as u32, state);
+        self.finish_or_suspend(frame_count);
         r
     }
 
+    #[cfg(feature = "async")]
+    pub(crate) fn is_suspended(&self) -> bool {
+        self.suspension.is_some()
+    }
+
+    /// Polls the future that the running script is waiting on.  Once it
+    /// completes, the script continues until it finishes or suspends
+    /// again.
+    #[cfg(feature = "async")]
+    pub(crate) fn poll_suspension(&mut self, state: &mut S, cx: &mut ::std::task::Context)
+    -> ::std::task::Poll<Result<(), InterpError>> {
+        use std::future::Future;
+        use std::task::Poll;
+
+        let value = match self.suspension {
+            Some(ref mut suspension) => match suspension.future.as_mut().poll(cx) {
+                Poll::Ready(value) => value,
+                Poll::Pending => return Poll::Pending,
+            },
+            None => return Poll::Ready(Ok(())),
+        };
+
+        let Suspension { name, resume_at, frame_count, .. } = self.suspension.take().unwrap();
+        self.replay.record(name.as_ref().map(|n| &n[..]), &value);
+        let r = self.stack.push(value).and_then(|_| self.execute(resume_at as u32, state));
+        self.finish_or_suspend(frame_count);
+        Poll::Ready(r)
+    }
+
+    #[cfg(feature = "async")]
+    fn finish_or_suspend(&mut self, frame_count: usize) {
+        match self.suspension {
+            Some(ref mut suspension) => suspension.frame_count = frame_count,
+            None => self.frames.truncate(frame_count),
+        }
+    }
+
     fn execute(&mut self,
                    start_at: u32,
                    state: &mut S)
@@ -252,10 +606,22 @@ impl <S: State> Vm<S> {
             code: &'a [Instr],
             stack: &'a mut Stack,
             globals: &'a mut Modules,
+            global_caches: &'a mut InlineCaches,
             interner: &'a mut SymbolIntern,
             compile_context: &'a CompileContext,
             frames: &'a mut Vec<Frame>,
             state: &'a mut S,
+            options: &'a mut VmOptions,
+            io: &'a mut HostIo,
+            coverage: &'a mut Option<Coverage>,
+            stats: &'a mut ExecStats,
+            rng: &'a mut Rng,
+            hooks: &'a mut Hooks,
+            resources: &'a mut Resources,
+            replay: &'a mut Replay,
+            forward_stub: usize,
+            #[cfg(feature = "async")]
+            suspension: &'a mut Option<Suspension>,
         }
 
         #[inline(always)]
@@ -265,14 +631,39 @@ impl <S: State> Vm<S> {
                 ref code,
                 ref mut stack,
                 ref mut globals,
+                ref mut global_caches,
                 ref mut interner,
                 ref compile_context,
                 ref mut frames,
-                ref mut state
+                ref mut state,
+                ref mut options,
+                ref mut io,
+                ref mut coverage,
+                ref mut stats,
+                ref mut rng,
+                ref mut hooks,
+                ref mut resources,
+                ref mut replay,
+                forward_stub,
+                ..
             } = ctx;
+            #[cfg(feature = "async")]
+            let suspension = &mut ctx.suspension;
             let i: &mut usize = *i;
 
+            if let Some(ref mut coverage) = **coverage {
+                coverage.hit(*i);
+            }
+
             let current_instruction = &code[*i];
+            stats.instructions += 1;
+            match current_instruction {
+                &Instr::Shift(_) |
+                &Instr::WrapCell |
+                &Instr::CreateClosure(_) |
+                &Instr::ConstructList(_) => stats.allocations += 1,
+                _ => {}
+            }
             let after_current = code.get(*i + 1);
             // Here lay some optimizations
             if let Some(after) = after_current {
@@ -284,20 +675,20 @@ impl <S: State> Vm<S> {
                     (&Instr::LoadConstant(_), &Instr::Pop) => {}
 
                     (&Instr::IntLit(added_with), &Instr::AddInt) => {
-                        let cur = stack.peek()?.expect_int_mut()?;
-                        *cur = *cur + added_with as i64;
+                        let cur = stack.peek()?;
+                        *cur = arith(Arith::Add, cur, &Value::Int(added_with as i64))?;
                     }
                     (&Instr::IntLit(subtract_by), &Instr::SubInt) => {
-                        let cur = stack.peek()?.expect_int_mut()?;
-                        *cur = *cur - subtract_by as i64;
+                        let cur = stack.peek()?;
+                        *cur = arith(Arith::Sub, cur, &Value::Int(subtract_by as i64))?;
                     }
                     (&Instr::IntLit(multiply_by), &Instr::MulInt) => {
-                        let cur = stack.peek()?.expect_int_mut()?;
-                        *cur = *cur * multiply_by as i64;
+                        let cur = stack.peek()?;
+                        *cur = arith(Arith::Mul, cur, &Value::Int(multiply_by as i64))?;
                     }
                     (&Instr::IntLit(divide_by), &Instr::DivInt) => {
-                        let cur = stack.peek()?.expect_int_mut()?;
-                        *cur = *cur / divide_by as i64;
+                        let cur = stack.peek()?;
+                        *cur = arith(Arith::Div, cur, &Value::Int(divide_by as i64))?;
                     }
                     (&Instr::IntLit(value), &Instr::Eq) => {
                         let cur = stack.peek()?;
@@ -388,7 +779,10 @@ impl <S: State> Vm<S> {
                     let saved_stack_len: u32;
 
                     loop {
-                        let next = frames.pop().expect("ran out of frames");
+                        let next = match frames.pop() {
+                            Some(next) => next,
+                            None => return Err(InterpError::ShiftWithoutReset),
+                        };
                         let done = match &next.reset_symbols {
                             &Some(ref s) => symbols_intersect(s, &shifting_symbols),
                             &None => false
@@ -464,12 +858,32 @@ impl <S: State> Vm<S> {
                 }
                 &Instr::ListIndex => {
                     let idx = stack.pop()?.expect_int()?;
-                    let lst = stack.pop()?.expect_list()?;
-                    let value = lst[idx as usize].clone();
+                    let indexed = stack.pop()?;
+                    let value = match indexed {
+                        // Strings are indexed by character.
+                        Value::String(_) | Value::Substring(_) => {
+                            let len = indexed.char_len()?;
+                            if idx >= 0 && (idx as usize) < len {
+                                indexed.char_substring(idx as usize, idx as usize + 1)?
+                            } else {
+                                return Err(InterpError::ListIndexOutOfBounds { index: idx, len: len });
+                            }
+                        }
+                        other => {
+                            let lst = other.expect_list()?;
+                            if idx >= 0 && (idx as usize) < lst.len() {
+                                lst[idx as usize].clone()
+                            } else {
+                                return Err(InterpError::ListIndexOutOfBounds { index: idx, len: lst.len() });
+                            }
+                        }
+                    };
                     stack.push(value)?;
                 }
                 &Instr::GetGlobal(symbol) => {
-                    if let Some(value) = globals.get(frames.last().unwrap().namespace, symbol).cloned() {
+                    let namespace = frames.last().unwrap().namespace;
+                    if let Some(slot) = global_caches.lookup(*i, globals, namespace, symbol) {
+                        let value = globals.get_slot(slot).clone();
                         stack.push(value)?;
                     } else {
                         return Err(InterpError::VariableNotFound(
@@ -478,7 +892,12 @@ impl <S: State> Vm<S> {
                 }
                 &Instr::PutGlobal(symbol) => {
                     let value = stack.pop()?;
-                    globals.set(frames.last().unwrap().namespace, symbol, value);
+                    let namespace = frames.last().unwrap().namespace;
+                    if let Some(slot) = global_caches.lookup(*i, globals, namespace, symbol) {
+                        *globals.get_slot_mut(slot) = value;
+                    } else {
+                        globals.set(namespace, symbol, value);
+                    }
                 }
                 &Instr::Assign(frame_pos) => {
                     let value = stack.pop()?;
@@ -490,6 +909,9 @@ impl <S: State> Vm<S> {
                 }
                 &Instr::Swap => {
                     let len = stack.len();
+                    if len < 2 {
+                        return Err(InterpError::StackUnderflow);
+                    }
                     stack.swap(len - 1, len - 2)?;
                 }
                 &Instr::NilLit => {
@@ -517,16 +939,16 @@ impl <S: State> Vm<S> {
                     *i = location.wrapping_sub(1) as usize;
                 }
                 &Instr::AddInt => {
-                    stack.binop_int(|a, b| a + b)?;
+                    stack.binop(|a, b| arith(Arith::Add, a, b))?;
                 }
                 &Instr::SubInt => {
-                    stack.binop_int(|a, b| a - b)?;
+                    stack.binop(|a, b| arith(Arith::Sub, a, b))?;
                 }
                 &Instr::MulInt => {
-                    stack.binop_int(|a, b| a * b)?;
+                    stack.binop(|a, b| arith(Arith::Mul, a, b))?;
                 }
                 &Instr::DivInt => {
-                    stack.binop_int(|a, b| a / b)?;
+                    stack.binop(|a, b| arith(Arith::Div, a, b))?;
                 }
                 &Instr::And => {
                     let a = stack.pop()?.expect_bool()?;
@@ -568,7 +990,11 @@ impl <S: State> Vm<S> {
                     let b = stack.peek()?;
                     *b = Value::Bool(&a != b);
                 }
-                &Instr::Execute(arg_count) => {
+                &Instr::Execute(_) | &Instr::ExecuteN => {
+                    let arg_count = match current_instruction {
+                        &Instr::Execute(arg_count) => arg_count,
+                        _ => stack.pop()?.expect_int()? as u32,
+                    };
                     let callable = stack.pop()?;
                     let callable = callable.decell();
                     match callable {
@@ -578,8 +1004,96 @@ impl <S: State> Vm<S> {
                             let user_fn = user_fn.correct::<S>();
                             let mut user_fn = user_fn.or(Err(
                                 InterpError::UserFnWithWrongStateType))?;
-                            let mut ctx = EphemeralContext::new(globals, interner);
-                            let result = user_fn.call(state, args, &mut ctx);
+                            if options.log_calls {
+                                trace!(target: "ares::vm", "calling native function with {} args", arg_count);
+                            }
+                            hooks.call(user_fn.name(), &args);
+                            // While replaying, the function isn't called at all,
+                            // unless it only forwards to other functions.
+                            let replayed = if user_fn.forwards() {
+                                None
+                            } else {
+                                replay.replayed(user_fn.name())?
+                            };
+                            let result = match replayed {
+                                Some(result) => result,
+                                None => {
+                                    let catch_panics = options.catch_native_panics;
+                                    let mut ctx = EphemeralContext::new(globals, interner, options, io, rng, resources);
+                                    let result = if catch_panics {
+                                        let call = panic::AssertUnwindSafe(|| user_fn.call(state, args, &mut ctx));
+                                        match panic::catch_unwind(call) {
+                                            Ok(result) => result,
+                                            Err(payload) => return Err(InterpError::NativePanic {
+                                                name: user_fn.name().map(String::from),
+                                                message: panic_message(&*payload),
+                                            }),
+                                        }
+                                    } else {
+                                        user_fn.call(state, args, &mut ctx)
+                                    };
+                                    if let Some(error) = ctx.error.take() {
+                                        return Err(match error {
+                                            InterpError::WrongArity { got, expected, span: None } => InterpError::WrongArity {
+                                                got: got,
+                                                expected: expected,
+                                                span: compile_context.call_site_span(*i as u32),
+                                            },
+                                            other => other,
+                                        });
+                                    }
+                                    #[cfg(feature = "async")]
+                                    {
+                                        if let Some(future) = ctx.pending.take() {
+                                            // The value is recorded once the future completes.
+                                            **suspension = Some(Suspension {
+                                                future: future,
+                                                name: user_fn.name().map(String::from),
+                                                resume_at: *i + 1,
+                                                frame_count: 0,
+                                            });
+                                            return Ok(false);
+                                        }
+                                    }
+                                    if let Some(forward) = ctx.forward.take() {
+                                        // The call is made by running the stub in a
+                                        // frame of its own, which returns to the
+                                        // instruction after this one.
+                                        let namespace = {
+                                            let caller = frames.last_mut().unwrap();
+                                            caller.resume_code_pos = *i;
+                                            caller.namespace
+                                        };
+                                        frames.push(Frame {
+                                            resume_code_pos: 0,
+                                            stack_frame: stack.len(),
+                                            namespace: namespace,
+                                            reset_symbols: None,
+                                            closure: None,
+                                        });
+                                        let (callee, args, stub) = match forward {
+                                            Forward::Tail { callee, args } => (callee, args, forward_stub + 1),
+                                            Forward::Then { callee, args, then } => {
+                                                stack.push(then)?;
+                                                (callee, args, forward_stub + 3)
+                                            }
+                                        };
+                                        let count = args.len() as i64;
+                                        for arg in args {
+                                            stack.push(arg)?;
+                                        }
+                                        stack.push(callee)?;
+                                        stack.push(Value::Int(count))?;
+                                        *i = stub;
+                                        return Ok(true);
+                                    }
+                                    if !user_fn.forwards() {
+                                        replay.record(user_fn.name(), &result);
+                                    }
+                                    result
+                                }
+                            };
+                            hooks.ret(user_fn.name(), &result);
                             stack.push(result)?;
                         }
                         Value::Closure(ref closure) => {
@@ -588,15 +1102,39 @@ impl <S: State> Vm<S> {
                             let local_defines_count = closure.class.local_defines_count;
                             let new_namespace = closure.class.namespace;
 
-                            if closure.class.has_rest_params { unimplemented!(); }
-
-                            if arg_count != expected_arg_count {
-                                return Err(InterpError::BadArity {
-                                    got: arg_count,
-                                    expected: expected_arg_count,
-                                });
+                            let call_pos = *i as u32;
+                            if options.log_calls {
+                                trace!(target: "ares::vm", "calling closure at {} with {} args from {}",
+                                       code_pos, arg_count, call_pos);
+                            }
+                            {
+                                let args = stack.as_slice();
+                                let name = closure.class.name.and_then(|name| interner.lookup(name));
+                                hooks.call(name, &args[args.len().saturating_sub(arg_count as usize)..]);
                             }
 
+                            let wrong_arity = || InterpError::WrongArity {
+                                got: arg_count,
+                                expected: expected_arg_count,
+                                span: compile_context.call_site_span(call_pos),
+                            };
+
+                            let arg_count = if closure.class.has_rest_params {
+                                // The last argument slot collects every extra argument
+                                // into a list.
+                                let fixed_arg_count = expected_arg_count - 1;
+                                if arg_count < fixed_arg_count {
+                                    return Err(wrong_arity());
+                                }
+                                let rest = stack.take_top(arg_count - fixed_arg_count)?;
+                                stack.push(rest.into())?;
+                                expected_arg_count
+                            } else if arg_count != expected_arg_count {
+                                return Err(wrong_arity());
+                            } else {
+                                arg_count
+                            };
+
                             if !closure.class.is_shifter {
                                 let last_item_on_stack = frames.last_mut().unwrap();
                                 last_item_on_stack.resume_code_pos = *i;
@@ -607,6 +1145,7 @@ impl <S: State> Vm<S> {
                                 stack_frame: stack.len() as u32 - arg_count as u32,
                                 namespace: new_namespace, //
                                 reset_symbols: closure.reset_symbols.borrow().clone(),
+                                closure: Some(closure.class.name),
                             });
 
                             *i = code_pos.wrapping_sub(1);
@@ -658,7 +1197,10 @@ impl <S: State> Vm<S> {
 
                             *i = (instruction_pos as usize).wrapping_sub(1);
                         }
-                        o => panic!("tried to call value ({:?})", o),
+                        other => return Err(InterpError::MismatchedType {
+                            value: other,
+                            expected: ValueKind::Closure,
+                        }),
                     }
                 }
                 &Instr::CreateClosure(class_id) => {
@@ -668,9 +1210,6 @@ impl <S: State> Vm<S> {
                     let instance = Closure { class: class, upvars: values, reset_symbols: RefCell::new(None)};
                     stack.push(instance.into())?;
                 }
-                &Instr::ExecuteN => {
-                    unimplemented!();
-                }
                 &Instr::Call(position) => {
                     let arg_count = stack.pop()?.expect_int()?;
                     let offset = position as usize;
@@ -685,6 +1224,7 @@ impl <S: State> Vm<S> {
                         stack_frame: stack.len() as u32 - arg_count as u32,
                         namespace: ns,
                         reset_symbols: None,
+                        closure: None,
                     });
 
                     *i = offset.wrapping_sub(1);
@@ -700,6 +1240,9 @@ impl <S: State> Vm<S> {
 
                     *i = resume_code_pos;
                     let return_value = stack.pop()?;
+                    if let Some(name) = cur.closure {
+                        hooks.ret(name.and_then(|name| interner.lookup(name)), &return_value);
+                    }
 
                     stack.truncate(truncate_to as usize)?;
                     stack.push(return_value)?;
@@ -716,12 +1259,25 @@ impl <S: State> Vm<S> {
                         *i += 1
                     }
                 }
+                &Instr::Require | &Instr::Ensure => {
+                    if !stack.pop()?.expect_bool()? {
+                        let kind = match current_instruction {
+                            &Instr::Require => ContractKind::Requires,
+                            _ => ContractKind::Ensures,
+                        };
+                        return Err(InterpError::ContractViolation {
+                            kind: kind,
+                            span: compile_context.contract_check_span(*i as u32),
+                        });
+                    }
+                }
             }
 
             *i = i.wrapping_add(1);
             Ok(true)
         }
 
+        let forward_stub = self.forward_stub();
         let mut i = start_at as usize;
 
         let mut ctx = ExecCtx {
@@ -729,10 +1285,22 @@ impl <S: State> Vm<S> {
             code: &self.code,
             stack: &mut self.stack,
             globals: &mut self.globals,
+            global_caches: &mut self.global_caches,
             interner: &mut self.interner,
             compile_context: &self.compile_context,
             frames: &mut self.frames,
             state: state,
+            options: &mut self.options,
+            io: &mut self.io,
+            coverage: &mut self.coverage,
+            stats: &mut self.stats,
+            rng: &mut self.rng,
+            hooks: &mut self.hooks,
+            resources: &mut self.resources,
+            replay: &mut self.replay,
+            forward_stub: forward_stub,
+            #[cfg(feature = "async")]
+            suspension: &mut self.suspension,
         };
 
         while *(ctx.i) < ctx.code.len(){
@@ -756,6 +1324,7 @@ impl <S: State> Vm<S> {
                 Ok(true) => {}
                 Ok(false) => { break; }
                 Err(e) => {
+                    ctx.hooks.error(&e);
                     self.last_code_position = *ctx.i;
                     return Err(e);
                 }
@@ -769,3 +1338,111 @@ impl <S: State> Vm<S> {
         println!("{:?}", self.stack);
     }
 }
+
+fn panic_message(payload: &(Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub(crate) enum Arith {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Applies `op` to two numbers, promoting the narrower one when their
+/// kinds differ:
+///
+/// * Int with int is an int.  Division that isn't even makes a rational,
+///   and overflow is an `IntegerOverflow` rather than a float.
+/// * Ints and rationals combine exactly, into a rational (or an int when
+///   the denominator works out to 1).
+/// * A float with an int, a rational or a float is a float.  Float
+///   division by zero follows IEEE and makes an infinity or `nan`.
+/// * A decimal with an int or a decimal is a decimal.
+///
+/// A decimal with a float or a rational would have to round one of them,
+/// so it fails with `NumericCoercion`.  Anything that isn't a number fails
+/// with `MismatchedType`.
+pub(crate) fn arith(op: Arith, a: &Value, b: &Value) -> Result<Value, InterpError> {
+    let overflow = InterpError::IntegerOverflow;
+    match (a, b) {
+        (&Value::Int(x), &Value::Int(y)) => match op {
+            Arith::Add => x.checked_add(y).map(Value::Int).ok_or(overflow),
+            Arith::Sub => x.checked_sub(y).map(Value::Int).ok_or(overflow),
+            Arith::Mul => x.checked_mul(y).map(Value::Int).ok_or(overflow),
+            Arith::Div if y == 0 => Err(InterpError::DivideByZero),
+            Arith::Div if x.checked_rem(y) == Some(0) => x.checked_div(y).map(Value::Int).ok_or(overflow),
+            Arith::Div => Rational::new(x, y).map(Rational::into_value),
+        },
+        #[cfg(feature = "decimal")]
+        (&Value::Decimal(_), _) | (_, &Value::Decimal(_)) => {
+            match (a, b) {
+                (&Value::Float(_), _) | (_, &Value::Float(_)) |
+                (&Value::Rational(_), _) | (_, &Value::Rational(_)) =>
+                    return Err(InterpError::NumericCoercion { left: a.kind(), right: b.kind() }),
+                _ => {}
+            }
+            let (x, y) = (as_decimal(a)?, as_decimal(b)?);
+            let result = match op {
+                Arith::Add => x.checked_add(&y),
+                Arith::Sub => x.checked_sub(&y),
+                Arith::Mul => x.checked_mul(&y),
+                Arith::Div => x.checked_div(&y),
+            };
+            result.map(Decimal::into_value)
+        }
+        (&Value::Float(_), _) | (_, &Value::Float(_)) => {
+            let (x, y) = (as_f64(a)?, as_f64(b)?);
+            Ok(Value::Float(match op {
+                Arith::Add => x + y,
+                Arith::Sub => x - y,
+                Arith::Mul => x * y,
+                Arith::Div => x / y,
+            }))
+        }
+        _ => {
+            let (x, y) = (as_rational(a)?, as_rational(b)?);
+            let result = match op {
+                Arith::Add => x.checked_add(&y),
+                Arith::Sub => x.checked_sub(&y),
+                Arith::Mul => x.checked_mul(&y),
+                Arith::Div => x.checked_div(&y),
+            };
+            result.map(Rational::into_value)
+        }
+    }
+}
+
+fn as_f64(value: &Value) -> Result<f64, InterpError> {
+    match value {
+        &Value::Int(i) => Ok(i as f64),
+        &Value::Float(f) => Ok(f),
+        &Value::Rational(ref r) => Ok(r.to_f64()),
+        other => Err(InterpError::MismatchedType { value: other.clone(), expected: ValueKind::Float }),
+    }
+}
+
+fn as_rational(value: &Value) -> Result<Rational, InterpError> {
+    match value {
+        &Value::Int(i) => Ok(Rational::from_int(i)),
+        &Value::Rational(ref r) => Ok(**r),
+        other => Err(InterpError::MismatchedType { value: other.clone(), expected: ValueKind::Int }),
+    }
+}
+
+#[cfg(feature = "decimal")]
+fn as_decimal(value: &Value) -> Result<Decimal, InterpError> {
+    match value {
+        &Value::Int(i) => Ok(Decimal::from_int(i)),
+        &Value::Decimal(ref d) => Ok(**d),
+        other => Err(InterpError::MismatchedType { value: other.clone(), expected: ValueKind::Decimal }),
+    }
+}
@@ -0,0 +1,196 @@
+use util::collections::HashMap;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, DeserializeSeed};
+use serde::ser::Error;
+
+use ares_syntax::{Symbol, SymbolIntern};
+use vm::{Value, ValueKind, Rational};
+#[cfg(feature = "decimal")]
+use vm::Decimal;
+
+// Deeper values are assumed to be cyclic through a cell.
+const MAX_DEPTH: u32 = 512;
+
+/// The serialized form of a value.  Symbols are stored as `S`, which is
+/// either their id or their name.
+#[derive(Serialize, Deserialize)]
+enum Repr<S> {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Symbol(S),
+    List(Vec<Repr<S>>),
+    Map(Vec<(Repr<S>, Repr<S>)>),
+    Rational(i64, i64),
+    #[cfg(feature = "decimal")]
+    Decimal(String),
+}
+
+fn to_repr<S, F>(value: &Value, symbol: &F, depth: u32) -> Result<Repr<S>, String>
+where F: Fn(Symbol) -> S {
+    if depth > MAX_DEPTH {
+        return Err("value is nested too deeply to serialize".into());
+    }
+
+    let unsupported = |kind: ValueKind| Err(format!("values of kind {:?} can't be serialized", kind));
+    Ok(match value {
+        &Value::Nil => Repr::Nil,
+        &Value::Bool(b) => Repr::Bool(b),
+        &Value::Int(i) => Repr::Int(i),
+        &Value::Float(f) => Repr::Float(f),
+        &Value::Rational(ref r) => Repr::Rational(r.numer(), r.denom()),
+        #[cfg(feature = "decimal")]
+        &Value::Decimal(ref d) => Repr::Decimal(d.digits()),
+        &Value::String(_) | &Value::Substring(_) => Repr::String(value.expect_str().unwrap().to_string()),
+        &Value::Symbol(s) => Repr::Symbol(symbol(s)),
+        &Value::Cell(ref c) => to_repr(&*c.borrow(), symbol, depth + 1)?,
+        &Value::List(ref items) => {
+            let items: Result<Vec<_>, String> =
+                items.iter().map(|v| to_repr(v, symbol, depth + 1)).collect();
+            Repr::List(items?)
+        }
+        &Value::Map(ref pairs) => {
+            let pairs = pairs.borrow();
+            let mut out = Vec::with_capacity(pairs.len());
+            for (k, v) in pairs.iter() {
+                out.push((to_repr(k, symbol, depth + 1)?, to_repr(v, symbol, depth + 1)?));
+            }
+            Repr::Map(out)
+        }
+        &Value::Closure(_) => return unsupported(ValueKind::Closure),
+        &Value::UserFn(_) => return unsupported(ValueKind::UserFn),
+        &Value::Continuation(_) => return unsupported(ValueKind::Continuation),
+        &Value::Handle(_) => return unsupported(ValueKind::Handle),
+    })
+}
+
+fn from_repr<S, F>(repr: Repr<S>, symbol: &mut F) -> Result<Value, String>
+where F: FnMut(S) -> Symbol {
+    Ok(match repr {
+        Repr::Nil => Value::Nil,
+        Repr::Bool(b) => Value::Bool(b),
+        Repr::Int(i) => Value::Int(i),
+        Repr::Float(f) => Value::Float(f),
+        Repr::String(s) => s.into(),
+        Repr::Symbol(s) => Value::Symbol(symbol(s)),
+        Repr::List(items) => {
+            let items: Result<Vec<_>, String> = items.into_iter().map(|r| from_repr(r, symbol)).collect();
+            items?.into()
+        }
+        Repr::Map(pairs) => {
+            let mut map = HashMap::with_capacity(pairs.len());
+            for (k, v) in pairs {
+                map.insert(from_repr(k, symbol)?, from_repr(v, symbol)?);
+            }
+            map.into()
+        }
+        // `to_repr` never writes these, so the data was made some other way.
+        Repr::Rational(n, d) => match Rational::new(n, d) {
+            Ok(r) => r.into_value(),
+            Err(_) => return Err(format!("{}/{} is not a valid rational", n, d)),
+        },
+        #[cfg(feature = "decimal")]
+        Repr::Decimal(digits) => match Decimal::parse(&digits) {
+            Some(d) => d.into_value(),
+            None => return Err(format!("{:?} is not a valid decimal", digits)),
+        },
+    })
+}
+
+/// Symbols are written as their ids, so they only keep their names when
+/// deserialized alongside the same (or a deserialized copy of the same)
+/// `SymbolIntern`.  Use `WithInterner` and `ValueSeed` to write names
+/// instead.
+impl Serialize for Value {
+    fn serialize<Se: Serializer>(&self, serializer: Se) -> Result<Se::Ok, Se::Error> {
+        to_repr(self, &Symbol::id, 0).map_err(Se::Error::custom)?.serialize(serializer)
+    }
+}
+
+impl <'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        let repr = Repr::<u32>::deserialize(deserializer)?;
+        from_repr(repr, &mut Symbol::from_id).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes a value with its symbols written by name.
+pub struct WithInterner<'a> {
+    pub value: &'a Value,
+    pub interner: &'a SymbolIntern,
+}
+
+impl <'a> Serialize for WithInterner<'a> {
+    fn serialize<Se: Serializer>(&self, serializer: Se) -> Result<Se::Ok, Se::Error> {
+        let interner = self.interner;
+        to_repr(self.value, &|s| interner.lookup_or_anon(s), 0)
+            .map_err(Se::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+/// Deserializes a value written by `WithInterner`, interning the names
+/// of its symbols.
+pub struct ValueSeed<'a> {
+    pub interner: &'a mut SymbolIntern,
+}
+
+impl <'a, 'de> DeserializeSeed<'de> for ValueSeed<'a> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        let repr = Repr::<String>::deserialize(deserializer)?;
+        let interner = self.interner;
+        from_repr(repr, &mut |name| interner.intern(name)).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(all(test, feature = "serde_json"))]
+mod test {
+    use util::collections::HashMap;
+    use serde::de::DeserializeSeed;
+    use serde_json;
+    use ares_syntax::SymbolIntern;
+    use vm::{Rational, Value};
+    use super::{WithInterner, ValueSeed};
+
+    #[test]
+    fn round_trip_by_name() {
+        let mut interner = SymbolIntern::new();
+        let mut map = HashMap::new();
+        map.insert(Value::Symbol(interner.intern("key")), vec![Value::Int(1), "two".into()].into());
+        let value: Value = map.into();
+
+        let text = serde_json::to_string(&WithInterner { value: &value, interner: &interner }).unwrap();
+
+        let mut fresh = SymbolIntern::new();
+        fresh.intern("shifts the ids");
+        let mut de = serde_json::Deserializer::from_str(&text);
+        let back = ValueSeed { interner: &mut fresh }.deserialize(&mut de).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert(Value::Symbol(fresh.intern("key")), vec![Value::Int(1), "two".into()].into());
+        assert_eq!(back, expected.into());
+    }
+
+    #[test]
+    fn round_trip_by_id() {
+        let mut interner = SymbolIntern::new();
+        let value: Value = vec![Value::Symbol(interner.intern("a")), Value::Float(1.5), Value::Nil].into();
+        let text = serde_json::to_string(&value).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn malformed_numbers_are_errors() {
+        assert!(serde_json::from_str::<Value>("{\"Rational\":[1,0]}").is_err());
+        assert!(serde_json::from_str::<Value>("{\"List\":[{\"Rational\":[1,0]}]}").is_err());
+        assert_eq!(serde_json::from_str::<Value>("{\"Rational\":[2,4]}").unwrap(),
+                   Rational::new(1, 2).unwrap().into_value());
+        #[cfg(feature = "decimal")]
+        assert!(serde_json::from_str::<Value>("{\"Decimal\":\"1.2.3\"}").is_err());
+    }
+}
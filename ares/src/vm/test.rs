@@ -417,6 +417,10 @@ fn basic_lambdas() {
         has_rest_params: false,
         is_shifter: false,
         namespace: Default::default(),
+        name: None,
+        position: None,
+        span: None,
+        parent: None,
     });
 
     vm.load_and_execute(&[
@@ -442,6 +446,10 @@ fn one_arg_lambda() {
         has_rest_params: false,
         is_shifter: false,
         namespace: Default::default(),
+        name: None,
+        position: None,
+        span: None,
+        parent: None,
     });
 
     vm.load_and_execute(&[
@@ -457,3 +465,223 @@ fn one_arg_lambda() {
     let result = vm.stack.pop().unwrap();
     assert_eq!(result, 100.into());
 }
+
+#[test]
+fn substrings() {
+    let whole: Value = "hello world".into();
+    let hello = whole.substring(0, 5).unwrap();
+    let world = whole.substring(6, 11).unwrap();
+    assert_eq!(hello, "hello".into());
+    assert_eq!(Value::from("world"), world);
+    assert_eq!(world.substring(1, 3).unwrap(), "or".into());
+    assert!(whole.substring(6, 12).is_err());
+
+    // Substrings point back into the original string
+    let source = whole.expect_string_ref().unwrap();
+    let sub = world.expect_str().unwrap();
+    assert_eq!(sub.as_ptr() as usize, source.as_ptr() as usize + 6);
+
+    let pieces = whole.split(" ").unwrap();
+    assert_eq!(pieces, vec![hello.clone(), world.clone()]);
+
+    // Substrings hash the same as an equal string
+    let mut map = ::util::collections::HashMap::new();
+    map.insert(Value::from("hello"), 1);
+    assert_eq!(map.get(&hello), Some(&1));
+}
+
+#[test]
+fn wrong_arity() {
+    let mut vm = Vm::<()>::new();
+    let closure_class_id = vm.compile_context.add_closure_class(ClosureClass {
+        code_offset: 5,
+        arg_count: 1,
+        local_defines_count: 0,
+        upvars_count: 0,
+        has_rest_params: false,
+        is_shifter: false,
+        namespace: Default::default(),
+        name: None,
+        position: None,
+        span: None,
+        parent: None,
+    });
+
+    let result = vm.load_and_execute(&[
+        Instr::IntLit(10),
+        Instr::IntLit(20),
+        Instr::CreateClosure(closure_class_id as u32),
+        Instr::Execute(2),
+        Instr::Jump(7),
+        Instr::Dup(0),
+        Instr::Ret
+    ], 0, &mut ());
+
+    assert_eq!(result, Err(InterpError::WrongArity {
+        got: 2,
+        expected: 1,
+        span: None,
+    }));
+}
+
+#[test]
+fn rest_params() {
+    let mut vm = Vm::<()>::new();
+    let closure_class_id = vm.compile_context.add_closure_class(ClosureClass {
+        code_offset: 6,
+        arg_count: 2,
+        local_defines_count: 0,
+        upvars_count: 0,
+        has_rest_params: true,
+        is_shifter: false,
+        namespace: Default::default(),
+        name: None,
+        position: None,
+        span: None,
+        parent: None,
+    });
+
+    vm.load_and_execute(&[
+        Instr::IntLit(10),
+        Instr::IntLit(20),
+        Instr::IntLit(30),
+        Instr::CreateClosure(closure_class_id as u32),
+        Instr::Execute(3),
+        Instr::Jump(8),
+        Instr::Dup(1),
+        Instr::Ret
+    ], 0, &mut ()).unwrap();
+
+    let result = vm.stack.pop().unwrap();
+    assert_eq!(result, vec![20, 30].into());
+}
+
+#[test]
+fn maps_change_in_place() {
+    let map = Value::map_from_iter(vec![("a", 1)]);
+    let shared = map.clone();
+    let pairs = map.expect_map_ref().unwrap();
+
+    assert_eq!(pairs.insert("b".into(), 2.into()), Ok(None));
+    assert_eq!(pairs.remove(&"a".into()), Ok(Some(1.into())));
+    pairs.entry("b".into(), || Value::Int(0), |v| *v = Value::Int(*v.expect_int_ref().unwrap() + 1)).unwrap();
+    pairs.entry("c".into(), || Value::Int(0), |_| ()).unwrap();
+
+    assert_eq!(pairs.len(), 2);
+    assert_eq!(shared.expect_map_ref().unwrap().get(&"b".into()), Some(3.into()));
+    assert_eq!(shared, Value::map_from_iter(vec![("b", 3), ("c", 0)]));
+}
+
+#[test]
+fn maps_can_contain_themselves() {
+    let make = || {
+        let map = Value::map_from_iter(vec![("a", 1)]);
+        map.expect_map_ref().unwrap().insert("self".into(), map.clone()).unwrap();
+        map
+    };
+    let (a, b) = (make(), make());
+    assert_eq!(a, b);
+    let interner = SymbolIntern::new();
+    assert_eq!(to_string_ordered(&a, &interner), to_string_ordered(&b, &interner));
+    assert!(to_string_ordered(&a, &interner).contains("{ ... }"));
+
+    let copy = a.deep_copy();
+    assert_eq!(copy, a);
+    let inner = copy.expect_map_ref().unwrap().get(&"self".into()).unwrap();
+    let address = |map: &Value| &**map.expect_map_ref().unwrap() as *const MapWrapper;
+    assert_eq!(address(&inner), address(&copy));
+    assert!(address(&copy) != address(&a));
+
+    // Using `a` as a key hashes it and freezes it, and the maps inside it.
+    let outer = Value::map_from_iter(vec![(a.clone(), 1)]);
+    assert_eq!(outer.expect_map_ref().unwrap().get(&b), Some(1.into()));
+    let frozen = a.expect_map_ref().unwrap();
+    assert!(frozen.is_frozen());
+    assert_eq!(frozen.insert("b".into(), 2.into()), Err(InterpError::MutatedMapKey));
+    assert_eq!(frozen.remove(&"a".into()), Err(InterpError::MutatedMapKey));
+    assert!(copy.expect_map_ref().unwrap().insert("b".into(), 2.into()).is_ok());
+}
+
+#[test]
+fn value_constructors() {
+    let mut interner = SymbolIntern::new();
+    let name = Value::symbol(&mut interner, "name");
+    assert_eq!(name, Value::Symbol(interner.intern("name")));
+
+    let value = Value::map(vec![(name.clone(), Value::list(vec![Value::string("x"), 1.into()]))]);
+    assert_eq!(to_string_helper(&value, &interner), "{'name, [x, 1]}");
+}
+
+#[test]
+fn sorted_map_pairs() {
+    let interner = SymbolIntern::new();
+    let map = Value::map(vec![(Value::string("b"), Value::Int(1)), (Value::Int(2), Value::Int(2)),
+                              (Value::string("a"), Value::Int(3)), (Value::Nil, Value::Int(4)),
+                              (Value::Int(1), Value::Int(5))]);
+    let pairs = map.expect_map_ref().unwrap().borrow();
+    let keys: Vec<Value> = pairs.sorted().into_iter().map(|(k, _)| k.clone()).collect();
+    assert_eq!(keys, vec![Value::Nil, 1.into(), 2.into(), Value::string("a"), Value::string("b")]);
+    assert_eq!(to_string_ordered(&map, &interner), "{nil, 41, 52, 2a, 3b, 1}");
+}
+
+#[test]
+fn numeric_promotion() {
+    let interner = SymbolIntern::new();
+    let numbers = [Value::Int(2), Value::Float(0.5), Rational::new(1, 4).unwrap().into_value()];
+    // Rows are the left operand and columns the right, in the order of
+    // `numbers`.
+    let expected = [
+        (Arith::Add, [["Int 4", "Float 2.5", "Rational 9/4"],
+                      ["Float 2.5", "Float 1", "Float 0.75"],
+                      ["Rational 9/4", "Float 0.75", "Rational 1/2"]]),
+        (Arith::Sub, [["Int 0", "Float 1.5", "Rational 7/4"],
+                      ["Float -1.5", "Float 0", "Float 0.25"],
+                      ["Rational -7/4", "Float -0.25", "Int 0"]]),
+        (Arith::Mul, [["Int 4", "Float 1", "Rational 1/2"],
+                      ["Float 1", "Float 0.25", "Float 0.125"],
+                      ["Rational 1/2", "Float 0.125", "Rational 1/16"]]),
+        (Arith::Div, [["Int 1", "Float 4", "Int 8"],
+                      ["Float 0.25", "Float 1", "Float 2"],
+                      ["Rational 1/8", "Float 0.5", "Int 1"]]),
+    ];
+    for &(op, ref table) in &expected {
+        for (row, a) in table.iter().zip(&numbers) {
+            for (cell, b) in row.iter().zip(&numbers) {
+                let result = arith(op, a, b).unwrap();
+                assert_eq!(format!("{:?} {}", result.kind(), to_string_helper(&result, &interner)), *cell);
+            }
+        }
+    }
+
+    assert_eq!(arith(Arith::Add, &Value::Int(i64::max_value()), &Value::Int(1)), Err(InterpError::IntegerOverflow));
+    assert_eq!(arith(Arith::Div, &Value::Int(1), &Value::Int(0)), Err(InterpError::DivideByZero));
+    assert_eq!(arith(Arith::Div, &numbers[2], &Value::Int(0)), Err(InterpError::DivideByZero));
+    assert_eq!(arith(Arith::Div, &Value::Float(1.0), &Value::Int(0)), Ok(Value::Float(::std::f64::INFINITY)));
+    for number in &numbers {
+        assert!(match arith(Arith::Add, number, &Value::Bool(true)) {
+            Err(InterpError::MismatchedType { value: Value::Bool(true), .. }) => true,
+            _ => false,
+        });
+    }
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn decimal_promotion() {
+    let price = Decimal::parse("1.25").unwrap().into_value();
+    assert_eq!(arith(Arith::Mul, &price, &Value::Int(2)), Ok(Decimal::parse("2.50").unwrap().into_value()));
+    assert_eq!(arith(Arith::Sub, &Value::Int(2), &price), Ok(Decimal::parse("0.75").unwrap().into_value()));
+
+    let coercion = |left, right| -> Result<Value, InterpError> {
+        Err(InterpError::NumericCoercion { left: left, right: right })
+    };
+    let third = Rational::new(1, 3).unwrap().into_value();
+    for &op in &[Arith::Add, Arith::Sub, Arith::Mul, Arith::Div] {
+        assert_eq!(arith(op, &price, &Value::Float(0.5)), coercion(ValueKind::Decimal, ValueKind::Float));
+        assert_eq!(arith(op, &Value::Float(0.5), &price), coercion(ValueKind::Float, ValueKind::Decimal));
+        assert_eq!(arith(op, &price, &third), coercion(ValueKind::Decimal, ValueKind::Rational));
+        assert_eq!(arith(op, &third, &price), coercion(ValueKind::Rational, ValueKind::Decimal));
+    }
+    assert_eq!(compare(&price, Value::Float(0.5), |a, b| a < b, |a, b| a < b),
+               Err(InterpError::NumericCoercion { left: ValueKind::Decimal, right: ValueKind::Float }));
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+/// Where the vm gets the current time from.  Hosts replace the clock to
+/// control what scripts see, or on targets without a system clock.
+pub trait Clock {
+    /// The time since the unix epoch.
+    fn now(&self) -> Duration;
+
+    /// Whether the clock reports the same times every time a script is
+    /// run.  `now` doesn't use clocks that don't while
+    /// `VmOptions::deterministic` is set.
+    fn is_deterministic(&self) -> bool {
+        true
+    }
+}
+
+/// The clock of the platform.  On `wasm32` it uses `Date.now()` when the
+/// `wasm` feature is enabled and is stuck at the epoch otherwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        system_now()
+    }
+
+    fn is_deterministic(&self) -> bool {
+        false
+    }
+}
+
+/// A clock that is stopped at one time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedClock(pub Duration);
+
+impl Clock for FixedClock {
+    fn now(&self) -> Duration {
+        self.0
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn system_now() -> Duration {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+fn system_now() -> Duration {
+    let millis = ::js_sys::Date::now();
+    Duration::from_millis(millis as u64)
+}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+fn system_now() -> Duration {
+    Duration::from_secs(0)
+}
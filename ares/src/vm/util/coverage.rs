@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use ares_syntax::Span;
+
+/// Counts how many times each instruction has been executed.
+#[derive(Debug)]
+pub struct Coverage {
+    counts: Vec<u32>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage { counts: vec![] }
+    }
+
+    /// Makes room for counts for `code_len` instructions.
+    pub fn grow_to(&mut self, code_len: usize) {
+        if self.counts.len() < code_len {
+            self.counts.resize(code_len, 0);
+        }
+    }
+
+    pub fn hit(&mut self, code_pos: usize) {
+        self.grow_to(code_pos + 1);
+        self.counts[code_pos] += 1;
+    }
+
+    pub fn count(&self, code_pos: usize) -> u32 {
+        self.counts.get(code_pos).cloned().unwrap_or(0)
+    }
+
+    /// Pairs every span that was compiled with the number of times that
+    /// the code for it started running.
+    pub fn report(&self, spans: &[(u32, Span)]) -> CoverageReport {
+        CoverageReport {
+            spans: spans.iter().map(|&(pos, span)| (span, self.count(pos as usize))).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageReport {
+    /// Every compiled span along with how many times it was executed.
+    pub spans: Vec<(Span, u32)>,
+}
+
+impl CoverageReport {
+    /// The spans that never ran.
+    pub fn uncovered(&self) -> Vec<Span> {
+        self.spans.iter().filter(|&&(_, count)| count == 0).map(|&(span, _)| span).collect()
+    }
+
+    /// Maps each line (starting at 1) of `source` that contains the start
+    /// of a span to the most number of times that any code starting on
+    /// that line ran.  Lines without any code are left out.
+    pub fn lines(&self, source: &str) -> BTreeMap<u32, u32> {
+        let mut lines = BTreeMap::new();
+        for &(span, count) in &self.spans {
+            let line = span.start_position(source).line();
+            let entry = lines.entry(line).or_insert(0);
+            if count > *entry {
+                *entry = count;
+            }
+        }
+        lines
+    }
+}
+
+#[test]
+fn line_report() {
+    let mut coverage = Coverage::new();
+    coverage.hit(0);
+    coverage.hit(0);
+    coverage.hit(3);
+
+    let source = "a;\nb;\nc;";
+    let spans = [(0, Span::new(0, 1)), (3, Span::new(3, 4)), (5, Span::new(6, 7))];
+    let report = coverage.report(&spans);
+    assert_eq!(report.uncovered(), vec![Span::new(6, 7)]);
+
+    let lines: Vec<(u32, u32)> = report.lines(source).into_iter().collect();
+    assert_eq!(lines, vec![(1, 2), (2, 1), (3, 0)]);
+}
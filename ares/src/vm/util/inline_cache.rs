@@ -0,0 +1,103 @@
+use ares_syntax::Symbol;
+use vm::Modules;
+
+#[derive(Debug, Copy, Clone)]
+struct CacheEntry {
+    namespace: Symbol,
+    slot: usize,
+    version: u64,
+}
+
+/// Per-instruction caches for global variable lookups.
+///
+/// Each `GetGlobal` and `PutGlobal` instruction remembers the slot
+/// that it found its global in last time, along with the version of
+/// `Modules` at the time.  As long as the version (and namespace) still
+/// match, the slot can be used without searching for the global again.
+#[derive(Debug)]
+pub struct InlineCaches {
+    entries: Vec<Option<CacheEntry>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl InlineCaches {
+    pub fn new() -> InlineCaches {
+        InlineCaches {
+            entries: vec![],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Makes room for caches for `code_len` instructions.
+    pub fn grow_to(&mut self, code_len: usize) {
+        if self.entries.len() < code_len {
+            self.entries.resize(code_len, None);
+        }
+    }
+
+    /// Finds the slot for a global that is accessed by the instruction at `code_pos`.
+    pub fn lookup(&mut self,
+                  code_pos: usize,
+                  modules: &Modules,
+                  namespace: Symbol,
+                  name: Symbol)
+                  -> Option<usize> {
+        self.grow_to(code_pos + 1);
+        if let Some(entry) = self.entries[code_pos] {
+            if entry.version == modules.version() && entry.namespace == namespace {
+                self.hits += 1;
+                return Some(entry.slot);
+            }
+        }
+
+        self.misses += 1;
+        let slot = modules.slot_of(namespace, name);
+        self.entries[code_pos] = slot.map(|slot| CacheEntry {
+            namespace: namespace,
+            slot: slot,
+            version: modules.version(),
+        });
+        slot
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+#[test]
+fn inline_cache_hits_until_layout_changes() {
+    use ares_syntax::SymbolIntern;
+    use vm::Value;
+
+    let mut interner = SymbolIntern::new();
+    let ns = interner.precomputed.default_namespace;
+    let foo = interner.intern("foo");
+    let bar = interner.intern("bar");
+
+    let mut modules = Modules::new();
+    let mut caches = InlineCaches::new();
+    modules.set(ns, foo, Value::Int(1));
+
+    assert_eq!(caches.lookup(0, &modules, ns, foo), Some(0));
+    assert_eq!(caches.lookup(0, &modules, ns, foo), Some(0));
+    assert_eq!((caches.hits(), caches.misses()), (1, 1));
+
+    // Overwriting an existing global doesn't move it.
+    modules.set(ns, foo, Value::Int(2));
+    assert_eq!(caches.lookup(0, &modules, ns, foo), Some(0));
+    assert_eq!((caches.hits(), caches.misses()), (2, 1));
+
+    // Adding a new global invalidates the cache.
+    modules.set(ns, bar, Value::Int(3));
+    assert_eq!(caches.lookup(0, &modules, ns, foo), Some(0));
+    assert_eq!((caches.hits(), caches.misses()), (2, 2));
+
+    assert_eq!(caches.lookup(1, &modules, ns, interner.intern("baz")), None);
+}
@@ -1,2 +1,8 @@
 pub mod stack;
 pub mod module;
+pub mod inline_cache;
+pub mod coverage;
+pub mod random;
+pub mod clock;
+pub mod replay;
+pub mod resources;
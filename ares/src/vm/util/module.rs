@@ -1,6 +1,6 @@
 use vm::*;
 use ares_syntax::*;
-use std::collections::HashMap;
+use util::collections::HashMap;
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
 struct GlobalName {
@@ -11,17 +11,63 @@ struct GlobalName {
 #[derive(Debug)]
 pub struct Modules {
     namespace_to_src: HashMap<Symbol, Option<String>>,
-    globals: Vec<(GlobalName, Value)>
+    globals: Vec<(GlobalName, Value)>,
+    // Incremented every time that the layout of `globals` changes so that
+    // cached slot indices can be checked for staleness.
+    version: u64,
 }
 
 impl Modules {
     pub fn new() -> Modules {
         Modules {
             namespace_to_src: HashMap::new(),
-            globals: vec![]
+            globals: vec![],
+            version: 0,
         }
     }
 
+    /// Copies every global with `Value::deep_copy`.
+    pub fn deep_copy(&self) -> Modules {
+        let mut copies = HashMap::new();
+        Modules {
+            namespace_to_src: self.namespace_to_src.clone(),
+            globals: self.globals.iter()
+                .map(|&(ref name, ref value)| {
+                    (GlobalName { namespace: name.namespace, name: name.name }, value.deep_copy_with(&mut copies))
+                })
+                .collect(),
+            version: self.version,
+        }
+    }
+
+    /// Replaces every global with a copy of the globals in `other`.  The
+    /// version always changes so that cached slots are looked up again.
+    pub fn restore_from(&mut self, other: &Modules) {
+        let version = ::std::cmp::max(self.version, other.version) + 1;
+        *self = other.deep_copy();
+        self.version = version;
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Finds the slot that a global is stored in.  The slot is valid
+    /// for as long as `version()` doesn't change.
+    pub fn slot_of(&self, namespace: Symbol, name: Symbol) -> Option<usize> {
+        self.globals.iter().position(|&(ref global_name, _)| {
+            global_name.namespace == namespace && global_name.name == name
+        })
+    }
+
+    pub fn get_slot(&self, slot: usize) -> &Value {
+        &self.globals[slot].1
+    }
+
+    pub fn get_slot_mut(&mut self, slot: usize) -> &mut Value {
+        &mut self.globals[slot].1
+    }
+
     pub fn load_library(&mut self, name: String, version: String, source: String, interner: &mut SymbolIntern) -> Symbol {
         let symbol = interner.intern(format!("{}@{}", name, version));
         if !self.namespace_to_src.contains_key(&symbol) {
@@ -44,6 +90,14 @@ impl Modules {
         }
     }
 
+    /// The names of every global that is defined in `namespace`.
+    pub fn names_in(&self, namespace: Symbol) -> Vec<Symbol> {
+        self.globals.iter()
+                    .filter(|&&(ref global_name, _)| global_name.namespace == namespace)
+                    .map(|&(ref global_name, _)| global_name.name)
+                    .collect()
+    }
+
     pub fn is_defined(&self, namespace: Symbol, name: Symbol) -> bool {
         self.get(namespace, name).is_some()
     }
@@ -81,6 +135,7 @@ impl Modules {
             namespace: namespace,
             name: name
         }, value));
+        self.version += 1;
         return None;
     }
 }
@@ -0,0 +1,50 @@
+use vm::util::clock::{Clock, SystemClock};
+
+/// The xorshift64* generator behind the random builtins.  It is fast and
+/// reproducible, but not suitable for anything security related.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // Run the seed through splitmix64 so that small seeds give
+        // unrelated sequences and the state is never zero.
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        Rng { state: if z == 0 { 1 } else { z } }
+    }
+
+    /// Seeds the generator from the current time.
+    pub fn from_time() -> Rng {
+        let now = SystemClock.now();
+        Rng::new(now.as_secs() ^ (now.subsec_nanos() as u64) << 32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer in `[0, bound)`.  `bound` must not be zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Reject the top of the range so that every result is equally likely.
+        let zone = ::std::u64::MAX - ::std::u64::MAX % bound;
+        loop {
+            let n = self.next_u64();
+            if n < zone {
+                return n % bound;
+            }
+        }
+    }
+}
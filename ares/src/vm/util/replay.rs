@@ -0,0 +1,107 @@
+use vm::{InterpError, Value};
+
+/// One call to a native function, with the value that it returned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayEntry {
+    pub name: Option<String>,
+    pub result: Value,
+}
+
+/// Every value that came into a script from native functions, in the
+/// order that the calls happened.  The clock and the random generator are
+/// only seen through native functions, so they are covered too.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayLog {
+    pub entries: Vec<ReplayEntry>,
+}
+
+impl ReplayLog {
+    pub fn new() -> ReplayLog {
+        ReplayLog::default()
+    }
+
+    /// The log as a list of `[name, result]` pairs, with `nil` for
+    /// functions that have no name.  The list can be saved with
+    /// `host::write_value` as long as every result can.
+    pub fn to_value(&self) -> Value {
+        let entries: Vec<Value> = self.entries.iter().map(|entry| {
+            let name = entry.name.as_ref().map_or(Value::Nil, |n| Value::string(n.clone()));
+            Value::list(vec![name, entry.result.clone()])
+        }).collect();
+        Value::list(entries)
+    }
+
+    /// Reads a log written by `to_value`.  Returns `None` if `value`
+    /// isn't shaped like one.
+    pub fn from_value(value: &Value) -> Option<ReplayLog> {
+        let mut log = ReplayLog::new();
+        for entry in value.expect_list_ref().ok()?.iter() {
+            let pair = entry.expect_list_ref().ok()?;
+            if pair.len() != 2 {
+                return None;
+            }
+            let name = match pair[0] {
+                Value::Nil => None,
+                ref name => Some(name.expect_str().ok()?.to_string()),
+            };
+            log.entries.push(ReplayEntry { name: name, result: pair[1].clone() });
+        }
+        Some(log)
+    }
+}
+
+/// Whether the results of native functions are being recorded, or fed
+/// back from a log instead of calling the functions.
+#[derive(Debug)]
+pub(crate) enum Replay {
+    Off,
+    Recording(ReplayLog),
+    Replaying {
+        log: ReplayLog,
+        /// The index of the entry for the next call.
+        next: usize,
+    },
+}
+
+impl Default for Replay {
+    fn default() -> Replay {
+        Replay::Off
+    }
+}
+
+impl Replay {
+    /// While replaying, returns the result that the next call returned
+    /// when it was recorded.  Calls that don't line up with the log mean
+    /// that the script has taken a different path than the one recorded.
+    pub(crate) fn replayed(&mut self, name: Option<&str>) -> Result<Option<Value>, InterpError> {
+        match self {
+            &mut Replay::Replaying { ref log, ref mut next } => {
+                let entry = match log.entries.get(*next) {
+                    Some(entry) => entry,
+                    None => return Err(InterpError::ReplayDiverged {
+                        expected: None,
+                        got: name.map(String::from),
+                    }),
+                };
+                if entry.name.as_ref().map(|n| &n[..]) != name {
+                    return Err(InterpError::ReplayDiverged {
+                        expected: entry.name.clone(),
+                        got: name.map(String::from),
+                    });
+                }
+                *next += 1;
+                Ok(Some(entry.result.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn record(&mut self, name: Option<&str>, result: &Value) {
+        if let &mut Replay::Recording(ref mut log) = self {
+            log.entries.push(ReplayEntry {
+                name: name.map(String::from),
+                result: result.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::any::Any;
+use std::fmt;
+
+use util::collections::HashMap;
+use vm::Value;
+
+/// Host objects that scripts hold on to through `Value::Handle`s, so that
+/// the objects themselves never have to live in the gc heap.
+///
+/// Ids are never reused, which means that a handle to a resource that has
+/// been removed stays invalid instead of pointing at a newer resource.
+#[derive(Default)]
+pub struct Resources {
+    next_id: u32,
+    entries: HashMap<u32, Box<Any>>,
+}
+
+impl Resources {
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// Stores `resource` and returns a handle to it.
+    pub fn insert<T: Any>(&mut self, resource: T) -> Value {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).expect("ran out of resource handles");
+        self.entries.insert(id, Box::new(resource));
+        Value::Handle(id)
+    }
+
+    /// The resource behind `handle`, if it is still alive and is a `T`.
+    pub fn get<T: Any>(&self, handle: &Value) -> Option<&T> {
+        match handle {
+            &Value::Handle(id) => self.entries.get(&id).and_then(|r| r.downcast_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut<T: Any>(&mut self, handle: &Value) -> Option<&mut T> {
+        match handle {
+            &Value::Handle(id) => self.entries.get_mut(&id).and_then(|r| r.downcast_mut()),
+            _ => None,
+        }
+    }
+
+    /// Takes the resource behind `handle` out of the table, invalidating
+    /// every copy of the handle.  Nothing is removed unless the resource
+    /// is a `T`.
+    pub fn remove<T: Any>(&mut self, handle: &Value) -> Option<T> {
+        let id = match handle {
+            &Value::Handle(id) if self.get::<T>(handle).is_some() => id,
+            _ => return None,
+        };
+        self.entries.remove(&id).and_then(|r| r.downcast().ok()).map(|r| *r)
+    }
+
+    pub fn contains(&self, handle: &Value) -> bool {
+        match handle {
+            &Value::Handle(id) => self.entries.contains_key(&id),
+            _ => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The id that the next inserted resource will get.  Every resource
+    /// inserted after this is called has an id at least this large.
+    pub(crate) fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// Removes every resource that was inserted since `next_id` returned
+    /// `id`.  Ids keep counting up, so none of them are handed out again.
+    pub(crate) fn remove_since(&mut self, id: u32) {
+        self.entries.retain(|&entry, _| entry < id);
+    }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Resources")
+         .field("next_id", &self.next_id)
+         .field("live", &self.entries.len())
+         .finish()
+    }
+}
+
+#[test]
+fn handles_are_typed_and_invalidated() {
+    let mut resources = Resources::new();
+    let a = resources.insert(String::from("file"));
+    let b = resources.insert(5u8);
+
+    assert_eq!(resources.get::<String>(&a).map(|s| &s[..]), Some("file"));
+    assert_eq!(resources.get::<u8>(&a), None);
+    *resources.get_mut::<u8>(&b).unwrap() += 1;
+
+    assert_eq!(resources.remove::<String>(&b), None);
+    assert_eq!(resources.remove::<u8>(&b), Some(6));
+    assert!(!resources.contains(&b));
+
+    let c = resources.insert(7u8);
+    assert!(c != b);
+    assert_eq!(resources.get::<u8>(&b), None);
+    assert_eq!(resources.len(), 2);
+}
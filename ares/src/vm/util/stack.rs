@@ -21,11 +21,12 @@ impl ::std::fmt::Debug for Stack {
 
 impl Stack {
     pub fn new() -> Stack {
-        use std::mem::{transmute, size_of};
-        use libc::malloc;
+        use std::mem;
         let capacity = 1000;
-        let allocated_size = size_of::<Value>() * capacity;
-        let ptr = unsafe { transmute(malloc(allocated_size)) };
+        // The buffer is managed by hand from here on.
+        let mut buffer: Vec<Value> = Vec::with_capacity(capacity);
+        let ptr = buffer.as_mut_ptr();
+        mem::forget(buffer);
 
         Stack {
             capacity: capacity,
@@ -179,10 +180,18 @@ impl Stack {
     }
 
     #[inline(always)]
-    pub fn binop_int<F: FnOnce(i64, i64) -> i64>(&mut self, f: F) -> Result<(), InterpError> {
+    pub fn binop_int<F: FnOnce(i64, i64) -> Result<i64, InterpError>>(&mut self, f: F) -> Result<(), InterpError> {
         let a = try!(try!(self.pop()).expect_int());
         let b = try!(try!(self.peek()).expect_int_mut());
-        *b = f(*b, a);
+        *b = try!(f(*b, a));
+        Ok(())
+    }
+
+    /// Replaces the top two values with `f(second, top)`.
+    pub fn binop<F: FnOnce(&Value, &Value) -> Result<Value, InterpError>>(&mut self, f: F) -> Result<(), InterpError> {
+        let a = try!(self.pop());
+        let b = try!(self.peek());
+        *b = try!(f(b, &a));
         Ok(())
     }
 
@@ -1,9 +1,13 @@
-use gc::{Gc, Trace, GcCell};
-use std::ops::Deref;
-use std::collections::HashMap;
+use gc::{Gc, Trace, GcCell, GcCellRef};
+use std::cmp::Ordering;
+use std::ops::{Deref, DerefMut};
+use util::collections::{HashMap, HashSet};
 use ares_syntax::{Symbol, SymbolIntern};
-use vm::{InterpError, Closure, Continuation};
+use vm::{InterpError, Closure, Continuation, Substring, Rational};
+#[cfg(feature = "decimal")]
+use vm::Decimal;
 use vm::concept::function::UserFunction;
+use vm::concept::string::char_boundaries;
 
 macro_rules! gen_expect {
     ($self_fn: ident, $ref_fn: ident, $mut_fn: ident, $selector: path, $out: ty, $expected: expr) => {
@@ -61,17 +65,24 @@ pub enum Value {
     List(Gc<Vec<Value>>),
     Map(Gc<MapWrapper>),
     String(Gc<String>),
+    Substring(Gc<Substring>),
     Float(f64),
     Int(i64),
+    /// Never has a denominator of 1; see `Rational::into_value`.
+    Rational(Gc<Rational>),
+    #[cfg(feature = "decimal")]
+    Decimal(Gc<Decimal>),
     Bool(bool),
     Symbol(Symbol),
     Closure(Gc<Closure>),
     UserFn(Gc<GcCell<UserFunction<()>>>),
     Cell(Gc<GcCell<Value>>),
     Continuation(Gc<Continuation>),
+    /// Refers to a host object in the vm's `Resources`.
+    Handle(u32),
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum ValueKind {
     Nil,
     List,
@@ -79,16 +90,33 @@ pub enum ValueKind {
     String,
     Float,
     Int,
+    Rational,
+    #[cfg(feature = "decimal")]
+    Decimal,
     Bool,
     Symbol,
     Closure,
     UserFn,
     Cell,
     Continuation,
+    Handle,
 }
 
+/// The contents of a map.  Host code can change a map in place through
+/// `insert`, `remove` and `entry`.  The pairs live in a `GcCell`, which
+/// roots them while they are borrowed mutably, so values added after the
+/// map was allocated are still seen by the collector.
+///
+/// A map that is used as a key in another map is frozen, along with the
+/// maps inside of it, and can't be changed after that.  A map can contain
+/// itself, so comparing, hashing, copying and printing maps all stop when
+/// they come back around to a map they are already in.
+#[derive(Finalize)]
+pub struct MapWrapper(GcCell<Pairs>, ::std::cell::Cell<bool>);
+
+/// The pairs of a map, borrowed from a `MapWrapper`.
 #[derive(Debug, PartialEq, Finalize)]
-pub struct MapWrapper(HashMap<Value, Value>);
+pub struct Pairs(HashMap<Value, Value>);
 
 unsafe impl Trace for Value {
     custom_trace!(this, {
@@ -96,6 +124,10 @@ unsafe impl Trace for Value {
             &Value::List(ref gc) => mark(gc),
             &Value::Map(ref gc) => mark(gc),
             &Value::String(ref gc) => mark(gc),
+            &Value::Substring(ref gc) => mark(gc),
+            &Value::Rational(ref gc) => mark(gc),
+            #[cfg(feature = "decimal")]
+            &Value::Decimal(ref gc) => mark(gc),
             &Value::Closure(ref gc) => mark(gc),
             &Value::UserFn(ref gc) => mark(gc),
             &Value::Cell(ref gc) => mark(gc),
@@ -106,6 +138,12 @@ unsafe impl Trace for Value {
 }
 
 unsafe impl Trace for MapWrapper {
+    custom_trace!(this, {
+        mark(&this.0);
+    });
+}
+
+unsafe impl Trace for Pairs {
     custom_trace!(this, {
         for (k, v) in &this.0 {
             mark(k);
@@ -114,13 +152,198 @@ unsafe impl Trace for MapWrapper {
     });
 }
 
-impl Deref for MapWrapper {
+impl Deref for Pairs {
     type Target = HashMap<Value, Value>;
     fn deref(&self) -> &HashMap<Value, Value> {
         &self.0
     }
 }
 
+impl DerefMut for Pairs {
+    fn deref_mut(&mut self) -> &mut HashMap<Value, Value> {
+        &mut self.0
+    }
+}
+
+impl Pairs {
+    /// The pairs sorted by key, so that they come out in the same order no
+    /// matter how the map was built.  Keys that have no order between
+    /// them, like two closures, are left in the order of the map.
+    pub fn sorted(&self) -> Vec<(&Value, &Value)> {
+        let mut pairs: Vec<(&Value, &Value)> = self.0.iter().collect();
+        pairs.sort_by(|a, b| order_keys(a.0, b.0));
+        pairs
+    }
+}
+
+fn kind_rank(value: &Value) -> u8 {
+    match value.kind() {
+        ValueKind::Nil => 0,
+        ValueKind::Bool => 1,
+        ValueKind::Int => 2,
+        ValueKind::Float => 3,
+        ValueKind::Rational => 4,
+        #[cfg(feature = "decimal")]
+        ValueKind::Decimal => 5,
+        ValueKind::String => 6,
+        ValueKind::Symbol => 7,
+        ValueKind::List => 8,
+        ValueKind::Map => 9,
+        ValueKind::Cell => 10,
+        ValueKind::Handle => 11,
+        ValueKind::Closure => 12,
+        ValueKind::UserFn => 13,
+        ValueKind::Continuation => 14,
+    }
+}
+
+/// A total order over values of the same kind, used to sort map keys.
+/// Unlike `<` it never fails and doesn't compare ints with floats.
+fn order_keys(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (&Value::Bool(a), &Value::Bool(b)) => a.cmp(&b),
+        (&Value::Int(a), &Value::Int(b)) => a.cmp(&b),
+        (&Value::Float(a), &Value::Float(b)) =>
+            a.partial_cmp(&b).unwrap_or_else(|| a.to_bits().cmp(&b.to_bits())),
+        (&Value::Rational(ref a), &Value::Rational(ref b)) => (**a).cmp(&**b),
+        (&Value::Symbol(a), &Value::Symbol(b)) => a.cmp(&b),
+        (&Value::Handle(a), &Value::Handle(b)) => a.cmp(&b),
+        (&Value::List(ref a), &Value::List(ref b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                match order_keys(a, b) {
+                    Ordering::Equal => {}
+                    other => return other,
+                }
+            }
+            a.len().cmp(&b.len())
+        }
+        (&Value::Map(ref a), &Value::Map(ref b)) => compare_maps(a, b, Ordering::Equal, || {
+            let (a, b) = (a.borrow(), b.borrow());
+            for ((ak, av), (bk, bv)) in a.sorted().into_iter().zip(b.sorted()) {
+                match order_keys(ak, bk).then_with(|| order_keys(av, bv)) {
+                    Ordering::Equal => {}
+                    other => return other,
+                }
+            }
+            a.len().cmp(&b.len())
+        }),
+        (&Value::Cell(ref a), &Value::Cell(ref b)) => order_keys(&*a.borrow(), &*b.borrow()),
+        _ => match (a.expect_str(), b.expect_str()) {
+            (Ok(a), Ok(b)) => a.cmp(b),
+            _ => kind_rank(a).cmp(&kind_rank(b)),
+        },
+    }
+}
+
+/// Freezes the maps in `key`, which is about to be used as a key.
+fn freeze_key(key: &Value) {
+    match key {
+        &Value::Map(ref map) => map.freeze(),
+        &Value::List(ref items) => for item in items.iter() {
+            freeze_key(item);
+        },
+        _ => {}
+    }
+}
+
+thread_local! {
+    // The pairs of maps that are being compared, so that comparing two
+    // maps that contain themselves assumes that the inner maps are equal
+    // instead of comparing them again.
+    static COMPARING: ::std::cell::RefCell<HashSet<(usize, usize)>> =
+        ::std::cell::RefCell::new(HashSet::new());
+}
+
+/// Compares `a` with `b` using `compare`, or returns `same` if they are
+/// the same map or are already being compared further up.
+fn compare_maps<R, F>(a: &MapWrapper, b: &MapWrapper, same: R, compare: F) -> R
+where F: FnOnce() -> R {
+    let pair = (a as *const MapWrapper as usize, b as *const MapWrapper as usize);
+    if pair.0 == pair.1 || COMPARING.with(|c| c.borrow().contains(&pair)) {
+        return same;
+    }
+    COMPARING.with(|c| c.borrow_mut().insert(pair));
+    let result = compare();
+    COMPARING.with(|c| c.borrow_mut().remove(&pair));
+    result
+}
+
+impl MapWrapper {
+    pub fn new(pairs: HashMap<Value, Value>) -> MapWrapper {
+        for key in pairs.keys() {
+            freeze_key(key);
+        }
+        MapWrapper(GcCell::new(Pairs(pairs)), ::std::cell::Cell::new(false))
+    }
+
+    /// True if this map is a key of another map.
+    pub fn is_frozen(&self) -> bool {
+        self.1.get()
+    }
+
+    fn freeze(&self) {
+        if !self.1.replace(true) {
+            for (k, v) in self.0.borrow().iter() {
+                freeze_key(k);
+                freeze_key(v);
+            }
+        }
+    }
+
+    fn check_unfrozen(&self) -> Result<(), InterpError> {
+        if self.is_frozen() { Err(InterpError::MutatedMapKey) } else { Ok(()) }
+    }
+
+    /// Borrows the pairs of the map.  Panics if they are being changed.
+    pub fn borrow(&self) -> GcCellRef<Pairs> {
+        self.0.borrow()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    /// Looks up `key`, cloning the value stored under it.
+    pub fn get(&self, key: &Value) -> Option<Value> {
+        self.0.borrow().get(key).cloned()
+    }
+
+    /// Stores `value` under `key`, returning the value that was there.
+    /// Fails with `InterpError::MutatedMapKey` if the map is frozen.
+    pub fn insert(&self, key: Value, value: Value) -> Result<Option<Value>, InterpError> {
+        self.check_unfrozen()?;
+        freeze_key(&key);
+        Ok(self.0.borrow_mut().insert(key, value))
+    }
+
+    pub fn remove(&self, key: &Value) -> Result<Option<Value>, InterpError> {
+        self.check_unfrozen()?;
+        Ok(self.0.borrow_mut().remove(key))
+    }
+
+    /// Calls `f` with the value stored under `key`, storing `default()`
+    /// there first if the key is missing.
+    pub fn entry<D, F, R>(&self, key: Value, default: D, f: F) -> Result<R, InterpError>
+    where D: FnOnce() -> Value, F: FnOnce(&mut Value) -> R {
+        self.check_unfrozen()?;
+        freeze_key(&key);
+        let mut pairs = self.0.borrow_mut();
+        Ok(f(pairs.entry(key).or_insert_with(default)))
+    }
+}
+
+impl PartialEq for MapWrapper {
+    fn eq(&self, other: &MapWrapper) -> bool {
+        compare_maps(self, other, true, || *self.0.borrow() == *other.0.borrow())
+    }
+}
+
+impl ::std::fmt::Debug for MapWrapper {
+    fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
+        write!(formatter, "{:?}", *self.0.borrow())
+    }
+}
+
 impl ::std::fmt::Debug for Value {
     fn fmt(&self, formatter: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
         let empty_interner = SymbolIntern::new();
@@ -142,9 +365,17 @@ impl PartialEq for Value {
                 gc_to_usize(gc1) == gc_to_usize(gc2) || &**gc1 == &**gc2
             }
             (&String(ref gc1), &String(ref gc2)) => &**gc1 == &**gc2,
+            (&String(ref gc1), &Substring(ref gc2)) => &gc1[..] == gc2.as_str(),
+            (&Substring(ref gc1), &String(ref gc2)) => gc1.as_str() == &gc2[..],
+            (&Substring(ref gc1), &Substring(ref gc2)) => gc1.as_str() == gc2.as_str(),
             (&Float(f1), &Float(f2)) => f1 == f2,
             (&Int(i1), &Int(i2)) => i1 == i2,
+            (&Rational(ref r1), &Rational(ref r2)) => **r1 == **r2,
+            #[cfg(feature = "decimal")]
+            (&Decimal(ref d1), &Decimal(ref d2)) => **d1 == **d2,
+            (&Nil, &Nil) => true,
             (&Bool(b1), &Bool(b2)) => b1 == b2,
+            (&Handle(h1), &Handle(h2)) => h1 == h2,
             (&Symbol(ref id1), &Symbol(ref id2)) => id1 == id2,
             // (&Closure(ref l1, b1), &Closure(ref l2, b2)) => l1 == l2 && b1 == b2,
             (&Cell(ref c1), &Cell(ref c2)) => &*c1.borrow() == &*c2.borrow(),
@@ -168,18 +399,211 @@ impl Value {
         }
     }
 
+    /// The kind that `expect_*` would ask for to get at this value.
+    /// Substrings are `String`s.
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            &Value::Nil => ValueKind::Nil,
+            &Value::List(_) => ValueKind::List,
+            &Value::Map(_) => ValueKind::Map,
+            &Value::String(_) | &Value::Substring(_) => ValueKind::String,
+            &Value::Float(_) => ValueKind::Float,
+            &Value::Int(_) => ValueKind::Int,
+            &Value::Rational(_) => ValueKind::Rational,
+            #[cfg(feature = "decimal")]
+            &Value::Decimal(_) => ValueKind::Decimal,
+            &Value::Bool(_) => ValueKind::Bool,
+            &Value::Symbol(_) => ValueKind::Symbol,
+            &Value::Closure(_) => ValueKind::Closure,
+            &Value::UserFn(_) => ValueKind::UserFn,
+            &Value::Cell(_) => ValueKind::Cell,
+            &Value::Continuation(_) => ValueKind::Continuation,
+            &Value::Handle(_) => ValueKind::Handle,
+        }
+    }
+
+    /// Whether this value can be called: a closure, a native function or a
+    /// continuation, or a cell holding one.
+    pub fn is_callable(&self) -> bool {
+        match self {
+            &Value::Closure(_) | &Value::UserFn(_) | &Value::Continuation(_) => true,
+            &Value::Cell(ref c) => c.borrow().is_callable(),
+            _ => false,
+        }
+    }
+
     gen_expect!(expect_string, expect_string_ref, expect_string_mut, Value::String, Gc<String>, ValueKind::String);
     gen_expect!(expect_list, expect_list_ref, expect_list_mut, Value::List, Gc<Vec<Value>>, ValueKind::List);
     gen_expect!(expect_map, expect_map_ref, expect_map_mut, Value::Map, Gc<MapWrapper>, ValueKind::Map);
     gen_expect!(expect_float, expect_float_ref, expect_float_mut, Value::Float, f64, ValueKind::Float);
     gen_expect!(expect_int, expect_int_ref, expect_int_mut, Value::Int, i64, ValueKind::Int);
+    gen_expect!(expect_rational, expect_rational_ref, expect_rational_mut, Value::Rational, Gc<Rational>, ValueKind::Rational);
+    #[cfg(feature = "decimal")]
+    gen_expect!(expect_decimal, expect_decimal_ref, expect_decimal_mut, Value::Decimal, Gc<Decimal>, ValueKind::Decimal);
     gen_expect!(expect_bool, expect_bool_ref, expect_bool_mut, Value::Bool, bool, ValueKind::Bool);
     gen_expect!(expect_symbol, expect_symbol_ref, expect_symbol_mut, Value::Symbol, Symbol, ValueKind::Symbol);
     gen_expect!(expect_closure, expect_closure_ref, expect_closure_mut, Value::Closure, Gc<Closure>, ValueKind::Closure);
     gen_expect!(expect_user_fn, expect_user_fn_ref, expect_user_fn_mut, Value::UserFn, Gc<GcCell<UserFunction<()>>>, ValueKind::UserFn);
     gen_expect!(expect_cell, expect_cell_ref, expect_cell_mut, Value::Cell, Gc<GcCell<Value>>, ValueKind::Cell);
+    gen_expect!(expect_handle, expect_handle_ref, expect_handle_mut, Value::Handle, u32, ValueKind::Handle);
     gen_expect!(expect_continuation, expect_continuation_ref, expect_continuation_mut, Value::Continuation, Gc<Continuation>, ValueKind::Continuation);
 
+    pub fn list<T: Into<Value>>(items: Vec<T>) -> Value {
+        items.into()
+    }
+
+    /// Makes a map out of key/value pairs.  The same as `map_from_iter`.
+    pub fn map<I, K, V>(pairs: I) -> Value
+    where I: IntoIterator<Item=(K, V)>, K: Into<Value>, V: Into<Value> {
+        Value::map_from_iter(pairs)
+    }
+
+    pub fn string<S: Into<String>>(s: S) -> Value {
+        Value::String(Gc::new(s.into()))
+    }
+
+    /// The symbol called `name`, interning it if it hasn't been seen yet.
+    pub fn symbol(interner: &mut SymbolIntern, name: &str) -> Value {
+        Value::Symbol(interner.intern(name))
+    }
+
+    /// Makes a map out of key/value pairs.
+    pub fn map_from_iter<I, K, V>(pairs: I) -> Value
+    where I: IntoIterator<Item=(K, V)>, K: Into<Value>, V: Into<Value> {
+        let pairs = pairs.into_iter().map(|(k, v)| (k.into(), v.into())).collect();
+        Value::Map(Gc::new(MapWrapper::new(pairs)))
+    }
+
+    /// Borrows the text of a string or substring without copying it.
+    pub fn expect_str(&self) -> Result<&str, InterpError> {
+        match self {
+            &Value::String(ref s) => Ok(&s[..]),
+            &Value::Substring(ref s) => Ok(s.as_str()),
+            other => Err(InterpError::MismatchedType {
+                value: other.clone(),
+                expected: ValueKind::String,
+            })
+        }
+    }
+
+    /// Returns the bytes `start .. end` of a string or substring.  It's an
+    /// error, rather than a panic, for either end to be out of bounds or
+    /// inside of a character.
+    ///
+    /// The result shares storage with `self`, so no string data is copied.
+    pub fn substring(&self, start: usize, end: usize) -> Result<Value, InterpError> {
+        let out_of_bounds = |len| InterpError::StringIndexOutOfBounds {
+            start: start,
+            end: end,
+            len: len,
+        };
+
+        let sub = match self {
+            &Value::String(ref s) =>
+                Substring::new(s.clone(), start .. end).ok_or_else(|| out_of_bounds(s.len()))?,
+            &Value::Substring(ref s) =>
+                s.slice(start .. end).ok_or_else(|| out_of_bounds(s.len()))?,
+            other => return Err(InterpError::MismatchedType {
+                value: other.clone(),
+                expected: ValueKind::String,
+            })
+        };
+        Ok(Value::Substring(Gc::new(sub)))
+    }
+
+    /// The number of characters in a string or substring.  See
+    /// `notes/strings.md` for what a character is.
+    pub fn char_len(&self) -> Result<usize, InterpError> {
+        Ok(char_boundaries(self.expect_str()?).len() - 1)
+    }
+
+    /// Like `substring`, but `start` and `end` count characters instead
+    /// of bytes, so every range that is in bounds is valid.
+    pub fn char_substring(&self, start: usize, end: usize) -> Result<Value, InterpError> {
+        let boundaries = char_boundaries(self.expect_str()?);
+        match (boundaries.get(start), boundaries.get(end)) {
+            (Some(&from), Some(&to)) if start <= end => self.substring(from, to),
+            _ => Err(InterpError::StringIndexOutOfBounds {
+                start: start,
+                end: end,
+                len: boundaries.len() - 1,
+            }),
+        }
+    }
+
+    /// Splits a string or substring on `separator`.  Every piece is a
+    /// substring that shares storage with `self`.
+    pub fn split(&self, separator: &str) -> Result<Vec<Value>, InterpError> {
+        let text = self.expect_str()?;
+        let base = text.as_ptr() as usize;
+        let mut out = vec![];
+        for piece in text.split(separator) {
+            let start = piece.as_ptr() as usize - base;
+            out.push(self.substring(start, start + piece.len())?);
+        }
+        Ok(out)
+    }
+
+    /// Copies this value so that mutating a cell in the copy (or in
+    /// anything that the copy contains) doesn't affect the original.
+    /// Cells that are shared inside of `self` are still shared in the
+    /// copy.  Native functions are not copied.
+    pub fn deep_copy(&self) -> Value {
+        self.deep_copy_with(&mut HashMap::new())
+    }
+
+    /// `deep_copy`, sharing `copies` (which maps the address of every
+    /// cell and map that has been copied to its copy) between several
+    /// values.
+    pub(crate) fn deep_copy_with(&self, copies: &mut HashMap<usize, Value>) -> Value {
+        match self {
+            &Value::Cell(ref cell) => {
+                let address = gc_to_usize(cell);
+                if let Some(copy) = copies.get(&address) {
+                    return copy.clone();
+                }
+                // Insert the copy before filling it in so that cycles
+                // through this cell terminate.
+                let copy = Gc::new(GcCell::new(Value::Nil));
+                copies.insert(address, Value::Cell(copy.clone()));
+                let contents = cell.borrow().deep_copy_with(copies);
+                *copy.borrow_mut() = contents;
+                Value::Cell(copy)
+            }
+            &Value::List(ref items) =>
+                Value::List(Gc::new(items.iter().map(|v| v.deep_copy_with(copies)).collect())),
+            &Value::Map(ref pairs) => {
+                let address = gc_to_usize(pairs);
+                if let Some(copy) = copies.get(&address) {
+                    return copy.clone();
+                }
+                // Like cells, maps can contain themselves.
+                let copy = Gc::new(MapWrapper::new(HashMap::new()));
+                copies.insert(address, Value::Map(copy.clone()));
+                for (k, v) in pairs.borrow().iter() {
+                    let (k, v) = (k.deep_copy_with(copies), v.deep_copy_with(copies));
+                    copy.0.borrow_mut().insert(k.clone(), v);
+                    freeze_key(&k);
+                }
+                if pairs.is_frozen() {
+                    copy.freeze();
+                }
+                Value::Map(copy)
+            }
+            &Value::Closure(ref closure) => {
+                let mut copy = (**closure).clone();
+                copy.upvars = closure.upvars.iter().map(|v| v.deep_copy_with(copies)).collect();
+                Value::Closure(Gc::new(copy))
+            }
+            &Value::Continuation(ref continuation) => {
+                let mut copy = (**continuation).clone();
+                copy.saved_stack = continuation.saved_stack.iter().map(|v| v.deep_copy_with(copies)).collect();
+                Value::Continuation(Gc::new(copy))
+            }
+            other => other.clone(),
+        }
+    }
+
     pub fn expect_nil(self) -> Result<(), InterpError> {
         if let Value::Nil = self { Ok(()) }
         else {
@@ -218,28 +642,55 @@ fn gc_to_usize<T: Trace>(gc: &Gc<T>) -> usize {
 }
 
 pub fn to_string_helper(value: &Value, interner: &SymbolIntern) -> String {
-    use std::collections::HashSet;
+    format_value(value, interner, false)
+}
+
+/// Like `to_string_helper`, but prints the pairs of maps sorted by key so
+/// that equal maps always print the same way.
+pub fn to_string_ordered(value: &Value, interner: &SymbolIntern) -> String {
+    format_value(value, interner, true)
+}
+
+fn format_value(value: &Value, interner: &SymbolIntern, ordered: bool) -> String {
     match value {
         &Value::Nil => "nil".to_string(),
         &Value::Int(i) => format!("{}", i),
+        &Value::Rational(ref r) => format!("{}", **r),
+        #[cfg(feature = "decimal")]
+        &Value::Decimal(ref d) => format!("{}", **d),
         &Value::Float(f) => format!("{}", f),
         &Value::String(ref s) => (&**s).clone(),
+        &Value::Substring(ref s) => s.as_str().to_string(),
         &Value::Bool(b) => format!("{}", b),
         &Value::Symbol(s) => format!("'{}", interner.lookup_or_anon(s)),
-        &Value::Closure(ref c) => format!("<Closure {}>", c.class.code_offset),
+        &Value::Closure(ref c) => {
+            let mut out = "<lambda".to_string();
+            if let Some(name) = c.class.name {
+                out.push(' ');
+                out.push_str(&interner.lookup_or_anon(name));
+            }
+            out.push_str(&format!("/{}", c.class.arg_count));
+            if let Some((line, column)) = c.class.position {
+                out.push_str(&format!(" @ {}:{}", line, column));
+            }
+            out.push('>');
+            out
+        }
         &Value::Continuation(ref c) => format!("<Continuation {:?}>", c),
+        &Value::Handle(id) => format!("<Handle {}>", id),
         &Value::UserFn(ref f) => {
             let f = f.borrow();
             let name = f.name().unwrap_or("{anon}");
             format!("<UserFn {}>", name)
         }
-        &Value::Cell(ref t) => format!("c {}", to_string_helper(&*t.borrow(), interner)),
+        &Value::Cell(ref t) => format!("c {}", format_value(&*t.borrow(), interner, ordered)),
 
         &ref l@Value::List(_) | &ref l@Value::Map(_) => {
             fn format_singles(vec: &Gc<Vec<Value>>,
                               buf: &mut String,
                               seen: &mut HashSet<usize>,
-                              interner: &SymbolIntern) {
+                              interner: &SymbolIntern,
+                              ordered: bool) {
                 let ptr = gc_to_usize(vec);
                 if seen.contains(&ptr) {
                     buf.push_str("[ ... ]")
@@ -247,7 +698,7 @@ pub fn to_string_helper(value: &Value, interner: &SymbolIntern) -> String {
                     seen.insert(ptr);
                     buf.push_str("[");
                     for v in vec.iter() {
-                        build_buf(v, buf, seen, interner);
+                        build_buf(v, buf, seen, interner, ordered);
                         buf.push_str(", ");
                     }
                     // remove trailing comma and space
@@ -262,17 +713,20 @@ pub fn to_string_helper(value: &Value, interner: &SymbolIntern) -> String {
             fn format_pairs(m: &Gc<MapWrapper>,
                             buf: &mut String,
                             seen: &mut HashSet<usize>,
-                            interner: &SymbolIntern) {
+                            interner: &SymbolIntern,
+                            ordered: bool) {
                 let ptr = gc_to_usize(m);
                 if seen.contains(&ptr) {
                     buf.push_str("{ ... }")
                 } else {
                     seen.insert(ptr);
                     buf.push_str("{");
-                    for (k, v) in m.iter() {
-                        build_buf(k, buf, seen, interner);
+                    let pairs = m.borrow();
+                    let pairs = if ordered { pairs.sorted() } else { pairs.iter().collect() };
+                    for (k, v) in pairs {
+                        build_buf(k, buf, seen, interner, ordered);
                         buf.push_str(", ");
-                        build_buf(v, buf, seen, interner);
+                        build_buf(v, buf, seen, interner, ordered);
                     }
                     buf.push_str("}");
                     seen.remove(&ptr);
@@ -281,16 +735,17 @@ pub fn to_string_helper(value: &Value, interner: &SymbolIntern) -> String {
             fn build_buf(cur: &Value,
                          buf: &mut String,
                          seen: &mut HashSet<usize>,
-                         interner: &SymbolIntern) {
+                         interner: &SymbolIntern,
+                         ordered: bool) {
                 match cur {
-                    &Value::List(ref v) => format_singles(v, buf, seen, interner),
-                    &Value::Map(ref m) => format_pairs(m, buf, seen, interner),
-                    other => buf.push_str(&to_string_helper(&other, interner)),
+                    &Value::List(ref v) => format_singles(v, buf, seen, interner, ordered),
+                    &Value::Map(ref m) => format_pairs(m, buf, seen, interner, ordered),
+                    other => buf.push_str(&format_value(&other, interner, ordered)),
                 }
             }
             let mut inner = String::new();
             let mut seen = HashSet::new();
-            build_buf(&l, &mut inner, &mut seen, interner);
+            build_buf(&l, &mut inner, &mut seen, interner, ordered);
             inner
         }
     }
@@ -304,15 +759,17 @@ impl ::std::hash::Hash for Value {
         match self {
             &Value::Nil => state.write_u8(0),
             &Value::List(ref rc) => rc.hash(state),
-            &Value::Map(ref rc) => {
-                for (k, v) in rc.iter() {
-                    k.hash(state);
-                    v.hash(state);
-                }
-            }
+            // Only the size of a map is hashed: its pairs come out in no
+            // particular order, and they might contain the map itself.
+            &Value::Map(ref rc) => rc.len().hash(state),
             &Value::String(ref rc) => rc.hash(state),
+            // Hashes the same way as a String with the same contents.
+            &Value::Substring(ref rc) => rc.as_str().hash(state),
             &Value::Float(f) => unsafe { state.write(&transmute::<_, [u8; 8]>(f)) },
             &Value::Int(i) => state.write_i64(i),
+            &Value::Rational(ref r) => r.hash(state),
+            #[cfg(feature = "decimal")]
+            &Value::Decimal(ref d) => d.hash(state),
             &Value::Bool(b) => {
                 let byte = if b {
                     1
@@ -332,6 +789,7 @@ impl ::std::hash::Hash for Value {
             &Value::UserFn(ref f) => {
                 state.write_usize(unsafe {transmute(&*f.borrow())})
             }
+            &Value::Handle(id) => state.write_u32(id),
         }
     }
 }
@@ -363,7 +821,7 @@ impl<T: Into<Value>> From<Vec<T>> for Value {
 
 impl<T: Into<Value> + ::std::hash::Hash + Eq> From<HashMap<T, T>> for Value {
     fn from(x: HashMap<T, T>) -> Value {
-        Value::Map(Gc::new(MapWrapper(x.into_iter().map(|(k, v)| (k.into(), v.into())).collect())))
+        Value::map_from_iter(x)
     }
 }
 
@@ -0,0 +1,91 @@
+use gc::GcCell;
+use vm::{Value, MapWrapper, Closure, Continuation, Rational, Symbol};
+use vm::concept::function::UserFunction;
+#[cfg(feature = "decimal")]
+use vm::Decimal;
+
+/// A borrowed look at a `Value` that is easier to match on: strings and
+/// substrings are both `Str`, and boxed values are already dereferenced.
+/// Made by `Value::view`, and used by `ares_match!`.
+pub enum ValueRef<'a> {
+    Nil,
+    Int(i64),
+    Float(f64),
+    Rational(Rational),
+    #[cfg(feature = "decimal")]
+    Decimal(Decimal),
+    Bool(bool),
+    Str(&'a str),
+    Symbol(Symbol),
+    List(&'a [Value]),
+    Map(&'a MapWrapper),
+    Closure(&'a Closure),
+    UserFn(&'a GcCell<UserFunction<()>>),
+    Cell(&'a GcCell<Value>),
+    Continuation(&'a Continuation),
+    Handle(u32),
+}
+
+impl Value {
+    pub fn view(&self) -> ValueRef {
+        match self {
+            &Value::Nil => ValueRef::Nil,
+            &Value::Int(i) => ValueRef::Int(i),
+            &Value::Float(f) => ValueRef::Float(f),
+            &Value::Rational(ref r) => ValueRef::Rational(**r),
+            #[cfg(feature = "decimal")]
+            &Value::Decimal(ref d) => ValueRef::Decimal(**d),
+            &Value::Bool(b) => ValueRef::Bool(b),
+            &Value::String(ref s) => ValueRef::Str(&s[..]),
+            &Value::Substring(ref s) => ValueRef::Str(s.as_str()),
+            &Value::Symbol(s) => ValueRef::Symbol(s),
+            &Value::List(ref items) => ValueRef::List(&items[..]),
+            &Value::Map(ref pairs) => ValueRef::Map(pairs),
+            &Value::Closure(ref c) => ValueRef::Closure(c),
+            &Value::UserFn(ref f) => ValueRef::UserFn(f),
+            &Value::Cell(ref c) => ValueRef::Cell(c),
+            &Value::Continuation(ref c) => ValueRef::Continuation(c),
+            &Value::Handle(id) => ValueRef::Handle(id),
+        }
+    }
+}
+
+/// Matches on a `Value` through `Value::view`.  Each arm names a variant
+/// of `ValueRef`, and the last arm has to be `_`.
+///
+/// ```ignore
+/// let doubled = ares_match!(args[0], {
+///     Int(i) => Value::Int(i * 2),
+///     Float(f) => Value::Float(f * 2.0),
+///     Str(s) => Value::string(format!("{}{}", s, s)),
+///     _ => return Err(InterpError::MismatchedType { value: args[0].clone(), expected: ValueKind::Int }),
+/// });
+/// ```
+#[macro_export]
+macro_rules! ares_match {
+    ($value: expr, { $($variant: ident $(($($binding: tt)*))* => $arm: expr,)* _ => $default: expr $(,)* }) => {
+        match $crate::vm::Value::view(&$value) {
+            $($crate::vm::ValueRef::$variant $(($($binding)*))* => $arm,)*
+            _ => $default,
+        }
+    };
+}
+
+#[test]
+fn ares_match_arms() {
+    fn describe(value: &Value) -> String {
+        ares_match!(value, {
+            Int(i) => format!("int {}", i),
+            Str(s) => format!("string {}", s),
+            List(items) => format!("{} items", items.len()),
+            Nil => "nothing".to_string(),
+            _ => "something else".to_string(),
+        })
+    }
+
+    assert_eq!(describe(&Value::Int(3)), "int 3");
+    assert_eq!(describe(&Value::string("hi")), "string hi");
+    assert_eq!(describe(&Value::list(vec![1, 2])), "2 items");
+    assert_eq!(describe(&Value::Nil), "nothing");
+    assert_eq!(describe(&Value::Bool(true)), "something else");
+}
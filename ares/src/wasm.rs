@@ -0,0 +1,73 @@
+//! Bindings for running scripts in the browser with `wasm-bindgen`.
+//!
+//! wasm32 has no threads, which suits the collector: it keeps its heap in
+//! thread local storage and every value stays on the one thread anyway.
+//! Output from the print builtins is captured and returned from
+//! `eval_str` instead of going to a stdout that doesn't exist.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use host::{Context, ContextLike, SandboxPolicy};
+
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Playground {
+    context: Context<()>,
+    output: Output,
+}
+
+impl Playground {
+    fn new() -> Playground {
+        let output = Output::default();
+        let mut context = Context::new();
+        context.set_output(Box::new(output.clone()), Box::new(output.clone()));
+        context.install_standard_library(&SandboxPolicy::new().allow_io(true).allow_time(true).allow_random(true));
+        Playground { context: context, output: output }
+    }
+}
+
+thread_local! {
+    static PLAYGROUND: RefCell<Playground> = RefCell::new(Playground::new());
+}
+
+/// Evaluates `source` in the playground's context, which keeps its
+/// globals between calls.  Returns whatever the script printed followed
+/// by its result or error.
+#[wasm_bindgen]
+pub fn eval_str(source: &str) -> String {
+    PLAYGROUND.with(|playground| {
+        let playground = &mut *playground.borrow_mut();
+        let result = match playground.context.eval(&mut (), source) {
+            Ok(Some(value)) => playground.context.format_value(&value),
+            Ok(None) => String::new(),
+            Err(e) => format!("error: {}", playground.context.format_error(e)),
+        };
+
+        let mut text = String::from_utf8_lossy(&playground.output.0.borrow()).into_owned();
+        playground.output.0.borrow_mut().clear();
+        text.push_str(&result);
+        text
+    })
+}
+
+/// Throws away every global defined by earlier calls to `eval_str`.
+#[wasm_bindgen]
+pub fn reset() {
+    PLAYGROUND.with(|playground| *playground.borrow_mut() = Playground::new());
+}
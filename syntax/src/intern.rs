@@ -12,15 +12,44 @@ impl Finalize for Symbol {
 
 }
 
-#[derive(Debug)]
+impl Symbol {
+    /// The number that identifies this symbol in the interner that made it.
+    pub fn id(self) -> u32 {
+        self.0
+    }
+
+    /// Rebuilds a symbol from `id`.  It is only meaningful when used with
+    /// an interner that produced (or was deserialized from one that
+    /// produced) that id.
+    pub fn from_id(id: u32) -> Symbol {
+        Symbol(id)
+    }
+}
+
+/// Symbols made by `gensym` are numbered from here up so that interning
+/// a new name never changes which symbols later gensyms produce.
+pub const GENSYM_START: u32 = 1 << 31;
+
+#[derive(Debug, Clone)]
 pub struct SymbolIntern {
     current_id: u32,
+    next_gensym: u32,
     sym_to_string: HashMap<Symbol, String>,
     string_to_sym: HashMap<String, Symbol>,
     pub precomputed: PrecomputedSymbols,
 }
 
-#[derive(Debug)]
+/// A copy of an interner's state that can be restored later with
+/// `SymbolIntern::restore`.
+#[derive(Debug, Clone)]
+pub struct InternSnapshot(SymbolIntern);
+
+/// The names that every interner interns first, in this order, so that
+/// they have the same ids in every interner.
+pub const PRECOMPUTED_NAMES: &'static [&'static str] =
+    &["if", "+", "quote", "list", "define", "lambda", "block", "set!", "shift", "reset"];
+
+#[derive(Debug, Clone)]
 pub struct PrecomputedSymbols {
     pub iff: Symbol,
     pub plus: Symbol,
@@ -29,7 +58,12 @@ pub struct PrecomputedSymbols {
     pub define: Symbol,
     pub lambda: Symbol,
     pub block: Symbol,
+    pub set: Symbol,
+    pub shift: Symbol,
+    pub reset: Symbol,
     pub default_namespace: Symbol,
+    /// Names registered with `SymbolIntern::with_precomputed`.
+    extra: Vec<(String, Symbol)>,
 }
 
 impl PrecomputedSymbols {
@@ -42,33 +76,118 @@ impl PrecomputedSymbols {
             define: Symbol(0),
             lambda: Symbol(0),
             block: Symbol(0),
+            set: Symbol(0),
+            shift: Symbol(0),
+            reset: Symbol(0),
             default_namespace: Symbol(0),
+            extra: vec![],
         }
     }
+
+    /// The precomputed symbol for `name`, whether it is one of
+    /// `PRECOMPUTED_NAMES` or was registered by the embedder.
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        let builtin = match name {
+            "if" => Some(self.iff),
+            "+" => Some(self.plus),
+            "quote" => Some(self.quote),
+            "list" => Some(self.list),
+            "define" => Some(self.define),
+            "lambda" => Some(self.lambda),
+            "block" => Some(self.block),
+            "set!" => Some(self.set),
+            "shift" => Some(self.shift),
+            "reset" => Some(self.reset),
+            _ => None,
+        };
+        builtin.or_else(|| self.extra.iter().find(|&&(ref n, _)| n == name).map(|&(_, s)| s))
+    }
+
+    /// The names registered with `SymbolIntern::with_precomputed`.
+    pub fn extra_names(&self) -> Vec<&str> {
+        self.extra.iter().map(|&(ref name, _)| &name[..]).collect()
+    }
 }
 
 impl SymbolIntern {
     pub fn new() -> SymbolIntern {
+        SymbolIntern::with_precomputed(&[])
+    }
+
+    /// An interner that also precomputes `extra`.  They are interned right
+    /// after `PRECOMPUTED_NAMES`, so interners built with the same list
+    /// agree on their ids no matter what is interned later.
+    pub fn with_precomputed(extra: &[&str]) -> SymbolIntern {
         let mut interner = SymbolIntern {
             current_id: 0,
+            next_gensym: GENSYM_START,
             sym_to_string: HashMap::new(),
             string_to_sym: HashMap::new(),
             precomputed: PrecomputedSymbols::new(),
         };
-        interner.precomputed.iff = interner.intern("if");
-        interner.precomputed.plus = interner.intern("+");
-        interner.precomputed.quote = interner.intern("quote");
-        interner.precomputed.list = interner.intern("list");
-        interner.precomputed.define = interner.intern("define");
-        interner.precomputed.lambda = interner.intern("lambda");
-        interner.precomputed.block = interner.intern("block");
+        interner.precompute(extra);
         interner.precomputed.default_namespace = interner.gensym();
         interner
     }
 
+    // Fills in `precomputed`, except for the default namespace.
+    fn precompute(&mut self, extra: &[&str]) {
+        let default_namespace = self.precomputed.default_namespace;
+        self.precomputed = PrecomputedSymbols {
+            iff: self.intern("if"),
+            plus: self.intern("+"),
+            quote: self.intern("quote"),
+            list: self.intern("list"),
+            define: self.intern("define"),
+            lambda: self.intern("lambda"),
+            block: self.intern("block"),
+            set: self.intern("set!"),
+            shift: self.intern("shift"),
+            reset: self.intern("reset"),
+            default_namespace: default_namespace,
+            extra: vec![],
+        };
+        for name in extra {
+            let symbol = self.intern(*name);
+            self.precomputed.extra.push((name.to_string(), symbol));
+        }
+    }
+
     pub fn gensym(&mut self) -> Symbol {
+        let ret = Symbol(self.next_gensym);
+        self.next_gensym += 1;
+        ret
+    }
+
+    /// How many symbols `gensym` has produced, counting from the last
+    /// call to `seed_gensym`.
+    pub fn gensym_counter(&self) -> u32 {
+        self.next_gensym - GENSYM_START
+    }
+
+    /// Makes `gensym` count up from `next`.  Symbols that were generated at or after `next`
+    /// will be handed out again, so only reseed once they are no longer
+    /// in use.
+    pub fn seed_gensym(&mut self, next: u32) {
+        self.next_gensym = GENSYM_START + next;
+    }
+
+    /// Captures every interned name and the gensym counter.
+    pub fn snapshot(&self) -> InternSnapshot {
+        InternSnapshot(self.clone())
+    }
+
+    /// Puts the interner back into the state it was in when `snapshot`
+    /// was taken.  Parsing and binding the same program after each
+    /// restore produces identical trees.
+    pub fn restore(&mut self, snapshot: &InternSnapshot) {
+        *self = snapshot.0.clone();
+    }
+
+    fn next_id(&mut self) -> Symbol {
         let ret = Symbol(self.current_id);
         self.current_id += 1;
+        assert!(self.current_id < GENSYM_START, "too many interned symbols");
         ret
     }
 
@@ -77,7 +196,7 @@ impl SymbolIntern {
             self.string_to_sym[symbol_str.as_ref()]
         } else {
             let symbol_str = symbol_str.into();
-            let symbol = self.gensym();
+            let symbol = self.next_id();
             self.sym_to_string.insert(symbol, symbol_str.clone());
             self.string_to_sym.insert(symbol_str, symbol);
             symbol
@@ -112,3 +231,163 @@ impl SymbolIntern {
             .unwrap_or_else(|| format!("s{}", id))
     }
 }
+
+/// Why a serialized interner couldn't be read back.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum InternDecodeError {
+    UnexpectedEnd,
+    InvalidUtf8,
+    DuplicateName(String),
+    UnknownVersion(u32),
+}
+
+const SERIAL_VERSION: u32 = 3;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl <'a> Reader<'a> {
+    fn u32(&mut self) -> Result<u32, InternDecodeError> {
+        if self.bytes.len() < 4 {
+            return Err(InternDecodeError::UnexpectedEnd);
+        }
+        let b = self.bytes;
+        self.bytes = &b[4..];
+        Ok((b[0] as u32) | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+    }
+
+    fn string(&mut self) -> Result<String, InternDecodeError> {
+        let len = self.u32()? as usize;
+        if self.bytes.len() < len {
+            return Err(InternDecodeError::UnexpectedEnd);
+        }
+        let (s, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(s.to_vec()).map_err(|_| InternDecodeError::InvalidUtf8)
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+}
+
+impl SymbolIntern {
+    /// Encodes every name along with the id it was given so that code
+    /// which refers to symbols by id can be loaded into another process.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut entries: Vec<(&Symbol, &String)> = self.sym_to_string.iter().collect();
+        entries.sort();
+
+        let mut out = vec![];
+        write_u32(&mut out, SERIAL_VERSION);
+        write_u32(&mut out, self.current_id);
+        write_u32(&mut out, self.next_gensym);
+        write_u32(&mut out, self.precomputed.default_namespace.0);
+        write_u32(&mut out, self.precomputed.extra.len() as u32);
+        for &(ref name, _) in &self.precomputed.extra {
+            write_u32(&mut out, name.len() as u32);
+            out.extend_from_slice(name.as_bytes());
+        }
+        write_u32(&mut out, entries.len() as u32);
+        for (&Symbol(id), name) in entries {
+            write_u32(&mut out, id);
+            write_u32(&mut out, name.len() as u32);
+            out.extend_from_slice(name.as_bytes());
+        }
+        out
+    }
+
+    /// Rebuilds an interner from the output of `serialize`.  Every symbol
+    /// resolves to the same name that it did in the original interner.
+    pub fn deserialize(bytes: &[u8]) -> Result<SymbolIntern, InternDecodeError> {
+        let mut reader = Reader { bytes: bytes };
+        let version = reader.u32()?;
+        if version != SERIAL_VERSION {
+            return Err(InternDecodeError::UnknownVersion(version));
+        }
+
+        let mut interner = SymbolIntern {
+            current_id: reader.u32()?,
+            next_gensym: reader.u32()?,
+            sym_to_string: HashMap::new(),
+            string_to_sym: HashMap::new(),
+            precomputed: PrecomputedSymbols::new(),
+        };
+        let default_namespace = Symbol(reader.u32()?);
+        let mut extra = vec![];
+        for _ in 0 .. reader.u32()? {
+            extra.push(reader.string()?);
+        }
+
+        for _ in 0 .. reader.u32()? {
+            let symbol = Symbol(reader.u32()?);
+            let name = reader.string()?;
+            if interner.string_to_sym.contains_key(&name) {
+                return Err(InternDecodeError::DuplicateName(name));
+            }
+            interner.sym_to_string.insert(symbol, name.clone());
+            interner.string_to_sym.insert(name, symbol);
+        }
+
+        interner.precomputed.default_namespace = default_namespace;
+        let extra: Vec<&str> = extra.iter().map(|s| &s[..]).collect();
+        interner.precompute(&extra);
+        Ok(interner)
+    }
+}
+
+#[test]
+fn gensym_is_independent_of_interning() {
+    let mut a = SymbolIntern::new();
+    let mut b = SymbolIntern::new();
+    b.intern("something");
+    assert_eq!(a.gensym(), b.gensym());
+
+    let snapshot = a.snapshot();
+    let first = a.gensym();
+    a.intern("else");
+    a.restore(&snapshot);
+    assert_eq!(a.gensym(), first);
+    assert!(!a.contains("else"));
+
+    a.seed_gensym(0);
+    assert_eq!(a.gensym_counter(), 0);
+}
+
+#[test]
+fn serialize_round_trip() {
+    let mut original = SymbolIntern::new();
+    let foo = original.intern("foo");
+    let generated = original.gen_sym_prefix("tmp");
+    original.gensym();
+
+    let mut copy = SymbolIntern::deserialize(&original.serialize()).unwrap();
+    assert_eq!(copy.lookup(foo), Some("foo"));
+    assert_eq!(copy.lookup(generated), original.lookup(generated));
+    assert_eq!(copy.intern("bar"), original.intern("bar"));
+    assert_eq!(copy.gensym(), original.gensym());
+    assert_eq!(copy.precomputed.default_namespace, original.precomputed.default_namespace);
+
+    assert_eq!(SymbolIntern::deserialize(&[1, 0]).unwrap_err(), InternDecodeError::UnexpectedEnd);
+
+    let mut old = original.serialize();
+    old[0] = 2;
+    assert_eq!(SymbolIntern::deserialize(&old).unwrap_err(), InternDecodeError::UnknownVersion(2));
+}
+
+#[test]
+fn precomputed_symbols_are_stable() {
+    let mut a = SymbolIntern::with_precomputed(&["http", "match"]);
+    let b = SymbolIntern::with_precomputed(&["http", "match"]);
+    a.intern("unrelated");
+
+    assert_eq!(a.precomputed.get("match"), b.precomputed.get("match"));
+    assert_eq!(a.precomputed.get("set!"), Some(a.precomputed.set));
+    assert_eq!(a.intern("match"), a.precomputed.get("match").unwrap());
+    assert_eq!(a.precomputed.get("unrelated"), None);
+
+    let copy = SymbolIntern::deserialize(&a.serialize()).unwrap();
+    assert_eq!(copy.precomputed.extra_names(), vec!["http", "match"]);
+    assert_eq!(copy.precomputed.get("http"), a.precomputed.get("http"));
+}
@@ -0,0 +1,255 @@
+use {Ast, Span, Symbol, SymbolIntern, TypeName};
+
+/// Builds a single JSON object one field at a time.
+///
+/// Every object produced by the `to_json` functions starts with a
+/// `"type"` field that names the node.
+pub struct JsonObject {
+    buf: String,
+}
+
+impl JsonObject {
+    pub fn new(kind: &str) -> JsonObject {
+        let mut buf = "{\"type\":".to_string();
+        buf.push_str(&json_string(kind));
+        JsonObject { buf: buf }
+    }
+
+    /// Adds a field whose value is already encoded as JSON.
+    pub fn raw<S: AsRef<str>>(mut self, key: &str, json: S) -> JsonObject {
+        self.buf.push(',');
+        self.buf.push_str(&json_string(key));
+        self.buf.push(':');
+        self.buf.push_str(json.as_ref());
+        self
+    }
+
+    pub fn string(self, key: &str, value: &str) -> JsonObject {
+        let value = json_string(value);
+        self.raw(key, value)
+    }
+
+    pub fn finish(mut self) -> String {
+        self.buf.push('}');
+        self.buf
+    }
+}
+
+/// Encodes `s` as a JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Joins already-encoded JSON values into an array.
+pub fn json_array<I: IntoIterator<Item=String>>(items: I) -> String {
+    let mut out = "[".to_string();
+    for (i, item) in items.into_iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+        out.push_str(&item);
+    }
+    out.push(']');
+    out
+}
+
+impl Span {
+    pub fn to_json(&self) -> String {
+        format!("{{\"start\":{},\"end\":{}}}", self.0, self.1)
+    }
+}
+
+fn symbol_json(symbol: Symbol, interner: &SymbolIntern) -> String {
+    json_string(&interner.lookup_or_anon(symbol))
+}
+
+fn symbols_json(symbols: &[Symbol], interner: &SymbolIntern) -> String {
+    json_array(symbols.iter().map(|&s| symbol_json(s, interner)))
+}
+
+impl <'ast> Ast<'ast> {
+    /// Encodes this tree as JSON, looking up the names of every symbol
+    /// in `interner`.
+    pub fn to_json(&self, interner: &SymbolIntern) -> String {
+        use Ast::*;
+
+        let many = |asts: &[Ast]| json_array(asts.iter().map(|a| a.to_json(interner)));
+        let node = |kind: &str, span: Span| JsonObject::new(kind).raw("span", span.to_json());
+        let binop = |kind: &str, l: &Ast, r: &Ast, span: Span| {
+            node(kind, span).raw("left", l.to_json(interner))
+                            .raw("right", r.to_json(interner))
+                            .finish()
+        };
+
+        match *self {
+            Identifier(s, span) => node("Identifier", span).raw("name", symbol_json(s, interner)).finish(),
+            BoolLit(b, span) => node("BoolLit", span).raw("value", b.to_string()).finish(),
+            StringLit(ref s, span) => node("StringLit", span).string("value", s).finish(),
+            IntLit(i, span) => node("IntLit", span).raw("value", i.to_string()).finish(),
+            FloatLit(f, span) => {
+                // JSON has no representation for infinities or NaN
+                let value = if f.is_finite() { format!("{:?}", f) } else { json_string(&f.to_string()) };
+                node("FloatLit", span).raw("value", value).finish()
+            }
+            DecimalLit(ref d, span) => node("DecimalLit", span).string("value", d).finish(),
+            SymbolLit(s, span) => node("SymbolLit", span).raw("value", symbol_json(s, interner)).finish(),
+            NilLit(span) => node("NilLit", span).finish(),
+
+            ListLit(ref e, span) => node("ListLit", span).raw("elements", many(e)).finish(),
+            MapLit(ref pairs, span) => {
+                let pairs = json_array(pairs.iter().map(|&(ref k, v)| {
+                    json_array(vec![k.to_json(interner), v.to_json(interner)])
+                }));
+                node("MapLit", span).raw("pairs", pairs).finish()
+            }
+            Quote(data, span) => node("Quote", span).raw("data", data.to_json(interner)).finish(),
+
+            ListAccess(l, r, span) => {
+                node("ListAccess", span).raw("target", l.to_json(interner))
+                                        .raw("index", r.to_json(interner))
+                                        .finish()
+            }
+            Add(l, r, span) => binop("Add", l, r, span),
+            Sub(l, r, span) => binop("Sub", l, r, span),
+            Mul(l, r, span) => binop("Mul", l, r, span),
+            Div(l, r, span) => binop("Div", l, r, span),
+            LessThan(l, r, span) => binop("LessThan", l, r, span),
+            LessThanOrEqual(l, r, span) => binop("LessThanOrEqual", l, r, span),
+            GreaterThan(l, r, span) => binop("GreaterThan", l, r, span),
+            GreaterThanOrEqual(l, r, span) => binop("GreaterThanOrEqual", l, r, span),
+            Equal(l, r, span) => binop("Equal", l, r, span),
+            NotEqual(l, r, span) => binop("NotEqual", l, r, span),
+
+            FnCall(f, ref args, span) => {
+                node("FnCall", span).raw("function", f.to_json(interner))
+                                    .raw("args", many(args))
+                                    .finish()
+            }
+            IfExpression(c, t, f, span) => {
+                node("IfExpression", span).raw("condition", c.to_json(interner))
+                                          .raw("then", t.to_json(interner))
+                                          .raw("else", f.to_json(interner))
+                                          .finish()
+            }
+            IfStatement(c, t, f, span) => {
+                let f = f.map(|f| f.to_json(interner)).unwrap_or_else(|| "null".to_string());
+                node("IfStatement", span).raw("condition", c.to_json(interner))
+                                         .raw("then", t.to_json(interner))
+                                         .raw("else", f)
+                                         .finish()
+            }
+            Closure(name, ref args, ref sig, body, span) => {
+                let name = name.map(|n| symbol_json(n, interner)).unwrap_or_else(|| "null".to_string());
+                let args = json_array(args.iter().map(|a| symbols_json(a, interner)));
+                let type_json = |t: Option<TypeName>| t.map(|t| symbol_json(t.name, interner))
+                                                       .unwrap_or_else(|| "null".to_string());
+                let types = match *sig {
+                    Some(ref sig) => JsonObject::new("Signature")
+                        .raw("params", json_array(sig.params.iter().map(|list| {
+                            json_array(list.iter().map(|&t| type_json(t)))
+                        })))
+                        .raw("ret", type_json(sig.ret))
+                        .finish(),
+                    None => "null".to_string(),
+                };
+                node("Closure", span).raw("name", name)
+                                     .raw("args", args)
+                                     .raw("types", types)
+                                     .raw("body", body.to_json(interner))
+                                     .finish()
+            }
+            Contract(ref requires, ref ensures, body, span) => {
+                node("Contract", span).raw("requires", many(requires))
+                                      .raw("ensures", many(ensures))
+                                      .raw("body", body.to_json(interner))
+                                      .finish()
+            }
+            Assign(s, value, span) => {
+                node("Assign", span).raw("name", symbol_json(s, interner))
+                                    .raw("value", value.to_json(interner))
+                                    .finish()
+            }
+            Define(s, value, span) => {
+                node("Define", span).raw("name", symbol_json(s, interner))
+                                    .raw("value", value.to_json(interner))
+                                    .finish()
+            }
+            Loop(ref bindings, body, span) => {
+                let bindings = json_array(bindings.iter().map(|&(name, ref init)| {
+                    json_array(vec![symbol_json(name, interner), init.to_json(interner)])
+                }));
+                node("Loop", span).raw("bindings", bindings)
+                                  .raw("body", body.to_json(interner))
+                                  .finish()
+            }
+            Recur(ref values, span) => node("Recur", span).raw("values", many(values)).finish(),
+            Do(body, span) => node("Do", span).raw("body", body.to_json(interner)).finish(),
+            Letrec(ref bindings, body, span) => {
+                let bindings = json_array(bindings.iter().map(|&(name, ref value)| {
+                    json_array(vec![symbol_json(name, interner), value.to_json(interner)])
+                }));
+                node("Letrec", span).raw("bindings", bindings)
+                                    .raw("body", body.to_json(interner))
+                                    .finish()
+            }
+            NamedLet(name, ref bindings, body, span) => {
+                let bindings = json_array(bindings.iter().map(|&(name, ref init)| {
+                    json_array(vec![symbol_json(name, interner), init.to_json(interner)])
+                }));
+                node("NamedLet", span).raw("name", symbol_json(name, interner))
+                                      .raw("bindings", bindings)
+                                      .raw("body", body.to_json(interner))
+                                      .finish()
+            }
+            BlockExpression(ref e, span) => node("BlockExpression", span).raw("body", many(e)).finish(),
+            BlockStatement(ref e, span) => node("BlockStatement", span).raw("body", many(e)).finish(),
+            Import(ref names, namespace, version, span) => {
+                node("Import", span).raw("names", symbols_json(names, interner))
+                                    .raw("namespace", symbol_json(namespace, interner))
+                                    .raw("version", version.to_json(interner))
+                                    .finish()
+            }
+
+            Reset(ref symbols, closure, span) => {
+                node("Reset", span).raw("symbols", many(symbols))
+                                   .raw("closure", closure.to_json(interner))
+                                   .finish()
+            }
+            Shift(ref symbols, closure, span) => {
+                node("Shift", span).raw("symbols", many(symbols))
+                                   .raw("closure", closure.to_json(interner))
+                                   .finish()
+            }
+        }
+    }
+}
+
+#[test]
+fn encode_ast() {
+    use typed_arena::Arena;
+    use parse_Expr;
+
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let ast = parse_Expr(&arena, &mut interner, "foo(1, \"hi\")").unwrap();
+    assert_eq!(ast.to_json(&interner),
+               "{\"type\":\"FnCall\",\"span\":{\"start\":0,\"end\":12},\
+                \"function\":{\"type\":\"Identifier\",\"span\":{\"start\":0,\"end\":3},\"name\":\"foo\"},\
+                \"args\":[{\"type\":\"IntLit\",\"span\":{\"start\":4,\"end\":5},\"value\":1},\
+                {\"type\":\"StringLit\",\"span\":{\"start\":7,\"end\":11},\"value\":\"hi\"}]}");
+    assert_eq!(json_string("a\"b\n"), "\"a\\\"b\\n\"");
+}
@@ -1,24 +1,113 @@
 extern crate typed_arena;
 #[macro_use]
 extern crate gc;
+extern crate lalrpop_util;
+
+use std::ops::Range;
 
 mod intern;
+mod json;
+mod numbers;
+mod owned;
+mod position;
+mod print;
 mod syntax;
+mod tokens;
+mod visit;
 
 pub use intern::*;
+pub use json::{JsonObject, json_string, json_array};
+pub use numbers::{ConversionError, parse_int, parse_float};
+pub use owned::AstOwned;
+pub use position::{Position, LspPosition};
+pub use print::{Printer, PrintError, format_program, format_program_source};
+pub use visit::{AstVisitor, AstFolder, walk_ast, fold_ast};
+pub use tokens::{Token, TokenIter, TokenKind, Trivia, TriviaKind, tokenize};
 pub use syntax::{parse_Expr, parse_Statement, parse_Program};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Span(u32, u32);
 
 impl Span {
+    pub fn new(start: u32, end: u32) -> Span {
+        Span(start, end)
+    }
+
     pub fn dummy() -> Span {
         Span(0, 0)
     }
+
+    /// The byte offset of the first character in this span.
+    pub fn start(&self) -> u32 {
+        self.0
+    }
+
+    /// The byte offset just past the last character in this span.
+    pub fn end(&self) -> u32 {
+        self.1
+    }
+
+    /// The bytes that this span covers, for slicing the source.
+    pub fn range(&self) -> Range<usize> {
+        self.0 as usize .. self.1 as usize
+    }
+
+    /// True if the byte at `offset` is inside of this span.
+    pub fn contains(&self, offset: u32) -> bool {
+        self.0 <= offset && offset < self.1
+    }
+
+    pub fn start_position(&self, source: &str) -> Position {
+        Position::of(source, self.0)
+    }
+
+    pub fn end_position(&self, source: &str) -> Position {
+        Position::of(source, self.1)
+    }
+
+    /// The start and end of this span as Language Server Protocol
+    /// positions.
+    pub fn to_lsp(&self, source: &str) -> (LspPosition, LspPosition) {
+        (LspPosition::of(source, self.0), LspPosition::of(source, self.1))
+    }
+
+    /// The span between two Language Server Protocol positions, if they
+    /// are both in `source`.
+    pub fn from_lsp(source: &str, start: LspPosition, end: LspPosition) -> Option<Span> {
+        Some(Span(start.to_offset(source)?, end.to_offset(source)?))
+    }
 }
 
 pub type AstRef<'ast> = &'ast Ast<'ast>;
 
+/// A type written in an annotation, like the `int` in `fn(x: int)`.
+/// Names are only looked up by the type checker, so an unknown name is a
+/// diagnostic rather than a parse error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TypeName {
+    pub name: Symbol,
+    pub span: Span,
+}
+
+/// The annotations on a closure.  `params` has the same shape as the
+/// closure's parameter lists, with `None` for each parameter that wasn't
+/// annotated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Vec<Option<TypeName>>>,
+    pub ret: Option<TypeName>,
+}
+
+impl Signature {
+    fn with_spans<F: Fn(Span) -> Span>(&self, f: &F) -> Signature {
+        let map = |t: Option<TypeName>| t.map(|t| TypeName { name: t.name, span: f(t.span) });
+        Signature {
+            params: self.params.iter().map(|list| list.iter().map(|&t| map(t)).collect()).collect(),
+            ret: map(self.ret),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Ast<'ast> {
     Identifier(Symbol, Span),
@@ -26,11 +115,19 @@ pub enum Ast<'ast> {
     StringLit(String, Span),
     IntLit(i64, Span),
     FloatLit(f64, Span),
+    /// `1.50d`, holding its digits without the suffix or underscores.
+    /// Kept as text so that the syntax doesn't depend on whether the vm
+    /// was built with decimals.
+    DecimalLit(String, Span),
     SymbolLit(Symbol, Span),
     NilLit(Span),
 
     ListLit(Vec<Ast<'ast>>, Span),
     MapLit(Vec<(Ast<'ast>, AstRef<'ast>)>, Span),
+    /// `'(a 1 (b))`, which is data rather than code.  Holds a `ListLit`
+    /// of the quoted items, where identifiers stand for symbols, `(..)`
+    /// is a `ListLit` and `{..}` is a `MapLit` of pairs.
+    Quote(AstRef<'ast>, Span),
 
     ListAccess(AstRef<'ast>, AstRef<'ast>, Span),
 
@@ -49,9 +146,30 @@ pub enum Ast<'ast> {
     FnCall(AstRef<'ast>, Vec<Ast<'ast>>, Span),
     IfExpression(AstRef<'ast>, AstRef<'ast>, AstRef<'ast>, Span),
     IfStatement(AstRef<'ast>, AstRef<'ast>, Option<AstRef<'ast>>, Span),
-    Closure(Option<Symbol>, Vec<Vec<Symbol>>, AstRef<'ast>, Span),
+    /// The signature is only present if some part of it was annotated.
+    Closure(Option<Symbol>, Vec<Vec<Symbol>>, Option<Box<Signature>>, AstRef<'ast>, Span),
+    /// The `requires` and `ensures` clauses of a closure, wrapped around
+    /// its body.  Only ever found as the body of a `Closure`.  The
+    /// `ensures` clauses can read what the body returned as `result`.
+    Contract(Vec<Ast<'ast>>, Vec<Ast<'ast>>, AstRef<'ast>, Span),
     Assign(Symbol, AstRef<'ast>, Span),
     Define(Symbol, AstRef<'ast>, Span),
+    /// `loop(name = init, ..) { body }` defines its variables and runs
+    /// the body, which can start it over with new values through `recur`.
+    Loop(Vec<(Symbol, Ast<'ast>)>, AstRef<'ast>, Span),
+    /// `recur(values..)` rebinds the variables of the innermost loop and
+    /// jumps back to its start.
+    Recur(Vec<Ast<'ast>>, Span),
+    /// `let name(var = init, ..) { body }` calls a function that takes the
+    /// variables and runs the body, and that can call itself as `name`.
+    NamedLet(Symbol, Vec<(Symbol, Ast<'ast>)>, AstRef<'ast>, Span),
+    /// `letrec(name = value, ..) { body }` defines names that every value
+    /// can refer to, then runs the body with them in scope.
+    Letrec(Vec<(Symbol, Ast<'ast>)>, AstRef<'ast>, Span),
+    /// `do { .. }` runs a block in its own scope wherever an expression
+    /// can go.  The block is always a `BlockExpression`; one written
+    /// without a final expression evaluates to nil.
+    Do(AstRef<'ast>, Span),
     BlockExpression(Vec<Ast<'ast>>, Span),
     BlockStatement(Vec<Ast<'ast>>, Span),
     Import(Vec<Symbol>, Symbol, AstRef<'ast>, Span),
@@ -71,12 +189,14 @@ impl <'ast> Ast<'ast> {
             Ast::StringLit(_, s) |
             Ast::IntLit(_, s) |
             Ast::FloatLit(_, s) |
+            Ast::DecimalLit(_, s) |
             Ast::SymbolLit(_, s) |
             Ast::NilLit(s) |
             Ast::Identifier(_, s) |
             Ast::ListLit(_,  s) |
             Ast::ListAccess(_,  _, s) |
             Ast::MapLit(_, s) |
+            Ast::Quote(_, s) |
             Ast::Add(_, _, s) |
             Ast::Sub(_, _, s) |
             Ast::Mul(_, _, s) |
@@ -92,9 +212,15 @@ impl <'ast> Ast<'ast> {
             Ast::FnCall(_, _, s) |
             Ast::IfExpression(_, _, _, s) |
             Ast::IfStatement(_, _, _, s) |
-            Ast::Closure(_, _, _, s) |
+            Ast::Closure(_, _, _, _, s) |
+            Ast::Contract(_, _, _, s) |
             Ast::Assign(_, _, s) |
             Ast::Define(_, _, s) |
+            Ast::Loop(_, _, s) |
+            Ast::Recur(_, s) |
+            Ast::NamedLet(_, _, _, s) |
+            Ast::Do(_, s) |
+            Ast::Letrec(_, _, s) |
             Ast::BlockExpression(_, s) |
             Ast::BlockStatement(_, s) |
             Ast::Shift(_, _, s) |
@@ -104,44 +230,90 @@ impl <'ast> Ast<'ast> {
     }
 
     fn with_default_span(self) -> Ast<'ast> {
+        self.with_span(&|_| Span::dummy())
+    }
+
+    // Replaces the span of this node (but not of its children) with
+    // `f` of it.
+    fn with_span<F: Fn(Span) -> Span>(self, f: &F) -> Ast<'ast> {
         use self::Ast::*;
         match self {
-            BoolLit(a, _) => BoolLit(a, Span::dummy()),
-            StringLit(a, _) => StringLit(a, Span::dummy()),
-            IntLit(a, _) => IntLit(a, Span::dummy()),
-            NilLit(_) => NilLit(Span::dummy()),
-            FloatLit(a, _) => FloatLit(a, Span::dummy()),
-            SymbolLit(a, _) => SymbolLit(a, Span::dummy()),
-            Identifier(a, _) => Identifier(a, Span::dummy()),
-            ListLit(a,  _) => ListLit(a, Span::dummy()),
-            ListAccess(a, b,  _) => ListAccess(a, b, Span::dummy()),
-            MapLit(a, _) => MapLit(a, Span::dummy()),
-            Add(l, r, _) => Add(l, r, Span::dummy()),
-            Sub(l, r, _) => Sub(l, r, Span::dummy()),
-            Mul(l, r, _) => Mul(l, r, Span::dummy()),
-            Div(l, r, _) => Div(l, r, Span::dummy()),
-            LessThan(l, r, _) => LessThan(l, r, Span::dummy()),
-            LessThanOrEqual(l, r, _) => LessThanOrEqual(l, r, Span::dummy()),
-            GreaterThan(l, r, _) => GreaterThan(l, r, Span::dummy()),
-            GreaterThanOrEqual(l, r, _) => GreaterThanOrEqual(l, r, Span::dummy()),
-            Equal(l, r, _) => Equal(l, r, Span::dummy()),
-            NotEqual(l, r, _) => NotEqual(l, r, Span::dummy()),
-            FnCall(o, a, _) => FnCall(o, a, Span::dummy()),
-            IfExpression(c, t, f, _) => IfExpression(c, t, f, Span::dummy()),
-            IfStatement(c, t, f, _) => IfStatement(c, t, f, Span::dummy()),
-            Closure(n, a, b, _) => Closure(n, a, b, Span::dummy()),
-            Assign(n, v, _) => Assign(n, v, Span::dummy()),
-            Define(n, v, _) => Define(n, v, Span::dummy()),
-            BlockExpression(b, _) => BlockExpression(b, Span::dummy()),
-            BlockStatement(b, _) => BlockStatement(b, Span::dummy()),
-            Shift(a, b, _) => Shift(a, b, Span::dummy()),
-            Reset(a, b, _) => Reset(a, b, Span::dummy()),
-            Import(a, b, c, _) => Import(a, b, c, Span::dummy()),
+            BoolLit(a, s) => BoolLit(a, f(s)),
+            StringLit(a, s) => StringLit(a, f(s)),
+            IntLit(a, s) => IntLit(a, f(s)),
+            NilLit(s) => NilLit(f(s)),
+            FloatLit(a, s) => FloatLit(a, f(s)),
+            DecimalLit(a, s) => DecimalLit(a, f(s)),
+            SymbolLit(a, s) => SymbolLit(a, f(s)),
+            Identifier(a, s) => Identifier(a, f(s)),
+            ListLit(a, s) => ListLit(a, f(s)),
+            ListAccess(a, b, s) => ListAccess(a, b, f(s)),
+            MapLit(a, s) => MapLit(a, f(s)),
+            Quote(a, s) => Quote(a, f(s)),
+            Add(l, r, s) => Add(l, r, f(s)),
+            Sub(l, r, s) => Sub(l, r, f(s)),
+            Mul(l, r, s) => Mul(l, r, f(s)),
+            Div(l, r, s) => Div(l, r, f(s)),
+            LessThan(l, r, s) => LessThan(l, r, f(s)),
+            LessThanOrEqual(l, r, s) => LessThanOrEqual(l, r, f(s)),
+            GreaterThan(l, r, s) => GreaterThan(l, r, f(s)),
+            GreaterThanOrEqual(l, r, s) => GreaterThanOrEqual(l, r, f(s)),
+            Equal(l, r, s) => Equal(l, r, f(s)),
+            NotEqual(l, r, s) => NotEqual(l, r, f(s)),
+            FnCall(o, a, s) => FnCall(o, a, f(s)),
+            IfExpression(c, t, e, s) => IfExpression(c, t, e, f(s)),
+            IfStatement(c, t, e, s) => IfStatement(c, t, e, f(s)),
+            Closure(n, a, t, b, s) => Closure(n, a, t.map(|t| Box::new(t.with_spans(f))), b, f(s)),
+            Contract(r, e, b, s) => Contract(r, e, b, f(s)),
+            Assign(n, v, s) => Assign(n, v, f(s)),
+            Define(n, v, s) => Define(n, v, f(s)),
+            Loop(v, b, s) => Loop(v, b, f(s)),
+            Recur(a, s) => Recur(a, f(s)),
+            NamedLet(n, v, b, s) => NamedLet(n, v, b, f(s)),
+            Do(b, s) => Do(b, f(s)),
+            Letrec(v, b, s) => Letrec(v, b, f(s)),
+            BlockExpression(b, s) => BlockExpression(b, f(s)),
+            BlockStatement(b, s) => BlockStatement(b, f(s)),
+            Shift(a, b, s) => Shift(a, b, f(s)),
+            Reset(a, b, s) => Reset(a, b, f(s)),
+            Import(a, b, c, s) => Import(a, b, c, f(s)),
         }
     }
 
+    /// Compares two trees while ignoring the spans of every node in them.
     pub fn equals_sans_span(&self, other: &Ast<'ast>) -> bool {
-        self.clone().with_default_span() == other.clone().with_default_span()
+        fn strip<'a>(arena: &'a typed_arena::Arena<Ast<'a>>, ast: &Ast<'a>) -> Ast<'a> {
+            struct StripSpans<'a>(&'a typed_arena::Arena<Ast<'a>>);
+            impl <'a> AstFolder<'a> for StripSpans<'a> {
+                fn arena(&self) -> &'a typed_arena::Arena<Ast<'a>> {
+                    self.0
+                }
+                fn fold(&mut self, ast: &Ast<'a>) -> Ast<'a> {
+                    fold_ast(self, ast).with_default_span()
+                }
+            }
+            StripSpans(arena).fold(ast)
+        }
+
+        let arena = typed_arena::Arena::new();
+        strip(&arena, self) == strip(&arena, other)
+    }
+
+    /// A copy of this tree with every span moved `offset` bytes later (or
+    /// earlier, if it is negative), for trees parsed out of a piece of a
+    /// larger source.  The copied nodes are allocated in `arena`.
+    pub fn with_offset(&self, offset: i64, arena: &'ast typed_arena::Arena<Ast<'ast>>) -> Ast<'ast> {
+        struct Offset<'a>(&'a typed_arena::Arena<Ast<'a>>, i64);
+        impl <'a> AstFolder<'a> for Offset<'a> {
+            fn arena(&self) -> &'a typed_arena::Arena<Ast<'a>> {
+                self.0
+            }
+            fn fold(&mut self, ast: &Ast<'a>) -> Ast<'a> {
+                let offset = self.1;
+                fold_ast(self, ast).with_span(&|s| Span((s.0 as i64 + offset) as u32, (s.1 as i64 + offset) as u32))
+            }
+        }
+        Offset(arena, offset).fold(self)
     }
 }
 
@@ -223,6 +395,7 @@ macro_rules! ast {
         (Ast::Closure(
                 None,
                 vec![$(vec![$($intern.intern(stringify!($symbol))),*]),*],
+                None,
                 $arena.alloc(Ast::Block(vec![$(ast!($arena, $intern, $name $arg))*], Span::dummy())),
                 Span::dummy()))
     };
@@ -230,6 +403,7 @@ macro_rules! ast {
         (Ast::Closure(
                 Some($intern.intern(stringify!($c_name))),
                 vec![$(vec![$($intern.intern(stringify!($symbol))),*]),*],
+                None,
                 $arena.alloc(Ast::Block(vec![$(ast!($arena, $intern, $name $arg))*], Span::dummy())),
                 Span::dummy()))
     };
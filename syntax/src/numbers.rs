@@ -0,0 +1,69 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use Span;
+
+/// A number literal that was tokenized, but doesn't stand for a number
+/// that the vm has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionError {
+    /// An integer that doesn't fit in an `i64`.
+    IntegerOverflow(Span),
+    /// A float that is too large to be anything but infinity, like `1e400`.
+    FloatOverflow(Span),
+    /// An `e` without any digits after it, like in `1e` or `2.5e-`.
+    MalformedExponent(Span),
+}
+
+impl ConversionError {
+    pub fn span(&self) -> Span {
+        match *self {
+            ConversionError::IntegerOverflow(span) |
+            ConversionError::FloatOverflow(span) |
+            ConversionError::MalformedExponent(span) => span,
+        }
+    }
+}
+
+// `_` can go anywhere after the first digit of a number, to group its
+// digits, and means nothing.
+fn without_underscores(text: &str) -> Cow<str> {
+    if text.contains('_') {
+        Cow::Owned(text.replace('_', ""))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Converts the text of an `IntLit` token, like `1_000_000`.
+pub fn parse_int(text: &str, span: Span) -> Result<i64, ConversionError> {
+    i64::from_str(&without_underscores(text)).map_err(|_| ConversionError::IntegerOverflow(span))
+}
+
+/// Converts the text of a `FloatLit` token, like `2.5e-3` or `1e9`.
+pub fn parse_float(text: &str, span: Span) -> Result<f64, ConversionError> {
+    // Anything that is tokenized as a float parses unless its exponent
+    // has no digits.
+    match f64::from_str(&without_underscores(text)) {
+        Ok(float) if float.is_infinite() => Err(ConversionError::FloatOverflow(span)),
+        Ok(float) => Ok(float),
+        Err(_) => Err(ConversionError::MalformedExponent(span)),
+    }
+}
+
+#[test]
+fn numbers_convert() {
+    let span = Span::new(0, 1);
+    assert_eq!(parse_int("1_000_000", span), Ok(1000000));
+    assert_eq!(parse_int("9223372036854775807", span), Ok(i64::max_value()));
+    assert_eq!(parse_int("9223372036854775808", span), Err(ConversionError::IntegerOverflow(span)));
+
+    assert_eq!(parse_float("1e9", span), Ok(1e9));
+    assert_eq!(parse_float("2.5e-3", span), Ok(2.5e-3));
+    assert_eq!(parse_float("1_0.2_5E+1_0", span), Ok(10.25e10));
+    assert_eq!(parse_float("3.", span), Ok(3.0));
+    assert_eq!(parse_float("1e400", span), Err(ConversionError::FloatOverflow(span)));
+    for malformed in &["1e", "2.5e-", "1E+", "1e_"] {
+        assert_eq!(parse_float(malformed, span), Err(ConversionError::MalformedExponent(span)), "{}", malformed);
+    }
+}
@@ -0,0 +1,221 @@
+use typed_arena::Arena;
+use {Ast, AstRef, Span, Symbol, Signature};
+
+/// An owned mirror of `Ast` that doesn't live inside of an arena.
+///
+/// This is easier to build and rewrite by hand than `Ast`; convert
+/// back with `to_ast` once the tree needs to be bound.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AstOwned {
+    Identifier(Symbol, Span),
+    BoolLit(bool, Span),
+    StringLit(String, Span),
+    IntLit(i64, Span),
+    FloatLit(f64, Span),
+    DecimalLit(String, Span),
+    SymbolLit(Symbol, Span),
+    NilLit(Span),
+
+    ListLit(Vec<AstOwned>, Span),
+    MapLit(Vec<(AstOwned, AstOwned)>, Span),
+    Quote(Box<AstOwned>, Span),
+
+    ListAccess(Box<AstOwned>, Box<AstOwned>, Span),
+
+    Add(Box<AstOwned>, Box<AstOwned>, Span),
+    Sub(Box<AstOwned>, Box<AstOwned>, Span),
+    Mul(Box<AstOwned>, Box<AstOwned>, Span),
+    Div(Box<AstOwned>, Box<AstOwned>, Span),
+
+    LessThan(Box<AstOwned>, Box<AstOwned>, Span),
+    LessThanOrEqual(Box<AstOwned>, Box<AstOwned>, Span),
+    GreaterThan(Box<AstOwned>, Box<AstOwned>, Span),
+    GreaterThanOrEqual(Box<AstOwned>, Box<AstOwned>, Span),
+    Equal(Box<AstOwned>, Box<AstOwned>, Span),
+    NotEqual(Box<AstOwned>, Box<AstOwned>, Span),
+
+    FnCall(Box<AstOwned>, Vec<AstOwned>, Span),
+    IfExpression(Box<AstOwned>, Box<AstOwned>, Box<AstOwned>, Span),
+    IfStatement(Box<AstOwned>, Box<AstOwned>, Option<Box<AstOwned>>, Span),
+    Closure(Option<Symbol>, Vec<Vec<Symbol>>, Option<Box<Signature>>, Box<AstOwned>, Span),
+    Contract(Vec<AstOwned>, Vec<AstOwned>, Box<AstOwned>, Span),
+    Assign(Symbol, Box<AstOwned>, Span),
+    Define(Symbol, Box<AstOwned>, Span),
+    Loop(Vec<(Symbol, AstOwned)>, Box<AstOwned>, Span),
+    Recur(Vec<AstOwned>, Span),
+    NamedLet(Symbol, Vec<(Symbol, AstOwned)>, Box<AstOwned>, Span),
+    Do(Box<AstOwned>, Span),
+    Letrec(Vec<(Symbol, AstOwned)>, Box<AstOwned>, Span),
+    BlockExpression(Vec<AstOwned>, Span),
+    BlockStatement(Vec<AstOwned>, Span),
+    Import(Vec<Symbol>, Symbol, Box<AstOwned>, Span),
+
+    Reset(Vec<AstOwned>, Box<AstOwned>, Span),
+    Shift(Vec<AstOwned>, Box<AstOwned>, Span),
+}
+
+impl AstOwned {
+    /// Copies an arena-allocated tree into an owned one.
+    pub fn from_ast(ast: &Ast) -> AstOwned {
+        use self::AstOwned as O;
+
+        fn b(ast: &Ast) -> Box<AstOwned> {
+            Box::new(AstOwned::from_ast(ast))
+        }
+        fn v(asts: &[Ast]) -> Vec<AstOwned> {
+            asts.iter().map(AstOwned::from_ast).collect()
+        }
+
+        match *ast {
+            Ast::Identifier(s, span) => O::Identifier(s, span),
+            Ast::BoolLit(b, span) => O::BoolLit(b, span),
+            Ast::StringLit(ref s, span) => O::StringLit(s.clone(), span),
+            Ast::IntLit(i, span) => O::IntLit(i, span),
+            Ast::FloatLit(f, span) => O::FloatLit(f, span),
+            Ast::DecimalLit(ref d, span) => O::DecimalLit(d.clone(), span),
+            Ast::SymbolLit(s, span) => O::SymbolLit(s, span),
+            Ast::NilLit(span) => O::NilLit(span),
+
+            Ast::ListLit(ref e, span) => O::ListLit(v(e), span),
+            Ast::MapLit(ref pairs, span) => {
+                O::MapLit(pairs.iter().map(|&(ref k, v)| (AstOwned::from_ast(k), AstOwned::from_ast(v))).collect(), span)
+            }
+
+            Ast::ListAccess(l, r, span) => O::ListAccess(b(l), b(r), span),
+            Ast::Add(l, r, span) => O::Add(b(l), b(r), span),
+            Ast::Sub(l, r, span) => O::Sub(b(l), b(r), span),
+            Ast::Mul(l, r, span) => O::Mul(b(l), b(r), span),
+            Ast::Div(l, r, span) => O::Div(b(l), b(r), span),
+            Ast::LessThan(l, r, span) => O::LessThan(b(l), b(r), span),
+            Ast::LessThanOrEqual(l, r, span) => O::LessThanOrEqual(b(l), b(r), span),
+            Ast::GreaterThan(l, r, span) => O::GreaterThan(b(l), b(r), span),
+            Ast::GreaterThanOrEqual(l, r, span) => O::GreaterThanOrEqual(b(l), b(r), span),
+            Ast::Equal(l, r, span) => O::Equal(b(l), b(r), span),
+            Ast::NotEqual(l, r, span) => O::NotEqual(b(l), b(r), span),
+
+            Ast::FnCall(f, ref args, span) => O::FnCall(b(f), v(args), span),
+            Ast::IfExpression(c, t, f, span) => O::IfExpression(b(c), b(t), b(f), span),
+            Ast::IfStatement(c, t, f, span) => O::IfStatement(b(c), b(t), f.map(b), span),
+            Ast::Closure(name, ref args, ref sig, body, span) => O::Closure(name, args.clone(), sig.clone(), b(body), span),
+            Ast::Contract(ref requires, ref ensures, body, span) => O::Contract(v(requires), v(ensures), b(body), span),
+            Ast::Assign(s, value, span) => O::Assign(s, b(value), span),
+            Ast::Define(s, value, span) => O::Define(s, b(value), span),
+            Ast::Loop(ref bindings, body, span) => {
+                O::Loop(bindings.iter().map(|&(name, ref init)| (name, AstOwned::from_ast(init))).collect(), b(body), span)
+            }
+            Ast::Recur(ref values, span) => O::Recur(v(values), span),
+            Ast::Do(body, span) => O::Do(b(body), span),
+            Ast::Quote(data, span) => O::Quote(b(data), span),
+            Ast::Letrec(ref bindings, body, span) => {
+                O::Letrec(bindings.iter().map(|&(name, ref value)| (name, AstOwned::from_ast(value))).collect(), b(body), span)
+            }
+            Ast::NamedLet(name, ref bindings, body, span) => {
+                O::NamedLet(name, bindings.iter().map(|&(name, ref init)| (name, AstOwned::from_ast(init))).collect(), b(body), span)
+            }
+            Ast::BlockExpression(ref e, span) => O::BlockExpression(v(e), span),
+            Ast::BlockStatement(ref e, span) => O::BlockStatement(v(e), span),
+            Ast::Import(ref names, namespace, version, span) => O::Import(names.clone(), namespace, b(version), span),
+
+            Ast::Reset(ref symbols, closure, span) => O::Reset(v(symbols), b(closure), span),
+            Ast::Shift(ref symbols, closure, span) => O::Shift(v(symbols), b(closure), span),
+        }
+    }
+
+    /// Rebuilds this tree as an `Ast`, allocating every child node
+    /// in `arena`.
+    pub fn to_ast<'ast>(&self, arena: &'ast Arena<Ast<'ast>>) -> Ast<'ast> {
+        use self::AstOwned as O;
+
+        let r = |o: &AstOwned| -> AstRef<'ast> { arena.alloc(o.to_ast(arena)) };
+        let v = |os: &[AstOwned]| -> Vec<Ast<'ast>> { os.iter().map(|o| o.to_ast(arena)).collect() };
+
+        match *self {
+            O::Identifier(s, span) => Ast::Identifier(s, span),
+            O::BoolLit(b, span) => Ast::BoolLit(b, span),
+            O::StringLit(ref s, span) => Ast::StringLit(s.clone(), span),
+            O::IntLit(i, span) => Ast::IntLit(i, span),
+            O::FloatLit(f, span) => Ast::FloatLit(f, span),
+            O::DecimalLit(ref d, span) => Ast::DecimalLit(d.clone(), span),
+            O::SymbolLit(s, span) => Ast::SymbolLit(s, span),
+            O::NilLit(span) => Ast::NilLit(span),
+
+            O::ListLit(ref e, span) => Ast::ListLit(v(e), span),
+            O::MapLit(ref pairs, span) => {
+                Ast::MapLit(pairs.iter().map(|&(ref k, ref val)| (k.to_ast(arena), r(val))).collect(), span)
+            }
+
+            O::ListAccess(ref l, ref rr, span) => Ast::ListAccess(r(l), r(rr), span),
+            O::Add(ref l, ref rr, span) => Ast::Add(r(l), r(rr), span),
+            O::Sub(ref l, ref rr, span) => Ast::Sub(r(l), r(rr), span),
+            O::Mul(ref l, ref rr, span) => Ast::Mul(r(l), r(rr), span),
+            O::Div(ref l, ref rr, span) => Ast::Div(r(l), r(rr), span),
+            O::LessThan(ref l, ref rr, span) => Ast::LessThan(r(l), r(rr), span),
+            O::LessThanOrEqual(ref l, ref rr, span) => Ast::LessThanOrEqual(r(l), r(rr), span),
+            O::GreaterThan(ref l, ref rr, span) => Ast::GreaterThan(r(l), r(rr), span),
+            O::GreaterThanOrEqual(ref l, ref rr, span) => Ast::GreaterThanOrEqual(r(l), r(rr), span),
+            O::Equal(ref l, ref rr, span) => Ast::Equal(r(l), r(rr), span),
+            O::NotEqual(ref l, ref rr, span) => Ast::NotEqual(r(l), r(rr), span),
+
+            O::FnCall(ref f, ref args, span) => Ast::FnCall(r(f), v(args), span),
+            O::IfExpression(ref c, ref t, ref f, span) => Ast::IfExpression(r(c), r(t), r(f), span),
+            O::IfStatement(ref c, ref t, ref f, span) => {
+                Ast::IfStatement(r(c), r(t), f.as_ref().map(|f| r(f)), span)
+            }
+            O::Closure(name, ref args, ref sig, ref body, span) => Ast::Closure(name, args.clone(), sig.clone(), r(body), span),
+            O::Contract(ref requires, ref ensures, ref body, span) => {
+                Ast::Contract(v(requires), v(ensures), r(body), span)
+            }
+            O::Assign(s, ref value, span) => Ast::Assign(s, r(value), span),
+            O::Define(s, ref value, span) => Ast::Define(s, r(value), span),
+            O::Loop(ref bindings, ref body, span) => {
+                Ast::Loop(bindings.iter().map(|&(name, ref init)| (name, init.to_ast(arena))).collect(), r(body), span)
+            }
+            O::Recur(ref values, span) => Ast::Recur(v(values), span),
+            O::Do(ref body, span) => Ast::Do(r(body), span),
+            O::Quote(ref data, span) => Ast::Quote(r(data), span),
+            O::Letrec(ref bindings, ref body, span) => {
+                Ast::Letrec(bindings.iter().map(|&(name, ref value)| (name, value.to_ast(arena))).collect(), r(body), span)
+            }
+            O::NamedLet(name, ref bindings, ref body, span) => {
+                Ast::NamedLet(name, bindings.iter().map(|&(name, ref init)| (name, init.to_ast(arena))).collect(), r(body), span)
+            }
+            O::BlockExpression(ref e, span) => Ast::BlockExpression(v(e), span),
+            O::BlockStatement(ref e, span) => Ast::BlockStatement(v(e), span),
+            O::Import(ref names, namespace, ref version, span) => {
+                Ast::Import(names.clone(), namespace, r(version), span)
+            }
+
+            O::Reset(ref symbols, ref closure, span) => Ast::Reset(v(symbols), r(closure), span),
+            O::Shift(ref symbols, ref closure, span) => Ast::Shift(v(symbols), r(closure), span),
+        }
+    }
+
+    /// Rebuilds this tree and allocates its root in `arena`.
+    pub fn alloc_in<'ast>(&self, arena: &'ast Arena<Ast<'ast>>) -> AstRef<'ast> {
+        arena.alloc(self.to_ast(arena))
+    }
+}
+
+impl <'a, 'ast> From<&'a Ast<'ast>> for AstOwned {
+    fn from(ast: &'a Ast<'ast>) -> AstOwned {
+        AstOwned::from_ast(ast)
+    }
+}
+
+#[test]
+fn round_trip() {
+    use {SymbolIntern, parse_Program};
+
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let program = "var a = [1, 2, 3]; fn foo(x) { if x < 2 then a[x] else 'y }; foo(a[0] + 1);";
+    let asts = parse_Program(&arena, &mut interner, program).unwrap();
+
+    let other_arena = Arena::new();
+    for ast in asts {
+        let owned = AstOwned::from(&ast);
+        let rebuilt = owned.to_ast(&other_arena);
+        assert_eq!(ast, rebuilt);
+        assert_eq!(owned, AstOwned::from_ast(&rebuilt));
+    }
+}
@@ -0,0 +1,127 @@
+/// A line and column in some source text.  Both start at 1, and columns
+/// count characters rather than bytes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    line: u32,
+    column: u32,
+}
+
+/// A position the way that the Language Server Protocol counts them: the
+/// line starts at 0 and the character is a count of UTF-16 code units.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LspPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+// Offsets past the end of `source`, or in the middle of a character, are
+// moved back to the nearest character boundary.
+fn clamp(source: &str, offset: u32) -> usize {
+    let mut offset = (offset as usize).min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+// The text of line `line` (counting from 0) without its line ending,
+// and the byte offset that it starts at.
+fn nth_line(source: &str, line: u32) -> Option<(usize, &str)> {
+    let mut start = 0;
+    for _ in 0 .. line {
+        start += source[start..].find('\n')? + 1;
+    }
+    let text = &source[start..];
+    Some((start, text.split('\n').next().unwrap_or(text)))
+}
+
+impl Position {
+    pub fn new(line: u32, column: u32) -> Position {
+        Position { line: line, column: column }
+    }
+
+    /// Finds the position of the byte at `offset` in `source`.
+    pub fn of(source: &str, offset: u32) -> Position {
+        let before = &source[.. clamp(source, offset)];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Position {
+            line: before.matches('\n').count() as u32 + 1,
+            column: before[line_start..].chars().count() as u32 + 1,
+        }
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    /// The byte offset of this position in `source`, if `source` has
+    /// such a line and column.  The column just past the end of a line
+    /// is allowed.
+    pub fn to_offset(&self, source: &str) -> Option<u32> {
+        if self.line == 0 || self.column == 0 {
+            return None;
+        }
+        let (start, text) = nth_line(source, self.line - 1)?;
+        let column = (self.column - 1) as usize;
+        let within = match text.char_indices().nth(column) {
+            Some((i, _)) => i,
+            None if text.chars().count() == column => text.len(),
+            None => return None,
+        };
+        Some((start + within) as u32)
+    }
+}
+
+impl LspPosition {
+    /// Finds the position of the byte at `offset` in `source`.
+    pub fn of(source: &str, offset: u32) -> LspPosition {
+        let before = &source[.. clamp(source, offset)];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        LspPosition {
+            line: before.matches('\n').count() as u32,
+            character: before[line_start..].encode_utf16().count() as u32,
+        }
+    }
+
+    /// The byte offset of this position in `source`, if `source` has
+    /// such a line and the character doesn't split a UTF-16 pair.
+    pub fn to_offset(&self, source: &str) -> Option<u32> {
+        let (start, text) = nth_line(source, self.line)?;
+        let mut units = 0;
+        for (i, c) in text.char_indices() {
+            if units == self.character {
+                return Some((start + i) as u32);
+            }
+            if units > self.character {
+                return None;
+            }
+            units += c.len_utf16() as u32;
+        }
+        if units == self.character {
+            Some((start + text.len()) as u32)
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn positions_round_trip() {
+    let source = "var a = 1;\nvar é = \"𝄞x\";";
+    let x = source.find('x').unwrap() as u32;
+
+    let position = Position::of(source, x);
+    assert_eq!((position.line(), position.column()), (2, 11));
+    assert_eq!(position.to_offset(source), Some(x));
+    assert_eq!(Position::new(3, 1).to_offset(source), None);
+
+    let lsp = LspPosition::of(source, x);
+    assert_eq!(lsp, LspPosition { line: 1, character: 11 });
+    assert_eq!(lsp.to_offset(source), Some(x));
+    // Between the two halves of the surrogate pair.
+    assert_eq!(LspPosition { line: 1, character: 10 }.to_offset(source), None);
+}
@@ -0,0 +1,741 @@
+use {Ast, Span, Symbol, SymbolIntern, TokenKind, tokenize};
+
+const INDENT: &'static str = "    ";
+
+// Binding strength of each kind of expression, mirroring the layers
+// of the grammar.  A child is wrapped in parens whenever it binds more
+// loosely than its position in the parent allows.
+const PREC_ASSIGN: u8 = 0;
+const PREC_IF: u8 = 1;
+const PREC_CONTINUATION: u8 = 2;
+const PREC_EQUALITY: u8 = 3;
+const PREC_RELATIONAL: u8 = 4;
+const PREC_ADDITIVE: u8 = 5;
+const PREC_MULTIPLICATIVE: u8 = 6;
+const PREC_POSTFIX: u8 = 7;
+const PREC_PRIMARY: u8 = 8;
+
+fn precedence(ast: &Ast) -> u8 {
+    match *ast {
+        Ast::Assign(..) | Ast::Define(..) | Ast::Import(..) | Ast::IfStatement(..) => PREC_ASSIGN,
+        Ast::IfExpression(..) => PREC_IF,
+        Ast::Reset(..) | Ast::Shift(..) => PREC_CONTINUATION,
+        Ast::Equal(..) | Ast::NotEqual(..) => PREC_EQUALITY,
+        Ast::LessThan(..) | Ast::LessThanOrEqual(..) |
+        Ast::GreaterThan(..) | Ast::GreaterThanOrEqual(..) => PREC_RELATIONAL,
+        Ast::Add(..) | Ast::Sub(..) => PREC_ADDITIVE,
+        Ast::Mul(..) | Ast::Div(..) => PREC_MULTIPLICATIVE,
+        Ast::ListAccess(..) | Ast::FnCall(..) => PREC_POSTFIX,
+        _ => PREC_PRIMARY,
+    }
+}
+
+/// Syntax that has no source that would parse back into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintError {
+    /// A float literal that is infinite or NaN.
+    NonFiniteFloat(Span),
+    /// A map literal outside of quoted data, which is the only place
+    /// that maps can be written, like `'({a 1})`.
+    MapOutsideQuote(Span),
+}
+
+/// Prints Asts back out as surface syntax with canonical indentation.
+///
+/// Everything but blocks is printed on a single line; every block puts
+/// each of its statements on its own line, indented four spaces deeper
+/// than the block itself.
+pub struct Printer<'i> {
+    interner: &'i SymbolIntern,
+    out: String,
+    indent: usize,
+    /// The source being printed back out, if any.
+    source: &'i str,
+    /// Where each `#;` in `source` that hasn't been printed yet starts.
+    comments: Vec<usize>,
+    next_comment: usize,
+    /// The first thing printed that won't parse.
+    error: Option<PrintError>,
+}
+
+impl <'i> Printer<'i> {
+    pub fn new(interner: &'i SymbolIntern) -> Printer<'i> {
+        Printer::with_source(interner, "")
+    }
+
+    /// A printer for the Asts that `source` parsed to.  The parser throws
+    /// away forms that are commented out with `#;`, so they are copied
+    /// from `source` instead, to where they were among the others.
+    pub fn with_source(interner: &'i SymbolIntern, source: &'i str) -> Printer<'i> {
+        let comments = tokenize(source).filter(|token| token.kind == TokenKind::DatumComment)
+                                       .map(|token| token.span.start() as usize)
+                                       .collect();
+        Printer {
+            interner: interner,
+            out: String::new(),
+            indent: 0,
+            source: source,
+            comments: comments,
+            next_comment: 0,
+            error: None,
+        }
+    }
+
+    /// The source printed so far, or the first part of it that wouldn't
+    /// parse back.
+    pub fn finish(self) -> Result<String, PrintError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.out),
+        }
+    }
+
+    fn fail(&mut self, error: PrintError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0 .. self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn name(&mut self, symbol: Symbol) {
+        let name = self.interner.lookup_or_anon(symbol);
+        self.out.push_str(&name);
+    }
+
+    // The source of the comments that haven't been printed that start
+    // before `end`.  Nothing else that is printed can be between a
+    // comment and the form after it, so they run up to `end`.
+    fn comments_before(&mut self, end: usize) -> Option<&'i str> {
+        let start = match self.comments.get(self.next_comment) {
+            Some(&start) if start < end => start,
+            _ => return None,
+        };
+        while self.comments.get(self.next_comment).map_or(false, |&start| start < end) {
+            self.next_comment += 1;
+        }
+        Some(self.source[start .. end].trim_end())
+    }
+
+    // Prints the comments before a statement, each statement on its own
+    // line.
+    fn statement_comments(&mut self, end: usize) {
+        if let Some(comments) = self.comments_before(end) {
+            self.out.push_str(comments);
+            self.newline();
+        }
+    }
+
+    // Prints the items of a list or the arguments to a call, which end
+    // at `end`, and any comments among them.
+    fn items(&mut self, items: &[Ast], end: usize) {
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                self.out.push_str(", ");
+            }
+            if let Some(comments) = self.comments_before(item.span().start() as usize) {
+                self.out.push_str(comments);
+                self.out.push(' ');
+            }
+            self.expression(item, PREC_ASSIGN);
+        }
+        if let Some(comments) = self.comments_before(end) {
+            if !items.is_empty() {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(comments);
+        }
+    }
+
+    fn comma<T, F>(&mut self, items: &[T], mut f: F) where F: FnMut(&mut Self, &T) {
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                self.out.push_str(", ");
+            }
+            f(self, item);
+        }
+    }
+
+    /// Prints every Ast of a program as a top-level statement.
+    pub fn program(&mut self, asts: &[Ast]) {
+        for (i, ast) in asts.iter().enumerate() {
+            if i != 0 {
+                self.newline();
+            }
+            self.statement_comments(ast.span().start() as usize);
+            self.statement(ast);
+        }
+        let end = self.source.len();
+        if let Some(comments) = self.comments_before(end) {
+            if !asts.is_empty() {
+                self.newline();
+            }
+            self.out.push_str(comments);
+        }
+    }
+
+    /// Prints `ast` so that it parses as a statement.
+    pub fn statement(&mut self, ast: &Ast) {
+        match *ast {
+            Ast::Define(name, value, _) => {
+                self.out.push_str("var ");
+                self.name(name);
+                self.out.push_str(" = ");
+                self.expression(value, PREC_IF);
+                self.out.push(';');
+            }
+            Ast::Import(..) | Ast::IfStatement(..) | Ast::BlockStatement(..) => {
+                self.expression(ast, PREC_ASSIGN);
+            }
+            _ => {
+                // An expression that starts with `{` or `if` would be
+                // read back as a block or if statement.
+                let start = self.out.len();
+                self.expression(ast, PREC_ASSIGN);
+                if self.out[start..].starts_with('{') || self.out[start..].starts_with("if ") {
+                    self.out.insert(start, '(');
+                    self.out.push(')');
+                }
+                self.out.push(';');
+            }
+        }
+    }
+
+    // Prints a block whose `}` is at `end`.
+    fn block(&mut self, statements: &[Ast], last: Option<&Ast>, end: usize) {
+        self.out.push('{');
+        self.indent += 1;
+        let mut empty = true;
+        for statement in statements {
+            self.newline();
+            self.statement_comments(statement.span().start() as usize);
+            self.statement(statement);
+            empty = false;
+        }
+        if let Some(last) = last {
+            self.newline();
+            self.statement_comments(last.span().start() as usize);
+            self.expression(last, PREC_IF);
+            empty = false;
+        }
+        if let Some(comments) = self.comments_before(end) {
+            self.newline();
+            self.out.push_str(comments);
+            empty = false;
+        }
+        self.indent -= 1;
+        if !empty {
+            self.newline();
+        }
+        self.out.push('}');
+    }
+
+    // Prints quoted data, where lists are written `(a b)` and maps
+    // `{k v}`.
+    fn datum(&mut self, ast: &Ast) {
+        match *ast {
+            Ast::ListLit(ref items, _) => {
+                self.out.push('(');
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        self.out.push(' ');
+                    }
+                    self.datum(item);
+                }
+                self.out.push(')');
+            }
+            Ast::MapLit(ref pairs, _) => {
+                self.out.push('{');
+                for (i, &(ref k, v)) in pairs.iter().enumerate() {
+                    if i != 0 {
+                        self.out.push(' ');
+                    }
+                    self.datum(k);
+                    self.out.push(' ');
+                    self.datum(v);
+                }
+                self.out.push('}');
+            }
+            _ => self.expression(ast, PREC_PRIMARY),
+        }
+    }
+
+    /// Prints `ast`, wrapping it in parens if it binds more loosely
+    /// than `min_precedence`.
+    pub fn expression(&mut self, ast: &Ast, min_precedence: u8) {
+        if precedence(ast) < min_precedence {
+            self.out.push('(');
+            self.expression(ast, PREC_ASSIGN);
+            self.out.push(')');
+            return;
+        }
+
+        match *ast {
+            Ast::Identifier(s, _) => self.name(s),
+            Ast::BoolLit(b, _) => self.out.push_str(if b { "true" } else { "false" }),
+            Ast::StringLit(ref s, _) => {
+                // The lexer keeps escape sequences as written, so the
+                // contents can be printed back out verbatim.
+                self.out.push('"');
+                self.out.push_str(s);
+                self.out.push('"');
+            }
+            Ast::IntLit(i, _) => self.out.push_str(&i.to_string()),
+            Ast::FloatLit(f, span) => {
+                if !f.is_finite() {
+                    self.fail(PrintError::NonFiniteFloat(span));
+                }
+                let mut s = f.to_string();
+                if !s.contains('.') {
+                    s.push_str(".0");
+                }
+                self.out.push_str(&s);
+            }
+            Ast::DecimalLit(ref d, _) => {
+                self.out.push_str(d);
+                self.out.push('d');
+            }
+            Ast::SymbolLit(s, _) => {
+                self.out.push('\'');
+                self.name(s);
+            }
+            Ast::NilLit(_) => self.out.push_str("nil"),
+
+            Ast::ListLit(ref elements, span) => {
+                self.out.push('[');
+                self.items(elements, (span.end() as usize).saturating_sub(1));
+                self.out.push(']');
+            }
+            Ast::MapLit(_, span) => {
+                self.fail(PrintError::MapOutsideQuote(span));
+                self.datum(ast);
+            }
+
+            Ast::Quote(data, _) => {
+                self.out.push('\'');
+                self.datum(data);
+            }
+
+            Ast::ListAccess(target, index, _) => {
+                self.expression(target, PREC_POSTFIX);
+                self.out.push('[');
+                self.expression(index, PREC_ASSIGN);
+                self.out.push(']');
+            }
+
+            Ast::Add(l, r, _) => self.binop(l, " + ", r, PREC_ADDITIVE),
+            Ast::Sub(l, r, _) => self.binop(l, " - ", r, PREC_ADDITIVE),
+            Ast::Mul(l, r, _) => self.binop(l, " * ", r, PREC_MULTIPLICATIVE),
+            Ast::Div(l, r, _) => self.binop(l, " / ", r, PREC_MULTIPLICATIVE),
+            Ast::LessThan(l, r, _) => self.binop(l, " < ", r, PREC_RELATIONAL),
+            Ast::LessThanOrEqual(l, r, _) => self.binop(l, " <= ", r, PREC_RELATIONAL),
+            Ast::GreaterThan(l, r, _) => self.binop(l, " > ", r, PREC_RELATIONAL),
+            Ast::GreaterThanOrEqual(l, r, _) => self.binop(l, " >= ", r, PREC_RELATIONAL),
+            Ast::Equal(l, r, _) => self.binop(l, " == ", r, PREC_EQUALITY),
+            Ast::NotEqual(l, r, _) => self.binop(l, " != ", r, PREC_EQUALITY),
+
+            Ast::FnCall(f, ref args, span) => {
+                self.expression(f, PREC_POSTFIX);
+                self.out.push('(');
+                self.items(args, (span.end() as usize).saturating_sub(1));
+                self.out.push(')');
+            }
+            Ast::IfExpression(c, t, f, _) => {
+                self.out.push_str("if ");
+                self.expression(c, PREC_CONTINUATION);
+                self.out.push_str(" then ");
+                self.expression(t, PREC_ASSIGN);
+                self.out.push_str(" else ");
+                self.expression(f, PREC_IF);
+            }
+            Ast::IfStatement(c, t, f, _) => {
+                self.out.push_str("if ");
+                self.expression(c, PREC_CONTINUATION);
+                self.out.push_str(" then ");
+                self.body(t);
+                if let Some(f) = f {
+                    self.out.push_str(" else ");
+                    self.body(f);
+                }
+            }
+            Ast::Closure(name, ref params, ref sig, body, _) => {
+                self.out.push_str("fn");
+                if let Some(name) = name {
+                    self.out.push(' ');
+                    self.name(name);
+                }
+                for (i, list) in params.iter().enumerate() {
+                    let types = sig.as_ref().and_then(|sig| sig.params.get(i));
+                    let typed: Vec<_> = list.iter().enumerate().map(|(j, &p)| {
+                        (p, types.and_then(|types| types.get(j).cloned()).and_then(|t| t))
+                    }).collect();
+                    self.out.push('(');
+                    self.comma(&typed, |this, &(p, t)| {
+                        this.name(p);
+                        if let Some(t) = t {
+                            this.out.push_str(": ");
+                            this.name(t.name);
+                        }
+                    });
+                    self.out.push(')');
+                }
+                if let Some(ret) = sig.as_ref().and_then(|sig| sig.ret) {
+                    self.out.push_str(": ");
+                    self.name(ret.name);
+                }
+                self.out.push(' ');
+                self.body(body);
+            }
+            Ast::Contract(ref requires, ref ensures, body, _) => {
+                for clause in requires {
+                    self.out.push_str("requires ");
+                    self.expression(clause, PREC_IF);
+                    self.out.push(' ');
+                }
+                for clause in ensures {
+                    self.out.push_str("ensures ");
+                    self.expression(clause, PREC_IF);
+                    self.out.push(' ');
+                }
+                self.body(body);
+            }
+            Ast::Assign(name, value, _) => {
+                self.name(name);
+                self.out.push_str(" = ");
+                self.expression(value, PREC_IF);
+            }
+            Ast::Define(..) => self.statement(ast),
+            Ast::Loop(ref bindings, body, _) => {
+                self.out.push_str("loop(");
+                self.comma(bindings, |this, &(name, ref init)| {
+                    this.name(name);
+                    this.out.push_str(" = ");
+                    this.expression(init, PREC_IF);
+                });
+                self.out.push_str(") ");
+                self.body(body);
+            }
+            Ast::NamedLet(name, ref bindings, body, _) => {
+                self.out.push_str("let ");
+                self.name(name);
+                self.out.push('(');
+                self.comma(bindings, |this, &(name, ref init)| {
+                    this.name(name);
+                    this.out.push_str(" = ");
+                    this.expression(init, PREC_IF);
+                });
+                self.out.push_str(") ");
+                self.body(body);
+            }
+            Ast::Letrec(ref bindings, body, _) => {
+                self.out.push_str("letrec(");
+                self.comma(bindings, |this, &(name, ref value)| {
+                    this.name(name);
+                    this.out.push_str(" = ");
+                    this.expression(value, PREC_IF);
+                });
+                self.out.push_str(") ");
+                self.body(body);
+            }
+            Ast::Do(body, _) => {
+                self.out.push_str("do ");
+                self.body(body);
+            }
+            Ast::Recur(ref values, span) => {
+                self.out.push_str("recur(");
+                self.items(values, (span.end() as usize).saturating_sub(1));
+                self.out.push(')');
+            }
+            Ast::BlockExpression(ref body, span) => {
+                let end = (span.end() as usize).saturating_sub(1);
+                match body.split_last() {
+                    Some((last, rest)) => self.block(rest, Some(last), end),
+                    None => self.block(&[], None, end),
+                }
+            }
+            Ast::BlockStatement(ref body, span) => self.block(body, None, (span.end() as usize).saturating_sub(1)),
+            Ast::Import(ref names, namespace, version, _) => {
+                self.out.push_str("import(");
+                self.comma(names, |this, &n| this.name(n));
+                self.out.push_str(") from ");
+                self.name(namespace);
+                self.out.push_str(" at ");
+                self.expression(version, PREC_PRIMARY);
+            }
+
+            Ast::Reset(ref symbols, closure, _) => {
+                self.out.push_str("reset");
+                self.continuation_symbols(symbols);
+                self.out.push(' ');
+                match *closure {
+                    Ast::Closure(_, _, _, body, _) => self.body(body),
+                    ref other => self.body(other),
+                }
+            }
+            Ast::Shift(ref symbols, closure, _) => {
+                self.out.push_str("shift");
+                self.continuation_symbols(symbols);
+                match *closure {
+                    // The short form, where the parser generates a
+                    // closure that returns the continuation
+                    Ast::Closure(None, ref params, _, &Ast::Identifier(body, _), _)
+                        if params.len() == 1 && params[0] == vec![body] => {}
+                    Ast::Closure(_, ref params, _, body, _) => {
+                        self.out.push(' ');
+                        if let Some(&k) = params.get(0).and_then(|p| p.get(0)) {
+                            self.name(k);
+                            self.out.push(' ');
+                        }
+                        self.body(body);
+                    }
+                    ref other => {
+                        self.out.push(' ');
+                        self.body(other);
+                    }
+                }
+            }
+        }
+    }
+
+    fn binop(&mut self, l: &Ast, op: &str, r: &Ast, precedence: u8) {
+        self.expression(l, precedence);
+        self.out.push_str(op);
+        self.expression(r, precedence + 1);
+    }
+
+    fn continuation_symbols(&mut self, symbols: &[Ast]) {
+        if !symbols.is_empty() {
+            self.out.push('(');
+            self.comma(symbols, |this, s| this.expression(s, PREC_ASSIGN));
+            self.out.push(')');
+        }
+    }
+
+    /// Prints the body of a closure or if statement, which must always
+    /// be a block.
+    fn body(&mut self, ast: &Ast) {
+        match *ast {
+            Ast::BlockExpression(..) | Ast::BlockStatement(..) | Ast::Contract(..) => {
+                self.expression(ast, PREC_PRIMARY)
+            }
+            ref other => self.block(&[], Some(other), 0),
+        }
+    }
+}
+
+impl <'ast> Ast<'ast> {
+    /// Prints this Ast as surface syntax that parses back into an equal
+    /// tree.  Statements are printed with their trailing `;`.
+    pub fn to_source(&self, interner: &SymbolIntern) -> Result<String, PrintError> {
+        let mut printer = Printer::new(interner);
+        match *self {
+            Ast::Define(..) | Ast::Import(..) | Ast::IfStatement(..) | Ast::BlockStatement(..) => {
+                printer.statement(self)
+            }
+            _ => printer.expression(self, PREC_ASSIGN),
+        }
+        printer.finish()
+    }
+}
+
+/// Prints a whole program with one top-level statement per line.
+pub fn format_program(asts: &[Ast], interner: &SymbolIntern) -> Result<String, PrintError> {
+    format_program_source(asts, interner, "")
+}
+
+/// Like `format_program`, but for the Asts that `source` parsed to, so
+/// that forms commented out with `#;` are kept.
+pub fn format_program_source(asts: &[Ast], interner: &SymbolIntern, source: &str) -> Result<String, PrintError> {
+    let mut printer = Printer::with_source(interner, source);
+    printer.program(asts);
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    printer.finish()
+}
+
+#[test]
+fn indentation() {
+    use typed_arena::Arena;
+    use parse_Program;
+
+    let arena = Arena::new();
+    let mut interner = SymbolIntern::new();
+    let program = "var x=1;fn foo(a,b){var c=a+b*(x-1);if c>2 then{print(c);}else{} c};  foo(1,2)";
+    let asts = parse_Program(&arena, &mut interner, program).unwrap();
+    assert_eq!(format_program(&asts, &interner).unwrap(), "\
+var x = 1;
+fn foo(a, b) {
+    var c = a + b * (x - 1);
+    if c > 2 then {
+        print(c);
+    } else {}
+    c
+};
+foo(1, 2);
+");
+}
+
+#[cfg(test)]
+mod test {
+    use typed_arena::Arena;
+    use {Ast, Span, SymbolIntern, parse_Expr, parse_Program};
+
+    /// A small xorshift generator so that failures are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, bound: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % bound
+        }
+    }
+
+    /// Quoted data, which can hold maps.
+    fn gen_datum<'a>(rng: &mut Rng, arena: &'a Arena<Ast<'a>>, interner: &mut SymbolIntern, depth: u32) -> Ast<'a> {
+        let s = Span::dummy();
+        let names = ["a", "b", "foo", "bar_2"];
+        match if depth == 0 { rng.next(4) } else { rng.next(6) } {
+            0 => Ast::Identifier(interner.intern(names[rng.next(4) as usize]), s),
+            1 => Ast::BoolLit(rng.next(2) == 0, s),
+            2 => Ast::IntLit(rng.next(1000) as i64, s),
+            3 => Ast::FloatLit(rng.next(1000) as f64 / 8.0, s),
+            4 => Ast::ListLit((0 .. rng.next(3)).map(|_| gen_datum(rng, arena, interner, depth - 1)).collect(), s),
+            _ => Ast::MapLit((0 .. rng.next(3)).map(|_| {
+                let k = gen_datum(rng, arena, interner, depth - 1);
+                (k, &*arena.alloc(gen_datum(rng, arena, interner, depth - 1)))
+            }).collect(), s),
+        }
+    }
+
+    /// Sets `unprintable` if the expression has a part that no source
+    /// parses back into.
+    fn gen_expr<'a>(rng: &mut Rng, arena: &'a Arena<Ast<'a>>, interner: &mut SymbolIntern, depth: u32,
+                    unprintable: &mut bool) -> Ast<'a> {
+        let s = Span::dummy();
+        let names = ["a", "b", "foo", "bar_2"];
+        let leaf = depth == 0 || rng.next(3) == 0;
+        if leaf {
+            return match rng.next(9) {
+                0 => Ast::Identifier(interner.intern(names[rng.next(4) as usize]), s),
+                1 => Ast::BoolLit(rng.next(2) == 0, s),
+                2 => Ast::StringLit(names[rng.next(4) as usize].to_string(), s),
+                3 => Ast::IntLit(rng.next(1000) as i64, s),
+                4 => Ast::FloatLit(rng.next(1000) as f64 / 8.0, s),
+                5 => Ast::SymbolLit(interner.intern(names[rng.next(4) as usize]), s),
+                6 => {
+                    let items = (0 .. rng.next(3)).map(|_| gen_datum(rng, arena, interner, 2)).collect();
+                    Ast::Quote(arena.alloc(Ast::ListLit(items, s)), s)
+                }
+                7 if rng.next(4) == 0 => {
+                    *unprintable = true;
+                    Ast::FloatLit(if rng.next(2) == 0 { ::std::f64::INFINITY } else { ::std::f64::NAN }, s)
+                }
+                8 if rng.next(4) == 0 => {
+                    *unprintable = true;
+                    Ast::MapLit(vec![(Ast::IntLit(1, s), &*arena.alloc(Ast::NilLit(s)))], s)
+                }
+                _ => Ast::NilLit(s),
+            };
+        }
+
+        let depth = depth - 1;
+        macro_rules! sub {
+            () => { arena.alloc(gen_expr(rng, arena, interner, depth, unprintable)) }
+        }
+        macro_rules! subs {
+            () => { (0 .. rng.next(3)).map(|_| gen_expr(rng, arena, interner, depth, unprintable)).collect() }
+        }
+
+        match rng.next(14) {
+            0 => Ast::ListLit(subs!(), s),
+            1 => Ast::ListAccess(sub!(), sub!(), s),
+            2 => Ast::Add(sub!(), sub!(), s),
+            3 => Ast::Sub(sub!(), sub!(), s),
+            4 => Ast::Mul(sub!(), sub!(), s),
+            5 => Ast::Div(sub!(), sub!(), s),
+            6 => Ast::LessThan(sub!(), sub!(), s),
+            7 => Ast::GreaterThanOrEqual(sub!(), sub!(), s),
+            8 => Ast::Equal(sub!(), sub!(), s),
+            9 => Ast::FnCall(sub!(), subs!(), s),
+            10 => Ast::IfExpression(sub!(), sub!(), sub!(), s),
+            11 => Ast::Assign(interner.intern(names[rng.next(4) as usize]), sub!(), s),
+            12 => {
+                let mut body: Vec<Ast> = subs!();
+                body.push(gen_expr(rng, arena, interner, depth, unprintable));
+                let params = vec![vec![interner.intern("a")], vec![]];
+                let body = arena.alloc(Ast::BlockExpression(body, s));
+                Ast::Closure(None, params, None, body, s)
+            }
+            _ => {
+                let closure = Ast::Closure(None, vec![vec![]], None, arena.alloc(Ast::BlockStatement(vec![], s)), s);
+                Ast::Reset(subs!(), arena.alloc(closure), s)
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_expressions() {
+        let arena = Arena::new();
+        let mut interner = SymbolIntern::new();
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+
+        for _ in 0 .. 500 {
+            let mut unprintable = false;
+            let ast = gen_expr(&mut rng, &arena, &mut interner, 4, &mut unprintable);
+            let source = match ast.to_source(&interner) {
+                Ok(source) => source,
+                Err(e) => {
+                    assert!(unprintable, "{:?} failed to print: {:?}", ast, e);
+                    continue;
+                }
+            };
+            assert!(!unprintable, "{:?} printed as {:?}", ast, source);
+            let reparsed = parse_Expr(&arena, &mut interner, &source)
+                .unwrap_or_else(|e| panic!("{:?} failed to parse: {:?}", source, e));
+            assert!(ast.equals_sans_span(&reparsed), "{:?} did not round trip", source);
+        }
+    }
+
+    #[test]
+    fn unprintable_syntax_is_an_error() {
+        use PrintError;
+
+        let arena = Arena::new();
+        let interner = SymbolIntern::new();
+        let s = Span::new(3, 7);
+        assert_eq!(Ast::FloatLit(::std::f64::INFINITY, s).to_source(&interner), Err(PrintError::NonFiniteFloat(s)));
+        assert_eq!(Ast::FloatLit(::std::f64::NAN, s).to_source(&interner), Err(PrintError::NonFiniteFloat(s)));
+        let map = Ast::MapLit(vec![(Ast::IntLit(1, s), &*arena.alloc(Ast::FloatLit(0.5, s)))], s);
+        assert_eq!(map.to_source(&interner), Err(PrintError::MapOutsideQuote(s)));
+        let quoted = Ast::Quote(arena.alloc(Ast::ListLit(vec![map], s)), s);
+        assert_eq!(quoted.to_source(&interner), Ok("'({1 0.5})".to_string()));
+    }
+
+    #[test]
+    fn round_trip_statements() {
+        let arena = Arena::new();
+        let mut interner = SymbolIntern::new();
+        let program = "var x = 1; var f = fn(a: int, b)(c): string { b }; \
+                       var g = fn(n) requires n > 0 ensures result > n { n + 1 }; \
+                       var h = loop(i = 0, acc = 1) { if i < 5 then recur(i + 1, acc * 2) else acc }; \
+                       var r = letrec(a = fn() { b() }, b = fn() { 1 }) { a() }; \
+                       var m = do { var t = 2; t * t }; \
+                       var k = let count(n = 3) { if n > 0 then count(n - 1) else n }; \
+                       if x then { x = 2; } else { {} } import(a, b) from c at \"1.0\" reset(x) { 5 }";
+        let asts = parse_Program(&arena, &mut interner, program).unwrap();
+        for ast in &asts {
+            let source = ast.to_source(&interner).unwrap();
+            let reparsed = parse_Program(&arena, &mut interner, &source).unwrap();
+            assert_eq!(reparsed.len(), 1);
+            assert!(ast.equals_sans_span(&reparsed[0]), "{:?} did not round trip", source);
+        }
+    }
+}
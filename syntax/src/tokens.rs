@@ -0,0 +1,345 @@
+use Span;
+
+const KEYWORDS: &'static [&'static str] = &[
+    "nil", "fn", "if", "then", "else", "var", "shift", "reset", "import", "from", "at",
+    "loop", "recur", "let", "do", "letrec",
+];
+
+// Longest operators first so that `==` isn't read as two `=`
+const PUNCTUATION: &'static [&'static str] = &[
+    "==", "!=", "<=", ">=", "<<", ">>", "&&", "||", "^^",
+    "{", "}", "[", "]", "(", ")", ",", ";", ":", ".", "=", "<", ">",
+    "+", "-", "*", "/", "%", "~", "!", "&", "|", "^",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier,
+    Keyword,
+    BoolLit,
+    IntLit,
+    FloatLit,
+    /// A number with a `d` suffix, like `1.50d`.
+    DecimalLit,
+    StringLit,
+    SymbolLit,
+    Punctuation,
+    /// `#;`, which comments out the form after it.
+    DatumComment,
+    /// A character that can't start any token.
+    Error,
+    /// The end of the source.  This token is empty, and only exists to
+    /// carry any trailing trivia.
+    Eof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// A `#!` line at the very start of the source, which lets a script
+    /// be run directly on Unix.  The newline after it isn't part of it.
+    Shebang,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'s> {
+    pub kind: TokenKind,
+    pub text: &'s str,
+    pub span: Span,
+    /// The trivia between the previous token and this one.
+    pub leading_trivia: Vec<Trivia>,
+}
+
+/// Splits source code into the same tokens that the parser sees,
+/// without parsing it.
+///
+/// Every byte of the source is covered by exactly one token or piece
+/// of trivia, so the source can be rebuilt from the stream.
+pub struct TokenIter<'s> {
+    source: &'s str,
+    position: usize,
+    done: bool,
+    /// Whether more source may follow `source`.
+    partial: bool,
+}
+
+impl <'s> TokenIter<'s> {
+    pub fn new(source: &'s str) -> TokenIter<'s> {
+        TokenIter {
+            source: source,
+            position: 0,
+            done: false,
+            partial: false,
+        }
+    }
+
+    /// Tokenizes a chunk of source that more may follow, like the part of
+    /// a file that has been read so far.  A token that runs up to the end
+    /// of the chunk might go on in the next one, so it isn't returned, and
+    /// there is no `Eof` token.
+    ///
+    /// To carry on once more source has arrived, start a new iterator on
+    /// the rest of the source from `offset()`.
+    pub fn partial(source: &'s str) -> TokenIter<'s> {
+        TokenIter {
+            partial: true,
+            ..TokenIter::new(source)
+        }
+    }
+
+    /// The number of bytes of the source that the tokens returned so far,
+    /// and their trivia, cover.
+    pub fn offset(&self) -> usize {
+        self.position
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span::new(start as u32, end as u32)
+    }
+
+    fn scan_while<F: Fn(char) -> bool>(&self, start: usize, f: F) -> usize {
+        self.source[start..].char_indices()
+                            .find(|&(_, c)| !f(c))
+                            .map(|(i, _)| start + i)
+                            .unwrap_or(self.source.len())
+    }
+
+    fn trivia(&mut self) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        if self.position == 0 && self.source.starts_with("#!") {
+            let end = self.source.find('\n').unwrap_or(self.source.len());
+            trivia.push(Trivia {
+                kind: TriviaKind::Shebang,
+                span: self.span(0, end),
+            });
+            self.position = end;
+        }
+        let end = self.scan_while(self.position, char::is_whitespace);
+        if end != self.position {
+            trivia.push(Trivia {
+                kind: TriviaKind::Whitespace,
+                span: self.span(self.position, end),
+            });
+            self.position = end;
+        }
+        trivia
+    }
+
+    /// Finds the end and kind of the token starting at `start`.
+    fn token_end(&self, start: usize) -> (usize, TokenKind) {
+        let rest = &self.source[start..];
+        let first = rest.chars().next().unwrap();
+        let is_ident_start = |c: char| c == '_' || c.is_ascii_alphabetic();
+        let is_ident = |c: char| c == '_' || c.is_ascii_alphanumeric();
+
+        if is_ident_start(first) {
+            let end = self.scan_while(start, is_ident);
+            let kind = match &self.source[start..end] {
+                "true" | "false" => TokenKind::BoolLit,
+                word if KEYWORDS.contains(&word) => TokenKind::Keyword,
+                _ => TokenKind::Identifier,
+            };
+            (end, kind)
+        } else if first.is_ascii_digit() {
+            let is_digit = |c: char| c == '_' || c.is_ascii_digit();
+            let mut end = self.scan_while(start, is_digit);
+            let mut kind = TokenKind::IntLit;
+            if self.source[end..].starts_with('.') {
+                kind = TokenKind::FloatLit;
+                end += 1;
+                if self.source[end..].starts_with(|c: char| c.is_ascii_digit()) {
+                    end = self.scan_while(end, is_digit);
+                }
+            }
+            // An exponent without any digits is still part of the number,
+            // for the parser to reject.
+            if self.source[end..].starts_with(|c: char| c == 'e' || c == 'E') {
+                kind = TokenKind::FloatLit;
+                end += 1;
+                if self.source[end..].starts_with(|c: char| c == '+' || c == '-') {
+                    end += 1;
+                }
+                end = self.scan_while(end, is_digit);
+            }
+            let has_fraction = !self.source[..end].ends_with('.');
+            let has_exponent = self.source[start..end].contains(|c: char| c == 'e' || c == 'E');
+            if has_fraction && !has_exponent && self.source[end..].starts_with('d') {
+                (end + 1, TokenKind::DecimalLit)
+            } else {
+                (end, kind)
+            }
+        } else if first == '"' {
+            let mut escaped = false;
+            for (i, c) in rest.char_indices().skip(1) {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => return (start + i + 1, TokenKind::StringLit),
+                    _ => escaped = false,
+                }
+            }
+            // An unterminated string runs to the end of the source
+            (self.source.len(), TokenKind::Error)
+        } else if first == '\'' && rest[1..].chars().next().map_or(false, &is_ident_start) {
+            (self.scan_while(start + 1, is_ident), TokenKind::SymbolLit)
+        } else if rest.starts_with("#;") {
+            (start + 2, TokenKind::DatumComment)
+        } else if let Some(p) = PUNCTUATION.iter().find(|p| rest.starts_with(*p)) {
+            (start + p.len(), TokenKind::Punctuation)
+        } else {
+            (start + first.len_utf8(), TokenKind::Error)
+        }
+    }
+}
+
+impl <'s> Iterator for TokenIter<'s> {
+    type Item = Token<'s>;
+
+    fn next(&mut self) -> Option<Token<'s>> {
+        if self.done {
+            return None;
+        }
+
+        let resume = self.position;
+        let trivia = self.trivia();
+        let start = self.position;
+        let (end, kind) = if start == self.source.len() {
+            (start, TokenKind::Eof)
+        } else {
+            self.token_end(start)
+        };
+        if self.partial && end == self.source.len() {
+            self.position = resume;
+            return None;
+        }
+        self.done = kind == TokenKind::Eof;
+        self.position = end;
+
+        Some(Token {
+            kind: kind,
+            text: &self.source[start..end],
+            span: self.span(start, end),
+            leading_trivia: trivia,
+        })
+    }
+}
+
+pub fn tokenize(source: &str) -> TokenIter {
+    TokenIter::new(source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_kinds() {
+        let kinds: Vec<(TokenKind, &str)> = tokenize("var x = 'a + 1.5 == \"s\\\"\";")
+            .map(|t| (t.kind, t.text))
+            .collect();
+        assert_eq!(kinds, vec![
+            (TokenKind::Keyword, "var"),
+            (TokenKind::Identifier, "x"),
+            (TokenKind::Punctuation, "="),
+            (TokenKind::SymbolLit, "'a"),
+            (TokenKind::Punctuation, "+"),
+            (TokenKind::FloatLit, "1.5"),
+            (TokenKind::Punctuation, "=="),
+            (TokenKind::StringLit, "\"s\\\"\""),
+            (TokenKind::Punctuation, ";"),
+            (TokenKind::Eof, ""),
+        ]);
+    }
+
+    #[test]
+    fn decimal_literals() {
+        let kinds: Vec<(TokenKind, &str)> = tokenize("1.50d 2d 3.d")
+            .map(|t| (t.kind, t.text))
+            .collect();
+        assert_eq!(kinds, vec![
+            (TokenKind::DecimalLit, "1.50d"),
+            (TokenKind::DecimalLit, "2d"),
+            (TokenKind::FloatLit, "3."),
+            (TokenKind::Identifier, "d"),
+            (TokenKind::Eof, ""),
+        ]);
+    }
+
+    #[test]
+    fn number_literals() {
+        let kinds: Vec<(TokenKind, &str)> = tokenize("1_000_000 1e9 2.5e-3 1E+1_0 1e 3.e 1._5 1_000.5_0d 1e5d")
+            .map(|t| (t.kind, t.text))
+            .collect();
+        assert_eq!(kinds, vec![
+            (TokenKind::IntLit, "1_000_000"),
+            (TokenKind::FloatLit, "1e9"),
+            (TokenKind::FloatLit, "2.5e-3"),
+            (TokenKind::FloatLit, "1E+1_0"),
+            (TokenKind::FloatLit, "1e"),
+            (TokenKind::FloatLit, "3.e"),
+            (TokenKind::FloatLit, "1."),
+            (TokenKind::Identifier, "_5"),
+            (TokenKind::DecimalLit, "1_000.5_0d"),
+            (TokenKind::FloatLit, "1e5"),
+            (TokenKind::Identifier, "d"),
+            (TokenKind::Eof, ""),
+        ]);
+    }
+
+    #[test]
+    fn datum_comments() {
+        let kinds: Vec<(TokenKind, &str)> = tokenize("#;a # ;")
+            .map(|t| (t.kind, t.text))
+            .collect();
+        assert_eq!(kinds, vec![
+            (TokenKind::DatumComment, "#;"),
+            (TokenKind::Identifier, "a"),
+            (TokenKind::Error, "#"),
+            (TokenKind::Punctuation, ";"),
+            (TokenKind::Eof, ""),
+        ]);
+    }
+
+    #[test]
+    fn shebangs_are_trivia() {
+        let tokens: Vec<Token> = tokenize("#!/usr/bin/env ares\nx").collect();
+        assert_eq!(tokens[0].text, "x");
+        assert_eq!(tokens[0].leading_trivia, vec![
+            Trivia { kind: TriviaKind::Shebang, span: Span::new(0, 19) },
+            Trivia { kind: TriviaKind::Whitespace, span: Span::new(19, 20) },
+        ]);
+        // Only at the very start.
+        assert_eq!(tokenize(" #!x").next().unwrap().kind, TokenKind::Error);
+    }
+
+    #[test]
+    fn partial_tokens_resume() {
+        let source = "var abc = 1.5 == \"a b\";";
+        for split in 0 .. source.len() + 1 {
+            let first = &source[.. split];
+            let mut tokens = TokenIter::partial(first);
+            let mut texts: Vec<String> = tokens.by_ref().map(|t| t.text.to_string()).collect();
+            let rest = format!("{}{}", &first[tokens.offset() ..], &source[split ..]);
+            texts.extend(tokenize(&rest).map(|t| t.text.to_string()));
+            assert_eq!(texts, vec!["var", "abc", "=", "1.5", "==", "\"a b\"", ";", ""], "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn trivia_covers_the_source() {
+        let source = "  fn(a)\n\t{ a }  ";
+        let mut rebuilt = String::new();
+        for token in tokenize(source) {
+            for trivia in &token.leading_trivia {
+                rebuilt.push_str(&source[trivia.span.start() as usize .. trivia.span.end() as usize]);
+            }
+            rebuilt.push_str(token.text);
+        }
+        assert_eq!(rebuilt, source);
+    }
+}
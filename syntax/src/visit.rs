@@ -0,0 +1,277 @@
+use typed_arena::Arena;
+use super::{Ast, AstRef};
+
+/// Walks over an Ast without modifying it.
+///
+/// Override `visit` to inspect the nodes that you are interested in, and
+/// call `walk_ast` from it to continue on to the children.
+pub trait AstVisitor<'ast> {
+    fn visit(&mut self, ast: AstRef<'ast>) {
+        walk_ast(self, ast)
+    }
+}
+
+/// Visits every direct child of `ast`.
+pub fn walk_ast<'ast, V: AstVisitor<'ast> + ?Sized>(visitor: &mut V, ast: AstRef<'ast>) {
+    match ast {
+        &Ast::Identifier(..) |
+        &Ast::BoolLit(..) |
+        &Ast::StringLit(..) |
+        &Ast::IntLit(..) |
+        &Ast::FloatLit(..) |
+        &Ast::DecimalLit(..) |
+        &Ast::SymbolLit(..) |
+        &Ast::NilLit(..) => {}
+
+        &Ast::ListLit(ref elements, _) |
+        &Ast::BlockExpression(ref elements, _) |
+        &Ast::BlockStatement(ref elements, _) => {
+            for element in elements {
+                visitor.visit(element);
+            }
+        }
+        &Ast::MapLit(ref pairs, _) => {
+            for &(ref k, v) in pairs {
+                visitor.visit(k);
+                visitor.visit(v);
+            }
+        }
+
+        &Ast::ListAccess(l, r, _) |
+        &Ast::Add(l, r, _) |
+        &Ast::Sub(l, r, _) |
+        &Ast::Mul(l, r, _) |
+        &Ast::Div(l, r, _) |
+        &Ast::LessThan(l, r, _) |
+        &Ast::LessThanOrEqual(l, r, _) |
+        &Ast::GreaterThan(l, r, _) |
+        &Ast::GreaterThanOrEqual(l, r, _) |
+        &Ast::Equal(l, r, _) |
+        &Ast::NotEqual(l, r, _) => {
+            visitor.visit(l);
+            visitor.visit(r);
+        }
+
+        &Ast::FnCall(receiver, ref args, _) => {
+            visitor.visit(receiver);
+            for arg in args {
+                visitor.visit(arg);
+            }
+        }
+        &Ast::IfExpression(c, t, f, _) => {
+            visitor.visit(c);
+            visitor.visit(t);
+            visitor.visit(f);
+        }
+        &Ast::IfStatement(c, t, f, _) => {
+            visitor.visit(c);
+            visitor.visit(t);
+            if let Some(f) = f {
+                visitor.visit(f);
+            }
+        }
+        &Ast::Closure(_, _, _, body, _) => visitor.visit(body),
+        &Ast::Contract(ref requires, ref ensures, body, _) => {
+            for clause in requires {
+                visitor.visit(clause);
+            }
+            visitor.visit(body);
+            for clause in ensures {
+                visitor.visit(clause);
+            }
+        }
+        &Ast::Assign(_, value, _) |
+        &Ast::Define(_, value, _) => visitor.visit(value),
+        &Ast::Loop(ref bindings, body, _) => {
+            for &(_, ref init) in bindings {
+                visitor.visit(init);
+            }
+            visitor.visit(body);
+        }
+        &Ast::Recur(ref values, _) => {
+            for value in values {
+                visitor.visit(value);
+            }
+        }
+        &Ast::Do(body, _) |
+        &Ast::Quote(body, _) => visitor.visit(body),
+        &Ast::Letrec(ref bindings, body, _) => {
+            for &(_, ref value) in bindings {
+                visitor.visit(value);
+            }
+            visitor.visit(body);
+        }
+        &Ast::NamedLet(_, ref bindings, body, _) => {
+            for &(_, ref init) in bindings {
+                visitor.visit(init);
+            }
+            visitor.visit(body);
+        }
+        &Ast::Import(_, _, version, _) => visitor.visit(version),
+        &Ast::Reset(ref symbols, closure, _) |
+        &Ast::Shift(ref symbols, closure, _) => {
+            for symbol in symbols {
+                visitor.visit(symbol);
+            }
+            visitor.visit(closure);
+        }
+    }
+}
+
+/// Rebuilds an Ast, allocating the new nodes in `arena()`.
+///
+/// Override `fold` to replace the nodes that you are interested in, and
+/// call `fold_ast` from it to rebuild the rest.
+pub trait AstFolder<'ast> {
+    fn arena(&self) -> &'ast Arena<Ast<'ast>>;
+
+    fn fold(&mut self, ast: &Ast<'ast>) -> Ast<'ast> {
+        fold_ast(self, ast)
+    }
+}
+
+/// Rebuilds `ast` by folding every direct child of it.
+pub fn fold_ast<'ast, F: AstFolder<'ast> + ?Sized>(folder: &mut F, ast: &Ast<'ast>) -> Ast<'ast> {
+    use self::Ast::*;
+
+    macro_rules! fold_ref {
+        ($e: expr) => {{
+            let folded = folder.fold($e);
+            let allocated: &'ast Ast<'ast> = folder.arena().alloc(folded);
+            allocated
+        }}
+    }
+
+    macro_rules! fold_vec {
+        ($e: expr) => {
+            $e.iter().map(|a| folder.fold(a)).collect()
+        }
+    }
+
+    match *ast {
+        Identifier(..) | BoolLit(..) | StringLit(..) | IntLit(..) |
+        FloatLit(..) | DecimalLit(..) | SymbolLit(..) | NilLit(..) => ast.clone(),
+
+        ListLit(ref e, s) => ListLit(fold_vec!(e), s),
+        BlockExpression(ref e, s) => BlockExpression(fold_vec!(e), s),
+        BlockStatement(ref e, s) => BlockStatement(fold_vec!(e), s),
+        MapLit(ref pairs, s) => {
+            let mut out = Vec::with_capacity(pairs.len());
+            for &(ref k, v) in pairs {
+                let k = folder.fold(k);
+                out.push((k, fold_ref!(v)));
+            }
+            MapLit(out, s)
+        }
+
+        ListAccess(l, r, s) => ListAccess(fold_ref!(l), fold_ref!(r), s),
+        Add(l, r, s) => Add(fold_ref!(l), fold_ref!(r), s),
+        Sub(l, r, s) => Sub(fold_ref!(l), fold_ref!(r), s),
+        Mul(l, r, s) => Mul(fold_ref!(l), fold_ref!(r), s),
+        Div(l, r, s) => Div(fold_ref!(l), fold_ref!(r), s),
+        LessThan(l, r, s) => LessThan(fold_ref!(l), fold_ref!(r), s),
+        LessThanOrEqual(l, r, s) => LessThanOrEqual(fold_ref!(l), fold_ref!(r), s),
+        GreaterThan(l, r, s) => GreaterThan(fold_ref!(l), fold_ref!(r), s),
+        GreaterThanOrEqual(l, r, s) => GreaterThanOrEqual(fold_ref!(l), fold_ref!(r), s),
+        Equal(l, r, s) => Equal(fold_ref!(l), fold_ref!(r), s),
+        NotEqual(l, r, s) => NotEqual(fold_ref!(l), fold_ref!(r), s),
+
+        FnCall(receiver, ref args, s) => {
+            let receiver = fold_ref!(receiver);
+            FnCall(receiver, fold_vec!(args), s)
+        }
+        IfExpression(c, t, f, s) => IfExpression(fold_ref!(c), fold_ref!(t), fold_ref!(f), s),
+        IfStatement(c, t, f, s) => {
+            let c = fold_ref!(c);
+            let t = fold_ref!(t);
+            let f = match f {
+                Some(f) => Some(fold_ref!(f)),
+                None => None,
+            };
+            IfStatement(c, t, f, s)
+        }
+        Closure(name, ref args, ref sig, body, s) => Closure(name, args.clone(), sig.clone(), fold_ref!(body), s),
+        Contract(ref requires, ref ensures, body, s) => {
+            let requires = fold_vec!(requires);
+            let body = fold_ref!(body);
+            Contract(requires, fold_vec!(ensures), body, s)
+        }
+        Assign(name, value, s) => Assign(name, fold_ref!(value), s),
+        Define(name, value, s) => Define(name, fold_ref!(value), s),
+        Loop(ref bindings, body, s) => {
+            let bindings = bindings.iter().map(|&(name, ref init)| (name, folder.fold(init))).collect();
+            Loop(bindings, fold_ref!(body), s)
+        }
+        Recur(ref values, s) => Recur(fold_vec!(values), s),
+        Do(body, s) => Do(fold_ref!(body), s),
+        Quote(data, s) => Quote(fold_ref!(data), s),
+        Letrec(ref bindings, body, s) => {
+            let bindings = bindings.iter().map(|&(name, ref value)| (name, folder.fold(value))).collect();
+            Letrec(bindings, fold_ref!(body), s)
+        }
+        NamedLet(name, ref bindings, body, s) => {
+            let bindings = bindings.iter().map(|&(name, ref init)| (name, folder.fold(init))).collect();
+            NamedLet(name, bindings, fold_ref!(body), s)
+        }
+        Import(ref names, namespace, version, s) => Import(names.clone(), namespace, fold_ref!(version), s),
+        Reset(ref symbols, closure, s) => {
+            let symbols = fold_vec!(symbols);
+            Reset(symbols, fold_ref!(closure), s)
+        }
+        Shift(ref symbols, closure, s) => {
+            let symbols = fold_vec!(symbols);
+            Shift(symbols, fold_ref!(closure), s)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use typed_arena::Arena;
+    use super::super::{parse_Expr, SymbolIntern};
+
+    #[test]
+    fn count_identifiers() {
+        struct Counter(u32);
+        impl <'ast> AstVisitor<'ast> for Counter {
+            fn visit(&mut self, ast: AstRef<'ast>) {
+                if let &Ast::Identifier(..) = ast {
+                    self.0 += 1;
+                }
+                walk_ast(self, ast);
+            }
+        }
+
+        let arena = Arena::new();
+        let mut interner = SymbolIntern::new();
+        let ast = parse_Expr(&arena, &mut interner, "foo(a, b + c)").unwrap();
+        let ast = arena.alloc(ast);
+        let mut counter = Counter(0);
+        counter.visit(ast);
+        assert_eq!(counter.0, 4);
+    }
+
+    #[test]
+    fn double_ints() {
+        struct Doubler<'ast>(&'ast Arena<Ast<'ast>>);
+        impl <'ast> AstFolder<'ast> for Doubler<'ast> {
+            fn arena(&self) -> &'ast Arena<Ast<'ast>> {
+                self.0
+            }
+            fn fold(&mut self, ast: &Ast<'ast>) -> Ast<'ast> {
+                match ast {
+                    &Ast::IntLit(i, s) => Ast::IntLit(i * 2, s),
+                    other => fold_ast(self, other),
+                }
+            }
+        }
+
+        let arena = Arena::new();
+        let mut interner = SymbolIntern::new();
+        let ast = parse_Expr(&arena, &mut interner, "1 + 2 * 3").unwrap();
+        let folded = Doubler(&arena).fold(&ast);
+        let expected = parse_Expr(&arena, &mut interner, "2 + 4 * 6").unwrap();
+        assert!(folded.equals_sans_span(&expected));
+    }
+}